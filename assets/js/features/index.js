@@ -14,3 +14,4 @@ export * from './autoscroll.js';
 export * from './animations.js';
 export * from './settings-panel.js';
 export * from './position.js';
+export * from './lazy-chapters.js';
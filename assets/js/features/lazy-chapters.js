@@ -0,0 +1,72 @@
+/**
+ * Franko Reader - Lazy Chapters Feature
+ * Fetches chapter bodies as their placeholders approach the viewport
+ */
+
+import { elements } from '../core/dom.js';
+
+// Start loading this far before a placeholder becomes visible
+const PRELOAD_MARGIN = '1500px 0px';
+
+const pending = new Set();
+
+/**
+ * Load the body of a placeholder chapter section
+ * @param {HTMLElement} section - Chapter section element
+ * @param {string} urlPattern - Chapter URL with `{}` in place of the index
+ * @returns {Promise<void>}
+ */
+export async function loadChapter(section, urlPattern) {
+    const index = section.dataset.chapter;
+    if (section.dataset.lazy !== 'true' || pending.has(index)) return;
+
+    pending.add(index);
+    try {
+        const response = await fetch(urlPattern.replace('{}', index));
+        const result = await response.json();
+        if (result.success && result.data) {
+            section.innerHTML = result.data.content_html;
+            section.style.minHeight = '';
+            delete section.dataset.lazy;
+        }
+    } catch (e) {
+        console.error(`Failed to load chapter ${index}:`, e);
+    } finally {
+        pending.delete(index);
+    }
+}
+
+/**
+ * Observe placeholder chapters and load them on demand
+ */
+export function initLazyChapters() {
+    const { readerContainer } = elements;
+    const urlPattern = readerContainer?.dataset.chapterUrl;
+    if (!urlPattern) return;
+
+    const sections = document.querySelectorAll('.chapter[data-lazy="true"]');
+
+    if ('IntersectionObserver' in window) {
+        const observer = new IntersectionObserver((entries) => {
+            entries.forEach(entry => {
+                if (entry.isIntersecting) {
+                    observer.unobserve(entry.target);
+                    loadChapter(entry.target, urlPattern);
+                }
+            });
+        }, { rootMargin: PRELOAD_MARGIN });
+
+        sections.forEach(section => observer.observe(section));
+    } else {
+        sections.forEach(section => loadChapter(section, urlPattern));
+    }
+
+    // Jump to the chapter requested in the URL
+    const initial = parseInt(readerContainer.dataset.initialChapter || '0');
+    if (initial > 0) {
+        const target = document.getElementById(`chapter-${initial}`);
+        if (target) {
+            target.scrollIntoView({ block: 'start' });
+        }
+    }
+}
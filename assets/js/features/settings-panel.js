@@ -249,6 +249,34 @@ function initFontFamilyControl() {
     });
 }
 
+/**
+ * List the user's theme files in the theme select
+ */
+async function addUserThemes() {
+    const themeSelect = document.getElementById('theme-select');
+    if (!themeSelect) return;
+
+    try {
+        const response = await fetch('/api/themes');
+        const result = await response.json();
+        const userThemes = (result.data || []).filter(theme => !theme.builtin);
+        if (userThemes.length === 0) return;
+
+        const group = document.createElement('optgroup');
+        group.label = 'Your Themes';
+        userThemes.forEach(theme => {
+            const option = document.createElement('option');
+            option.value = theme.name;
+            option.textContent = theme.name;
+            group.appendChild(option);
+        });
+        themeSelect.appendChild(group);
+        themeSelect.value = readingSettings.theme;
+    } catch (e) {
+        console.warn('Could not load themes:', e);
+    }
+}
+
 /**
  * Initialize theme controls
  */
@@ -261,6 +289,8 @@ function initThemeControls() {
         saveSettings();
     });
 
+    addUserThemes();
+
     // Legacy theme buttons
     document.querySelectorAll('[data-theme]').forEach(btn => {
         btn.addEventListener('click', () => {
@@ -37,11 +37,13 @@ export const ALL_THEMES = [
 export function applyTheme(theme, customColors = null) {
     const root = document.documentElement;
 
-    // Remove all theme classes
+    // Remove all theme classes, user themes included
     ALL_THEMES.forEach(t => root.classList.remove(t));
+    if (root.dataset.theme) root.classList.remove(root.dataset.theme);
 
     // Add new theme class
     root.classList.add(theme);
+    root.dataset.theme = theme;
 
     // Apply custom colors when custom theme is selected
     if (theme === 'custom' && customColors) {
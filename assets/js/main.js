@@ -23,6 +23,7 @@ import { startAutoScroll, stopAutoScroll, getAutoScrollSpeed } from './features/
 import { initAnimations } from './features/animations.js';
 import { initSettingsPanel, toggleSettingsPanel, setPositionCallbacks, getReadingSettings } from './features/settings-panel.js';
 import { getReadingPosition, restoreReadingPosition } from './features/position.js';
+import { initLazyChapters } from './features/lazy-chapters.js';
 
 /**
  * Initialize the reader application
@@ -51,6 +52,7 @@ function init() {
     initAnimations();
     initLayoutControls();
     initChapterTracking();
+    initLazyChapters();
     initTocNavigation();
 
     // Initialize settings panel with position preservation
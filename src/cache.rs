@@ -0,0 +1,455 @@
+//! Persistent cache of parsed books
+//!
+//! Parsing large books (PDFs especially) can take seconds, so parsed books
+//! are serialized with bincode into the cache directory. Entries are named
+//! by the content hash of the book, so a moved or renamed book still finds
+//! its entry, and record the parser version they were made with. A small
+//! reference file per path remembers the size, modification time and hash
+//! of the file last seen there, so unchanged books are not hashed again.
+//! Image data is never cached; see [`crate::formats::load_resource`].
+//!
+//! Entry layout: header, each chapter in order, then the book skeleton with
+//! the chapter offsets, and finally the skeleton's offset as a little-endian
+//! `u64`. Chapters can therefore be read one at a time.
+
+use crate::cli::CacheCommand;
+use crate::config::Config;
+use crate::formats::lazy::ChapterLoader;
+use crate::formats::{self, BookSkeleton, Chapter, LazyBook, PARSER_VERSION};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Extension of cache entry files
+const ENTRY_EXT: &str = "bin";
+
+/// Extension of the files remembering which entry a path's book has
+const REF_EXT: &str = "ref";
+
+/// On-disk cache of parsed books
+#[derive(Debug, Clone)]
+pub struct BookCache {
+    dir: PathBuf,
+    max_size: u64,
+    enabled: bool,
+}
+
+/// Cache usage summary
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub total_size: u64,
+    pub max_size: u64,
+}
+
+/// Header written before the serialized book
+#[derive(Debug, Serialize, Deserialize)]
+struct EntryHeader {
+    parser_version: u32,
+    hash: String,
+}
+
+/// The book file last seen at a path
+#[derive(Debug, Serialize, Deserialize)]
+struct PathRef {
+    size: u64,
+    modified: u128,
+    hash: String,
+}
+
+impl BookCache {
+    /// Create a cache from the library configuration
+    pub fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            dir: config.cache_dir()?,
+            max_size: config.library.cache_max_size_mb * 1024 * 1024,
+            enabled: config.library.cache_enabled,
+        })
+    }
+
+    /// Open a book, parsing it only when no valid cache entry exists
+    ///
+    /// Chapters of a cached book are read from disk on demand. Cache
+    /// failures are logged and never prevent the book from loading.
+    pub fn open(&self, path: &Path) -> Result<LazyBook> {
+        if !self.enabled {
+            return formats::open_lazy(path);
+        }
+
+        let meta = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read book: {}", path.display()))?;
+        let size = meta.len();
+        let modified = modified_nanos(&meta);
+
+        // The file is only hashed when it changed since it was last seen here
+        let ref_path = self.ref_path(path);
+        let known = read_ref(&ref_path)
+            .filter(|known| known.size == size && known.modified == modified)
+            .map(|known| known.hash);
+        let seen = known.is_some();
+        let hash = match known {
+            Some(hash) => hash,
+            None => hash_file(path)?,
+        };
+        let entry = self.entry_path(&hash);
+        let remember = || {
+            if seen {
+                return;
+            }
+            let known = PathRef {
+                size,
+                modified,
+                hash: hash.clone(),
+            };
+            if let Err(e) = write_ref(&ref_path, &known) {
+                warn!(
+                    "Failed to write cache reference {}: {}",
+                    ref_path.display(),
+                    e
+                );
+            }
+        };
+
+        match self.open_entry(&entry, &hash) {
+            Ok(Some(mut book)) => {
+                debug!("Cache hit for {}", path.display());
+                touch(&entry);
+                remember();
+                book.skeleton.source_path = path.to_path_buf();
+                return Ok(book);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring unreadable cache entry {}: {}", entry.display(), e),
+        }
+
+        let header = EntryHeader {
+            parser_version: PARSER_VERSION,
+            hash: hash.clone(),
+        };
+        match self.write_entry(&entry, &header, path) {
+            Ok(()) => match self.open_entry(&entry, &hash) {
+                Ok(Some(book)) => {
+                    remember();
+                    self.evict();
+                    return Ok(book);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to reopen cache entry {}: {}", entry.display(), e),
+            },
+            Err(e) => warn!("Failed to write cache entry {}: {}", entry.display(), e),
+        }
+
+        formats::open_lazy(path)
+    }
+
+    /// Remove all cache entries, returning how many were deleted
+    pub fn clear(&self) -> Result<usize> {
+        let mut removed = 0;
+        for (path, _, _) in self.entries()? {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        }
+        for path in self.files(REF_EXT)? {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(removed)
+    }
+
+    /// Get cache usage statistics
+    pub fn stats(&self) -> Result<CacheStats> {
+        let entries = self.entries()?;
+        Ok(CacheStats {
+            dir: self.dir.clone(),
+            entries: entries.len(),
+            total_size: entries.iter().map(|(_, size, _)| size).sum(),
+            max_size: self.max_size,
+        })
+    }
+
+    /// Entry of the book with content hash `hash`
+    fn entry_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", &hash[..32], ENTRY_EXT))
+    }
+
+    /// Reference file of the book at `path`
+    fn ref_path(&self, path: &Path) -> PathBuf {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let key = blake3::hash(path.to_string_lossy().as_bytes()).to_hex();
+        self.dir.join(format!("{}.{}", &key[..32], REF_EXT))
+    }
+
+    /// Open an entry, returning `None` if it is missing, from another parser
+    /// version or for another book
+    fn open_entry(&self, entry: &Path, hash: &str) -> Result<Option<LazyBook>> {
+        let file = match File::open(entry) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+
+        let header: EntryHeader = bincode::deserialize_from(&mut reader)?;
+        if header.parser_version != PARSER_VERSION || header.hash != hash {
+            return Ok(None);
+        }
+
+        reader.seek(SeekFrom::End(-8))?;
+        let mut trailer = [0u8; 8];
+        reader.read_exact(&mut trailer)?;
+        reader.seek(SeekFrom::Start(u64::from_le_bytes(trailer)))?;
+
+        let skeleton: BookSkeleton = bincode::deserialize_from(&mut reader)?;
+        let offsets: Vec<u64> = bincode::deserialize_from(&mut reader)?;
+        if offsets.len() != skeleton.chapters.len() {
+            anyhow::bail!("Corrupt cache entry: chapter count mismatch");
+        }
+
+        let chapters = CachedChapters {
+            reader: Mutex::new(reader),
+            offsets,
+        };
+        Ok(Some(LazyBook::new(skeleton, Box::new(chapters))))
+    }
+
+    /// Parse a book straight into a new cache entry
+    fn write_entry(&self, entry: &Path, header: &EntryHeader, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first so readers never see partial entries
+        let tmp = entry.with_extension(format!("{}.{}", ENTRY_EXT, uuid::Uuid::new_v4()));
+        let result = (|| -> Result<()> {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            bincode::serialize_into(&mut writer, header)?;
+            let mut pos = bincode::serialized_size(header)?;
+
+            let mut offsets = Vec::new();
+            let skeleton = formats::parse_streaming(path, &mut |chapter: Chapter| {
+                offsets.push(pos);
+                bincode::serialize_into(&mut writer, &chapter)?;
+                pos += bincode::serialized_size(&chapter)?;
+                Ok(())
+            })?;
+
+            bincode::serialize_into(&mut writer, &skeleton)?;
+            bincode::serialize_into(&mut writer, &offsets)?;
+            writer.write_all(&pos.to_le_bytes())?;
+            writer.into_inner().map_err(|e| e.into_error())?;
+            std::fs::rename(&tmp, entry)?;
+            Ok(())
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    /// Drop least recently used entries until the cache fits its size limit
+    fn evict(&self) {
+        let mut entries = match self.entries() {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to list cache entries: {}", e);
+                return;
+            }
+        };
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_size {
+            return;
+        }
+
+        entries.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                debug!("Evicted cache entry {}", path.display());
+                total -= size;
+            }
+        }
+    }
+
+    /// List entries as (path, size, last use)
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = Vec::new();
+        for path in self.files(ENTRY_EXT)? {
+            let meta = std::fs::metadata(&path)?;
+            let used = meta.modified().unwrap_or(UNIX_EPOCH);
+            entries.push((path, meta.len(), used));
+        }
+        Ok(entries)
+    }
+
+    /// List the cache files with extension `ext`
+    fn files(&self, ext: &str) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for item in std::fs::read_dir(&self.dir)? {
+            let path = item?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(ext) {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Reads chapters of a cached book from its entry file
+struct CachedChapters {
+    reader: Mutex<BufReader<File>>,
+    offsets: Vec<u64>,
+}
+
+impl ChapterLoader for CachedChapters {
+    fn load(&self, index: usize) -> Result<Chapter> {
+        let offset = *self
+            .offsets
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("Chapter {} out of range", index + 1))?;
+        let mut reader = self.reader.lock().unwrap_or_else(|e| e.into_inner());
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(bincode::deserialize_from(&mut *reader)?)
+    }
+}
+
+/// Read a path's reference file, if it exists and is readable
+fn read_ref(ref_path: &Path) -> Option<PathRef> {
+    let file = File::open(ref_path).ok()?;
+    bincode::deserialize_from(BufReader::new(file)).ok()
+}
+
+fn write_ref(ref_path: &Path, known: &PathRef) -> Result<()> {
+    std::fs::write(ref_path, bincode::serialize(known)?)?;
+    Ok(())
+}
+
+fn modified_nanos(meta: &std::fs::Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// Hash a file's content, for recognizing it after it is touched or moved
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Mark an entry as recently used for eviction ordering
+fn touch(entry: &Path) {
+    if let Ok(file) = File::options().append(true).open(entry) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Handle cache commands
+pub fn handle_command(cmd: CacheCommand, config: &Config) -> Result<()> {
+    let cache = BookCache::new(config)?;
+
+    match cmd {
+        CacheCommand::Clear => {
+            let removed = cache.clear()?;
+            println!("Removed {} cached books", removed);
+        }
+        CacheCommand::Stats => {
+            let stats = cache.stats()?;
+            println!("Directory:   {}", stats.dir.display());
+            println!("Entries:     {}", stats.entries);
+            println!(
+                "Size:        {:.1} MB / {:.1} MB",
+                stats.total_size as f64 / (1024.0 * 1024.0),
+                stats.max_size as f64 / (1024.0 * 1024.0)
+            );
+            if !config.library.cache_enabled {
+                println!("Caching is disabled (library.cache_enabled = false)");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cache(dir: &Path) -> BookCache {
+        let mut config = Config::default();
+        config.library.cache_dir = Some(dir.join("cache"));
+        BookCache::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_cache_roundtrip_and_invalidation() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache = test_cache(temp.path());
+        let book_path = temp.path().join("book.md");
+        std::fs::write(&book_path, "# One\n\nFirst version.").unwrap();
+
+        let book = cache.open(&book_path).unwrap();
+        assert_eq!(cache.stats().unwrap().entries, 1);
+
+        let cached = cache.open(&book_path).unwrap();
+        assert_eq!(cached.metadata().title, book.metadata().title);
+        assert_eq!(cached.chapter_count(), book.chapter_count());
+        assert_eq!(
+            cached.load_chapter(0).unwrap().blocks.len(),
+            book.load_chapter(0).unwrap().blocks.len()
+        );
+
+        // Changed content gets its own entry; the old one is left to eviction
+        std::fs::write(&book_path, "# Two\n\nA changed and longer version.").unwrap();
+        let reparsed = cache.open(&book_path).unwrap();
+        assert_eq!(reparsed.metadata().title, "Two");
+        assert_eq!(cache.stats().unwrap().entries, 2);
+
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert_eq!(cache.stats().unwrap().entries, 0);
+        assert!(cache.files(REF_EXT).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_moved_book_keeps_its_entry() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache = test_cache(temp.path());
+        let book_path = temp.path().join("book.md");
+        std::fs::write(&book_path, "# One\n\nSome text.").unwrap();
+        cache.open(&book_path).unwrap();
+
+        let moved = temp.path().join("renamed.md");
+        std::fs::rename(&book_path, &moved).unwrap();
+        let entry = cache.entry_path(&hash_file(&moved).unwrap());
+        let written = std::fs::read(&entry).unwrap();
+
+        let book = cache.open(&moved).unwrap();
+        assert_eq!(book.skeleton.source_path, moved);
+        assert_eq!(cache.stats().unwrap().entries, 1);
+        assert_eq!(std::fs::read(&entry).unwrap(), written);
+
+        // The new path is remembered, so the file is not hashed again
+        let known = read_ref(&cache.ref_path(&moved)).unwrap();
+        assert_eq!(known.hash, hash_file(&moved).unwrap());
+    }
+}
@@ -54,6 +54,51 @@ pub enum Commands {
         bind: String,
     },
 
+    /// Convert a book, or a folder of Markdown/TXT files, to EPUB, Markdown, text or HTML
+    Convert {
+        /// Book file or folder to convert
+        input: PathBuf,
+
+        /// Output file; the format follows its extension (.epub, .md, .txt, .html).
+        /// Defaults to an EPUB next to the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Override the book title
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Override the author (repeat for several)
+        #[arg(long)]
+        author: Vec<String>,
+
+        /// Wrap width for plain text output (0 to disable; default: tui.max_width)
+        #[arg(short, long)]
+        width: Option<usize>,
+
+        /// Include the book's library annotations as highlights and comments
+        #[arg(short, long)]
+        annotations: bool,
+    },
+
+    /// Diagnose books that fail to load or show little content
+    Doctor {
+        /// Book files to check
+        paths: Vec<PathBuf>,
+
+        /// Check every book in the library
+        #[arg(short, long)]
+        library: bool,
+
+        /// Print the reports as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Parsed-book cache commands
+    #[command(subcommand)]
+    Cache(CacheCommand),
+
     /// Initialize configuration file with defaults
     Init,
 }
@@ -115,6 +160,52 @@ pub enum LibraryCommand {
         id: String,
     },
 
+    /// Edit a book's metadata
+    Edit {
+        /// Book ID
+        id: String,
+
+        /// New title
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Author (repeat for several; replaces the current authors)
+        #[arg(long = "author")]
+        authors: Vec<String>,
+
+        /// Series name (empty to remove the book from its series)
+        #[arg(long)]
+        series: Option<String>,
+
+        /// Position in the series
+        #[arg(long)]
+        series_index: Option<f32>,
+
+        /// Book subject (repeat for several; replaces the current subjects)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// Remove all subjects
+        #[arg(long, conflicts_with = "tags")]
+        clear_tags: bool,
+
+        /// New description
+        #[arg(long)]
+        description: Option<String>,
+
+        /// New publisher
+        #[arg(long)]
+        publisher: Option<String>,
+
+        /// Language code
+        #[arg(long)]
+        language: Option<String>,
+
+        /// Also write the changes into the book file (EPUB or Markdown)
+        #[arg(long)]
+        write_file: bool,
+    },
+
     /// Import books from a directory
     Import {
         /// Directory to import from
@@ -218,6 +309,15 @@ pub enum AnnotationCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Remove all cached books
+    Clear,
+
+    /// Show cache size and entry count
+    Stats,
+}
+
 #[derive(Subcommand)]
 pub enum ConfigCommand {
     /// Show current configuration
@@ -53,6 +53,7 @@ impl Default for TuiConfig {
             max_width: 80,
             margin_left: 4,
             margin_right: 4,
+            image_protocol: "auto".to_string(),
         }
     }
 }
@@ -97,6 +98,10 @@ impl Default for LibraryConfig {
             auto_index: true,
             backup_enabled: true,
             backup_count: 5,
+            cache_enabled: true,
+            cache_dir: None,
+            cache_max_size_mb: 512,
+            auto_add_opened: false,
         }
     }
 }
@@ -24,6 +24,7 @@ pub enum Action {
     GoToTop,
     GoToBottom,
     GoToLine,
+    GoToPercent,
     NextChapter,
     PrevChapter,
     NextParagraph,
@@ -51,6 +52,8 @@ pub enum Action {
     ListBookmarks,
     GotoBookmark,
     RemoveBookmark,
+    SetMark,
+    GoToMark,
 
     // Annotations
     AddAnnotation,
@@ -71,8 +74,14 @@ pub enum Action {
     RecentBooks,
     BookInfo,
 
+    // Links
+    FollowLink,
+    JumpBack,
+    JumpForward,
+
     // UI
     Help,
+    TableOfContents,
     Quit,
     ForceQuit,
     Escape,
@@ -89,8 +98,28 @@ pub enum Action {
     ClearSelection,
 }
 
+impl Action {
+    /// Look an action up by its config name, such as `scroll_down`
+    pub fn from_name(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    /// The action's config name
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            _ => format!("{:?}", self),
+        }
+    }
+}
+
 /// A key binding
+///
+/// `key` is a key name such as `PageDown`, or a sequence in Vim notation
+/// such as `gg` or `<C-w>j`; `modifiers` apply to its last key. In config
+/// files a binding can also be written as just its key.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RawKeyBinding")]
 pub struct KeyBinding {
     pub key: String,
     pub modifiers: Vec<String>,
@@ -98,6 +127,62 @@ pub struct KeyBinding {
     pub mode: Option<String>,
 }
 
+/// A key binding as written in a config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawKeyBinding {
+    Key(String),
+    Full {
+        key: String,
+        #[serde(default)]
+        modifiers: Vec<String>,
+        #[serde(default)]
+        mode: Option<String>,
+    },
+}
+
+impl From<RawKeyBinding> for KeyBinding {
+    fn from(raw: RawKeyBinding) -> Self {
+        match raw {
+            RawKeyBinding::Key(key) => KeyBinding::new(&key),
+            RawKeyBinding::Full {
+                key,
+                modifiers,
+                mode,
+            } => KeyBinding {
+                key,
+                modifiers,
+                mode,
+            },
+        }
+    }
+}
+
+/// One binding or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(KeyBinding),
+    Many(Vec<KeyBinding>),
+}
+
+/// Read custom bindings, where an action may be given a single binding
+fn deserialize_bindings<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<Action, Vec<KeyBinding>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw = HashMap::<Action, OneOrMany>::deserialize(deserializer)?;
+    Ok(raw
+        .into_iter()
+        .map(|(action, bindings)| match bindings {
+            OneOrMany::One(binding) => (action, vec![binding]),
+            OneOrMany::Many(bindings) => (action, bindings),
+        })
+        .collect())
+}
+
 impl KeyBinding {
     pub fn new(key: &str) -> Self {
         Self {
@@ -136,6 +221,7 @@ pub struct Keybindings {
     pub preset: String,
 
     /// Custom bindings (override preset)
+    #[serde(deserialize_with = "deserialize_bindings")]
     pub bindings: HashMap<Action, Vec<KeyBinding>>,
 
     /// Leader key for multi-key commands
@@ -147,11 +233,41 @@ pub struct Keybindings {
 
 impl Default for Keybindings {
     fn default() -> Self {
-        Self::vim_preset()
+        Self {
+            preset: "vim".to_string(),
+            bindings: HashMap::new(),
+            leader: " ".to_string(),
+            timeout: 1000,
+        }
     }
 }
 
 impl Keybindings {
+    /// A preset by name; `custom` starts with no bindings at all
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "vim" => Some(Self::vim_preset()),
+            "emacs" => Some(Self::emacs_preset()),
+            "reader" => Some(Self::reader_preset()),
+            "custom" => Some(Self {
+                preset: "custom".to_string(),
+                bindings: HashMap::new(),
+                leader: String::new(),
+                timeout: 1000,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The preset's bindings with the custom ones laid over them
+    pub fn resolved(&self) -> HashMap<Action, Vec<KeyBinding>> {
+        let mut resolved = Self::preset(&self.preset).unwrap_or_else(Self::vim_preset);
+        for (action, bindings) in &self.bindings {
+            resolved.bind(*action, bindings.clone());
+        }
+        resolved.bindings
+    }
+
     /// Create Vim-style keybindings
     pub fn vim_preset() -> Self {
         let mut bindings = HashMap::new();
@@ -165,14 +281,6 @@ impl Keybindings {
             Action::ScrollDown,
             vec![KeyBinding::new("j"), KeyBinding::new("Down")],
         );
-        bindings.insert(
-            Action::ScrollLeft,
-            vec![KeyBinding::new("h"), KeyBinding::new("Left")],
-        );
-        bindings.insert(
-            Action::ScrollRight,
-            vec![KeyBinding::new("l"), KeyBinding::new("Right")],
-        );
         bindings.insert(
             Action::PageUp,
             vec![KeyBinding::new("b").with_ctrl(), KeyBinding::new("PageUp")],
@@ -189,20 +297,32 @@ impl Keybindings {
         bindings.insert(Action::HalfPageDown, vec![KeyBinding::new("d").with_ctrl()]);
         bindings.insert(
             Action::GoToTop,
-            vec![KeyBinding::new("g"), KeyBinding::new("Home")],
+            vec![KeyBinding::new("gg"), KeyBinding::new("Home")],
         );
         bindings.insert(
             Action::GoToBottom,
             vec![KeyBinding::new("G"), KeyBinding::new("End")],
         );
-        bindings.insert(Action::GoToLine, vec![KeyBinding::new(":")]);
+        bindings.insert(Action::GoToLine, vec![KeyBinding::new("g").with_ctrl()]);
+        bindings.insert(Action::GoToPercent, vec![KeyBinding::new("%")]);
         bindings.insert(
             Action::NextChapter,
-            vec![KeyBinding::new("]"), KeyBinding::new("n")],
+            vec![
+                KeyBinding::new("]"),
+                KeyBinding::new("n"),
+                KeyBinding::new("l"),
+                KeyBinding::new("Right"),
+            ],
         );
         bindings.insert(
             Action::PrevChapter,
-            vec![KeyBinding::new("["), KeyBinding::new("N")],
+            vec![
+                KeyBinding::new("["),
+                KeyBinding::new("N"),
+                KeyBinding::new("p"),
+                KeyBinding::new("h"),
+                KeyBinding::new("Left"),
+            ],
         );
         bindings.insert(Action::NextParagraph, vec![KeyBinding::new("}")]);
         bindings.insert(Action::PrevParagraph, vec![KeyBinding::new("{")]);
@@ -217,17 +337,27 @@ impl Keybindings {
             Action::SearchPrev,
             vec![KeyBinding::new("N").in_mode("search")],
         );
-        bindings.insert(Action::ClearSearch, vec![KeyBinding::new("Escape")]);
+        bindings.insert(
+            Action::ClearSearch,
+            vec![KeyBinding::new("Escape").in_mode("search")],
+        );
 
-        // Bookmarks
-        bindings.insert(Action::AddBookmark, vec![KeyBinding::new("m")]);
-        bindings.insert(Action::ListBookmarks, vec![KeyBinding::new("'")]);
-        bindings.insert(Action::GotoBookmark, vec![KeyBinding::new("`")]);
+        // Bookmarks and marks
+        bindings.insert(Action::AddBookmark, vec![KeyBinding::new("b")]);
+        bindings.insert(Action::ListBookmarks, vec![KeyBinding::new("B")]);
+        bindings.insert(Action::AddAnnotation, vec![KeyBinding::new("a")]);
+        bindings.insert(Action::ListAnnotations, vec![KeyBinding::new("A")]);
+        bindings.insert(Action::SetMark, vec![KeyBinding::new("m")]);
+        bindings.insert(
+            Action::GoToMark,
+            vec![KeyBinding::new("'"), KeyBinding::new("`")],
+        );
 
         // UI
         bindings.insert(Action::ToggleFullscreen, vec![KeyBinding::new("f")]);
         bindings.insert(Action::ToggleSidebar, vec![KeyBinding::new("s")]);
         bindings.insert(Action::ToggleStatusBar, vec![KeyBinding::new("S")]);
+        bindings.insert(Action::ToggleLineNumbers, vec![KeyBinding::new("L")]);
         bindings.insert(
             Action::IncreaseFontSize,
             vec![KeyBinding::new("="), KeyBinding::new("+")],
@@ -252,15 +382,20 @@ impl Keybindings {
                 KeyBinding::new("l").with_ctrl(),
             ],
         );
-        bindings.insert(Action::ToggleTheme, vec![KeyBinding::new("t")]);
+        bindings.insert(Action::ToggleTheme, vec![KeyBinding::new("T")]);
+        bindings.insert(Action::TableOfContents, vec![KeyBinding::new("t")]);
         bindings.insert(Action::CommandPalette, vec![KeyBinding::new(":")]);
 
+        // Links; terminals send Ctrl+i as Tab
+        bindings.insert(Action::FollowLink, vec![KeyBinding::new("F")]);
+        bindings.insert(Action::JumpBack, vec![KeyBinding::new("o").with_ctrl()]);
+        bindings.insert(Action::JumpForward, vec![KeyBinding::new("Tab")]);
+
         // Selection
         bindings.insert(Action::StartSelection, vec![KeyBinding::new("v")]);
         bindings.insert(Action::CopySelection, vec![KeyBinding::new("y")]);
 
         // Library
-        bindings.insert(Action::OpenLibrary, vec![KeyBinding::new("L")]);
         bindings.insert(Action::BookInfo, vec![KeyBinding::new("i")]);
 
         Self {
@@ -312,19 +447,52 @@ impl Keybindings {
             vec![KeyBinding::new(">").with_alt(), KeyBinding::new("End")],
         );
         bindings.insert(Action::GoToLine, vec![KeyBinding::new("g").with_alt()]);
+        bindings.insert(Action::NextChapter, vec![KeyBinding::new("n").with_alt()]);
+        bindings.insert(Action::PrevChapter, vec![KeyBinding::new("p").with_alt()]);
 
         // Search
         bindings.insert(Action::Search, vec![KeyBinding::new("s").with_ctrl()]);
-        bindings.insert(Action::SearchNext, vec![KeyBinding::new("s").with_ctrl()]);
-        bindings.insert(Action::SearchPrev, vec![KeyBinding::new("r").with_ctrl()]);
+        bindings.insert(
+            Action::SearchNext,
+            vec![KeyBinding::new("s").with_ctrl().in_mode("search")],
+        );
+        bindings.insert(
+            Action::SearchPrev,
+            vec![KeyBinding::new("r").with_ctrl().in_mode("search")],
+        );
+        bindings.insert(
+            Action::ClearSearch,
+            vec![KeyBinding::new("g").with_ctrl().in_mode("search")],
+        );
 
         // UI
         bindings.insert(Action::Quit, vec![KeyBinding::new("q").with_ctrl()]);
+        bindings.insert(Action::ForceQuit, vec![KeyBinding::new("c").with_ctrl()]);
         bindings.insert(Action::Help, vec![KeyBinding::new("h").with_ctrl()]);
         bindings.insert(
             Action::Escape,
             vec![KeyBinding::new("g").with_ctrl(), KeyBinding::new("Escape")],
         );
+        bindings.insert(
+            Action::CommandPalette,
+            vec![KeyBinding::new("x").with_alt()],
+        );
+        bindings.insert(
+            Action::TableOfContents,
+            vec![KeyBinding::new("t").with_alt()],
+        );
+
+        // Registers stand in for marks
+        bindings.insert(Action::SetMark, vec![KeyBinding::new("<C-x>r<Space>")]);
+        bindings.insert(Action::GoToMark, vec![KeyBinding::new("<C-x>rj")]);
+
+        // Links
+        bindings.insert(Action::FollowLink, vec![KeyBinding::new("o").with_alt()]);
+        bindings.insert(Action::JumpBack, vec![KeyBinding::new("Left").with_alt()]);
+        bindings.insert(
+            Action::JumpForward,
+            vec![KeyBinding::new("Right").with_alt()],
+        );
 
         // Selection
         bindings.insert(
@@ -388,11 +556,21 @@ impl Keybindings {
             Action::DecreaseFontSize,
             vec![KeyBinding::new("-").with_ctrl()],
         );
-        bindings.insert(Action::Help, vec![KeyBinding::new("F1")]);
+        bindings.insert(
+            Action::Help,
+            vec![KeyBinding::new("F1"), KeyBinding::new("?")],
+        );
         bindings.insert(
             Action::Quit,
             vec![KeyBinding::new("q"), KeyBinding::new("Escape")],
         );
+        bindings.insert(Action::ForceQuit, vec![KeyBinding::new("c").with_ctrl()]);
+        bindings.insert(Action::TableOfContents, vec![KeyBinding::new("t")]);
+        bindings.insert(Action::CommandPalette, vec![KeyBinding::new(":")]);
+
+        // Links
+        bindings.insert(Action::FollowLink, vec![KeyBinding::new("Enter")]);
+        bindings.insert(Action::JumpBack, vec![KeyBinding::new("Backspace")]);
 
         Self {
             preset: "reader".to_string(),
@@ -1,7 +1,7 @@
 //! Configuration loading, saving, and command handling
 
 use super::structs::Config;
-use super::{keybindings, theme};
+use super::{keybindings, ThemeRegistry};
 use crate::cli::ConfigCommand;
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
@@ -66,6 +66,14 @@ impl Config {
             None => Ok(self.data_dir()?.join("library.db")),
         }
     }
+
+    /// Get the parsed-book cache directory
+    pub fn cache_dir(&self) -> Result<PathBuf> {
+        match &self.library.cache_dir {
+            Some(p) => Ok(p.clone()),
+            None => Ok(self.data_dir()?.join("cache")),
+        }
+    }
 }
 
 /// Get the default configuration file path
@@ -126,9 +134,16 @@ pub fn handle_command(cmd: ConfigCommand, config: &Config) -> Result<()> {
         }
         ConfigCommand::Themes => {
             println!("Available themes:");
-            for theme in theme::BUILTIN_THEMES {
+            for theme in ThemeRegistry::load().names() {
                 println!("  - {}", theme);
             }
+            #[cfg(feature = "syntax-highlighting")]
+            {
+                println!("\nAvailable syntax themes:");
+                for theme in crate::highlight::theme_names() {
+                    println!("  - {}", theme);
+                }
+            }
         }
         ConfigCommand::Keybindings => {
             println!("Available keybinding presets:");
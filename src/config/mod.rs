@@ -10,11 +10,13 @@
 mod defaults;
 pub mod keybindings;
 mod loader;
+pub mod registry;
 mod structs;
 pub mod theme;
 
 // Re-export main types
-pub use keybindings::Keybindings;
+pub use keybindings::{Action, Keybindings};
 pub use loader::{config_path, handle_command, init_config};
-pub use structs::Config;
+pub use registry::ThemeRegistry;
+pub use structs::{Config, MarkdownConfig, ReaderConfig};
 pub use theme::ThemeConfig;
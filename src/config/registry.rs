@@ -0,0 +1,381 @@
+//! Theme registry
+//!
+//! Built-in themes ship as TOML palettes in `assets/themes`, user themes as
+//! TOML files in `~/.config/franko/themes`. Both load into [`ThemeConfig`],
+//! which styles the TUI and generates the web reader's theme stylesheets.
+
+use super::theme::ThemeConfig;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Built-in theme files, the web reader's default dark theme first
+const BUILTIN: &[(&str, &str)] = &[
+    ("dark", include_str!("../../assets/themes/dark.toml")),
+    ("amoled", include_str!("../../assets/themes/amoled.toml")),
+    (
+        "atom-one-dark",
+        include_str!("../../assets/themes/atom-one-dark.toml"),
+    ),
+    (
+        "atom-one-light",
+        include_str!("../../assets/themes/atom-one-light.toml"),
+    ),
+    (
+        "ayu-dark",
+        include_str!("../../assets/themes/ayu-dark.toml"),
+    ),
+    (
+        "ayu-light",
+        include_str!("../../assets/themes/ayu-light.toml"),
+    ),
+    (
+        "ayu-mirage",
+        include_str!("../../assets/themes/ayu-mirage.toml"),
+    ),
+    (
+        "catppuccin-frappe",
+        include_str!("../../assets/themes/catppuccin-frappe.toml"),
+    ),
+    (
+        "catppuccin-latte",
+        include_str!("../../assets/themes/catppuccin-latte.toml"),
+    ),
+    (
+        "catppuccin-macchiato",
+        include_str!("../../assets/themes/catppuccin-macchiato.toml"),
+    ),
+    (
+        "catppuccin-mocha",
+        include_str!("../../assets/themes/catppuccin-mocha.toml"),
+    ),
+    ("cobalt2", include_str!("../../assets/themes/cobalt2.toml")),
+    ("dracula", include_str!("../../assets/themes/dracula.toml")),
+    (
+        "everforest-dark",
+        include_str!("../../assets/themes/everforest-dark.toml"),
+    ),
+    (
+        "everforest-light",
+        include_str!("../../assets/themes/everforest-light.toml"),
+    ),
+    (
+        "flexoki-dark",
+        include_str!("../../assets/themes/flexoki-dark.toml"),
+    ),
+    (
+        "flexoki-light",
+        include_str!("../../assets/themes/flexoki-light.toml"),
+    ),
+    (
+        "github-dark",
+        include_str!("../../assets/themes/github-dark.toml"),
+    ),
+    (
+        "github-light",
+        include_str!("../../assets/themes/github-light.toml"),
+    ),
+    (
+        "gruvbox-dark",
+        include_str!("../../assets/themes/gruvbox-dark.toml"),
+    ),
+    (
+        "gruvbox-light",
+        include_str!("../../assets/themes/gruvbox-light.toml"),
+    ),
+    (
+        "high-contrast",
+        include_str!("../../assets/themes/high-contrast.toml"),
+    ),
+    ("horizon", include_str!("../../assets/themes/horizon.toml")),
+    ("iceberg", include_str!("../../assets/themes/iceberg.toml")),
+    (
+        "kanagawa",
+        include_str!("../../assets/themes/kanagawa.toml"),
+    ),
+    ("kindle", include_str!("../../assets/themes/kindle.toml")),
+    ("kobo", include_str!("../../assets/themes/kobo.toml")),
+    ("light", include_str!("../../assets/themes/light.toml")),
+    (
+        "material-dark",
+        include_str!("../../assets/themes/material-dark.toml"),
+    ),
+    (
+        "midnight-blue",
+        include_str!("../../assets/themes/midnight-blue.toml"),
+    ),
+    ("monokai", include_str!("../../assets/themes/monokai.toml")),
+    (
+        "night-owl",
+        include_str!("../../assets/themes/night-owl.toml"),
+    ),
+    (
+        "night-owl-light",
+        include_str!("../../assets/themes/night-owl-light.toml"),
+    ),
+    ("nord", include_str!("../../assets/themes/nord.toml")),
+    (
+        "one-dark",
+        include_str!("../../assets/themes/one-dark.toml"),
+    ),
+    (
+        "oxocarbon-dark",
+        include_str!("../../assets/themes/oxocarbon-dark.toml"),
+    ),
+    (
+        "palenight",
+        include_str!("../../assets/themes/palenight.toml"),
+    ),
+    ("paper", include_str!("../../assets/themes/paper.toml")),
+    (
+        "poimandres",
+        include_str!("../../assets/themes/poimandres.toml"),
+    ),
+    (
+        "rose-pine",
+        include_str!("../../assets/themes/rose-pine.toml"),
+    ),
+    (
+        "rose-pine-dawn",
+        include_str!("../../assets/themes/rose-pine-dawn.toml"),
+    ),
+    (
+        "rose-pine-moon",
+        include_str!("../../assets/themes/rose-pine-moon.toml"),
+    ),
+    ("sepia", include_str!("../../assets/themes/sepia.toml")),
+    (
+        "shades-of-purple",
+        include_str!("../../assets/themes/shades-of-purple.toml"),
+    ),
+    (
+        "solarized-dark",
+        include_str!("../../assets/themes/solarized-dark.toml"),
+    ),
+    (
+        "solarized-light",
+        include_str!("../../assets/themes/solarized-light.toml"),
+    ),
+    (
+        "synthwave84",
+        include_str!("../../assets/themes/synthwave84.toml"),
+    ),
+    (
+        "tokyo-night",
+        include_str!("../../assets/themes/tokyo-night.toml"),
+    ),
+    ("vesper", include_str!("../../assets/themes/vesper.toml")),
+    (
+        "warm-night",
+        include_str!("../../assets/themes/warm-night.toml"),
+    ),
+    ("zenburn", include_str!("../../assets/themes/zenburn.toml")),
+];
+
+/// Built-in themes and the theme files in a directory
+pub struct ThemeRegistry {
+    dir: Option<PathBuf>,
+}
+
+impl ThemeRegistry {
+    /// Built-in themes and the user's theme files
+    pub fn load() -> Self {
+        Self::new(themes_dir())
+    }
+
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// Names of the built-in themes, then of the theme files
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN.iter().map(|(name, _)| name.to_string()).collect();
+        let mut files: Vec<String> = self
+            .dir
+            .as_ref()
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let stem = path.file_stem()?.to_str()?.to_string();
+                (path.extension()? == "toml" && valid_name(&stem)).then_some(stem)
+            })
+            .filter(|name| !names.contains(name))
+            .collect();
+        files.sort();
+        names.extend(files);
+        names
+    }
+
+    /// A theme by name: a theme file if there is one, else a built-in theme
+    pub fn get(&self, name: &str) -> Result<ThemeConfig> {
+        if !valid_name(name) {
+            anyhow::bail!("Unknown theme: {}", name);
+        }
+        if let Some(path) = self.dir.as_ref().map(|d| d.join(format!("{}.toml", name))) {
+            if path.exists() {
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot read theme {}", path.display()))?;
+                return parse(name, &text)
+                    .with_context(|| format!("Invalid theme {}", path.display()));
+            }
+        }
+        builtin(name).ok_or_else(|| anyhow::anyhow!("Unknown theme: {}", name))
+    }
+
+    /// The web stylesheet of a theme
+    pub fn css(&self, name: &str) -> Result<String> {
+        Ok(self.get(name)?.web_css())
+    }
+
+    /// The web stylesheet pulling in every theme, served in place of a
+    /// hand-written index
+    pub fn index_css(&self) -> String {
+        let mut css = String::from("/* Franko Reader - Theme Index, generated */\n\n");
+        css.push_str("@import url('custom.css');\n");
+        for name in self.names() {
+            css.push_str(&format!("@import url('/static/themes/{}.css');\n", name));
+        }
+        css
+    }
+}
+
+/// A built-in theme by name; `default` is the dark theme
+pub fn builtin(name: &str) -> Option<ThemeConfig> {
+    let name = if name == "default" { "dark" } else { name };
+    BUILTIN
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(name, text)| parse(name, text).expect("valid built-in theme"))
+}
+
+/// Whether a name is a built-in theme
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN.iter().any(|(n, _)| *n == name)
+}
+
+/// Read a theme: a `[palette]` or a `base` theme (dark by default) with any
+/// of its `[ui]`, `[content]`, `[syntax]` and `[web]` values replaced
+fn parse(name: &str, text: &str) -> Result<ThemeConfig> {
+    let mut file: toml::Table = toml::from_str(text)?;
+    let mut base = match (file.remove("palette"), file.remove("base")) {
+        (Some(palette), _) => ThemeConfig::from_palette(name, &palette.try_into()?),
+        (None, Some(toml::Value::String(base))) => {
+            builtin(&base).ok_or_else(|| anyhow::anyhow!("Unknown base theme: {}", base))?
+        }
+        (None, Some(_)) => anyhow::bail!("base must be a theme name"),
+        (None, None) => ThemeConfig::dark(),
+    };
+
+    base.active = name.to_string();
+    // Built-in themes stop here, before defaults that are built-ins themselves
+    if file.is_empty() {
+        return Ok(base);
+    }
+
+    let mut value = toml::Value::try_from(&base)?;
+    merge(&mut value, toml::Value::Table(file));
+    let mut theme: ThemeConfig = value.try_into()?;
+    theme.active = name.to_string();
+    Ok(theme)
+}
+
+/// Lay a table's values over another's, table by table
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(base), toml::Value::Table(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(slot) => merge(slot, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Theme names double as file names and CSS classes
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Directory of user theme files, `themes/` beside the config file
+fn themes_dir() -> Option<PathBuf> {
+    let config = super::config_path().ok()?;
+    Some(config.parent()?.join("themes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtins() {
+        for (name, _) in BUILTIN {
+            assert_eq!(builtin(name).unwrap().active, *name);
+        }
+
+        let nord = builtin("nord").unwrap();
+        assert_eq!(nord.content.background.to_css(), "#2e3440");
+        assert_eq!(nord.web.bg_hover, "#4c566a");
+        assert_eq!(builtin("default").unwrap().active, "dark");
+        assert!(builtin("nope").is_none());
+        assert!(is_builtin("kanagawa"));
+    }
+
+    #[test]
+    fn test_theme_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("mine.toml"),
+            "base = \"nord\"\n[content]\nheading = \"#ff0000\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("paper-white.toml"),
+            "[palette]\nbg-primary = \"#ffffff\"\nbg-secondary = \"#f4f4f4\"\n\
+             bg-tertiary = \"#eeeeee\"\nbg-elevated = \"#e0e0e0\"\n\
+             text-primary = \"#111111\"\ntext-secondary = \"#333333\"\n\
+             text-muted = \"#777777\"\naccent-primary = \"#0055cc\"\n\
+             accent-secondary = \"#3377dd\"\nsuccess = \"#228822\"\n\
+             warning = \"#cc8800\"\nerror = \"#cc2222\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let registry = ThemeRegistry::new(Some(dir.path().to_path_buf()));
+
+        let theme = registry.get("mine").unwrap();
+        assert_eq!(theme.active, "mine");
+        assert_eq!(theme.content.heading.to_css(), "#ff0000");
+        assert_eq!(theme.content.background.to_css(), "#2e3440");
+        #[cfg(feature = "tui")]
+        assert_eq!(
+            theme.content.heading.to_ratatui(),
+            ratatui::style::Color::Rgb(255, 0, 0)
+        );
+
+        let css = registry.css("paper-white").unwrap();
+        assert!(css.contains(".paper-white {"));
+        assert!(css.contains("--bg-primary: #ffffff;"));
+        assert!(css.contains("--bg-hover: #e0e0e0;"));
+        assert!(registry
+            .index_css()
+            .contains("@import url('/static/themes/paper-white.css');"));
+
+        let names = registry.names();
+        assert_eq!(names.first().map(String::as_str), Some("dark"));
+        assert_eq!(names.last().map(String::as_str), Some("paper-white"));
+        assert_eq!(names.len(), BUILTIN.len() + 2);
+
+        std::fs::write(dir.path().join("bad.toml"), "base = \"nope\"\n").unwrap();
+        assert!(registry.get("bad").is_err());
+        assert!(registry.get("missing").is_err());
+        assert!(registry.get("../mine").is_err());
+    }
+}
@@ -113,6 +113,9 @@ pub struct TuiConfig {
 
     /// Right margin
     pub margin_right: usize,
+
+    /// Inline image protocol (auto, kitty, sixel, iterm2, halfblocks, none)
+    pub image_protocol: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -211,6 +214,18 @@ pub struct LibraryConfig {
 
     /// Number of backups to keep
     pub backup_count: usize,
+
+    /// Cache parsed books on disk
+    pub cache_enabled: bool,
+
+    /// Parsed-book cache directory
+    pub cache_dir: Option<PathBuf>,
+
+    /// Maximum cache size in megabytes
+    pub cache_max_size_mb: u64,
+
+    /// Add books opened in the TUI to the library
+    pub auto_add_opened: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2,28 +2,10 @@
 //!
 //! Supports both TUI and Web themes with customizable colors
 
+use super::registry::{self, ThemeRegistry};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-/// Built-in theme names
-pub const BUILTIN_THEMES: &[&str] = &[
-    "default",
-    "dark",
-    "light",
-    "solarized-dark",
-    "solarized-light",
-    "gruvbox-dark",
-    "gruvbox-light",
-    "dracula",
-    "nord",
-    "monokai",
-    "tokyo-night",
-    "catppuccin-mocha",
-    "catppuccin-latte",
-    "sepia",
-    "paper",
-    "high-contrast",
-];
-
 /// Color representation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -75,6 +57,8 @@ impl Color {
         use ratatui::style::Color as RColor;
 
         match self {
+            // Hex colors read from TOML come in as names
+            Color::Named(hex) if hex.starts_with('#') => Color::hex(hex).to_ratatui(),
             Color::Named(name) => match name.to_lowercase().as_str() {
                 "black" => RColor::Black,
                 "red" => RColor::Red,
@@ -125,6 +109,9 @@ pub struct ThemeConfig {
 
     /// Syntax highlighting colors
     pub syntax: SyntaxTheme,
+
+    /// Web-only styling
+    pub web: WebTheme,
 }
 
 /// UI element colors
@@ -190,6 +177,9 @@ pub struct UiTheme {
 
     /// Popup/modal border
     pub popup_border: Color,
+
+    /// Border line style (plain, rounded, double, thick)
+    pub border_style: String,
 }
 
 /// Content/reading area colors
@@ -267,6 +257,57 @@ pub struct SyntaxTheme {
     pub punctuation: Color,
 }
 
+/// Web reader styling with no TUI counterpart, as CSS values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebTheme {
+    /// Hovered element background
+    pub bg_hover: String,
+
+    /// Subtle border
+    pub border: String,
+
+    /// Stronger border
+    pub border_light: String,
+
+    pub shadow_sm: String,
+    pub shadow_md: String,
+    pub shadow_lg: String,
+    pub shadow_xl: String,
+
+    /// Accent glow around featured elements
+    pub shadow_glow: String,
+}
+
+/// The core colors of a theme, named as in the web reader's CSS variables
+///
+/// Built-in themes are palettes; a theme file can give one instead of a
+/// `base` theme.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Palette {
+    pub bg_primary: String,
+    pub bg_secondary: String,
+    pub bg_tertiary: String,
+    pub bg_elevated: String,
+    pub text_primary: String,
+    pub text_secondary: String,
+    pub text_muted: String,
+    pub accent_primary: String,
+    pub accent_secondary: String,
+    pub success: String,
+    pub warning: String,
+    pub error: String,
+    pub bg_hover: Option<String>,
+    pub border: Option<String>,
+    pub border_light: Option<String>,
+    pub shadow_sm: Option<String>,
+    pub shadow_md: Option<String>,
+    pub shadow_lg: Option<String>,
+    pub shadow_xl: Option<String>,
+    pub shadow_glow: Option<String>,
+}
+
 impl Default for ThemeConfig {
     fn default() -> Self {
         Self::dark()
@@ -314,155 +355,186 @@ impl ThemeConfig {
         self.set_foreground(foreground);
     }
 
-    // ========== Built-in themes ==========
+    /// Stylesheet for reading content outside the web UI, such as exported
+    /// HTML books
+    pub fn reading_css(&self) -> String {
+        let c = &self.content;
+        format!(
+            r#"body {{ background: {bg}; color: {text}; margin: 0; }}
+.book {{ max-width: 42em; margin: 0 auto; padding: 2em 1.5em; font-family: Georgia, "Times New Roman", serif; line-height: 1.6; }}
+h1, h2, h3, h4, h5, h6 {{ color: {heading}; line-height: 1.25; }}
+a {{ color: {link}; }}
+a:visited {{ color: {link_visited}; }}
+em {{ color: {emphasis}; }}
+strong {{ color: {strong}; }}
+blockquote {{ color: {quote}; background: {quote_bg}; border-left: 4px solid {quote_border}; margin: 1em 0; padding: 0.5em 1em; }}
+code {{ color: {code}; background: {code_bg}; padding: 0.1em 0.3em; border-radius: 3px; }}
+pre {{ background: {code_block_bg}; padding: 1em; overflow-x: auto; }}
+pre code {{ background: none; padding: 0; }}
+figure {{ margin: 1.5em 0; text-align: center; }}
+figure img {{ max-width: 100%; }}
+figcaption, .caption {{ color: {footnote}; font-size: 0.9em; font-style: italic; }}
+table {{ border-collapse: collapse; margin: 1em 0; }}
+th, td {{ border: 1px solid {quote_border}; padding: 0.3em 0.6em; }}
+aside {{ color: {footnote}; font-size: 0.9em; }}
+mark {{ background: {annotation}; color: {bg}; }}
+.annotation {{ border-left: 3px solid {annotation}; padding-left: 0.8em; margin: 0 0 1em 0; font-style: italic; }}
+.smallcaps {{ font-variant: small-caps; }}
+.link {{ color: {link}; text-decoration: underline; }}
+.title-page {{ text-align: center; margin-bottom: 3em; }}
+.book-author {{ font-size: 1.2em; }}
+.toc {{ margin-bottom: 3em; }}
+.chapter {{ margin-top: 3em; }}
+"#,
+            bg = c.background.to_css(),
+            text = c.text.to_css(),
+            heading = c.heading.to_css(),
+            link = c.link.to_css(),
+            link_visited = c.link_visited.to_css(),
+            emphasis = c.emphasis.to_css(),
+            strong = c.strong.to_css(),
+            quote = c.quote.to_css(),
+            quote_bg = c.quote_bg.to_css(),
+            quote_border = c.quote_border.to_css(),
+            code = c.code.to_css(),
+            code_bg = c.code_bg.to_css(),
+            code_block_bg = c.code_block_bg.to_css(),
+            footnote = c.footnote.to_css(),
+            annotation = c.annotation.to_css(),
+        )
+    }
+
+    /// CSS custom properties for the web reader, set on the theme's class
+    pub fn web_css(&self) -> String {
+        let (ui, web) = (&self.ui, &self.web);
+        let vars = [
+            ("bg-primary", ui.background.to_css()),
+            ("bg-secondary", ui.sidebar_bg.to_css()),
+            ("bg-tertiary", ui.current_line.to_css()),
+            ("bg-elevated", ui.border.to_css()),
+            ("bg-hover", web.bg_hover.clone()),
+            ("text-primary", ui.foreground.to_css()),
+            ("text-secondary", ui.sidebar_fg.to_css()),
+            ("text-muted", ui.line_numbers.to_css()),
+            ("accent-primary", ui.primary.to_css()),
+            ("accent-secondary", ui.secondary.to_css()),
+            ("success", ui.success.to_css()),
+            ("warning", ui.warning.to_css()),
+            ("error", ui.error.to_css()),
+            ("border", web.border.clone()),
+            ("border-light", web.border_light.clone()),
+            ("shadow-sm", web.shadow_sm.clone()),
+            ("shadow-md", web.shadow_md.clone()),
+            ("shadow-lg", web.shadow_lg.clone()),
+            ("shadow-xl", web.shadow_xl.clone()),
+            ("shadow-glow", web.shadow_glow.clone()),
+        ];
+
+        let mut css = format!(
+            "/* Franko Reader - {name} theme, generated */\n\n.{name} {{\n",
+            name = self.active
+        );
+        for (name, value) in vars {
+            css.push_str(&format!("    --{}: {};\n", name, value));
+        }
+        css.push_str("}\n");
+        css
+    }
 
     /// Dark theme (default)
     pub fn dark() -> Self {
-        Self {
-            active: "dark".to_string(),
-            ui: UiTheme {
-                background: Color::hex("#1a1b26"),
-                foreground: Color::hex("#a9b1d6"),
-                primary: Color::hex("#7aa2f7"),
-                secondary: Color::hex("#bb9af7"),
-                success: Color::hex("#9ece6a"),
-                warning: Color::hex("#e0af68"),
-                error: Color::hex("#f7768e"),
-                border: Color::hex("#414868"),
-                selection_bg: Color::hex("#33467c"),
-                selection_fg: Color::hex("#c0caf5"),
-                statusbar_bg: Color::hex("#1f2335"),
-                statusbar_fg: Color::hex("#737aa2"),
-                sidebar_bg: Color::hex("#1f2335"),
-                sidebar_fg: Color::hex("#a9b1d6"),
-                scrollbar: Color::hex("#292e42"),
-                scrollbar_thumb: Color::hex("#565f89"),
-                line_numbers: Color::hex("#3b4261"),
-                current_line: Color::hex("#292e42"),
-                popup_bg: Color::hex("#1f2335"),
-                popup_border: Color::hex("#7aa2f7"),
-            },
-            content: ContentTheme {
-                text: Color::hex("#c0caf5"),
-                background: Color::hex("#1a1b26"),
-                heading: Color::hex("#7dcfff"),
-                link: Color::hex("#7aa2f7"),
-                link_visited: Color::hex("#bb9af7"),
-                emphasis: Color::hex("#bb9af7"),
-                strong: Color::hex("#c0caf5"),
-                quote: Color::hex("#9aa5ce"),
-                quote_bg: Color::hex("#1f2335"),
-                quote_border: Color::hex("#565f89"),
-                code: Color::hex("#73daca"),
-                code_bg: Color::hex("#292e42"),
-                code_block_bg: Color::hex("#1f2335"),
-                footnote: Color::hex("#737aa2"),
-                search_highlight: Color::hex("#e0af68"),
-                bookmark: Color::hex("#f7768e"),
-                annotation: Color::hex("#9ece6a"),
-                progress: Color::hex("#7aa2f7"),
-            },
-            syntax: SyntaxTheme::default(),
-        }
+        registry::builtin("dark").expect("built-in dark theme")
     }
 
-    /// Light theme
-    pub fn light() -> Self {
-        Self {
-            active: "light".to_string(),
-            ui: UiTheme {
-                background: Color::hex("#ffffff"),
-                foreground: Color::hex("#1a1b26"),
-                primary: Color::hex("#2e7de9"),
-                secondary: Color::hex("#9854f1"),
-                success: Color::hex("#587539"),
-                warning: Color::hex("#8c6c3e"),
-                error: Color::hex("#f52a65"),
-                border: Color::hex("#dcdfe4"),
-                selection_bg: Color::hex("#b6d6fd"),
-                selection_fg: Color::hex("#1a1b26"),
-                statusbar_bg: Color::hex("#e9e9ed"),
-                statusbar_fg: Color::hex("#6172b0"),
-                sidebar_bg: Color::hex("#e9e9ed"),
-                sidebar_fg: Color::hex("#1a1b26"),
-                scrollbar: Color::hex("#e9e9ed"),
-                scrollbar_thumb: Color::hex("#9699a3"),
-                line_numbers: Color::hex("#9699a3"),
-                current_line: Color::hex("#e9e9ed"),
-                popup_bg: Color::hex("#ffffff"),
-                popup_border: Color::hex("#2e7de9"),
-            },
-            content: ContentTheme {
-                text: Color::hex("#1a1b26"),
-                background: Color::hex("#ffffff"),
-                heading: Color::hex("#0f4b6e"),
-                link: Color::hex("#2e7de9"),
-                link_visited: Color::hex("#9854f1"),
-                emphasis: Color::hex("#9854f1"),
-                strong: Color::hex("#1a1b26"),
-                quote: Color::hex("#6172b0"),
-                quote_bg: Color::hex("#e9e9ed"),
-                quote_border: Color::hex("#9699a3"),
-                code: Color::hex("#587539"),
-                code_bg: Color::hex("#e9e9ed"),
-                code_block_bg: Color::hex("#f5f5f5"),
-                footnote: Color::hex("#6172b0"),
-                search_highlight: Color::hex("#ffe872"),
-                bookmark: Color::hex("#f52a65"),
-                annotation: Color::hex("#587539"),
-                progress: Color::hex("#2e7de9"),
-            },
-            syntax: SyntaxTheme::light(),
+    /// The colors of the active theme
+    ///
+    /// A theme file or built-in theme of that name wins; `custom` uses the
+    /// colors set in the config itself.
+    pub fn resolve(&self) -> Result<Self> {
+        if self.active == "custom" {
+            return Ok(self.clone());
         }
+        ThemeRegistry::load().get(&self.active)
     }
 
-    /// Sepia theme (paper-like, easy on eyes)
-    pub fn sepia() -> Self {
+    /// Expand a palette into colors for every part of the reader
+    pub(super) fn from_palette(name: &str, p: &Palette) -> Self {
+        let or = |value: &Option<String>, fallback: &str| {
+            value.clone().unwrap_or_else(|| fallback.to_string())
+        };
+        // Only palettes without shadows fall back to the dark theme's
+        let shadows = WebTheme::default;
+
         Self {
-            active: "sepia".to_string(),
+            active: name.to_string(),
             ui: UiTheme {
-                background: Color::hex("#f4ecd8"),
-                foreground: Color::hex("#5b4636"),
-                primary: Color::hex("#8b4513"),
-                secondary: Color::hex("#a0522d"),
-                success: Color::hex("#556b2f"),
-                warning: Color::hex("#b8860b"),
-                error: Color::hex("#a52a2a"),
-                border: Color::hex("#d4c4a8"),
-                selection_bg: Color::hex("#d4c4a8"),
-                selection_fg: Color::hex("#5b4636"),
-                statusbar_bg: Color::hex("#e8dccc"),
-                statusbar_fg: Color::hex("#7a6550"),
-                sidebar_bg: Color::hex("#e8dccc"),
-                sidebar_fg: Color::hex("#5b4636"),
-                scrollbar: Color::hex("#d4c4a8"),
-                scrollbar_thumb: Color::hex("#b8a890"),
-                line_numbers: Color::hex("#a89880"),
-                current_line: Color::hex("#e8dccc"),
-                popup_bg: Color::hex("#f4ecd8"),
-                popup_border: Color::hex("#8b4513"),
+                background: Color::hex(&p.bg_primary),
+                foreground: Color::hex(&p.text_primary),
+                primary: Color::hex(&p.accent_primary),
+                secondary: Color::hex(&p.accent_secondary),
+                success: Color::hex(&p.success),
+                warning: Color::hex(&p.warning),
+                error: Color::hex(&p.error),
+                border: Color::hex(&p.bg_elevated),
+                selection_bg: Color::hex(&p.accent_primary),
+                selection_fg: Color::hex(&p.bg_primary),
+                statusbar_bg: Color::hex(&p.bg_secondary),
+                statusbar_fg: Color::hex(&p.text_secondary),
+                sidebar_bg: Color::hex(&p.bg_secondary),
+                sidebar_fg: Color::hex(&p.text_secondary),
+                scrollbar: Color::hex(&p.bg_tertiary),
+                scrollbar_thumb: Color::hex(&p.text_muted),
+                line_numbers: Color::hex(&p.text_muted),
+                current_line: Color::hex(&p.bg_tertiary),
+                popup_bg: Color::hex(&p.bg_secondary),
+                popup_border: Color::hex(&p.accent_primary),
+                border_style: "rounded".to_string(),
             },
             content: ContentTheme {
-                text: Color::hex("#5b4636"),
-                background: Color::hex("#f4ecd8"),
-                heading: Color::hex("#4a3728"),
-                link: Color::hex("#8b4513"),
-                link_visited: Color::hex("#a0522d"),
-                emphasis: Color::hex("#654321"),
-                strong: Color::hex("#3d2817"),
-                quote: Color::hex("#7a6550"),
-                quote_bg: Color::hex("#e8dccc"),
-                quote_border: Color::hex("#b8a890"),
-                code: Color::hex("#556b2f"),
-                code_bg: Color::hex("#e8dccc"),
-                code_block_bg: Color::hex("#e8dccc"),
-                footnote: Color::hex("#7a6550"),
-                search_highlight: Color::hex("#ffd700"),
-                bookmark: Color::hex("#a52a2a"),
-                annotation: Color::hex("#556b2f"),
-                progress: Color::hex("#8b4513"),
+                text: Color::hex(&p.text_primary),
+                background: Color::hex(&p.bg_primary),
+                heading: Color::hex(&p.accent_primary),
+                link: Color::hex(&p.accent_secondary),
+                link_visited: Color::hex(&p.text_secondary),
+                emphasis: Color::hex(&p.accent_secondary),
+                strong: Color::hex(&p.text_primary),
+                quote: Color::hex(&p.text_secondary),
+                quote_bg: Color::hex(&p.bg_secondary),
+                quote_border: Color::hex(&p.text_muted),
+                code: Color::hex(&p.success),
+                code_bg: Color::hex(&p.bg_tertiary),
+                code_block_bg: Color::hex(&p.bg_secondary),
+                footnote: Color::hex(&p.text_muted),
+                search_highlight: Color::hex(&p.warning),
+                bookmark: Color::hex(&p.error),
+                annotation: Color::hex(&p.success),
+                progress: Color::hex(&p.accent_primary),
+            },
+            syntax: SyntaxTheme {
+                keyword: Color::hex(&p.accent_secondary),
+                string: Color::hex(&p.success),
+                number: Color::hex(&p.warning),
+                comment: Color::hex(&p.text_muted),
+                function: Color::hex(&p.accent_primary),
+                variable: Color::hex(&p.text_primary),
+                type_name: Color::hex(&p.accent_primary),
+                constant: Color::hex(&p.warning),
+                operator: Color::hex(&p.text_secondary),
+                punctuation: Color::hex(&p.text_muted),
+            },
+            web: WebTheme {
+                bg_hover: or(&p.bg_hover, &p.bg_elevated),
+                border: or(&p.border, &p.bg_elevated),
+                border_light: or(&p.border_light, &p.text_muted),
+                shadow_sm: p.shadow_sm.clone().unwrap_or_else(|| shadows().shadow_sm),
+                shadow_md: p.shadow_md.clone().unwrap_or_else(|| shadows().shadow_md),
+                shadow_lg: p.shadow_lg.clone().unwrap_or_else(|| shadows().shadow_lg),
+                shadow_xl: p.shadow_xl.clone().unwrap_or_else(|| shadows().shadow_xl),
+                shadow_glow: p
+                    .shadow_glow
+                    .clone()
+                    .unwrap_or_else(|| shadows().shadow_glow),
             },
-            syntax: SyntaxTheme::default(),
         }
     }
 }
@@ -481,56 +553,34 @@ impl Default for ContentTheme {
 
 impl Default for SyntaxTheme {
     fn default() -> Self {
-        Self {
-            keyword: Color::hex("#bb9af7"),
-            string: Color::hex("#9ece6a"),
-            number: Color::hex("#ff9e64"),
-            comment: Color::hex("#565f89"),
-            function: Color::hex("#7aa2f7"),
-            variable: Color::hex("#c0caf5"),
-            type_name: Color::hex("#2ac3de"),
-            constant: Color::hex("#ff9e64"),
-            operator: Color::hex("#89ddff"),
-            punctuation: Color::hex("#9aa5ce"),
-        }
+        ThemeConfig::dark().syntax
     }
 }
 
-impl SyntaxTheme {
-    pub fn light() -> Self {
-        Self {
-            keyword: Color::hex("#9854f1"),
-            string: Color::hex("#587539"),
-            number: Color::hex("#b15c00"),
-            comment: Color::hex("#9699a3"),
-            function: Color::hex("#2e7de9"),
-            variable: Color::hex("#1a1b26"),
-            type_name: Color::hex("#006c86"),
-            constant: Color::hex("#b15c00"),
-            operator: Color::hex("#006c86"),
-            punctuation: Color::hex("#6172b0"),
-        }
+impl Default for WebTheme {
+    fn default() -> Self {
+        ThemeConfig::dark().web
     }
 }
 
-/// Complete theme struct combining all theme data
-#[derive(Debug, Clone)]
-pub struct Theme {
-    pub name: String,
-    pub config: ThemeConfig,
-}
-
-impl Theme {
-    pub fn from_name(name: &str) -> Self {
-        let config = match name {
-            "light" => ThemeConfig::light(),
-            "sepia" | "paper" => ThemeConfig::sepia(),
-            _ => ThemeConfig::dark(),
-        };
-
-        Self {
-            name: name.to_string(),
-            config,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_css() {
+        let css = ThemeRegistry::new(None).get("nord").unwrap().web_css();
+        assert!(css.contains(".nord {"));
+        assert!(css.contains("--bg-primary: #2e3440;"));
+        assert!(css.contains("--accent-primary: #88c0d0;"));
+        assert!(css.contains("--border: rgba(76, 86, 106, 0.5);"));
+
+        let mut theme = ThemeConfig::dark();
+        theme.active = "mine".to_string();
+        theme.ui.primary = Color::hex("#ff0000");
+        let css = theme.web_css();
+        assert!(css.contains(".mine {"));
+        assert!(css.contains("--accent-primary: #ff0000;"));
+        assert_eq!(css.matches(": ").count(), 20);
     }
 }
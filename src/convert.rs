@@ -0,0 +1,220 @@
+//! Book conversion
+//!
+//! Parses any supported input, or a folder of Markdown/TXT files, and hands
+//! the resulting [`Book`] to the matching writer in `formats::writer`. The
+//! output format follows the output file's extension.
+
+use crate::config::Config;
+use crate::formats::writer::{self, Highlight, WriteOptions};
+use crate::formats::{self, Book, BookContent, BookMetadata, Chapter, ContentBlock};
+use crate::hyphenate::Hyphenator;
+use crate::library::Library;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Options given on the command line
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Override the book title
+    pub title: Option<String>,
+
+    /// Override the authors
+    pub authors: Vec<String>,
+
+    /// Wrap width for plain text output
+    pub width: Option<usize>,
+
+    /// Include the book's library annotations
+    pub annotations: bool,
+}
+
+/// Convert `input` and write it to `output`
+pub fn handle_command(
+    input: &Path,
+    output: Option<&Path>,
+    options: Options,
+    config: &Config,
+) -> Result<()> {
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => default_output(input)?,
+    };
+    writer::check_output(&output)?;
+    if output.exists() && output.canonicalize().ok() == input.canonicalize().ok() {
+        bail!("Refusing to overwrite the input file {}", input.display());
+    }
+
+    let mut book = if input.is_dir() {
+        load_folder(input)?
+    } else {
+        formats::parse_book(input)
+            .with_context(|| format!("Failed to parse {}", input.display()))?
+    };
+
+    if let Some(title) = options.title {
+        book.metadata.title = title;
+    }
+    if !options.authors.is_empty() {
+        book.metadata.authors = options.authors;
+    }
+
+    let write_options = WriteOptions {
+        width: options.width.unwrap_or(match config.tui.max_width {
+            0 => 80,
+            width => width,
+        }),
+        stylesheet: config.theme.reading_css(),
+        hyphenator: Hyphenator::new(&config.reader, book.metadata.language.as_deref()),
+        highlights: if options.annotations {
+            library_highlights(input, config)?
+        } else {
+            Vec::new()
+        },
+    };
+
+    writer::write_book(&book, &output, &write_options)?;
+
+    println!(
+        "Converted: {} → {} ({} chapters)",
+        input.display(),
+        output.display(),
+        book.content.chapters.len()
+    );
+    Ok(())
+}
+
+/// Annotations stored in the library for the book at `path`
+fn library_highlights(path: &Path, config: &Config) -> Result<Vec<Highlight>> {
+    let library = Library::new(config)?;
+    let target = path.canonicalize()?;
+    let entry = library
+        .books()
+        .into_iter()
+        .find(|e| e.path.canonicalize().ok().as_deref() == Some(target.as_path()));
+
+    let Some(entry) = entry else {
+        warn!(
+            "{} is not in the library, no annotations to include",
+            path.display()
+        );
+        return Ok(Vec::new());
+    };
+
+    Ok(library
+        .get_annotations(&entry.id)?
+        .into_iter()
+        .map(|a| Highlight {
+            chapter: a.chapter,
+            block: a.block,
+            text: a.text,
+            note: a.note,
+        })
+        .collect())
+}
+
+/// Load images now, while the file they are relative to is known
+fn embed_images(chapter: &mut Chapter, file: &Path) {
+    for block in &mut chapter.blocks {
+        if let ContentBlock::Image { src, data, .. } = block {
+            if data.is_none() {
+                *data = formats::load_resource(file, src)
+                    .ok()
+                    .flatten()
+                    .map(|(bytes, _)| bytes);
+            }
+        }
+    }
+}
+
+/// `book.md` becomes `book.epub`, a folder `notes/` becomes `notes.epub`
+fn default_output(input: &Path) -> Result<PathBuf> {
+    let ext = writer::OutputFormat::Epub.extension();
+    if input.file_name().is_some() {
+        return Ok(input.with_extension(ext));
+    }
+
+    // `.` and `..` have no name of their own
+    let name = input
+        .canonicalize()?
+        .file_name()
+        .map(PathBuf::from)
+        .context("Cannot derive an output name, please pass --output")?;
+    Ok(name.with_extension(ext))
+}
+
+/// Build one book from every book file in a folder, in name order
+///
+/// Each file contributes its chapters; the folder name becomes the title
+/// and the first file with authors provides the remaining metadata.
+fn load_folder(dir: &Path) -> Result<Book> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && formats::is_book_file(p))
+        .collect();
+    files.sort();
+
+    if files.is_empty() {
+        bail!("No books found in {}", dir.display());
+    }
+
+    let mut metadata: Option<BookMetadata> = None;
+    let mut content = BookContent::default();
+
+    for file in &files {
+        let book = match formats::parse_book(file) {
+            Ok(book) => book,
+            Err(e) => {
+                warn!("Skipping {}: {}", file.display(), e);
+                continue;
+            }
+        };
+
+        if metadata.is_none() && !book.metadata.authors.is_empty() {
+            metadata = Some(book.metadata.clone());
+        }
+
+        let stem = file
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        for mut chapter in book.content.chapters {
+            chapter.id = format!("{}-{}", stem, chapter.id);
+            embed_images(&mut chapter, file);
+            chapter.order = content.chapters.len();
+            if chapter.title.is_none() {
+                chapter.title = chapter
+                    .blocks
+                    .iter()
+                    .find_map(|b| match b {
+                        ContentBlock::Heading { text, .. } => Some(text.clone()),
+                        _ => None,
+                    })
+                    .or_else(|| Some(stem.clone()));
+            }
+            content.chapters.push(chapter);
+        }
+    }
+
+    if content.chapters.is_empty() {
+        bail!("None of the files in {} could be parsed", dir.display());
+    }
+
+    let mut metadata = metadata.unwrap_or_default();
+    metadata.title = dir
+        .canonicalize()?
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Untitled")
+        .to_string();
+    metadata.word_count = Some(content.word_count());
+
+    Ok(Book {
+        metadata,
+        content,
+        source_path: dir.to_path_buf(),
+        format: "folder".to_string(),
+    })
+}
@@ -0,0 +1,91 @@
+//! Diagnostic reports for problem books
+
+use crate::config::Config;
+use crate::formats::diagnose::{self, Report, Status};
+use crate::library::Library;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Handle the doctor command
+///
+/// A single book gets a full report. When several books are checked, only
+/// those with findings are shown in detail, followed by a summary.
+pub fn handle_command(
+    mut paths: Vec<PathBuf>,
+    library: bool,
+    json: bool,
+    config: &Config,
+) -> Result<()> {
+    if library {
+        let mut books = Library::new(config)?.books();
+        books.sort_by(|a, b| a.metadata.title.cmp(&b.metadata.title));
+        paths.extend(books.into_iter().map(|b| b.path));
+    }
+    if paths.is_empty() {
+        anyhow::bail!("Nothing to check; pass book files or --library");
+    }
+
+    let reports: Vec<Report> = paths.iter().map(|p| diagnose::diagnose(p)).collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        let detailed = reports.len() == 1;
+        for report in &reports {
+            if detailed || report.status >= Status::Warning {
+                print_report(report);
+            } else {
+                println!("{} {}", symbol(report.status), report.path.display());
+            }
+        }
+        if !detailed {
+            let count = |status| reports.iter().filter(|r| r.status == status).count();
+            println!(
+                "\nChecked {} books: {} ok, {} with warnings, {} with errors",
+                reports.len(),
+                count(Status::Ok),
+                count(Status::Warning),
+                count(Status::Error)
+            );
+        }
+    }
+
+    let failed = reports.iter().filter(|r| r.status == Status::Error).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} books have errors", failed, reports.len());
+    }
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "{} {} ({})",
+        symbol(report.status),
+        report.path.display(),
+        report.format
+    );
+    for stage in &report.stages {
+        println!(
+            "  {} {:<10} {}",
+            symbol(stage.status),
+            stage.name,
+            stage.details.join("; ")
+        );
+        for warning in &stage.warnings {
+            println!("      ! {}", warning);
+        }
+        if let Some(error) = &stage.error {
+            println!("      ✗ {}", error);
+        }
+    }
+    println!();
+}
+
+fn symbol(status: Status) -> &'static str {
+    match status {
+        Status::Skipped => "-",
+        Status::Ok => "✓",
+        Status::Warning => "⚠",
+        Status::Error => "✗",
+    }
+}
@@ -97,6 +97,74 @@ impl BookMetadata {
     }
 }
 
+/// A set of metadata changes; `None` leaves a field untouched
+///
+/// An empty string clears an optional field. Tags map to the book's
+/// subjects, which is where EPUB and Markdown files keep them; the user's
+/// library tags are separate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataEdit {
+    pub title: Option<String>,
+    pub authors: Option<Vec<String>>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub tags: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+}
+
+impl MetadataEdit {
+    /// Whether the edit changes anything
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.authors.is_none()
+            && self.series.is_none()
+            && self.series_index.is_none()
+            && self.tags.is_none()
+            && self.description.is_none()
+            && self.publisher.is_none()
+            && self.language.is_none()
+    }
+
+    /// Apply the changes to `metadata`
+    pub fn apply(&self, metadata: &mut BookMetadata) {
+        fn optional(value: &str) -> Option<String> {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        }
+
+        if let Some(title) = &self.title {
+            metadata.title = title.trim().to_string();
+        }
+        if let Some(authors) = &self.authors {
+            metadata.authors = authors.iter().filter_map(|a| optional(a)).collect();
+        }
+        if let Some(series) = &self.series {
+            metadata.series = optional(series);
+            if metadata.series.is_none() {
+                metadata.series_index = None;
+            }
+        }
+        if let Some(index) = self.series_index {
+            metadata.series_index = Some(index);
+        }
+        if let Some(tags) = &self.tags {
+            metadata.subjects = tags.iter().filter_map(|t| optional(t)).collect();
+        }
+        if let Some(description) = &self.description {
+            metadata.description = optional(description);
+        }
+        if let Some(publisher) = &self.publisher {
+            metadata.publisher = optional(publisher);
+        }
+        if let Some(language) = &self.language {
+            metadata.language = optional(language);
+        }
+    }
+}
+
 /// Book content container
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BookContent {
@@ -150,6 +218,10 @@ pub struct Chapter {
 
     /// Order in the book
     pub order: usize,
+
+    /// Link targets in the chapter, as fragment ids and their block index
+    #[serde(default)]
+    pub anchors: Vec<(String, usize)>,
 }
 
 impl Chapter {
@@ -160,28 +232,42 @@ impl Chapter {
             number: None,
             blocks: Vec::new(),
             order,
+            anchors: Vec::new(),
         }
     }
 
     /// Get display title
     pub fn display_title(&self) -> String {
-        if let Some(title) = &self.title {
-            if let Some(num) = self.number {
-                format!("Chapter {}: {}", num, title)
-            } else {
-                title.clone()
-            }
-        } else if let Some(num) = self.number {
-            format!("Chapter {}", num)
-        } else {
-            format!("Section {}", self.order + 1)
-        }
+        display_title(self.title.as_deref(), self.number, self.order)
     }
 
     /// Get word count for this chapter
     pub fn word_count(&self) -> usize {
         self.blocks.iter().map(|b| b.word_count()).sum()
     }
+
+    /// Find the block a fragment id points at
+    pub fn anchor(&self, fragment: &str) -> Option<usize> {
+        self.anchors
+            .iter()
+            .find(|(id, _)| id == fragment)
+            .map(|&(_, block)| block)
+    }
+}
+
+/// Format a chapter title for display
+pub(crate) fn display_title(title: Option<&str>, number: Option<usize>, order: usize) -> String {
+    if let Some(title) = title {
+        if let Some(num) = number {
+            format!("Chapter {}: {}", num, title)
+        } else {
+            title.to_string()
+        }
+    } else if let Some(num) = number {
+        format!("Chapter {}", num)
+    } else {
+        format!("Section {}", order + 1)
+    }
 }
 
 /// A content block (paragraph, heading, etc.)
@@ -237,6 +323,9 @@ pub enum ContentBlock {
 
     /// Empty space/break
     Break,
+
+    /// A display math formula, as TeX source
+    Math { tex: String },
 }
 
 impl ContentBlock {
@@ -261,6 +350,7 @@ impl ContentBlock {
                 }
                 text
             }
+            ContentBlock::Math { tex } => super::math::to_unicode(tex),
             ContentBlock::Separator | ContentBlock::Break => String::new(),
         }
     }
@@ -292,6 +382,12 @@ pub struct TextStyle {
 
     /// Style type
     pub style_type: StyleType,
+
+    /// Extra data for the style, such as the TeX source of inline math
+    ///
+    /// Links keep their target here: a URL, or `chapter-id#fragment` for
+    /// places in the book, where an empty chapter id is the same chapter.
+    pub data: Option<String>,
 }
 
 /// Types of text styling
@@ -306,6 +402,7 @@ pub enum StyleType {
     Superscript,
     Subscript,
     SmallCaps,
+    Math,
 }
 
 /// Table of contents entry
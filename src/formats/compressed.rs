@@ -0,0 +1,322 @@
+//! Transparent decompression of compressed books
+//!
+//! Books stored as `.gz`, `.zst`, `.bz2`, `.xz` or inside a zip holding a
+//! single book are unpacked to a temporary file so the regular parsers can
+//! read them. The compressed file stays the book's canonical path. Books
+//! unpacking to more than [`MAX_UNPACKED`] bytes are refused, so a
+//! decompression bomb cannot fill the temporary directory.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Largest book, in bytes, that will be unpacked
+pub const MAX_UNPACKED: u64 = 1 << 30;
+
+/// Compression wrapper around a book file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Zip,
+}
+
+impl Compression {
+    /// Identify a compression format from the first bytes of a file
+    ///
+    /// A zip match only means the file is a zip archive; it may still be a
+    /// book container such as EPUB.
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1F, 0x8B]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if header.starts_with(b"PK\x03\x04") {
+            Some(Compression::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Get the compression name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+            Compression::Zip => "zip",
+        }
+    }
+}
+
+/// A decompressed copy of a book, removed again when dropped
+#[derive(Debug)]
+pub struct Unpacked {
+    dir: PathBuf,
+    /// Path of the decompressed book
+    pub path: PathBuf,
+}
+
+impl Drop for Unpacked {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Get the compression wrapping a book, if any
+///
+/// Zip archives only count when they hold a single book rather than being a
+/// book container themselves.
+pub fn compression(path: &Path) -> Option<Compression> {
+    let mut header = [0u8; 8];
+    let len = File::open(path).ok()?.read(&mut header).ok()?;
+
+    match Compression::sniff(&header[..len])? {
+        Compression::Zip => {
+            let file = File::open(path).ok()?;
+            zip_book_entry(&mut zip::ZipArchive::new(BufReader::new(file)).ok()?)
+                .map(|_| Compression::Zip)
+        }
+        other => Some(other),
+    }
+}
+
+/// Decompress a book to a temporary file
+///
+/// Returns `None` when the file is not compressed.
+pub fn unpack(path: &Path) -> Result<Option<Unpacked>> {
+    let Some(compression) = compression(path) else {
+        return Ok(None);
+    };
+
+    let dir = std::env::temp_dir().join(format!("franko-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    // Construct early so the directory is cleaned up on error
+    let mut unpacked = Unpacked {
+        path: dir.clone(),
+        dir,
+    };
+
+    with_inner(path, compression, |name, reader| {
+        unpacked.path = unpacked.dir.join(name);
+        copy_to(reader, &unpacked.path, MAX_UNPACKED)
+    })?;
+
+    Ok(Some(unpacked))
+}
+
+/// Read the start of the book a compressed file wraps, without unpacking
+/// the rest, along with the wrapped file's name
+///
+/// Returns `None` when the file is not compressed or cannot be read.
+pub fn peek(path: &Path, len: usize) -> Option<(PathBuf, Vec<u8>)> {
+    let compression = compression(path)?;
+    with_inner(path, compression, |name, reader| {
+        let mut head = Vec::with_capacity(len);
+        reader.take(len as u64).read_to_end(&mut head)?;
+        Ok((name.to_path_buf(), head))
+    })
+    .ok()
+}
+
+/// Stream the book wrapped by a compressed file into `read`, with the
+/// wrapped file's name
+fn with_inner<T>(
+    path: &Path,
+    compression: Compression,
+    read: impl FnOnce(&Path, &mut dyn Read) -> Result<T>,
+) -> Result<T> {
+    let file = BufReader::new(File::open(path)?);
+    match compression {
+        Compression::Zip => {
+            let mut archive = zip::ZipArchive::new(file)?;
+            let index = zip_book_entry(&mut archive).context("No book found in zip archive")?;
+            let mut entry = archive.by_index(index)?;
+            let name = entry
+                .enclosed_name()
+                .and_then(|n| n.file_name().map(PathBuf::from))
+                .unwrap_or_else(|| PathBuf::from("book"));
+            read(&name, &mut entry)
+        }
+        _ => {
+            let mut reader: Box<dyn Read> = match compression {
+                Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+                Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+                Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(file)),
+                Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(file)),
+                Compression::Zip => unreachable!(),
+            };
+            read(&inner_name(path), &mut reader)
+        }
+    }
+}
+
+/// Copy at most `limit` bytes to a new file, failing on anything longer
+fn copy_to(reader: &mut dyn Read, dest: &Path, limit: u64) -> Result<()> {
+    let mut out = File::create(dest)?;
+    let copied = std::io::copy(&mut reader.take(limit + 1), &mut out)
+        .with_context(|| format!("Failed to decompress to {}", dest.display()))?;
+    if copied > limit {
+        anyhow::bail!("Decompressed book is larger than {} bytes", limit);
+    }
+    Ok(())
+}
+
+/// Name of the wrapped file: `book.md.gz` becomes `book.md`
+fn inner_name(path: &Path) -> PathBuf {
+    let compressed_ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .is_some_and(|e| matches!(e.as_str(), "gz" | "zst" | "bz2" | "xz"));
+
+    let name = if compressed_ext {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    name.map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("book"))
+}
+
+/// Find the single book inside a zip archive
+///
+/// Archives that are book containers (EPUB, CBZ, ...) or that hold more than
+/// one candidate file are rejected.
+fn zip_book_entry<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Option<usize> {
+    let names: Vec<String> = archive.file_names().map(|n| n.to_string()).collect();
+    if names
+        .iter()
+        .any(|n| n == "mimetype" || n == "META-INF/container.xml")
+    {
+        return None;
+    }
+
+    let mut candidates = (0..archive.len()).filter(|&i| {
+        archive.name_for_index(i).is_some_and(|name| {
+            !name.ends_with('/') && !name.starts_with("__MACOSX/") && !is_hidden(name)
+        })
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(index), None) => {
+            let name = archive.name_for_index(index)?;
+            // A lone image is a one-page comic, not a compressed book
+            let ext = Path::new(name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_lowercase())
+                .unwrap_or_default();
+            if matches!(
+                ext.as_str(),
+                "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp"
+            ) {
+                None
+            } else {
+                Some(index)
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_hidden(name: &str) -> bool {
+    Path::new(name)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_sniff_compression() {
+        assert_eq!(
+            Compression::sniff(&[0x1F, 0x8B, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::sniff(&[0x28, 0xB5, 0x2F, 0xFD]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::sniff(b"BZh91AY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::sniff(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_inner_name() {
+        assert_eq!(
+            inner_name(Path::new("/a/book.md.gz")),
+            PathBuf::from("book.md")
+        );
+        assert_eq!(inner_name(Path::new("/a/book")), PathBuf::from("book"));
+    }
+
+    #[test]
+    fn test_unpack_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&path).unwrap(), Default::default());
+        encoder.write_all(b"Hello from a gzip book").unwrap();
+        encoder.finish().unwrap();
+
+        let unpacked = unpack(&path).unwrap().unwrap();
+        assert!(unpacked.path.ends_with("notes.txt"));
+        assert_eq!(
+            std::fs::read_to_string(&unpacked.path).unwrap(),
+            "Hello from a gzip book"
+        );
+
+        let temp = unpacked.path.clone();
+        drop(unpacked);
+        assert!(!temp.exists());
+
+        let (name, head) = peek(&path, 10).unwrap();
+        assert_eq!(
+            (name, head),
+            (PathBuf::from("notes.txt"), b"Hello from".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_copy_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out");
+        assert!(copy_to(&mut std::io::repeat(0), &dest, 1000).is_err());
+        assert_eq!(std::fs::metadata(&dest).unwrap().len(), 1001);
+        assert!(copy_to(&mut &b"small"[..], &dest, 5).is_ok());
+    }
+
+    #[test]
+    fn test_zip_single_entry_only() {
+        let stored = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+
+        let mut single = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        single.add_directory("books/", stored).unwrap();
+        single.start_file("books/story.fb2", stored).unwrap();
+        single.write_all(b"<FictionBook/>").unwrap();
+        let mut single = zip::ZipArchive::new(single.finish().unwrap()).unwrap();
+        assert_eq!(zip_book_entry(&mut single), Some(1));
+
+        let mut many = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in ["a.txt", "b.txt"] {
+            many.start_file(name, stored).unwrap();
+            many.write_all(b"text").unwrap();
+        }
+        let mut many = zip::ZipArchive::new(many.finish().unwrap()).unwrap();
+        assert_eq!(zip_book_entry(&mut many), None);
+    }
+}
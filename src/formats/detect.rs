@@ -0,0 +1,362 @@
+//! Content sniffing for book files
+//!
+//! Identifies a file's real format from magic numbers, zip container entries
+//! and XML root elements. The file extension is only consulted when the
+//! content itself is inconclusive. Compressed books report the format of the
+//! file they wrap.
+
+use super::BookFormat;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// Number of leading bytes inspected when sniffing
+const SNIFF_LEN: usize = 4096;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Detect the format of a file, falling back to its extension when unsure
+pub fn detect(path: &Path) -> BookFormat {
+    let header = match read_header(path) {
+        Ok(header) => header,
+        Err(_) => return BookFormat::from_path(path),
+    };
+
+    if let Some(format) = sniff_file(path, &header) {
+        return format;
+    }
+
+    #[cfg(feature = "compressed")]
+    if let Some(format) = sniff_compressed(path) {
+        return format;
+    }
+
+    by_name(path, &header)
+}
+
+/// The format a file's name claims, checked against whether it is text
+fn by_name(path: &Path, header: &[u8]) -> BookFormat {
+    let claimed = BookFormat::from_path(path);
+    if has_bom(header) || looks_like_text(header) {
+        return match claimed {
+            BookFormat::Markdown | BookFormat::PlainText | BookFormat::Html => claimed,
+            // No extension at all: a text file is the safest guess
+            _ if path.extension().is_none() => BookFormat::PlainText,
+            _ => BookFormat::Unknown,
+        };
+    }
+
+    claimed
+}
+
+/// Identify a file by content alone, returning `None` when there is no
+/// confident match
+pub fn sniff(path: &Path) -> Option<BookFormat> {
+    let header = read_header(path).ok()?;
+    let format = sniff_file(path, &header);
+
+    #[cfg(feature = "compressed")]
+    let format = format.or_else(|| sniff_compressed(path));
+
+    // A BOM only says the file is text, so its extension names the format
+    format.or_else(|| {
+        has_bom(&header).then(|| match BookFormat::from_path(path) {
+            format @ (BookFormat::Markdown | BookFormat::Html) => format,
+            _ => BookFormat::PlainText,
+        })
+    })
+}
+
+/// Identify a format from a buffer holding the start of a file
+///
+/// Zip containers are only classified when their first entry is an
+/// uncompressed `mimetype` file (EPUB, ODT). Use [`sniff`] on a path to
+/// inspect the full zip directory.
+pub fn sniff_bytes(data: &[u8]) -> Option<BookFormat> {
+    if data.starts_with(b"PK\x03\x04") {
+        return sniff_zip_mimetype(data);
+    }
+
+    // Some PDF writers put junk before the header, but text merely
+    // mentioning one is not a PDF
+    if data.trim_ascii_start().starts_with(b"%PDF-")
+        || (!looks_like_text(data) && find(&data[..data.len().min(1024)], b"%PDF-").is_some())
+    {
+        return Some(BookFormat::Pdf);
+    }
+
+    let text = decode_text(data);
+    let head = text.trim_start().to_lowercase();
+
+    if head.starts_with("<?xml") || head.starts_with("<fictionbook") {
+        if head.contains("<fictionbook") {
+            return Some(BookFormat::Fb2);
+        }
+        if head.contains("<html") {
+            return Some(BookFormat::Html);
+        }
+    }
+
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some(BookFormat::Html);
+    }
+
+    None
+}
+
+/// Whether data starts with a UTF-8 or UTF-16 byte order mark
+fn has_bom(data: &[u8]) -> bool {
+    data.starts_with(UTF8_BOM) || data.starts_with(UTF16_LE_BOM) || data.starts_with(UTF16_BE_BOM)
+}
+
+/// Decode text content, honoring UTF-8 and UTF-16 byte order marks
+pub fn decode_text(data: &[u8]) -> String {
+    if let Some(rest) = data.strip_prefix(UTF8_BOM) {
+        String::from_utf8_lossy(rest).into_owned()
+    } else if let Some(rest) = data.strip_prefix(UTF16_LE_BOM) {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(rest) = data.strip_prefix(UTF16_BE_BOM) {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(data).into_owned()
+    }
+}
+
+/// Name the encoding [`decode_text`] will use for `data`
+pub fn text_encoding(data: &[u8]) -> &'static str {
+    if data.starts_with(UTF8_BOM) {
+        "UTF-8 with BOM"
+    } else if data.starts_with(UTF16_LE_BOM) {
+        "UTF-16 LE"
+    } else if data.starts_with(UTF16_BE_BOM) {
+        "UTF-16 BE"
+    } else {
+        "UTF-8"
+    }
+}
+
+/// Read a text file, honoring byte order marks
+pub fn read_text(path: &Path) -> std::io::Result<String> {
+    Ok(decode_text(&std::fs::read(path)?))
+}
+
+/// Identify an image format from its first bytes
+pub fn image_mime(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+        "image/png"
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.len() > 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else if data.starts_with(b"BM") {
+        "image/bmp"
+    } else if data.starts_with(b"<svg") || data.starts_with(b"<?xml") {
+        "image/svg+xml"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    file.by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn sniff_file(path: &Path, header: &[u8]) -> Option<BookFormat> {
+    if let Some(format) = sniff_bytes(header) {
+        return Some(format);
+    }
+
+    if header.starts_with(b"PK\x03\x04") {
+        let file = File::open(path).ok()?;
+        return sniff_zip_entries(file);
+    }
+
+    None
+}
+
+/// Detect the format of the book wrapped by a compressed file
+///
+/// Only the start of the book is decompressed. The inner file name still
+/// carries the original extension (`book.md.gz`), so it is consulted when
+/// the content is inconclusive.
+#[cfg(feature = "compressed")]
+fn sniff_compressed(path: &Path) -> Option<BookFormat> {
+    let (name, header) = super::compressed::peek(path, SNIFF_LEN)?;
+    match sniff_bytes(&header).unwrap_or_else(|| by_name(&name, &header)) {
+        BookFormat::Unknown => None,
+        format => Some(format),
+    }
+}
+
+/// Check the stored `mimetype` entry that EPUB and OpenDocument place first
+fn sniff_zip_mimetype(data: &[u8]) -> Option<BookFormat> {
+    // Local file header: name length at 26, extra length at 28, name at 30
+    if data.len() < 30 {
+        return None;
+    }
+    let name_len = u16::from_le_bytes([data[26], data[27]]) as usize;
+    let extra_len = u16::from_le_bytes([data[28], data[29]]) as usize;
+    let name = data.get(30..30 + name_len)?;
+    if name != b"mimetype" {
+        return None;
+    }
+
+    let start = 30 + name_len + extra_len;
+    let content = data.get(start..(start + 64).min(data.len()))?;
+    let content = String::from_utf8_lossy(content);
+
+    if content.starts_with("application/epub+zip") {
+        Some(BookFormat::Epub)
+    } else if content.starts_with("application/vnd.oasis.opendocument.text") {
+        Some(BookFormat::Odt)
+    } else {
+        None
+    }
+}
+
+/// Classify a zip container by the entries it holds
+fn sniff_zip_entries<R: Read + Seek>(reader: R) -> Option<BookFormat> {
+    let mut archive = zip::ZipArchive::new(reader).ok()?;
+
+    if let Ok(mut entry) = archive.by_name("mimetype") {
+        let mut mime = String::new();
+        if entry.read_to_string(&mut mime).is_ok() {
+            match mime.trim() {
+                "application/epub+zip" => return Some(BookFormat::Epub),
+                "application/vnd.oasis.opendocument.text" => return Some(BookFormat::Odt),
+                _ => {}
+            }
+        }
+    }
+
+    let names: Vec<String> = archive.file_names().map(|n| n.to_lowercase()).collect();
+
+    if names.iter().any(|n| n == "meta-inf/container.xml") {
+        return Some(BookFormat::Epub);
+    }
+    if names.iter().any(|n| n == "word/document.xml") {
+        return Some(BookFormat::Docx);
+    }
+
+    let files: Vec<&String> = names.iter().filter(|n| !n.ends_with('/')).collect();
+    let images = files.iter().filter(|n| is_image_name(n)).count();
+    if images > 0 && images * 10 >= files.len() * 9 {
+        return Some(BookFormat::Cbz);
+    }
+
+    None
+}
+
+fn is_image_name(name: &str) -> bool {
+    [".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Heuristic: no NUL bytes and mostly valid UTF-8
+fn looks_like_text(data: &[u8]) -> bool {
+    if data.is_empty() {
+        return true;
+    }
+    if data.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        // The header may cut a multi-byte sequence in half
+        Err(e) => e.valid_up_to() + 4 >= data.len(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_sniff_pdf() {
+        assert_eq!(sniff_bytes(b"%PDF-1.7\n%\xE2\xE3"), Some(BookFormat::Pdf));
+        assert_eq!(
+            sniff_bytes(b"\x00\xFFjunk%PDF-1.4\n%\xE2\xE3"),
+            Some(BookFormat::Pdf)
+        );
+        assert_eq!(sniff_bytes(b"# Notes\n\nSaved as %PDF-1.4 once."), None);
+    }
+
+    #[test]
+    fn test_sniff_fb2_with_bom() {
+        let mut data = UTF8_BOM.to_vec();
+        data.extend_from_slice(
+            b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\">",
+        );
+        assert_eq!(sniff_bytes(&data), Some(BookFormat::Fb2));
+    }
+
+    #[test]
+    fn test_sniff_plain_text_is_unsure() {
+        assert_eq!(sniff_bytes(b"Just some words."), None);
+        assert_eq!(sniff_bytes(&[0xFF, 0xFE, b'H', 0, b'i', 0]), None);
+    }
+
+    #[test]
+    fn test_bom_keeps_text_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let markdown = dir.path().join("book.md");
+        let mut data = UTF8_BOM.to_vec();
+        data.extend_from_slice(b"# Title\n\nText.\n");
+        std::fs::write(&markdown, &data).unwrap();
+        assert_eq!(detect(&markdown), BookFormat::Markdown);
+        assert_eq!(sniff(&markdown), Some(BookFormat::Markdown));
+
+        let bare = dir.path().join("book");
+        std::fs::write(&bare, [0xFF, 0xFE, b'H', 0, b'i', 0]).unwrap();
+        assert_eq!(detect(&bare), BookFormat::PlainText);
+        assert_eq!(sniff(&bare), Some(BookFormat::PlainText));
+    }
+
+    #[test]
+    fn test_decode_utf16_le() {
+        assert_eq!(decode_text(&[0xFF, 0xFE, b'H', 0, b'i', 0]), "Hi");
+    }
+
+    #[test]
+    fn test_sniff_zip_containers() {
+        let mut epub = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let stored = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        epub.start_file("mimetype", stored).unwrap();
+        epub.write_all(b"application/epub+zip").unwrap();
+        let epub = epub.finish().unwrap().into_inner();
+        assert_eq!(sniff_bytes(&epub), Some(BookFormat::Epub));
+
+        let mut cbz = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for page in ["001.jpg", "002.jpg"] {
+            cbz.start_file(page, stored).unwrap();
+            cbz.write_all(b"\xFF\xD8\xFF").unwrap();
+        }
+        let cbz = cbz.finish().unwrap();
+        assert_eq!(sniff_bytes(cbz.get_ref()), None);
+        assert_eq!(sniff_zip_entries(cbz), Some(BookFormat::Cbz));
+    }
+}
@@ -0,0 +1,656 @@
+//! Book diagnostics
+//!
+//! Runs each parser stage on its own — container, metadata, spine or pages,
+//! text extraction, table of contents and cover — and records what it
+//! found, so a book that fails to load or shows almost no content can be
+//! traced to the stage at fault.
+
+use super::{detect, Book, BookFormat, ContentBlock};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Chapters with fewer words than this are reported as near-empty
+const LOW_CHAPTER_WORDS: usize = 50;
+
+/// PDFs averaging fewer words per page than this are probably scanned
+const LOW_PAGE_WORDS: usize = 20;
+
+/// How many offending chapters to name in a warning
+const MAX_LISTED: usize = 5;
+
+/// Outcome of a stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Skipped,
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Findings of one parser stage
+#[derive(Debug, Clone, Serialize)]
+pub struct Stage {
+    pub name: &'static str,
+    pub status: Status,
+    pub details: Vec<String>,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl Stage {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            details: Vec::new(),
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn skipped(name: &'static str, reason: &str) -> Self {
+        let mut stage = Self::new(name);
+        stage.status = Status::Skipped;
+        stage.details.push(reason.to_string());
+        stage
+    }
+
+    fn detail(&mut self, text: impl Into<String>) {
+        self.details.push(text.into());
+    }
+
+    fn warn(&mut self, text: impl Into<String>) {
+        self.warnings.push(text.into());
+        self.status = self.status.max(Status::Warning);
+    }
+
+    fn fail(&mut self, error: impl std::fmt::Display) {
+        // `{:#}` keeps anyhow's context chain on one line
+        self.error = Some(format!("{:#}", error));
+        self.status = Status::Error;
+    }
+}
+
+/// Diagnostic report for one book file
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub path: PathBuf,
+    pub format: String,
+    pub status: Status,
+    pub stages: Vec<Stage>,
+}
+
+impl Report {
+    fn push(&mut self, stage: Stage) {
+        self.status = self.status.max(stage.status);
+        self.stages.push(stage);
+    }
+}
+
+/// Run every stage on a book file
+pub fn diagnose(path: &Path) -> Report {
+    let mut report = Report {
+        path: path.to_path_buf(),
+        format: BookFormat::Unknown.name().to_string(),
+        status: Status::Ok,
+        stages: Vec::new(),
+    };
+
+    let mut container = Stage::new("container");
+    match std::fs::metadata(path) {
+        Ok(meta) => container.detail(format!("{} bytes", meta.len())),
+        Err(e) => {
+            container.fail(format!("Cannot read file: {}", e));
+            report.push(container);
+            return report;
+        }
+    }
+
+    #[cfg(feature = "compressed")]
+    let unpacked = match super::compressed::unpack(path) {
+        Ok(unpacked) => unpacked,
+        Err(e) => {
+            container.fail(e);
+            report.push(container);
+            return report;
+        }
+    };
+    #[cfg(feature = "compressed")]
+    let path = match &unpacked {
+        Some(unpacked) => {
+            if let Some(compression) = super::compressed::compression(&report.path) {
+                container.detail(format!("{} compressed", compression.name()));
+            }
+            unpacked.path.as_path()
+        }
+        None => path,
+    };
+
+    let format = BookFormat::detect(path);
+    let claimed = BookFormat::from_path(path);
+    report.format = format.name().to_string();
+    container.detail(format!("Detected as {}", format.name()));
+    if claimed != BookFormat::Unknown && claimed != format {
+        container.warn(format!(
+            "File extension claims {}, but the content is {}",
+            claimed.name(),
+            format.name()
+        ));
+    }
+    if !format.is_supported() {
+        container.fail(format!("Unsupported format: {}", format.name()));
+        report.push(container);
+        return report;
+    }
+
+    match format {
+        #[cfg(feature = "epub")]
+        BookFormat::Epub => epub::container(path, &mut container),
+        #[cfg(feature = "pdf")]
+        BookFormat::Pdf => pdf::container(path, &mut container),
+        _ => text_container(path, &mut container),
+    }
+    let failed = container.status == Status::Error;
+    report.push(container);
+    if failed {
+        return report;
+    }
+
+    report.push(metadata(path));
+    match format {
+        #[cfg(feature = "epub")]
+        BookFormat::Epub => report.push(epub::spine(path)),
+        #[cfg(feature = "pdf")]
+        BookFormat::Pdf => report.push(pdf::pages(path)),
+        _ => {}
+    }
+
+    let (text, book) = text(path, format);
+    report.push(text);
+    match &book {
+        Some(book) => report.push(toc(path, format, book)),
+        None => report.push(Stage::skipped("toc", "Text extraction failed")),
+    }
+    report.push(cover(path, format));
+    report
+}
+
+/// Check how a text-based file is encoded
+fn text_container(path: &Path, stage: &mut Stage) {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return stage.fail(format!("Cannot read file: {}", e)),
+    };
+
+    let encoding = detect::text_encoding(&data);
+    stage.detail(format!("Encoding: {}", encoding));
+    if encoding == "UTF-8" && std::str::from_utf8(&data).is_err() {
+        let decoded = detect::decode_text(&data);
+        stage.warn(format!(
+            "Not valid UTF-8: {} invalid byte sequence(s) will show as �",
+            decoded.matches('\u{FFFD}').count()
+        ));
+    }
+    if !encoding.starts_with("UTF-16") && data.contains(&0) {
+        stage.warn("Contains NUL bytes; this may be a binary file");
+    }
+}
+
+fn metadata(path: &Path) -> Stage {
+    let mut stage = Stage::new("metadata");
+    match super::get_metadata(path) {
+        Ok(metadata) => {
+            stage.detail(format!("Title: {}", metadata.title));
+            stage.detail(format!("Author(s): {}", metadata.authors_string()));
+            if let Some(language) = &metadata.language {
+                stage.detail(format!("Language: {}", language));
+            }
+            let title = metadata.title.trim();
+            if title.is_empty() || title == "Unknown Title" {
+                stage.warn("No title");
+            }
+            if metadata.authors.is_empty() {
+                stage.warn("No authors");
+            }
+        }
+        Err(e) => stage.fail(e),
+    }
+    stage
+}
+
+/// Parse the whole book and look for missing or garbled text
+fn text(path: &Path, format: BookFormat) -> (Stage, Option<Book>) {
+    let mut stage = Stage::new("text");
+    let book = match super::parse_book(path) {
+        Ok(book) => book,
+        Err(e) => {
+            stage.fail(e);
+            return (stage, None);
+        }
+    };
+
+    let chapters = &book.content.chapters;
+    let words: usize = chapters.iter().map(|c| c.word_count()).sum();
+    stage.detail(format!("{} chapters, {} words", chapters.len(), words));
+    if chapters.is_empty() {
+        stage.warn("No chapters were extracted");
+    }
+
+    let mut empty = Vec::new();
+    let mut short = Vec::new();
+    for chapter in chapters {
+        let has_images = chapter
+            .blocks
+            .iter()
+            .any(|b| matches!(b, ContentBlock::Image { .. }));
+        match chapter.word_count() {
+            0 if !has_images => empty.push(chapter.display_title()),
+            count if count < LOW_CHAPTER_WORDS => short.push(chapter.display_title()),
+            _ => {}
+        }
+    }
+    if !empty.is_empty() {
+        stage.warn(format!(
+            "{} empty chapter(s): {}",
+            empty.len(),
+            list(&empty)
+        ));
+    }
+    if !short.is_empty() {
+        stage.warn(format!(
+            "{} chapter(s) under {} words, such as cover or title pages: {}",
+            short.len(),
+            LOW_CHAPTER_WORDS,
+            list(&short)
+        ));
+    }
+
+    let text: String = chapters
+        .iter()
+        .flat_map(|c| c.blocks.iter())
+        .map(|b| b.text())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let replaced = text.matches('\u{FFFD}').count();
+    if replaced > 0 {
+        stage.warn(format!(
+            "{} undecodable character(s) (�); the source encoding may be wrong",
+            replaced
+        ));
+    }
+    let mojibake = ["Ã©", "Ã¨", "Ã¶", "Ã¼", "â€™", "â€œ", "â€”"]
+        .iter()
+        .map(|pattern| text.matches(pattern).count())
+        .sum::<usize>();
+    if mojibake > 0 {
+        stage.warn(format!(
+            "{} sequence(s) like \"Ã©\" or \"â€™\": UTF-8 text decoded as Latin-1",
+            mojibake
+        ));
+    }
+
+    #[cfg(feature = "pdf")]
+    if format == BookFormat::Pdf {
+        pdf::text(path, &book, words, &mut stage);
+    }
+    #[cfg(not(feature = "pdf"))]
+    let _ = (path, format);
+
+    (stage, Some(book))
+}
+
+fn toc(path: &Path, format: BookFormat, book: &Book) -> Stage {
+    let mut stage = Stage::new("toc");
+    fn count(entries: &[super::TocEntry]) -> usize {
+        entries.iter().map(|e| 1 + count(&e.children)).sum()
+    }
+
+    let entries = count(&book.content.toc);
+    stage.detail(format!("{} entries", entries));
+    if entries == 0 && book.content.chapters.len() > 1 {
+        stage.warn("No table of contents; the chapter list is used instead");
+    }
+
+    #[cfg(feature = "epub")]
+    if format == BookFormat::Epub {
+        epub::navigation(path, &mut stage);
+    }
+    #[cfg(not(feature = "epub"))]
+    let _ = (path, format);
+
+    stage
+}
+
+fn cover(path: &Path, format: BookFormat) -> Stage {
+    if format != BookFormat::Epub {
+        return Stage::skipped("cover", "Covers are only extracted from EPUB files");
+    }
+
+    let mut stage = Stage::new("cover");
+    match super::extract_cover(path) {
+        Ok(Some((data, mime))) => {
+            stage.detail(format!("{}, {} bytes", mime, data.len()));
+            let sniffed = detect::image_mime(&data);
+            if sniffed == "application/octet-stream" {
+                stage.warn("Cover data is not a recognized image");
+            } else if sniffed != mime {
+                stage.warn(format!("Cover is declared as {} but is {}", mime, sniffed));
+            }
+        }
+        Ok(None) => stage.warn("No cover image found"),
+        Err(e) => stage.fail(e),
+    }
+    stage
+}
+
+/// Join the first few names of a list
+fn list(names: &[String]) -> String {
+    let mut shown = names[..names.len().min(MAX_LISTED)].join(", ");
+    if names.len() > MAX_LISTED {
+        shown.push_str(&format!(", … ({} more)", names.len() - MAX_LISTED));
+    }
+    shown
+}
+
+#[cfg(feature = "epub")]
+mod epub {
+    use super::Stage;
+    use regex::Regex;
+    use std::collections::HashSet;
+    use std::fs::File;
+    use std::io::{BufReader, Read};
+    use std::path::{Path, PathBuf};
+
+    /// Encryption algorithms that only obfuscate embedded fonts
+    const FONT_OBFUSCATION: &[&str] = &[
+        "http://www.idpf.org/2008/embedding",
+        "http://ns.adobe.com/pdf/enc#RC",
+    ];
+
+    type Doc = epub::doc::EpubDoc<BufReader<File>>;
+
+    fn open(path: &Path) -> anyhow::Result<Doc> {
+        epub::doc::EpubDoc::new(path).map_err(|e| anyhow::anyhow!("Failed to open EPUB: {:?}", e))
+    }
+
+    /// Check the zip container, its mimetype and package document
+    pub fn container(path: &Path, stage: &mut Stage) {
+        let mut archive = match File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|f| Ok(zip::ZipArchive::new(BufReader::new(f))?))
+        {
+            Ok(archive) => archive,
+            Err(e) => return stage.fail(format!("Not a readable zip archive: {}", e)),
+        };
+        stage.detail(format!("{} archive entries", archive.len()));
+
+        match archive.by_index(0) {
+            Ok(mut first) if first.name() == "mimetype" => {
+                let stored = first.compression() == zip::CompressionMethod::Stored;
+                let mut mimetype = String::new();
+                let _ = first.read_to_string(&mut mimetype);
+                if mimetype.trim() != "application/epub+zip" {
+                    stage.warn(format!("Unexpected mimetype: {:?}", mimetype.trim()));
+                }
+                if !stored {
+                    stage.warn("The mimetype entry is compressed; strict readers reject this");
+                }
+            }
+            _ => stage.warn("The first archive entry is not `mimetype`"),
+        }
+
+        let mut read = |name: &str| -> Option<String> {
+            let mut text = String::new();
+            archive.by_name(name).ok()?.read_to_string(&mut text).ok()?;
+            Some(text)
+        };
+        let Some(container) = read("META-INF/container.xml") else {
+            return stage.fail("META-INF/container.xml is missing");
+        };
+        let rootfile = Regex::new(r#"full-path\s*=\s*["']([^"']+)["']"#)
+            .unwrap()
+            .captures(&container)
+            .map(|c| c[1].to_string());
+        match rootfile {
+            Some(opf) if read(&opf).is_some() => stage.detail(format!("Package: {}", opf)),
+            Some(opf) => return stage.fail(format!("Package document {} is missing", opf)),
+            None => return stage.fail("container.xml does not name a package document"),
+        }
+
+        if let Some(encryption) = read("META-INF/encryption.xml") {
+            let algorithms: Vec<String> =
+                Regex::new(r#"EncryptionMethod\b[^>]*Algorithm\s*=\s*["']([^"']+)["']"#)
+                    .unwrap()
+                    .captures_iter(&encryption)
+                    .map(|c| c[1].to_string())
+                    .collect();
+            if algorithms
+                .iter()
+                .all(|a| FONT_OBFUSCATION.contains(&a.as_str()))
+            {
+                stage.detail("Embedded fonts are obfuscated");
+            } else {
+                stage.warn("Content is encrypted (DRM); text will be unreadable");
+            }
+        }
+
+        match open(path) {
+            Ok(doc) => stage.detail(match doc.version {
+                epub::doc::EpubVersion::Version2_0 => "EPUB 2.0".to_string(),
+                epub::doc::EpubVersion::Version3_0 => "EPUB 3.0".to_string(),
+                epub::doc::EpubVersion::Unknown(version) => format!("EPUB {}", version),
+            }),
+            Err(e) => stage.fail(e),
+        }
+    }
+
+    /// Check that every spine item exists and is decodable
+    pub fn spine(path: &Path) -> Stage {
+        let mut stage = Stage::new("spine");
+        let mut doc = match open(path) {
+            Ok(doc) => doc,
+            Err(e) => {
+                stage.fail(e);
+                return stage;
+            }
+        };
+
+        let spine = doc.spine.clone();
+        let linear = spine.iter().filter(|item| item.linear).count();
+        stage.detail(format!(
+            "{} items ({} non-linear)",
+            spine.len(),
+            spine.len() - linear
+        ));
+        if spine.is_empty() {
+            stage.fail("The spine is empty");
+            return stage;
+        }
+
+        let declared = Regex::new(r#"^<\?xml[^>]*encoding\s*=\s*["']([^"']+)["']"#).unwrap();
+        let mut missing = Vec::new();
+        for item in &spine {
+            let Some((data, mime)) = doc.get_resource(&item.idref) else {
+                missing.push(item.idref.clone());
+                continue;
+            };
+            if !mime.contains("html") && !mime.contains("xml") {
+                stage.warn(format!("{} is {}, not XHTML", item.idref, mime));
+            }
+            if std::str::from_utf8(&data).is_err() {
+                let head = String::from_utf8_lossy(&data[..data.len().min(200)]).to_string();
+                let encoding = declared
+                    .captures(&head)
+                    .map(|c| format!(" (declares {})", &c[1]))
+                    .unwrap_or_default();
+                stage.warn(format!(
+                    "{} is not valid UTF-8{}; some characters will show as �",
+                    item.idref, encoding
+                ));
+            }
+        }
+        if !missing.is_empty() {
+            stage.warn(format!(
+                "{} spine item(s) missing from the archive and skipped: {}",
+                missing.len(),
+                super::list(&missing)
+            ));
+        }
+        stage
+    }
+
+    /// Compare the book's own navigation with its spine
+    pub fn navigation(path: &Path, stage: &mut Stage) {
+        let Ok(doc) = open(path) else { return };
+
+        fn flatten(points: &[epub::doc::NavPoint], out: &mut Vec<PathBuf>) {
+            for point in points {
+                out.push(point.content.clone());
+                flatten(&point.children, out);
+            }
+        }
+        let mut targets = Vec::new();
+        flatten(&doc.toc, &mut targets);
+        if targets.is_empty() {
+            stage.warn("The book has no NCX navigation");
+            return;
+        }
+        stage.detail(format!("{} navigation points in the book", targets.len()));
+
+        let spine: HashSet<PathBuf> = doc
+            .spine
+            .iter()
+            .filter_map(|item| doc.resources.get(&item.idref))
+            .map(|resource| resource.path.clone())
+            .collect();
+        let outside = targets
+            .iter()
+            .filter(|target| {
+                let file = target.to_string_lossy();
+                let file = file.split('#').next().unwrap_or_default();
+                !spine.contains(Path::new(file))
+            })
+            .count();
+        if outside > 0 {
+            stage.warn(format!(
+                "{} navigation point(s) link outside the spine",
+                outside
+            ));
+        }
+    }
+}
+
+#[cfg(feature = "pdf")]
+mod pdf {
+    use super::{Book, Stage, LOW_PAGE_WORDS};
+    use std::path::Path;
+
+    /// Marker text of the page-range placeholders used when no text is found
+    const PLACEHOLDERS: &[&str] = &["[PDF content from pages", "[PDF text extraction failed"];
+
+    pub fn container(path: &Path, stage: &mut Stage) {
+        match lopdf::Document::load(path) {
+            Ok(doc) => {
+                stage.detail(format!("PDF version {}", doc.version));
+                if doc.is_encrypted() {
+                    stage.warn("The PDF is encrypted; text extraction may fail");
+                }
+            }
+            Err(e) => stage.fail(format!("Failed to load PDF: {}", e)),
+        }
+    }
+
+    pub fn pages(path: &Path) -> Stage {
+        let mut stage = Stage::new("pages");
+        match lopdf::Document::load(path) {
+            Ok(doc) => {
+                let pages = doc.get_pages().len();
+                stage.detail(format!("{} pages", pages));
+                if pages == 0 {
+                    stage.fail("The PDF has no pages");
+                }
+            }
+            Err(e) => stage.fail(format!("Failed to load PDF: {}", e)),
+        }
+        stage
+    }
+
+    /// Report when extraction fell back to placeholders, and why
+    pub fn text(path: &Path, book: &Book, words: usize, stage: &mut Stage) {
+        let placeholders = book
+            .content
+            .chapters
+            .iter()
+            .flat_map(|c| c.blocks.iter())
+            .filter(|b| {
+                let text = b.text();
+                PLACEHOLDERS.iter().any(|p| text.starts_with(p))
+            })
+            .count();
+
+        if placeholders > 0 {
+            let reason = match pdf_extract::extract_text(path) {
+                Ok(_) => "pdf-extract found no text".to_string(),
+                Err(e) => format!("pdf-extract failed: {}", e),
+            };
+            stage.warn(format!(
+                "{}; fell back to {} page-range placeholder(s) (scanned or image-only PDF?)",
+                reason, placeholders
+            ));
+            return;
+        }
+
+        if let Ok(doc) = lopdf::Document::load(path) {
+            let pages = doc.get_pages().len();
+            if pages > 0 && words / pages < LOW_PAGE_WORDS {
+                stage.warn(format!(
+                    "Only {} words per page on average; pages may be scanned images",
+                    words / pages
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose_markdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "# Notes\n\nShort.\n").unwrap();
+
+        let report = diagnose(&path);
+        assert_eq!(report.format, "Markdown");
+        let names: Vec<&str> = report.stages.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["container", "metadata", "text", "toc", "cover"]);
+
+        let text = &report.stages[2];
+        assert_eq!(text.status, Status::Warning);
+        assert!(text.warnings[0].contains("under 50 words"));
+        assert_eq!(report.stages[4].status, Status::Skipped);
+        assert_eq!(report.status, Status::Warning);
+    }
+
+    #[test]
+    fn test_diagnose_invalid_utf8_and_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.txt");
+        std::fs::write(&path, b"Caf\xe9 au lait\n").unwrap();
+
+        let report = diagnose(&path);
+        let container = &report.stages[0];
+        assert!(container.warnings[0].starts_with("Not valid UTF-8: 1 invalid"));
+        assert!(report.stages[2]
+            .warnings
+            .iter()
+            .any(|w| w.contains("undecodable")));
+
+        let missing = diagnose(&dir.path().join("missing.epub"));
+        assert_eq!(missing.status, Status::Error);
+        assert_eq!(missing.stages.len(), 1);
+    }
+}
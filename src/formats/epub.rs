@@ -1,8 +1,15 @@
 //! EPUB format parser
 
+use super::book::{StyleType, TextStyle};
+use super::lazy::{BookSkeleton, ChapterInfo, ChapterLoader, LazyBook};
+use super::math::{self, Formulas};
 use super::{Book, BookContent, BookMetadata, Chapter, ContentBlock, TocEntry};
 use anyhow::Result;
-use std::path::Path;
+use epub::doc::ResourceItem;
+use html2text::render::text_renderer::{TaggedLine, TaggedLineElement, TextDecorator};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
 
 /// Parse an EPUB file
 pub fn parse(path: &Path) -> Result<Book> {
@@ -43,6 +50,25 @@ pub fn extract_cover_from_path(path: &Path) -> Result<Option<(Vec<u8>, String)>>
     Ok(extract_cover(&mut doc))
 }
 
+/// Load a resource (image, stylesheet, ...) from the EPUB archive
+pub fn load_resource(path: &Path, src: &str) -> Result<Option<(Vec<u8>, String)>> {
+    let mut doc = epub::doc::EpubDoc::new(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open EPUB: {:?}", e))?;
+
+    // Content documents reference resources relative to the OPF directory
+    let candidates = [doc.root_base.join(src), Path::new(src).to_path_buf()];
+    for candidate in candidates {
+        if let Some(data) = doc.get_resource_by_path(&candidate) {
+            let mime = doc
+                .get_resource_mime_by_path(&candidate)
+                .unwrap_or_else(|| detect_image_mime(&data));
+            return Ok(Some((data, mime)));
+        }
+    }
+
+    Ok(None)
+}
+
 fn get_metadata_string(
     doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
     key: &str,
@@ -50,6 +76,18 @@ fn get_metadata_string(
     doc.mdata(key).map(|item| item.value.clone())
 }
 
+fn get_metadata_strings(
+    doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
+    key: &str,
+) -> Vec<String> {
+    doc.metadata
+        .iter()
+        .filter(|item| item.property == key)
+        .map(|item| item.value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
 fn extract_cover(
     doc: &mut epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
 ) -> Option<(Vec<u8>, String)> {
@@ -117,7 +155,6 @@ fn extract_cover(
 }
 
 /// Detect image MIME type from magic bytes
-#[allow(dead_code)]
 fn detect_image_mime(data: &[u8]) -> String {
     if data.len() < 4 {
         return "image/jpeg".to_string();
@@ -145,19 +182,35 @@ fn extract_metadata(
 ) -> Result<BookMetadata> {
     let title = get_metadata_string(doc, "title").unwrap_or_else(|| "Unknown Title".to_string());
 
-    let authors = get_metadata_string(doc, "creator")
-        .map(|a| vec![a])
-        .unwrap_or_default();
+    let authors = get_metadata_strings(doc, "creator");
 
     let publisher = get_metadata_string(doc, "publisher");
     let language = get_metadata_string(doc, "language");
     let description = get_metadata_string(doc, "description");
     let published = get_metadata_string(doc, "date");
 
-    // Extract subjects/tags
-    let subjects = get_metadata_string(doc, "subject")
-        .map(|s| s.split(',').map(|t| t.trim().to_string()).collect())
-        .unwrap_or_default();
+    // Extract subjects/tags, which some books list comma-separated
+    let subjects = get_metadata_strings(doc, "subject")
+        .iter()
+        .flat_map(|s| s.split(','))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    // Calibre's series metadata, falling back to an EPUB 3 collection
+    let collection = doc
+        .metadata
+        .iter()
+        .find(|item| item.property == "belongs-to-collection");
+    let series = get_metadata_string(doc, "calibre:series")
+        .or_else(|| collection.map(|item| item.value.clone()));
+    let series_index = get_metadata_string(doc, "calibre:series_index")
+        .or_else(|| {
+            collection
+                .and_then(|item| item.refinement("group-position"))
+                .map(|r| r.value.clone())
+        })
+        .and_then(|index| index.trim().parse().ok());
 
     Ok(BookMetadata {
         title,
@@ -168,8 +221,8 @@ fn extract_metadata(
         published,
         subjects,
         isbn: get_metadata_string(doc, "identifier"),
-        series: None,
-        series_index: None,
+        series,
+        series_index,
         cover: None,
         cover_mime: None,
         word_count: None,
@@ -182,32 +235,13 @@ fn extract_content(path: &Path) -> Result<BookContent> {
         .map_err(|e| anyhow::anyhow!("Failed to open EPUB: {:?}", e))?;
 
     let mut chapters = Vec::new();
-    let mut order = 0;
 
     // Get the spine (reading order)
     let spine = doc.spine.clone();
 
     for spine_item in &spine {
-        // spine_item is a SpineItem, we need to get its idref
-        let chapter_id = &spine_item.idref;
-
-        // Try to get the content
-        if let Some((content, _mime)) = doc.get_resource(chapter_id) {
-            let html = String::from_utf8_lossy(&content).to_string();
-            let blocks = parse_html_content(&html);
-
-            // Try to extract title from first heading
-            let title = blocks.iter().find_map(|b| match b {
-                ContentBlock::Heading { text, level } if *level <= 2 => Some(text.clone()),
-                _ => None,
-            });
-
-            let mut chapter = Chapter::new(chapter_id.clone(), order);
-            chapter.title = title;
-            chapter.blocks = blocks;
-
+        if let Some(chapter) = load_spine_chapter(&mut doc, &spine_item.idref, chapters.len()) {
             chapters.push(chapter);
-            order += 1;
         }
     }
 
@@ -217,11 +251,217 @@ fn extract_content(path: &Path) -> Result<BookContent> {
     Ok(BookContent { chapters, toc })
 }
 
-fn parse_html_content(html: &str) -> Vec<ContentBlock> {
+/// Parse an EPUB one spine item at a time, handing each chapter to `sink`
+pub fn parse_streaming(
+    path: &Path,
+    sink: &mut dyn FnMut(Chapter) -> Result<()>,
+) -> Result<BookSkeleton> {
+    let mut doc = epub::doc::EpubDoc::new(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open EPUB: {:?}", e))?;
+
+    let mut skeleton = BookSkeleton::new(
+        extract_metadata(&doc)?,
+        path.to_path_buf(),
+        "epub".to_string(),
+    );
+    skeleton.toc = build_toc(&doc);
+
+    let spine = doc.spine.clone();
+    for spine_item in &spine {
+        let order = skeleton.chapters.len();
+        if let Some(chapter) = load_spine_chapter(&mut doc, &spine_item.idref, order) {
+            skeleton.chapters.push(ChapterInfo::from(&chapter));
+            sink(chapter)?;
+        }
+    }
+
+    Ok(skeleton)
+}
+
+/// Open an EPUB whose chapters are parsed when first read
+///
+/// The skeleton comes from the OPF spine; chapters are summarized from
+/// their markup without being parsed.
+pub fn open_lazy(path: &Path) -> Result<LazyBook> {
+    let mut doc = epub::doc::EpubDoc::new(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open EPUB: {:?}", e))?;
+
+    let mut skeleton = BookSkeleton::new(
+        extract_metadata(&doc)?,
+        path.to_path_buf(),
+        "epub".to_string(),
+    );
+    skeleton.toc = build_toc(&doc);
+
+    let spine = doc.spine.clone();
+    for spine_item in &spine {
+        if let Some((content, _mime)) = doc.get_resource(&spine_item.idref) {
+            let order = skeleton.chapters.len();
+            let html = String::from_utf8_lossy(&content);
+            skeleton
+                .chapters
+                .push(summarize_chapter(&spine_item.idref, order, &html));
+        }
+    }
+    let ids = skeleton.chapters.iter().map(|c| c.id.clone()).collect();
+
+    Ok(LazyBook::new(
+        skeleton,
+        Box::new(SpineLoader {
+            doc: Mutex::new(doc),
+            ids,
+        }),
+    ))
+}
+
+/// Loads chapters straight from the EPUB archive
+struct SpineLoader {
+    doc: Mutex<epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>>,
+    ids: Vec<String>,
+}
+
+impl ChapterLoader for SpineLoader {
+    fn load(&self, index: usize) -> Result<Chapter> {
+        let id = self
+            .ids
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("Chapter {} out of range", index + 1))?;
+        let mut doc = self.doc.lock().unwrap_or_else(|e| e.into_inner());
+        load_spine_chapter(&mut doc, id, index)
+            .ok_or_else(|| anyhow::anyhow!("Missing EPUB resource: {}", id))
+    }
+}
+
+fn load_spine_chapter(
+    doc: &mut epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>,
+    chapter_id: &str,
+    order: usize,
+) -> Option<Chapter> {
+    let (content, _mime) = doc.get_resource(chapter_id)?;
+    let html = String::from_utf8_lossy(&content).to_string();
+    let (mut blocks, anchors) = parse_html_content(&html);
+
+    // Point links at other spine items by chapter id
+    let base = doc.resources.get(chapter_id)?.path.clone();
+    for block in &mut blocks {
+        if let ContentBlock::Paragraph { styles, .. } = block {
+            for style in styles.iter_mut() {
+                if let (StyleType::Link, Some(href)) = (style.style_type, &style.data) {
+                    style.data = Some(resolve_href(href, &base, &doc.resources));
+                }
+            }
+        }
+    }
+
+    // Try to extract title from first heading
+    let title = blocks.iter().find_map(|b| match b {
+        ContentBlock::Heading { text, level } if *level <= 2 => Some(text.clone()),
+        _ => None,
+    });
+
+    let mut chapter = Chapter::new(chapter_id.to_string(), order);
+    chapter.title = title;
+    chapter.blocks = blocks;
+    chapter.anchors = anchors;
+    Some(chapter)
+}
+
+/// Summary of a content document, found without parsing it
+///
+/// The title is the first `h1` or `h2`, as for a parsed chapter. Words are
+/// counted in the text between tags and blocks by their opening tags, so
+/// both counts are estimates.
+fn summarize_chapter(id: &str, order: usize, html: &str) -> ChapterInfo {
+    let body = html.find("<body").map_or(html, |start| &html[start..]);
+    let heading = regex::Regex::new(r"(?is)<h[12][^>]*>(.*?)</h[12]\s*>").unwrap();
+    let block =
+        regex::Regex::new(r"(?i)<(?:p|h[1-6]|li|pre|blockquote|img|table|dt|dd)[\s/>]").unwrap();
+
+    ChapterInfo {
+        id: id.to_string(),
+        title: heading
+            .captures(body)
+            .map(|c| {
+                strip_tags(&c[1])
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|title| !title.is_empty()),
+        number: None,
+        order,
+        // Tags between blocks separate words too
+        word_count: strip_tags(&body.replace('<', " <"))
+            .split_whitespace()
+            .count(),
+        block_count: block.find_iter(body).count(),
+    }
+}
+
+/// Rewrite a link from the chapter at `base` as `chapter-id#fragment`
+///
+/// Links within the chapter keep just their fragment. URLs, and links to
+/// files that are not in the book, are left as they are.
+fn resolve_href(href: &str, base: &Path, resources: &HashMap<String, ResourceItem>) -> String {
+    let (file, fragment) = href.split_once('#').unwrap_or((href, ""));
+    if file.is_empty() || file.contains(':') {
+        return href.to_string();
+    }
+
+    let mut target = PathBuf::new();
+    for component in base
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(file)
+        .components()
+    {
+        match component {
+            Component::ParentDir => {
+                target.pop();
+            }
+            Component::CurDir => {}
+            other => target.push(other),
+        }
+    }
+
+    match resources.iter().find(|(_, item)| item.path == target) {
+        Some((id, _)) => format!("{}#{}", id, fragment),
+        None => href.to_string(),
+    }
+}
+
+/// Parse a content document into blocks and the anchors in them
+fn parse_html_content(html: &str) -> (Vec<ContentBlock>, Vec<(String, usize)>) {
+    let mut formulas = Formulas::default();
+    let html = extract_mathml(html, &mut formulas);
+    let (blocks, mut anchors) = parse_html_blocks(&html);
+    let blocks = formulas.resolve_anchored(blocks, &mut anchors);
+    (blocks, anchors)
+}
+
+/// Replace MathML elements with formula placeholders
+///
+/// Formulas with `display="block"` become math blocks, others inline math.
+fn extract_mathml(html: &str, formulas: &mut Formulas) -> String {
+    let math_re =
+        regex::Regex::new(r"(?is)<(?:\w+:)?math\b([^>]*)>.*?</(?:\w+:)?math\s*>").unwrap();
+    let display_re = regex::Regex::new(r#"(?i)\bdisplay\s*=\s*["']block["']"#).unwrap();
+
+    math_re
+        .replace_all(html, |cap: &regex::Captures| {
+            match math::mathml_to_tex(&cap[0]) {
+                Some(tex) => formulas.placeholder(&tex, display_re.is_match(&cap[1])),
+                None => String::new(),
+            }
+        })
+        .into_owned()
+}
+
+fn parse_html_blocks(html: &str) -> (Vec<ContentBlock>, Vec<(String, usize)>) {
     let mut blocks = Vec::new();
 
     // Try html2text first with a wider width for better text extraction
-    let text = html2text::from_read(html.as_bytes(), 10000);
+    let (text, styles, fragments) = html_to_text(html);
 
     // If html2text gives us nothing useful, try manual extraction
     if text.trim().is_empty() || text.trim().len() < 20 {
@@ -288,15 +528,21 @@ fn parse_html_content(html: &str) -> Vec<ContentBlock> {
             }
         }
 
-        return blocks;
+        return (blocks, Vec::new());
     }
 
     // Process html2text output
+    let mut offset = 0;
+    // Where each block's paragraph ends in the text
+    let mut ends = Vec::new();
     for para in text.split("\n\n") {
+        let start = offset + para.len() - para.trim_start().len();
+        offset += para.len() + 2;
         let trimmed = para.trim();
         if trimmed.is_empty() {
             continue;
         }
+        ends.push(offset);
 
         // Detect headings (lines that are short and possibly styled)
         if trimmed.len() < 100 && !trimmed.contains('.') && trimmed.lines().count() == 1 {
@@ -333,12 +579,183 @@ fn parse_html_content(html: &str) -> Vec<ContentBlock> {
         } else {
             blocks.push(ContentBlock::Paragraph {
                 text: trimmed.to_string(),
-                styles: Vec::new(),
+                styles: styles_within(&styles, start, start + trimmed.len()),
             });
         }
     }
 
-    blocks
+    // Fragments belong to the first block ending after them
+    let last = blocks.len().saturating_sub(1);
+    let anchors = fragments
+        .into_iter()
+        .map(|(id, at)| (id, ends.iter().position(|&end| end > at).unwrap_or(last)))
+        .collect();
+    (blocks, anchors)
+}
+
+/// Inline markup reported by [`MarkupDecorator`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Markup {
+    #[default]
+    Plain,
+    Style(StyleType),
+    Link(String),
+}
+
+/// Renders HTML like html2text's plain output, but reports emphasis, code
+/// and links as annotations instead of `*`, backticks and link references
+struct MarkupDecorator;
+
+impl TextDecorator for MarkupDecorator {
+    type Annotation = Markup;
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Markup) {
+        (String::new(), Markup::Link(url.to_string()))
+    }
+
+    fn decorate_link_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_em_start(&self) -> (String, Markup) {
+        (String::new(), Markup::Style(StyleType::Italic))
+    }
+
+    fn decorate_em_end(&self) -> String {
+        String::new()
+    }
+
+    fn decorate_strong_start(&self) -> (String, Markup) {
+        (String::new(), Markup::Style(StyleType::Bold))
+    }
+
+    fn decorate_strong_end(&self) -> String {
+        String::new()
+    }
+
+    fn decorate_strikeout_start(&self) -> (String, Markup) {
+        (String::new(), Markup::Style(StyleType::Strikethrough))
+    }
+
+    fn decorate_strikeout_end(&self) -> String {
+        String::new()
+    }
+
+    fn decorate_code_start(&self) -> (String, Markup) {
+        (String::new(), Markup::Style(StyleType::Code))
+    }
+
+    fn decorate_code_end(&self) -> String {
+        String::new()
+    }
+
+    fn decorate_preformat_first(&self) -> Markup {
+        Markup::Plain
+    }
+
+    fn decorate_preformat_cont(&self) -> Markup {
+        Markup::Plain
+    }
+
+    fn decorate_image(&mut self, _src: &str, title: &str) -> (String, Markup) {
+        (title.to_string(), Markup::Plain)
+    }
+
+    fn header_prefix(&self, level: usize) -> String {
+        "#".repeat(level) + " "
+    }
+
+    fn quote_prefix(&self) -> String {
+        "> ".to_string()
+    }
+
+    fn unordered_item_prefix(&self) -> String {
+        "* ".to_string()
+    }
+
+    fn ordered_item_prefix(&self, i: i64) -> String {
+        format!("{}. ", i)
+    }
+
+    fn make_subblock_decorator(&self) -> Self {
+        MarkupDecorator
+    }
+
+    fn decorate_superscript_start(&self) -> (String, Markup) {
+        (String::new(), Markup::Style(StyleType::Superscript))
+    }
+
+    fn decorate_superscript_end(&self) -> String {
+        String::new()
+    }
+
+    fn finalise(&mut self, _links: Vec<String>) -> Vec<TaggedLine<Markup>> {
+        Vec::new()
+    }
+}
+
+/// Convert HTML to text, with its inline styles and fragment ids as byte
+/// offsets
+fn html_to_text(html: &str) -> (String, Vec<TextStyle>, Vec<(String, usize)>) {
+    let lines = html2text::config::with_decorator(MarkupDecorator)
+        .lines_from_read(html.as_bytes(), 10000)
+        .unwrap_or_default();
+
+    let mut text = String::new();
+    let mut styles: Vec<TextStyle> = Vec::new();
+    let mut fragments = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        for element in line.iter() {
+            let piece = match element {
+                TaggedLineElement::Str(piece) => piece,
+                TaggedLineElement::FragmentStart(id) => {
+                    fragments.push((id.clone(), text.len()));
+                    continue;
+                }
+            };
+            let start = text.len();
+            text.push_str(&piece.s);
+            for markup in &piece.tag {
+                let (style_type, data) = match markup {
+                    Markup::Plain => continue,
+                    Markup::Style(style_type) => (*style_type, None),
+                    Markup::Link(url) => (StyleType::Link, Some(url.clone())),
+                };
+                // Runs of the same markup arrive in several pieces
+                let previous = styles
+                    .iter_mut()
+                    .rev()
+                    .find(|s| s.end == start && s.style_type == style_type && s.data == data);
+                match previous {
+                    Some(style) => style.end = text.len(),
+                    None => styles.push(TextStyle {
+                        start,
+                        end: text.len(),
+                        style_type,
+                        data,
+                    }),
+                }
+            }
+        }
+    }
+    styles.retain(|s| s.start < s.end);
+    (text, styles, fragments)
+}
+
+/// The styles inside `start..end`, clipped and made relative to `start`
+fn styles_within(styles: &[TextStyle], start: usize, end: usize) -> Vec<TextStyle> {
+    styles
+        .iter()
+        .filter(|s| s.start < end && s.end > start)
+        .map(|s| TextStyle {
+            start: s.start.max(start) - start,
+            end: s.end.min(end) - start,
+            ..s.clone()
+        })
+        .collect()
 }
 
 /// Strip HTML tags from text
@@ -373,3 +790,75 @@ fn build_toc(doc: &epub::doc::EpubDoc<std::io::BufReader<std::fs::File>>) -> Vec
         .map(|(i, item)| TocEntry::new(format!("Section {}", i + 1), item.idref.clone(), 0))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_href() {
+        let resources: HashMap<String, ResourceItem> = [
+            ("ch1", "OEBPS/Text/ch1.xhtml"),
+            ("notes", "OEBPS/notes.xhtml"),
+        ]
+        .into_iter()
+        .map(|(id, path)| {
+            let item = ResourceItem {
+                path: PathBuf::from(path),
+                mime: "application/xhtml+xml".to_string(),
+                properties: None,
+            };
+            (id.to_string(), item)
+        })
+        .collect();
+        let base = Path::new("OEBPS/Text/ch1.xhtml");
+
+        assert_eq!(
+            resolve_href("../notes.xhtml#n3", base, &resources),
+            "notes#n3"
+        );
+        assert_eq!(resolve_href("./ch1.xhtml", base, &resources), "ch1#");
+        assert_eq!(resolve_href("#top", base, &resources), "#top");
+        assert_eq!(
+            resolve_href("missing.xhtml#x", base, &resources),
+            "missing.xhtml#x"
+        );
+        assert_eq!(
+            resolve_href("https://example.org/a.html#b", base, &resources),
+            "https://example.org/a.html#b"
+        );
+    }
+
+    #[test]
+    fn test_summarize_chapter() {
+        let html = "<html><head><title>Book</title></head><body>\
+            <h1 class=\"title\">The <em>First</em>\n Chapter</h1>\
+            <p>One two three.</p><p>Four &amp; five.</p>\
+            <ul><li>six</li></ul><hr/>\
+            </body></html>";
+        let info = summarize_chapter("ch1", 0, html);
+        assert_eq!(info.title.as_deref(), Some("The First Chapter"));
+        assert_eq!((info.word_count, info.block_count), (10, 4));
+        assert_eq!(info.block_count, parse_html_content(html).0.len());
+    }
+
+    #[test]
+    fn test_anchors_follow_blocks() {
+        let html = "<html><body>\
+            <p>The first paragraph of the chapter, long enough to keep.</p>\
+            <p id=\"second\">The second paragraph, with <a href=\"#first\">a link</a>.</p>\
+            <p>Third <span id=\"inline\">paragraph</span> here.</p>\
+            </body></html>";
+        let (blocks, anchors) = parse_html_content(html);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            anchors,
+            vec![("second".to_string(), 1), ("inline".to_string(), 2)]
+        );
+        let ContentBlock::Paragraph { styles, .. } = &blocks[1] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(styles[0].data.as_deref(), Some("#first"));
+    }
+}
@@ -0,0 +1,247 @@
+//! Chapter-on-demand book loading
+//!
+//! A [`LazyBook`] holds only the book skeleton (metadata, TOC and per-chapter
+//! statistics). Chapter bodies are produced by a [`ChapterLoader`] when first
+//! needed and kept in a small LRU so memory stays bounded for huge books.
+
+use super::book::display_title;
+use super::{Book, BookMetadata, Chapter, TocEntry};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Number of chapter bodies kept in memory
+const LOADED_CHAPTERS: usize = 8;
+
+/// Everything about a book except its chapter bodies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookSkeleton {
+    /// Book metadata
+    pub metadata: BookMetadata,
+
+    /// Table of contents
+    pub toc: Vec<TocEntry>,
+
+    /// Per-chapter summaries, in reading order
+    pub chapters: Vec<ChapterInfo>,
+
+    /// Original file path
+    pub source_path: PathBuf,
+
+    /// Format of the source file
+    pub format: String,
+}
+
+impl BookSkeleton {
+    /// Build a skeleton from a book's metadata, without any chapters
+    pub fn new(metadata: BookMetadata, source_path: PathBuf, format: String) -> Self {
+        Self {
+            metadata,
+            toc: Vec::new(),
+            chapters: Vec::new(),
+            source_path,
+            format,
+        }
+    }
+}
+
+/// Summary of a chapter, available without loading its body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterInfo {
+    /// Unique identifier
+    pub id: String,
+
+    /// Chapter title
+    pub title: Option<String>,
+
+    /// Chapter number (if applicable)
+    pub number: Option<usize>,
+
+    /// Order in the book
+    pub order: usize,
+
+    /// Number of words in the chapter
+    pub word_count: usize,
+
+    /// Number of content blocks in the chapter
+    pub block_count: usize,
+}
+
+impl ChapterInfo {
+    /// Get display title
+    pub fn display_title(&self) -> String {
+        display_title(self.title.as_deref(), self.number, self.order)
+    }
+}
+
+impl From<&Chapter> for ChapterInfo {
+    fn from(chapter: &Chapter) -> Self {
+        Self {
+            id: chapter.id.clone(),
+            title: chapter.title.clone(),
+            number: chapter.number,
+            order: chapter.order,
+            word_count: chapter.word_count(),
+            block_count: chapter.blocks.len(),
+        }
+    }
+}
+
+/// Source of chapter bodies for a [`LazyBook`]
+pub trait ChapterLoader: Send + Sync {
+    /// Load the chapter at `index` (reading order)
+    fn load(&self, index: usize) -> Result<Chapter>;
+}
+
+/// Loader over chapters that are already in memory
+struct InMemory(Vec<Chapter>);
+
+impl ChapterLoader for InMemory {
+    fn load(&self, index: usize) -> Result<Chapter> {
+        self.0
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Chapter {} out of range", index + 1))
+    }
+}
+
+/// A book whose chapters are loaded on demand
+pub struct LazyBook {
+    /// Metadata, TOC and chapter summaries
+    pub skeleton: BookSkeleton,
+
+    loader: Box<dyn ChapterLoader>,
+    loaded: Mutex<VecDeque<(usize, Arc<Chapter>)>>,
+}
+
+impl std::fmt::Debug for LazyBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyBook")
+            .field("skeleton", &self.skeleton)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LazyBook {
+    /// Create a lazy book from a skeleton and a chapter loader
+    pub fn new(skeleton: BookSkeleton, loader: Box<dyn ChapterLoader>) -> Self {
+        Self {
+            skeleton,
+            loader,
+            loaded: Mutex::new(VecDeque::with_capacity(LOADED_CHAPTERS)),
+        }
+    }
+
+    /// Wrap an already parsed book
+    pub fn from_book(book: Book) -> Self {
+        let skeleton = BookSkeleton {
+            chapters: book
+                .content
+                .chapters
+                .iter()
+                .map(ChapterInfo::from)
+                .collect(),
+            toc: book.content.toc,
+            metadata: book.metadata,
+            source_path: book.source_path,
+            format: book.format,
+        };
+        Self::new(skeleton, Box::new(InMemory(book.content.chapters)))
+    }
+
+    /// Get book metadata
+    pub fn metadata(&self) -> &BookMetadata {
+        &self.skeleton.metadata
+    }
+
+    /// Get chapter summaries
+    pub fn chapters(&self) -> &[ChapterInfo] {
+        &self.skeleton.chapters
+    }
+
+    /// Get total number of chapters
+    pub fn chapter_count(&self) -> usize {
+        self.skeleton.chapters.len()
+    }
+
+    /// Load a chapter body, reusing it if it was loaded recently
+    pub fn load_chapter(&self, index: usize) -> Result<Arc<Chapter>> {
+        if index >= self.chapter_count() {
+            anyhow::bail!("Chapter {} out of range", index + 1);
+        }
+
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pos) = loaded.iter().position(|(i, _)| *i == index) {
+            let entry = loaded.remove(pos).expect("position is in range");
+            let chapter = entry.1.clone();
+            loaded.push_back(entry);
+            return Ok(chapter);
+        }
+
+        let chapter = Arc::new(self.loader.load(index)?);
+        if loaded.len() >= LOADED_CHAPTERS {
+            loaded.pop_front();
+        }
+        loaded.push_back((index, chapter.clone()));
+        Ok(chapter)
+    }
+
+    /// Load a chapter body, logging failures
+    pub fn chapter(&self, index: usize) -> Option<Arc<Chapter>> {
+        match self.load_chapter(index) {
+            Ok(chapter) => Some(chapter),
+            Err(e) => {
+                tracing::warn!("Failed to load chapter {}: {}", index + 1, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::ContentBlock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl ChapterLoader for Counting {
+        fn load(&self, index: usize) -> Result<Chapter> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut chapter = Chapter::new(format!("ch{}", index), index);
+            chapter.blocks.push(ContentBlock::Paragraph {
+                text: "word".to_string(),
+                styles: vec![],
+            });
+            Ok(chapter)
+        }
+    }
+
+    #[test]
+    fn test_lazy_book_loads_on_demand() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut skeleton =
+            BookSkeleton::new(BookMetadata::default(), PathBuf::new(), "test".into());
+        skeleton.chapters = (0..20)
+            .map(|i| ChapterInfo::from(&Chapter::new(format!("ch{}", i), i)))
+            .collect();
+        let book = LazyBook::new(skeleton, Box::new(Counting(loads.clone())));
+
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+        assert_eq!(book.load_chapter(3).unwrap().id, "ch3");
+        book.load_chapter(3).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // Older chapters fall out of the LRU and are loaded again
+        for i in 0..LOADED_CHAPTERS {
+            book.load_chapter(10 + i).unwrap();
+        }
+        book.load_chapter(3).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), LOADED_CHAPTERS + 2);
+
+        assert!(book.load_chapter(20).is_err());
+    }
+}
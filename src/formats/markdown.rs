@@ -1,5 +1,7 @@
 //! Markdown format parser
 
+use super::book::{StyleType, TextStyle};
+use super::math::Formulas;
 use super::{Book, BookContent, BookMetadata, Chapter, ContentBlock, TocEntry};
 use anyhow::{Context, Result};
 use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
@@ -7,7 +9,7 @@ use std::path::Path;
 
 /// Parse a Markdown file
 pub fn parse(path: &Path) -> Result<Book> {
-    let content_str = std::fs::read_to_string(path)
+    let content_str = super::detect::read_text(path)
         .with_context(|| format!("Failed to read file: {}", path.display()))?;
 
     let (metadata, content) = parse_markdown(&content_str);
@@ -37,6 +39,7 @@ pub fn parse(path: &Path) -> Result<Book> {
 fn parse_markdown(source: &str) -> (BookMetadata, BookContent) {
     let mut metadata = BookMetadata::default();
     let mut content_start = 0;
+    let mut math = false;
 
     // Check for YAML frontmatter
     if source.starts_with("---") {
@@ -48,7 +51,7 @@ fn parse_markdown(source: &str) -> (BookMetadata, BookContent) {
             for line in frontmatter.lines() {
                 if let Some((key, value)) = line.split_once(':') {
                     let key = key.trim().to_lowercase();
-                    let value = value.trim().trim_matches(&['"', '\''][..]).to_string();
+                    let value = unquote(value.trim());
 
                     match key.as_str() {
                         "title" => metadata.title = value,
@@ -63,6 +66,11 @@ fn parse_markdown(source: &str) -> (BookMetadata, BookContent) {
                                 value.split(',').map(|s| s.trim().to_string()).collect();
                         }
                         "lang" | "language" => metadata.language = Some(value),
+                        "publisher" => metadata.publisher = Some(value),
+                        "isbn" => metadata.isbn = Some(value),
+                        "series" => metadata.series = Some(value),
+                        "series_index" => metadata.series_index = value.parse().ok(),
+                        "math" => math = value.eq_ignore_ascii_case("true"),
                         _ => {}
                     }
                 }
@@ -71,7 +79,7 @@ fn parse_markdown(source: &str) -> (BookMetadata, BookContent) {
     }
 
     let md_content = &source[content_start..];
-    let content = parse_markdown_content(md_content);
+    let content = parse_markdown_content(md_content, math);
 
     // If no title from frontmatter, try to get from first heading
     if metadata.title.is_empty() {
@@ -89,13 +97,25 @@ fn parse_markdown(source: &str) -> (BookMetadata, BookContent) {
     (metadata, content)
 }
 
-fn parse_markdown_content(source: &str) -> BookContent {
+/// Parse the document body; `math` enables `$…$` and `$$…$$` formulas
+fn parse_markdown_content(source: &str, math: bool) -> BookContent {
+    let mut formulas = Formulas::default();
+    let source = if math {
+        extract_math(source, &mut formulas)
+    } else {
+        source.to_string()
+    };
     let options = Options::all();
-    let parser = Parser::new_ext(source, options);
+    let parser = Parser::new_ext(&source, options);
 
     let mut blocks = Vec::new();
     let mut toc = Vec::new();
+    let mut anchors = Vec::new();
+    let mut heading_id = None;
     let mut current_text = String::new();
+    let mut current_styles = Vec::new();
+    // Styles still open: type, start offset and link target
+    let mut open_styles: Vec<(StyleType, usize, Option<String>)> = Vec::new();
     let mut heading_level = 0u8;
     let mut list_ordered = false;
     let mut list_items = Vec::new();
@@ -110,8 +130,9 @@ fn parse_markdown_content(source: &str) -> BookContent {
 
     for event in parser {
         match event {
-            Event::Start(Tag::Heading { level, .. }) => {
-                flush_text(&mut current_text, &mut blocks);
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
+                heading_id = id.map(|id| id.to_string());
                 heading_level = match level {
                     HeadingLevel::H1 => 1,
                     HeadingLevel::H2 => 2,
@@ -123,6 +144,7 @@ fn parse_markdown_content(source: &str) -> BookContent {
             }
             Event::End(TagEnd::Heading(_)) => {
                 let text = std::mem::take(&mut current_text);
+                current_styles.clear();
 
                 // Add to TOC
                 if heading_level <= 3 {
@@ -133,6 +155,9 @@ fn parse_markdown_content(source: &str) -> BookContent {
                     ));
                 }
 
+                // Headings are link targets by their `{#id}` or a slug
+                let anchor = heading_id.take().unwrap_or_else(|| slug(&text));
+                anchors.push((anchor, blocks.len()));
                 blocks.push(ContentBlock::Heading {
                     level: heading_level,
                     text,
@@ -140,10 +165,10 @@ fn parse_markdown_content(source: &str) -> BookContent {
             }
             Event::Start(Tag::Paragraph) => {}
             Event::End(TagEnd::Paragraph) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
             }
             Event::Start(Tag::List(ordered)) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 list_ordered = ordered.is_some();
                 list_items.clear();
             }
@@ -155,12 +180,14 @@ fn parse_markdown_content(source: &str) -> BookContent {
             }
             Event::Start(Tag::Item) => {
                 current_text.clear();
+                current_styles.clear();
             }
             Event::End(TagEnd::Item) => {
                 list_items.push(std::mem::take(&mut current_text));
+                current_styles.clear();
             }
             Event::Start(Tag::CodeBlock(kind)) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 in_code_block = true;
                 code_lang = match kind {
                     pulldown_cmark::CodeBlockKind::Fenced(lang) => {
@@ -183,7 +210,7 @@ fn parse_markdown_content(source: &str) -> BookContent {
                 in_code_block = false;
             }
             Event::Start(Tag::BlockQuote) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 in_quote = true;
                 quote_text.clear();
             }
@@ -195,7 +222,7 @@ fn parse_markdown_content(source: &str) -> BookContent {
                 in_quote = false;
             }
             Event::Start(Tag::Table(_)) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 table_headers.clear();
                 table_rows.clear();
             }
@@ -221,14 +248,16 @@ fn parse_markdown_content(source: &str) -> BookContent {
             }
             Event::Start(Tag::TableCell) => {
                 current_text.clear();
+                current_styles.clear();
             }
             Event::End(TagEnd::TableCell) => {
                 current_row.push(std::mem::take(&mut current_text));
+                current_styles.clear();
             }
             Event::Start(Tag::Image {
                 dest_url, title, ..
             }) => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 blocks.push(ContentBlock::Image {
                     src: dest_url.to_string(),
                     alt: None,
@@ -249,10 +278,51 @@ fn parse_markdown_content(source: &str) -> BookContent {
                     current_text.push_str(&text);
                 }
             }
+            Event::Start(Tag::Emphasis) => {
+                open_styles.push((StyleType::Italic, current_text.len(), None));
+            }
+            Event::Start(Tag::Strong) => {
+                open_styles.push((StyleType::Bold, current_text.len(), None));
+            }
+            Event::Start(Tag::Strikethrough) => {
+                open_styles.push((StyleType::Strikethrough, current_text.len(), None));
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                open_styles.push((
+                    StyleType::Link,
+                    current_text.len(),
+                    Some(dest_url.to_string()),
+                ));
+            }
+            Event::End(
+                TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link,
+            ) => {
+                if let Some((style_type, start, data)) = open_styles.pop() {
+                    if !in_quote && start < current_text.len() {
+                        current_styles.push(TextStyle {
+                            start,
+                            end: current_text.len(),
+                            style_type,
+                            data,
+                        });
+                    }
+                }
+            }
             Event::Code(code) => {
-                current_text.push('`');
-                current_text.push_str(&code);
-                current_text.push('`');
+                if in_quote {
+                    quote_text.push('`');
+                    quote_text.push_str(&code);
+                    quote_text.push('`');
+                } else {
+                    let start = current_text.len();
+                    current_text.push_str(&code);
+                    current_styles.push(TextStyle {
+                        start,
+                        end: current_text.len(),
+                        style_type: StyleType::Code,
+                        data: None,
+                    });
+                }
             }
             Event::SoftBreak => {
                 if in_code_block {
@@ -273,19 +343,24 @@ fn parse_markdown_content(source: &str) -> BookContent {
                 }
             }
             Event::Rule => {
-                flush_text(&mut current_text, &mut blocks);
+                flush_text(&mut current_text, &mut current_styles, &mut blocks);
                 blocks.push(ContentBlock::Separator);
             }
             _ => {}
         }
     }
 
-    flush_text(&mut current_text, &mut blocks);
+    flush_text(&mut current_text, &mut current_styles, &mut blocks);
+
+    for entry in &mut toc {
+        entry.title = formulas.resolve_text(&entry.title);
+    }
 
     // Create a single chapter for the document
     let mut chapter = Chapter::new("main".to_string(), 0);
     chapter.title = None; // Will be set from first H1 if present
-    chapter.blocks = blocks;
+    chapter.blocks = formulas.resolve_anchored(blocks, &mut anchors);
+    chapter.anchors = anchors;
 
     BookContent {
         chapters: vec![chapter],
@@ -293,12 +368,174 @@ fn parse_markdown_content(source: &str) -> BookContent {
     }
 }
 
-fn flush_text(text: &mut String, blocks: &mut Vec<ContentBlock>) {
+/// A heading's id as generated by GitHub: lowercase, with spaces as
+/// hyphens and punctuation dropped
+fn slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            c if c.is_alphanumeric() || c == '_' => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Replace `$$…$$` and `$…$` formulas with placeholders
+///
+/// Fenced code blocks, code spans and escaped dollar signs are left alone.
+/// As in Pandoc, an inline formula must not start or end with whitespace
+/// and its closing `$` must not be followed by a digit, so prices such as
+/// `$5 and $10` stay text. Formulas cannot contain backticks.
+fn extract_math(source: &str, formulas: &mut Formulas) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chunk = String::new();
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let run = marker.map_or(0, |m| trimmed.len() - trimmed.trim_start_matches(m).len());
+
+        match (fence, marker) {
+            (None, Some(m)) if run >= 3 && line.len() - trimmed.len() < 4 => {
+                out.push_str(&extract_inline_math(&std::mem::take(&mut chunk), formulas));
+                fence = Some((m, run));
+                out.push_str(line);
+            }
+            (Some((m, len)), Some(c))
+                if c == m && run >= len && trimmed[run..].trim().is_empty() =>
+            {
+                fence = None;
+                out.push_str(line);
+            }
+            (Some(_), _) => out.push_str(line),
+            (None, _) => chunk.push_str(line),
+        }
+    }
+    out.push_str(&extract_inline_math(&chunk, formulas));
+    out
+}
+
+/// Replace formulas in text outside fenced code blocks
+fn extract_inline_math(text: &str, formulas: &mut Formulas) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' => {
+                // Keep escapes, including `\$`, for the Markdown parser
+                let len = rest.chars().nth(1).map_or(1, |n| 1 + n.len_utf8());
+                out.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+            '`' => {
+                // A code span ends at the next backtick run of the same length
+                let run = rest.len() - rest.trim_start_matches('`').len();
+                let mut end = run;
+                let mut search = run;
+                while let Some(i) = rest[search..].find('`') {
+                    let start = search + i;
+                    let len = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+                    if len == run {
+                        end = start + len;
+                        break;
+                    }
+                    search = start + len;
+                }
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+            '$' if rest.starts_with("$$") => match rest[2..].find("$$") {
+                Some(end) if !rest[2..2 + end].trim().is_empty() => {
+                    out.push_str(&formulas.placeholder(&rest[2..2 + end], true));
+                    rest = &rest[end + 4..];
+                }
+                _ => {
+                    out.push_str("$$");
+                    rest = &rest[2..];
+                }
+            },
+            '$' => match inline_formula_end(&rest[1..]) {
+                Some(end) => {
+                    out.push_str(&formulas.placeholder(&rest[1..1 + end], false));
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    out.push('$');
+                    rest = &rest[1..];
+                }
+            },
+            c => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// Find the closing `$` of an inline formula, given the text after the opening one
+fn inline_formula_end(text: &str) -> Option<usize> {
+    if text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            // Code spans take precedence over formulas
+            '`' => return None,
+            '$' if !escaped => {
+                let valid = i > 0
+                    && !text[..i].ends_with(char::is_whitespace)
+                    && !text[i + 1..].starts_with(|c: char| c.is_ascii_digit())
+                    && !text[..i].contains("\n\n");
+                return valid.then_some(i);
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Strip YAML-style quotes from a frontmatter value
+fn unquote(value: &str) -> String {
+    let quoted = |q: char| value.len() >= 2 && value.starts_with(q) && value.ends_with(q);
+
+    if quoted('"') {
+        let mut out = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.extend(chars.next()),
+                c => out.push(c),
+            }
+        }
+        out
+    } else if quoted('\'') {
+        value[1..value.len() - 1].replace("''", "'")
+    } else {
+        value.trim_matches(&['"', '\''][..]).to_string()
+    }
+}
+
+fn flush_text(text: &mut String, styles: &mut Vec<TextStyle>, blocks: &mut Vec<ContentBlock>) {
     let trimmed = text.trim();
+    let leading = text.len() - text.trim_start().len();
+    let mut styles = std::mem::take(styles);
     if !trimmed.is_empty() {
+        for style in &mut styles {
+            style.start = style.start.saturating_sub(leading).min(trimmed.len());
+            style.end = style.end.saturating_sub(leading).min(trimmed.len());
+        }
+        styles.retain(|s| s.start < s.end);
+        styles.sort_by_key(|s| s.start);
         blocks.push(ContentBlock::Paragraph {
             text: trimmed.to_string(),
-            styles: Vec::new(),
+            styles,
         });
     }
     text.clear();
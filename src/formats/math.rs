@@ -0,0 +1,2002 @@
+//! Math formulas
+//!
+//! Formulas are stored as TeX source: `ContentBlock::Math` for display math
+//! and `StyleType::Math` spans for inline math, whose text is a one-line
+//! Unicode rendering of the formula. EPUB MathML is converted to TeX while
+//! parsing. Both are parsed into a small expression tree that renders as
+//! MathML for the web reader and HTML exports, and as Unicode text (with
+//! stacked fractions and scripts for display math) in the terminal.
+
+use super::book::{StyleType, TextStyle};
+use super::ContentBlock;
+use unicode_width::UnicodeWidthStr;
+
+/// Greek letters by TeX command
+const GREEK: &[(&str, char)] = &[
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ϵ'),
+    ("varepsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("vartheta", 'ϑ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("varpi", 'ϖ'),
+    ("rho", 'ρ'),
+    ("varrho", 'ϱ'),
+    ("sigma", 'σ'),
+    ("varsigma", 'ς'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'ϕ'),
+    ("varphi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Delta", 'Δ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Upsilon", 'Υ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+    ("Omega", 'Ω'),
+];
+
+/// Operators and other symbols by TeX command
+///
+/// When several commands share a symbol, the first one is used when
+/// writing TeX.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("times", "×"),
+    ("cdot", "·"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("propto", "∝"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("oint", "∮"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("star", "⋆"),
+    ("ast", "∗"),
+    ("cdots", "⋯"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("prime", "′"),
+    ("angle", "∠"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("mid", "∣"),
+    ("vdash", "⊢"),
+    ("models", "⊨"),
+    ("therefore", "∴"),
+    ("because", "∵"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("vert", "|"),
+    ("Vert", "‖"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("aleph", "ℵ"),
+    ("degree", "°"),
+];
+
+/// Accents by TeX command: combining mark and spacing form
+const ACCENTS: &[(&str, char, char)] = &[
+    ("hat", '\u{302}', '^'),
+    ("widehat", '\u{302}', '^'),
+    ("bar", '\u{304}', '¯'),
+    ("overline", '\u{305}', '‾'),
+    ("vec", '\u{20D7}', '→'),
+    ("dot", '\u{307}', '˙'),
+    ("ddot", '\u{308}', '¨'),
+    ("tilde", '\u{303}', '~'),
+    ("widetilde", '\u{303}', '~'),
+];
+
+/// Function names written upright
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "dim",
+    "ker", "deg", "gcd", "arg", "Pr",
+];
+
+/// Operators whose limits are stacked above and below in display math
+const LARGE_OPERATORS: &[&str] = &[
+    "∑", "∏", "∐", "⋃", "⋂", "lim", "liminf", "limsup", "max", "min", "sup", "inf",
+];
+
+/// Binary operators and relations, which get spaces around them
+const BINARY: &[&str] = &[
+    "=", "+", "−", "<", ">", "≤", "≥", "≠", "≈", "≡", "∼", "≃", "≅", "∝", "≪", "≫", "±", "∓", "×",
+    "·", "÷", "→", "←", "↔", "⇒", "⇐", "⇔", "⟹", "⟺", "↦", "∈", "∉", "∋", "⊂", "⊆", "⊃", "⊇", "∪",
+    "∩", "∖", "∧", "∨", "⊕", "⊗", "∣", "⊢", "⊨", ":=",
+];
+
+/// Unicode superscript forms
+const SUPERSCRIPTS: &[(char, char)] = &[
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('−', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('a', 'ᵃ'),
+    ('b', 'ᵇ'),
+    ('c', 'ᶜ'),
+    ('d', 'ᵈ'),
+    ('e', 'ᵉ'),
+    ('f', 'ᶠ'),
+    ('g', 'ᵍ'),
+    ('h', 'ʰ'),
+    ('i', 'ⁱ'),
+    ('j', 'ʲ'),
+    ('k', 'ᵏ'),
+    ('l', 'ˡ'),
+    ('m', 'ᵐ'),
+    ('n', 'ⁿ'),
+    ('o', 'ᵒ'),
+    ('p', 'ᵖ'),
+    ('r', 'ʳ'),
+    ('s', 'ˢ'),
+    ('t', 'ᵗ'),
+    ('u', 'ᵘ'),
+    ('v', 'ᵛ'),
+    ('w', 'ʷ'),
+    ('x', 'ˣ'),
+    ('y', 'ʸ'),
+    ('z', 'ᶻ'),
+    ('T', 'ᵀ'),
+    ('′', '′'),
+    ('*', '*'),
+    ('∗', '*'),
+];
+
+/// Unicode subscript forms
+const SUBSCRIPTS: &[(char, char)] = &[
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('−', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('h', 'ₕ'),
+    ('i', 'ᵢ'),
+    ('j', 'ⱼ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('o', 'ₒ'),
+    ('p', 'ₚ'),
+    ('r', 'ᵣ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('u', 'ᵤ'),
+    ('v', 'ᵥ'),
+    ('x', 'ₓ'),
+];
+
+/// Marks a formula placeholder; the formula index follows
+const INLINE_MARK: char = '\u{E000}';
+const DISPLAY_MARK: char = '\u{E002}';
+const END_MARK: char = '\u{E001}';
+
+/// A parsed formula
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Variable or function name
+    Ident(String),
+    Number(String),
+    /// Operator, relation, fence or other symbol
+    Operator(String),
+    Text(String),
+    Row(Vec<Node>),
+    Frac(Box<Node>, Box<Node>),
+    /// Radicand and optional index
+    Sqrt(Box<Node>, Option<Box<Node>>),
+    Scripts {
+        base: Box<Node>,
+        sub: Option<Box<Node>>,
+        sup: Option<Box<Node>>,
+    },
+    Matrix(Vec<Vec<Node>>),
+    /// Accented node and the accent's spacing form
+    Accent(Box<Node>, char),
+}
+
+/// Wrap several nodes in a row, or return a single node as is
+fn group(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        Node::Row(nodes)
+    }
+}
+
+// TeX parsing
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(String),
+    /// A text-like command and its raw argument
+    Word(String, String),
+    Char(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Align,
+    NewRow,
+}
+
+fn tokenize(tex: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = tex.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek().copied() {
+                Some('\\') => {
+                    chars.next();
+                    tokens.push(Token::NewRow);
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+                        name.push(c);
+                        chars.next();
+                    }
+                    if matches!(
+                        name.as_str(),
+                        "text"
+                            | "textrm"
+                            | "textit"
+                            | "textbf"
+                            | "mbox"
+                            | "mathrm"
+                            | "operatorname"
+                    ) {
+                        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                            chars.next();
+                        }
+                        if chars.peek() == Some(&'{') {
+                            chars.next();
+                            let mut depth = 1;
+                            let mut arg = String::new();
+                            for c in chars.by_ref() {
+                                match c {
+                                    '{' => depth += 1,
+                                    '}' => depth -= 1,
+                                    _ => {}
+                                }
+                                if depth == 0 {
+                                    break;
+                                }
+                                arg.push(c);
+                            }
+                            tokens.push(Token::Word(name, arg));
+                            continue;
+                        }
+                    }
+                    tokens.push(Token::Command(name));
+                }
+                Some(c) => {
+                    chars.next();
+                    tokens.push(Token::Command(c.to_string()));
+                }
+                None => {}
+            },
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '^' => tokens.push(Token::Sup),
+            '_' => tokens.push(Token::Sub),
+            '&' => tokens.push(Token::Align),
+            '~' => tokens.push(Token::Command(" ".to_string())),
+            '%' => while chars.next().is_some_and(|c| c != '\n') {},
+            c if c.is_whitespace() => {}
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+
+    tokens
+}
+
+struct TexParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl TexParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse nodes up to a closing brace, `&`, `\\`, `\right`, `\end` or
+    /// the `end` character
+    fn row(&mut self, end: Option<char>) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Close | Token::Align | Token::NewRow => break,
+                Token::Command(c) if c == "right" || c == "end" => break,
+                Token::Char(c) if Some(*c) == end => break,
+                Token::Sup | Token::Sub | Token::Char('\'') => {
+                    let script = if self.next() == Some(Token::Char('\'')) {
+                        (false, Node::Operator("′".to_string()))
+                    } else {
+                        let sub = self.tokens[self.pos - 1] == Token::Sub;
+                        (sub, self.argument())
+                    };
+                    let base = nodes.pop().unwrap_or(Node::Row(Vec::new()));
+                    nodes.push(attach(base, script.0, script.1));
+                }
+                _ => {
+                    if let Some(node) = self.atom() {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Parse a command argument: a braced group or a single token
+    fn argument(&mut self) -> Node {
+        match self.peek() {
+            Some(Token::Open) => self.atom().unwrap_or(Node::Row(Vec::new())),
+            Some(_) => loop {
+                match self.peek() {
+                    None | Some(Token::Close) => break Node::Row(Vec::new()),
+                    _ => {
+                        if let Some(node) = self.atom() {
+                            break node;
+                        }
+                    }
+                }
+            },
+            None => Node::Row(Vec::new()),
+        }
+    }
+
+    /// Read the name in a `{name}` group
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        if self.eat(&Token::Open) {
+            while let Some(Token::Char(c)) = self.peek() {
+                name.push(*c);
+                self.pos += 1;
+            }
+            self.eat(&Token::Close);
+        }
+        name
+    }
+
+    fn atom(&mut self) -> Option<Node> {
+        match self.next()? {
+            Token::Open => {
+                let nodes = self.row(None);
+                self.eat(&Token::Close);
+                Some(group(nodes))
+            }
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(Token::Char(c)) = self.peek() {
+                    let digit_follows = matches!(
+                        self.tokens.get(self.pos + 1),
+                        Some(Token::Char(d)) if d.is_ascii_digit()
+                    );
+                    if c.is_ascii_digit() || (*c == '.' && digit_follows) {
+                        number.push(*c);
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                if number == "." {
+                    Some(Node::Operator(number))
+                } else {
+                    Some(Node::Number(number))
+                }
+            }
+            Token::Char(c) if c.is_alphabetic() => Some(Node::Ident(c.to_string())),
+            Token::Char('-') => Some(Node::Operator("−".to_string())),
+            Token::Char('*') => Some(Node::Operator("∗".to_string())),
+            Token::Char(c) => Some(Node::Operator(c.to_string())),
+            Token::Word(command, arg) => match command.as_str() {
+                "mathrm" | "operatorname" => Some(Node::Ident(arg)),
+                _ => Some(Node::Text(arg)),
+            },
+            Token::Command(name) => self.command(&name),
+            // Stray structure tokens are dropped
+            Token::Close | Token::Align | Token::NewRow | Token::Sup | Token::Sub => None,
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Option<Node> {
+        if let Some((_, c)) = GREEK.iter().find(|(n, _)| *n == name) {
+            return Some(Node::Ident(c.to_string()));
+        }
+        if let Some((_, s)) = SYMBOLS.iter().find(|(n, _)| *n == name) {
+            return Some(Node::Operator(s.to_string()));
+        }
+        if FUNCTIONS.contains(&name) {
+            return Some(Node::Ident(name.to_string()));
+        }
+        if let Some((_, _, spacing)) = ACCENTS.iter().find(|(n, _, _)| *n == name) {
+            return Some(Node::Accent(Box::new(self.argument()), *spacing));
+        }
+
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.argument();
+                let den = self.argument();
+                Some(Node::Frac(Box::new(num), Box::new(den)))
+            }
+            "binom" => {
+                let top = self.argument();
+                let bottom = self.argument();
+                Some(Node::Row(vec![
+                    Node::Operator("(".to_string()),
+                    Node::Matrix(vec![vec![top], vec![bottom]]),
+                    Node::Operator(")".to_string()),
+                ]))
+            }
+            "sqrt" => {
+                let index = if self.eat(&Token::Char('[')) {
+                    let index = group(self.row(Some(']')));
+                    self.eat(&Token::Char(']'));
+                    Some(Box::new(index))
+                } else {
+                    None
+                };
+                Some(Node::Sqrt(Box::new(self.argument()), index))
+            }
+            "left" => {
+                let mut nodes: Vec<Node> = self.delimiter().into_iter().collect();
+                nodes.extend(self.row(None));
+                if self.eat(&Token::Command("right".to_string())) {
+                    nodes.extend(self.delimiter());
+                }
+                Some(Node::Row(nodes))
+            }
+            "begin" => {
+                let env = self.name();
+                if env == "array" {
+                    self.name();
+                }
+                let matrix = self.matrix();
+                if self.eat(&Token::Command("end".to_string())) {
+                    self.name();
+                }
+                let fences = match env.trim_end_matches('*') {
+                    "pmatrix" => Some(("(", ")")),
+                    "bmatrix" => Some(("[", "]")),
+                    "Bmatrix" => Some(("{", "}")),
+                    "vmatrix" => Some(("|", "|")),
+                    "Vmatrix" => Some(("‖", "‖")),
+                    "cases" => Some(("{", "")),
+                    _ => None,
+                };
+                Some(match fences {
+                    Some((open, close)) => {
+                        let mut nodes = vec![Node::Operator(open.to_string()), matrix];
+                        if !close.is_empty() {
+                            nodes.push(Node::Operator(close.to_string()));
+                        }
+                        Node::Row(nodes)
+                    }
+                    None => matrix,
+                })
+            }
+            "mathbb" => Some(match self.argument() {
+                Node::Ident(s) => Node::Ident(s.chars().map(double_struck).collect()),
+                other => other,
+            }),
+            "mathbf" | "mathit" | "mathsf" | "mathtt" | "mathcal" | "mathscr" | "mathfrak"
+            | "boldsymbol" | "bm" => Some(self.argument()),
+            "," | ";" | ":" | " " | "quad" | "qquad" | "enspace" => {
+                Some(Node::Text(" ".to_string()))
+            }
+            "{" | "}" | "#" | "$" | "%" | "&" | "_" => Some(Node::Operator(name.to_string())),
+            "|" => Some(Node::Operator("‖".to_string())),
+            "!" | "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "big"
+            | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => None,
+            "end" | "right" => None,
+            _ => Some(Node::Ident(name.to_string())),
+        }
+    }
+
+    /// Parse the delimiter after `\left` or `\right`
+    fn delimiter(&mut self) -> Option<Node> {
+        match self.next()? {
+            Token::Char('.') => None,
+            Token::Char(c) => Some(Node::Operator(c.to_string())),
+            Token::Command(name) => self.command(&name),
+            _ => None,
+        }
+    }
+
+    /// Parse rows of `&`-separated cells up to `\end`
+    fn matrix(&mut self) -> Node {
+        let mut rows = Vec::new();
+        loop {
+            let mut cells = Vec::new();
+            loop {
+                cells.push(group(self.row(None)));
+                if !self.eat(&Token::Align) {
+                    break;
+                }
+            }
+            rows.push(cells);
+            if !self.eat(&Token::NewRow) {
+                break;
+            }
+        }
+        // A trailing `\\` leaves an empty last row
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|r| r.len() == 1 && r[0] == Node::Row(Vec::new()))
+        {
+            rows.pop();
+        }
+        Node::Matrix(rows)
+    }
+}
+
+fn attach(base: Node, is_sub: bool, script: Node) -> Node {
+    match base {
+        Node::Scripts { base, sub, sup }
+            if (is_sub && sub.is_none()) || (!is_sub && sup.is_none()) =>
+        {
+            let script = Some(Box::new(script));
+            if is_sub {
+                Node::Scripts {
+                    base,
+                    sub: script,
+                    sup,
+                }
+            } else {
+                Node::Scripts {
+                    base,
+                    sub,
+                    sup: script,
+                }
+            }
+        }
+        base => {
+            let script = Some(Box::new(script));
+            let (sub, sup) = if is_sub {
+                (script, None)
+            } else {
+                (None, script)
+            };
+            Node::Scripts {
+                base: Box::new(base),
+                sub,
+                sup,
+            }
+        }
+    }
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        c if c.is_ascii_uppercase() => {
+            char::from_u32(0x1D538 + (c as u32 - 'A' as u32)).unwrap_or(c)
+        }
+        c => c,
+    }
+}
+
+fn parse_tex(tex: &str) -> Node {
+    let mut parser = TexParser {
+        tokens: tokenize(tex),
+        pos: 0,
+    };
+    let mut nodes = Vec::new();
+    loop {
+        nodes.extend(parser.row(None));
+        // Skip anything the row parser stopped at outside its context
+        if parser.next().is_none() {
+            break;
+        }
+    }
+    group(nodes)
+}
+
+// TeX output
+
+fn to_tex(node: &Node) -> String {
+    match node {
+        Node::Ident(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => GREEK
+                    .iter()
+                    .find(|(_, g)| *g == c)
+                    .map(|(name, _)| format!("\\{}", name))
+                    .unwrap_or_else(|| s.clone()),
+                _ if FUNCTIONS.contains(&s.as_str()) => format!("\\{}", s),
+                _ => format!("\\mathrm{{{}}}", s),
+            }
+        }
+        Node::Number(s) => s.clone(),
+        Node::Operator(s) => match s.as_str() {
+            "−" => "-".to_string(),
+            "∗" => "*".to_string(),
+            "{" | "}" | "#" | "$" | "%" | "&" | "_" => format!("\\{}", s),
+            "|" | "(" | ")" | "[" | "]" => s.clone(),
+            _ => SYMBOLS
+                .iter()
+                .find(|(_, sym)| sym == s)
+                .map(|(name, _)| format!("\\{}", name))
+                .unwrap_or_else(|| s.clone()),
+        },
+        Node::Text(s) if s.trim().is_empty() => "\\,".to_string(),
+        Node::Text(s) => format!("\\text{{{}}}", s),
+        Node::Row(nodes) => nodes.iter().map(to_tex).collect::<Vec<_>>().join(" "),
+        Node::Frac(num, den) => format!("\\frac{{{}}}{{{}}}", to_tex(num), to_tex(den)),
+        Node::Sqrt(x, None) => format!("\\sqrt{{{}}}", to_tex(x)),
+        Node::Sqrt(x, Some(index)) => {
+            format!("\\sqrt[{}]{{{}}}", to_tex(index), to_tex(x))
+        }
+        Node::Scripts { base, sub, sup } => {
+            let mut out = match base.as_ref() {
+                Node::Row(_) | Node::Scripts { .. } | Node::Frac(..) => {
+                    format!("{{{}}}", to_tex(base))
+                }
+                _ => to_tex(base),
+            };
+            if let Some(sub) = sub {
+                out.push_str(&format!("_{{{}}}", to_tex(sub)));
+            }
+            if let Some(sup) = sup {
+                out.push_str(&format!("^{{{}}}", to_tex(sup)));
+            }
+            out
+        }
+        Node::Matrix(rows) => format!(
+            "\\begin{{matrix}} {} \\end{{matrix}}",
+            rows.iter()
+                .map(|r| r.iter().map(to_tex).collect::<Vec<_>>().join(" & "))
+                .collect::<Vec<_>>()
+                .join(" \\\\ ")
+        ),
+        Node::Accent(x, spacing) => {
+            let name = ACCENTS
+                .iter()
+                .find(|(_, _, s)| s == spacing)
+                .map(|(name, _, _)| *name)
+                .unwrap_or("hat");
+            format!("\\{}{{{}}}", name, to_tex(x))
+        }
+    }
+}
+
+// MathML input
+
+/// A parsed XML node
+#[derive(Debug)]
+enum Xml {
+    Element {
+        name: String,
+        attrs: Vec<(String, String)>,
+        children: Vec<Xml>,
+    },
+    Text(String),
+}
+
+impl Xml {
+    fn attr(&self, key: &str) -> Option<&str> {
+        match self {
+            Xml::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+            Xml::Text(_) => None,
+        }
+    }
+
+    fn elements(&self) -> Vec<&Xml> {
+        match self {
+            Xml::Element { children, .. } => children
+                .iter()
+                .filter(|c| matches!(c, Xml::Element { .. }))
+                .collect(),
+            Xml::Text(_) => Vec::new(),
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Xml::Element { children, .. } => children.iter().map(|c| c.text()).collect(),
+            Xml::Text(text) => text.clone(),
+        }
+    }
+
+    /// Find the first element with this local name, depth first
+    fn find(&self, local: &str) -> Option<&Xml> {
+        match self {
+            Xml::Element { name, children, .. } => {
+                if name == local {
+                    return Some(self);
+                }
+                children.iter().find_map(|c| c.find(local))
+            }
+            Xml::Text(_) => None,
+        }
+    }
+}
+
+/// An element still being parsed: name, attributes and children so far
+type OpenElement = (String, Vec<(String, String)>, Vec<Xml>);
+
+fn parse_xml(source: &str) -> Vec<Xml> {
+    let token_re = regex::Regex::new(
+        r"(?s)<!--.*?-->|<[!?][^>]*>|<(/?)([A-Za-z][\w:.-]*)([^>]*?)(/?)>|([^<]+)",
+    )
+    .unwrap();
+    let attr_re = regex::Regex::new(r#"([\w:.-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+    let mut stack: Vec<OpenElement> = vec![(String::new(), Vec::new(), Vec::new())];
+
+    for cap in token_re.captures_iter(source) {
+        if let Some(text) = cap.get(5) {
+            stack
+                .last_mut()
+                .unwrap()
+                .2
+                .push(Xml::Text(decode_entities(text.as_str())));
+            continue;
+        }
+        let Some(name) = cap.get(2) else { continue };
+        let name = local_name(name.as_str()).to_string();
+
+        if !cap[1].is_empty() {
+            // Close the matching element, and any left open inside it
+            if let Some(depth) = stack.iter().rposition(|(n, _, _)| *n == name) {
+                while stack.len() > depth.max(1) {
+                    let (name, attrs, children) = stack.pop().unwrap();
+                    stack.last_mut().unwrap().2.push(Xml::Element {
+                        name,
+                        attrs,
+                        children,
+                    });
+                }
+            }
+            continue;
+        }
+
+        let attrs = attr_re
+            .captures_iter(&cap[3])
+            .map(|a| {
+                let value = a.get(2).or(a.get(3)).map(|v| v.as_str()).unwrap_or("");
+                (local_name(&a[1]).to_string(), decode_entities(value))
+            })
+            .collect();
+        if cap[4].is_empty() {
+            stack.push((name, attrs, Vec::new()));
+        } else {
+            stack.last_mut().unwrap().2.push(Xml::Element {
+                name,
+                attrs,
+                children: Vec::new(),
+            });
+        }
+    }
+
+    while stack.len() > 1 {
+        let (name, attrs, children) = stack.pop().unwrap();
+        stack.last_mut().unwrap().2.push(Xml::Element {
+            name,
+            attrs,
+            children,
+        });
+    }
+    stack
+        .pop()
+        .map(|(_, _, children)| children)
+        .unwrap_or_default()
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn decode_entities(text: &str) -> String {
+    let entity_re = regex::Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|[A-Za-z]+);").unwrap();
+    entity_re
+        .replace_all(text, |cap: &regex::Captures| {
+            let entity = &cap[1];
+            let code = if let Some(hex) = entity.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok()
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok()
+            } else {
+                None
+            };
+            if let Some(code) = code {
+                return char::from_u32(code).map(String::from).unwrap_or_default();
+            }
+            match entity {
+                "lt" => "<",
+                "gt" => ">",
+                "amp" => "&",
+                "quot" => "\"",
+                "apos" => "'",
+                "nbsp" => " ",
+                "minus" => "−",
+                "times" => "×",
+                "infin" => "∞",
+                "le" => "≤",
+                "ge" => "≥",
+                "ne" => "≠",
+                "InvisibleTimes" | "it" | "ApplyFunction" | "af" | "InvisibleComma" | "ic" => "",
+                _ => return cap[0].to_string(),
+            }
+            .to_string()
+        })
+        .into_owned()
+}
+
+/// Convert a MathML element to TeX
+///
+/// A TeX annotation, as written by many converters, is used as is.
+pub fn mathml_to_tex(mathml: &str) -> Option<String> {
+    let nodes = parse_xml(mathml);
+    let math = nodes.iter().find_map(|n| n.find("math"))?;
+
+    if let Some(tex) = math_annotation(math) {
+        return Some(tex);
+    }
+
+    let tex = to_tex(&mathml_row(&math.elements()));
+    if tex.trim().is_empty() {
+        None
+    } else {
+        Some(tex)
+    }
+}
+
+fn math_annotation(math: &Xml) -> Option<String> {
+    let mut found = None;
+    let mut stack = vec![math];
+    while let Some(node) = stack.pop() {
+        if let Xml::Element { name, children, .. } = node {
+            if name == "annotation"
+                && node
+                    .attr("encoding")
+                    .is_some_and(|e| e.to_lowercase().contains("tex"))
+            {
+                found = Some(node.text().trim().to_string());
+                break;
+            }
+            stack.extend(children.iter());
+        }
+    }
+    found.filter(|tex| !tex.is_empty())
+}
+
+fn mathml_row(children: &[&Xml]) -> Node {
+    group(children.iter().filter_map(|c| mathml_node(c)).collect())
+}
+
+fn mathml_node(xml: &Xml) -> Option<Node> {
+    let Xml::Element { name, .. } = xml else {
+        return None;
+    };
+    let children = xml.elements();
+    let child = |i: usize| {
+        children
+            .get(i)
+            .and_then(|c| mathml_node(c))
+            .map(Box::new)
+            .unwrap_or_else(|| Box::new(Node::Row(Vec::new())))
+    };
+    // Invisible operators carry no visible content
+    let text = || {
+        xml.text()
+            .chars()
+            .filter(|c| !matches!(c, '\u{2061}'..='\u{2064}'))
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+
+    match name.as_str() {
+        "mi" => Some(text()).filter(|t| !t.is_empty()).map(Node::Ident),
+        "mn" => Some(text()).filter(|t| !t.is_empty()).map(Node::Number),
+        "mo" => Some(text())
+            .filter(|t| !t.is_empty())
+            .map(|t| Node::Operator(if t == "-" { "−".to_string() } else { t })),
+        "mtext" | "ms" => Some(Node::Text(xml.text())),
+        "mspace" => Some(Node::Text(" ".to_string())),
+        "mfrac" => Some(Node::Frac(child(0), child(1))),
+        "msqrt" => Some(Node::Sqrt(Box::new(mathml_row(&children)), None)),
+        "mroot" => Some(Node::Sqrt(child(0), Some(child(1)))),
+        "msub" | "munder" => Some(Node::Scripts {
+            base: child(0),
+            sub: Some(child(1)),
+            sup: None,
+        }),
+        "mover" => {
+            let over = child(1);
+            let accent = match over.as_ref() {
+                Node::Operator(s) => {
+                    let mut chars = s.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => ACCENTS
+                            .iter()
+                            .find(|(_, combining, spacing)| *combining == c || *spacing == c)
+                            .map(|(_, _, spacing)| *spacing),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            Some(match accent {
+                Some(spacing) if xml.attr("accent") != Some("false") => {
+                    Node::Accent(child(0), spacing)
+                }
+                _ => Node::Scripts {
+                    base: child(0),
+                    sub: None,
+                    sup: Some(over),
+                },
+            })
+        }
+        "msup" => Some(Node::Scripts {
+            base: child(0),
+            sub: None,
+            sup: Some(child(1)),
+        }),
+        "msubsup" | "munderover" => Some(Node::Scripts {
+            base: child(0),
+            sub: Some(child(1)),
+            sup: Some(child(2)),
+        }),
+        "mtable" => Some(Node::Matrix(
+            children
+                .iter()
+                .map(|row| {
+                    row.elements()
+                        .iter()
+                        .map(|cell| mathml_row(&cell.elements()))
+                        .collect()
+                })
+                .collect(),
+        )),
+        "mfenced" => {
+            let open = xml.attr("open").unwrap_or("(");
+            let close = xml.attr("close").unwrap_or(")");
+            let separator = xml.attr("separators").unwrap_or(",").trim();
+            let mut nodes = vec![Node::Operator(open.to_string())];
+            for (i, c) in children.iter().enumerate() {
+                if i > 0 && !separator.is_empty() {
+                    nodes.push(Node::Operator(separator.chars().take(1).collect()));
+                }
+                nodes.extend(mathml_node(c));
+            }
+            nodes.push(Node::Operator(close.to_string()));
+            Some(Node::Row(nodes))
+        }
+        "semantics" | "mmultiscripts" => children.first().and_then(|c| mathml_node(c)),
+        "annotation" | "annotation-xml" | "none" | "mprescripts" | "mphantom" => None,
+        _ => Some(mathml_row(&children)),
+    }
+}
+
+// MathML output
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render TeX as a MathML element
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let mut out = String::from("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"");
+    if display {
+        out.push_str(" display=\"block\"");
+    }
+    out.push('>');
+    write_mathml(&parse_tex(tex), display, &mut out);
+    out.push_str("</math>");
+    out
+}
+
+fn write_mathml(node: &Node, display: bool, out: &mut String) {
+    match node {
+        Node::Ident(s) => out.push_str(&format!("<mi>{}</mi>", escape_xml(s))),
+        Node::Number(s) => out.push_str(&format!("<mn>{}</mn>", escape_xml(s))),
+        Node::Operator(s) => out.push_str(&format!("<mo>{}</mo>", escape_xml(s))),
+        Node::Text(s) => out.push_str(&format!("<mtext>{}</mtext>", escape_xml(s))),
+        Node::Row(nodes) => {
+            out.push_str("<mrow>");
+            for node in nodes {
+                write_mathml(node, display, out);
+            }
+            out.push_str("</mrow>");
+        }
+        Node::Frac(num, den) => {
+            out.push_str("<mfrac>");
+            write_mathml(num, display, out);
+            write_mathml(den, display, out);
+            out.push_str("</mfrac>");
+        }
+        Node::Sqrt(x, None) => {
+            out.push_str("<msqrt>");
+            write_mathml(x, display, out);
+            out.push_str("</msqrt>");
+        }
+        Node::Sqrt(x, Some(index)) => {
+            out.push_str("<mroot>");
+            write_mathml(x, display, out);
+            write_mathml(index, display, out);
+            out.push_str("</mroot>");
+        }
+        Node::Scripts { base, sub, sup } => {
+            let (sub_tag, sup_tag, both_tag) = if display && is_large_operator(base) {
+                ("munder", "mover", "munderover")
+            } else {
+                ("msub", "msup", "msubsup")
+            };
+            let tag = match (sub, sup) {
+                (Some(_), Some(_)) => both_tag,
+                (Some(_), None) => sub_tag,
+                _ => sup_tag,
+            };
+            out.push_str(&format!("<{}>", tag));
+            write_mathml(base, display, out);
+            for script in [sub, sup].into_iter().flatten() {
+                write_mathml(script, display, out);
+            }
+            out.push_str(&format!("</{}>", tag));
+        }
+        Node::Matrix(rows) => {
+            out.push_str("<mtable>");
+            for row in rows {
+                out.push_str("<mtr>");
+                for cell in row {
+                    out.push_str("<mtd>");
+                    write_mathml(cell, display, out);
+                    out.push_str("</mtd>");
+                }
+                out.push_str("</mtr>");
+            }
+            out.push_str("</mtable>");
+        }
+        Node::Accent(x, spacing) => {
+            out.push_str("<mover accent=\"true\">");
+            write_mathml(x, display, out);
+            out.push_str(&format!(
+                "<mo>{}</mo></mover>",
+                escape_xml(&spacing.to_string())
+            ));
+        }
+    }
+}
+
+fn is_large_operator(node: &Node) -> bool {
+    match node {
+        Node::Operator(s) | Node::Ident(s) => LARGE_OPERATORS.contains(&s.as_str()),
+        _ => false,
+    }
+}
+
+// Unicode output
+
+/// Whether each node of a row gets spaces around it
+fn spaced(nodes: &[Node]) -> Vec<bool> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| match node {
+            Node::Operator(op) if BINARY.contains(&op.as_str()) && i > 0 => !matches!(
+                &nodes[i - 1],
+                Node::Operator(prev)
+                    if !matches!(prev.as_str(), ")" | "]" | "}" | "|" | "‖" | "⟩" | "!" | "′")
+            ),
+            _ => false,
+        })
+        .collect()
+}
+
+/// Map every character to a script form, if possible
+fn script_chars(text: &str, table: &[(char, char)]) -> Option<String> {
+    text.chars()
+        .map(|c| table.iter().find(|(plain, _)| *plain == c).map(|(_, s)| *s))
+        .collect()
+}
+
+/// Render a formula on one line
+///
+/// Tight rendering leaves out the spaces around operators, for scripts.
+fn linear(node: &Node, tight: bool) -> String {
+    match node {
+        Node::Ident(s) | Node::Number(s) | Node::Text(s) => s.clone(),
+        Node::Operator(s) => s.clone(),
+        Node::Row(nodes) => {
+            let mut out = String::new();
+            for (node, spaced) in nodes.iter().zip(spaced(nodes)) {
+                let text = linear(node, tight);
+                if spaced && !tight {
+                    out.push_str(&format!(" {} ", text));
+                } else if !tight && text == "," {
+                    out.push_str(", ");
+                } else {
+                    out.push_str(&text);
+                }
+            }
+            out.trim_end().to_string()
+        }
+        Node::Frac(num, den) => {
+            if let (Node::Number(n), Node::Number(d)) = (num.as_ref(), den.as_ref()) {
+                let vulgar = match (n.as_str(), d.as_str()) {
+                    ("1", "2") => Some("½"),
+                    ("1", "3") => Some("⅓"),
+                    ("2", "3") => Some("⅔"),
+                    ("1", "4") => Some("¼"),
+                    ("3", "4") => Some("¾"),
+                    _ => None,
+                };
+                if let Some(vulgar) = vulgar {
+                    return vulgar.to_string();
+                }
+            }
+            format!("{}/{}", operand(num, tight), operand(den, tight))
+        }
+        Node::Sqrt(x, index) => {
+            let root = match index.as_deref().map(|i| linear(i, true)) {
+                None => "√".to_string(),
+                Some(i) if i == "3" => "∛".to_string(),
+                Some(i) if i == "4" => "∜".to_string(),
+                Some(i) => match script_chars(&i, SUPERSCRIPTS) {
+                    Some(i) => format!("{}√", i),
+                    None => format!("({})√", i),
+                },
+            };
+            format!("{}{}", root, operand(x, tight))
+        }
+        Node::Scripts { base, sub, sup } => {
+            let mut out = operand(base, tight);
+            for (script, table, mark) in [(sub, SUBSCRIPTS, '_'), (sup, SUPERSCRIPTS, '^')] {
+                if let Some(script) = script {
+                    let text = linear(script, true);
+                    match script_chars(&text, table) {
+                        Some(s) => out.push_str(&s),
+                        None if text.chars().count() == 1 => {
+                            out.push(mark);
+                            out.push_str(&text);
+                        }
+                        None => out.push_str(&format!("{}({})", mark, text)),
+                    }
+                }
+            }
+            out
+        }
+        Node::Matrix(rows) => rows
+            .iter()
+            .map(|r| {
+                r.iter()
+                    .map(|c| linear(c, tight))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+        Node::Accent(x, spacing) => {
+            let combining = ACCENTS
+                .iter()
+                .find(|(_, _, s)| s == spacing)
+                .map(|(_, c, _)| *c)
+                .unwrap_or('\u{302}');
+            format!("{}{}", operand(x, tight), combining)
+        }
+    }
+}
+
+/// Render a node on one line, in parentheses if it has several parts
+fn operand(node: &Node, tight: bool) -> String {
+    let text = linear(node, tight);
+    let fenced = matches!(
+        node,
+        Node::Row(nodes) if matches!(nodes.first(), Some(Node::Operator(o)) if o == "(" || o == "[")
+    );
+    match node {
+        Node::Row(nodes) if nodes.len() > 1 && !fenced => format!("({})", text),
+        Node::Frac(..) => format!("({})", text),
+        _ => text,
+    }
+}
+
+/// Render TeX as one line of Unicode text
+pub fn to_unicode(tex: &str) -> String {
+    linear(&parse_tex(tex), false)
+}
+
+/// A block of text lines with a baseline row, for display layout
+struct Layout {
+    lines: Vec<String>,
+    baseline: usize,
+}
+
+impl Layout {
+    fn text(text: String) -> Self {
+        Layout {
+            lines: vec![text],
+            baseline: 0,
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.lines.iter().map(|l| l.width()).max().unwrap_or(0)
+    }
+
+    fn below(&self) -> usize {
+        self.lines.len() - self.baseline - 1
+    }
+
+    /// Stack blocks vertically, centered, with the given baseline
+    fn stack(parts: Vec<Layout>, baseline: usize) -> Self {
+        let width = parts.iter().map(|p| p.width()).max().unwrap_or(0);
+        let mut lines = Vec::new();
+        for part in parts {
+            for line in part.lines {
+                lines.push(pad(&center(&line, width), width));
+            }
+        }
+        Layout { lines, baseline }
+    }
+
+    /// Join blocks side by side, aligned on their baselines
+    fn join(parts: Vec<Layout>) -> Self {
+        let above = parts.iter().map(|p| p.baseline).max().unwrap_or(0);
+        let below = parts.iter().map(|p| p.below()).max().unwrap_or(0);
+        let mut lines = vec![String::new(); above + below + 1];
+        for part in &parts {
+            let width = part.width();
+            let offset = above - part.baseline;
+            for (row, line) in lines.iter_mut().enumerate() {
+                match row.checked_sub(offset).and_then(|r| part.lines.get(r)) {
+                    Some(text) => line.push_str(&pad(text, width)),
+                    None => line.push_str(&" ".repeat(width)),
+                }
+            }
+        }
+        Layout {
+            lines,
+            baseline: above,
+        }
+    }
+}
+
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
+
+fn center(text: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        " ".repeat(width.saturating_sub(text.width()) / 2),
+        text
+    )
+}
+
+/// A fence stretched to `height` rows
+fn tall_fence(fence: &str, height: usize, baseline: usize) -> Option<Layout> {
+    let (top, middle, bottom, center) = match fence {
+        "(" => ('⎛', '⎜', '⎝', '⎜'),
+        ")" => ('⎞', '⎟', '⎠', '⎟'),
+        "[" => ('⎡', '⎢', '⎣', '⎢'),
+        "]" => ('⎤', '⎥', '⎦', '⎥'),
+        "{" => ('⎧', '⎪', '⎩', '⎨'),
+        "}" => ('⎫', '⎪', '⎭', '⎬'),
+        "|" | "∣" => ('│', '│', '│', '│'),
+        "‖" => ('‖', '‖', '‖', '‖'),
+        _ => return None,
+    };
+    let lines = (0..height)
+        .map(|row| {
+            if row == 0 {
+                top
+            } else if row == height - 1 {
+                bottom
+            } else if row == height / 2 {
+                center
+            } else {
+                middle
+            }
+            .to_string()
+        })
+        .collect();
+    Some(Layout { lines, baseline })
+}
+
+fn layout(node: &Node) -> Layout {
+    match node {
+        Node::Row(nodes) => {
+            let spaced = spaced(nodes);
+            let mut parts: Vec<Option<Layout>> = nodes
+                .iter()
+                .zip(&spaced)
+                .map(|(node, spaced)| match node {
+                    Node::Operator(op) if tall_fence(op, 2, 0).is_some() => None,
+                    Node::Operator(op) if *spaced => Some(Layout::text(format!(" {} ", op))),
+                    Node::Operator(op) if op == "," => Some(Layout::text(", ".to_string())),
+                    node => Some(layout(node)),
+                })
+                .collect();
+
+            // Fences stretch to the height of the rest of the row
+            let above = parts
+                .iter()
+                .flatten()
+                .map(|p| p.baseline)
+                .max()
+                .unwrap_or(0);
+            let below = parts.iter().flatten().map(|p| p.below()).max().unwrap_or(0);
+            for (part, node) in parts.iter_mut().zip(nodes) {
+                if part.is_none() {
+                    if let Node::Operator(op) = node {
+                        *part = if above + below > 0 {
+                            tall_fence(op, above + below + 1, above)
+                        } else {
+                            Some(Layout::text(op.clone()))
+                        };
+                    }
+                }
+            }
+            Layout::join(parts.into_iter().flatten().collect())
+        }
+        Node::Frac(num, den) => {
+            let num = layout(num);
+            let den = layout(den);
+            let width = num.width().max(den.width()) + 2;
+            let baseline = num.lines.len();
+            Layout::stack(vec![num, Layout::text("─".repeat(width)), den], baseline)
+        }
+        Node::Sqrt(x, index) => {
+            let inner = layout(x);
+            let width = inner.width();
+            let index = index
+                .as_deref()
+                .map(|i| linear(i, true))
+                .map(|i| script_chars(&i, SUPERSCRIPTS).unwrap_or(i))
+                .unwrap_or_default();
+            let indent = " ".repeat(index.width());
+            let last = inner.lines.len() - 1;
+            let mut lines = vec![format!("{} {}", indent, "_".repeat(width))];
+            for (row, line) in inner.lines.iter().enumerate() {
+                if row == last {
+                    lines.push(format!("{}√{}", index, pad(line, width)));
+                } else {
+                    lines.push(format!("{}│{}", indent, pad(line, width)));
+                }
+            }
+            Layout {
+                lines,
+                baseline: inner.baseline + 1,
+            }
+        }
+        Node::Scripts { base, sub, sup } => {
+            // Scripts that fit on one line are set tight, without spaces
+            let script = |node: &Node| match layout(node) {
+                l if l.lines.len() == 1 => Layout::text(linear(node, true)),
+                l => l,
+            };
+            let sub_layout = sub.as_deref().map(script);
+            let sup_layout = sup.as_deref().map(script);
+
+            if is_large_operator(base) && !matches!(base.as_ref(), Node::Operator(op) if op == "∫")
+            {
+                let base = layout(base);
+                let mut parts = Vec::new();
+                let mut baseline = base.baseline;
+                if let Some(sup) = sup_layout {
+                    baseline += sup.lines.len();
+                    parts.push(sup);
+                }
+                parts.push(base);
+                parts.extend(sub_layout);
+                return Layout::stack(parts, baseline);
+            }
+
+            // Short scripts use Unicode sub- and superscript characters
+            let inline = [(sub, SUBSCRIPTS), (sup, SUPERSCRIPTS)]
+                .iter()
+                .map(|(script, table)| match script {
+                    Some(s) => script_chars(&linear(s, true), table),
+                    None => Some(String::new()),
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(scripts) = inline {
+                return Layout::join(vec![layout(base), Layout::text(scripts.concat())]);
+            }
+
+            let base = layout(base);
+            let baseline = sup_layout.as_ref().map_or(0, |s| s.lines.len()) + base.baseline;
+            let sup_height = sup_layout.as_ref().map_or(0, |s| s.lines.len());
+            let sub_height = sub_layout.as_ref().map_or(0, |s| s.lines.len());
+            let empty = |height: usize| Layout {
+                lines: vec![String::new(); height],
+                baseline: 0,
+            };
+
+            let mut base_column = vec![empty(sup_height), base, empty(sub_height)];
+            base_column.retain(|l| !l.lines.is_empty());
+            let base_column = Layout {
+                lines: base_column.into_iter().flat_map(|l| l.lines).collect(),
+                baseline: 0,
+            };
+            let script_column = Layout {
+                lines: sup_layout
+                    .map(|s| s.lines)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(std::iter::repeat_n(
+                        String::new(),
+                        base_column.lines.len() - sup_height - sub_height,
+                    ))
+                    .chain(sub_layout.map(|s| s.lines).unwrap_or_default())
+                    .collect(),
+                baseline: 0,
+            };
+            let mut joined = Layout::join(vec![base_column, script_column]);
+            joined.baseline = baseline;
+            joined
+        }
+        Node::Matrix(rows) => {
+            let cells: Vec<Vec<Layout>> = rows
+                .iter()
+                .map(|r| r.iter().map(layout).collect())
+                .collect();
+            let columns = cells.iter().map(|r| r.len()).max().unwrap_or(0);
+            let widths: Vec<usize> = (0..columns)
+                .map(|c| {
+                    cells
+                        .iter()
+                        .filter_map(|r| r.get(c))
+                        .map(|l| l.width())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+
+            let mut lines = Vec::new();
+            for row in cells {
+                let mut parts = Vec::new();
+                for (c, cell) in row.into_iter().enumerate() {
+                    if c > 0 {
+                        parts.push(Layout::text("  ".to_string()));
+                    }
+                    let width = widths[c];
+                    parts.push(Layout {
+                        lines: cell.lines.iter().map(|l| pad(l, width)).collect(),
+                        baseline: cell.baseline,
+                    });
+                }
+                lines.extend(Layout::join(parts).lines);
+            }
+            let baseline = lines.len().saturating_sub(1) / 2;
+            Layout { lines, baseline }
+        }
+        Node::Accent(x, spacing) => {
+            let text = linear(x, true);
+            if text.chars().count() == 1 {
+                Layout::text(linear(node, true))
+            } else {
+                let inner = layout(x);
+                let baseline = inner.baseline + 1;
+                Layout::stack(vec![Layout::text(spacing.to_string()), inner], baseline)
+            }
+        }
+        node => Layout::text(linear(node, false)),
+    }
+}
+
+/// Lay out TeX as display math, one string per line
+pub fn to_lines(tex: &str) -> Vec<String> {
+    layout(&parse_tex(tex))
+        .lines
+        .into_iter()
+        .map(|l| l.trim_end().to_string())
+        .collect()
+}
+
+// Placeholders
+
+/// Formulas pulled out of a document before its text is parsed
+///
+/// Each formula is replaced by a placeholder made of private-use
+/// characters, which passes through the Markdown and HTML parsers as plain
+/// text. [`Formulas::resolve`] then turns placeholders in the parsed blocks
+/// into math blocks and inline math spans.
+#[derive(Debug, Default)]
+pub struct Formulas {
+    tex: Vec<String>,
+}
+
+/// A piece of text with placeholders
+enum Segment<'a> {
+    Text(&'a str),
+    Formula(&'a str, bool),
+}
+
+impl Formulas {
+    /// Store a formula and return its placeholder
+    pub fn placeholder(&mut self, tex: &str, display: bool) -> String {
+        self.tex.push(tex.trim().to_string());
+        let mark = if display { DISPLAY_MARK } else { INLINE_MARK };
+        format!("{}{}{}", mark, self.tex.len() - 1, END_MARK)
+    }
+
+    fn segments<'a>(&'a self, mut text: &'a str) -> Vec<Segment<'a>> {
+        let mut segments = Vec::new();
+        while let Some(start) = text.find([INLINE_MARK, DISPLAY_MARK]) {
+            let display = text[start..].starts_with(DISPLAY_MARK);
+            let rest = &text[start + INLINE_MARK.len_utf8()..];
+            let formula = rest.find(END_MARK).and_then(|end| {
+                let tex = self.tex.get(rest[..end].parse::<usize>().ok()?)?;
+                Some((tex, &rest[end + END_MARK.len_utf8()..]))
+            });
+            match formula {
+                Some((tex, after)) => {
+                    segments.push(Segment::Text(&text[..start]));
+                    segments.push(Segment::Formula(tex, display));
+                    text = after;
+                }
+                None => {
+                    segments.push(Segment::Text(&text[..start]));
+                    text = rest;
+                }
+            }
+        }
+        segments.push(Segment::Text(text));
+        segments
+    }
+
+    /// Whether the text is a single display formula
+    fn is_display(&self, text: &str) -> bool {
+        let segments = self.segments(text.trim());
+        matches!(
+            segments.as_slice(),
+            [
+                Segment::Text(""),
+                Segment::Formula(_, true),
+                Segment::Text("")
+            ]
+        )
+    }
+
+    /// Replace placeholders with the one-line rendering of their formula
+    pub fn resolve_text(&self, text: &str) -> String {
+        self.segments(text)
+            .into_iter()
+            .map(|s| match s {
+                Segment::Text(t) => t.to_string(),
+                Segment::Formula(tex, _) => to_unicode(tex),
+            })
+            .collect()
+    }
+
+    /// Turn placeholders in parsed blocks into math
+    ///
+    /// Display formulas split their paragraph around a `ContentBlock::Math`;
+    /// inline formulas become `StyleType::Math` spans. Elsewhere, such as in
+    /// headings and list items, formulas are rendered as plain text.
+    pub fn resolve(&self, blocks: Vec<ContentBlock>) -> Vec<ContentBlock> {
+        if self.tex.is_empty() {
+            return blocks;
+        }
+
+        let mut out = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            match block {
+                ContentBlock::Paragraph { text, styles }
+                    if text.contains([INLINE_MARK, DISPLAY_MARK]) =>
+                {
+                    self.split_paragraph(&text, &styles, &mut out)
+                }
+                // Short lines of symbols can be mistaken for headings
+                ContentBlock::Heading { text, .. } if self.is_display(&text) => {
+                    self.split_paragraph(&text, &[], &mut out)
+                }
+                ContentBlock::Heading { level, text } => out.push(ContentBlock::Heading {
+                    level,
+                    text: self.resolve_text(&text),
+                }),
+                ContentBlock::Quote { text, attribution } => out.push(ContentBlock::Quote {
+                    text: self.resolve_text(&text),
+                    attribution,
+                }),
+                ContentBlock::List { ordered, items } => out.push(ContentBlock::List {
+                    ordered,
+                    items: items.iter().map(|i| self.resolve_text(i)).collect(),
+                }),
+                ContentBlock::Footnote { id, content } => out.push(ContentBlock::Footnote {
+                    id,
+                    content: self.resolve_text(&content),
+                }),
+                ContentBlock::Table { headers, rows } => out.push(ContentBlock::Table {
+                    headers: headers.iter().map(|h| self.resolve_text(h)).collect(),
+                    rows: rows
+                        .iter()
+                        .map(|r| r.iter().map(|c| self.resolve_text(c)).collect())
+                        .collect(),
+                }),
+                block => out.push(block),
+            }
+        }
+        out
+    }
+
+    /// Like [`Formulas::resolve`], moving `anchors` to where their blocks end up
+    pub fn resolve_anchored(
+        &self,
+        blocks: Vec<ContentBlock>,
+        anchors: &mut [(String, usize)],
+    ) -> Vec<ContentBlock> {
+        let mut out = Vec::with_capacity(blocks.len());
+        let mut starts = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            starts.push(out.len());
+            out.extend(self.resolve(vec![block]));
+        }
+        for (_, block) in anchors.iter_mut() {
+            *block = starts
+                .get(*block)
+                .copied()
+                .unwrap_or(out.len().saturating_sub(1));
+        }
+        out
+    }
+
+    /// Split a paragraph around its display formulas
+    ///
+    /// The paragraph's own styles are carried over to the pieces.
+    fn split_paragraph(&self, text: &str, original: &[TextStyle], out: &mut Vec<ContentBlock>) {
+        let mut current = String::new();
+        let mut styles = Vec::new();
+        // Text runs of the current piece: original start and end, new start
+        let mut runs = Vec::new();
+
+        for segment in self.segments(text) {
+            match segment {
+                Segment::Text(t) => {
+                    let start = t.as_ptr() as usize - text.as_ptr() as usize;
+                    runs.push((start, start + t.len(), current.len()));
+                    current.push_str(t);
+                }
+                Segment::Formula(tex, false) => {
+                    let start = current.len();
+                    current.push_str(&to_unicode(tex));
+                    styles.push(TextStyle {
+                        start,
+                        end: current.len(),
+                        style_type: StyleType::Math,
+                        data: Some(tex.to_string()),
+                    });
+                }
+                Segment::Formula(tex, true) => {
+                    styles.extend(carry_styles(original, &runs));
+                    push_paragraph(&mut current, &mut styles, out);
+                    runs.clear();
+                    out.push(ContentBlock::Math {
+                        tex: tex.to_string(),
+                    });
+                }
+            }
+        }
+        styles.extend(carry_styles(original, &runs));
+        push_paragraph(&mut current, &mut styles, out);
+    }
+}
+
+/// Move styles onto text runs that were copied to new offsets
+///
+/// Each run is its original start and end and its new start. Styles are
+/// clipped to the runs, and dropped if nothing of them is left.
+fn carry_styles(styles: &[TextStyle], runs: &[(usize, usize, usize)]) -> Vec<TextStyle> {
+    styles
+        .iter()
+        .filter_map(|style| {
+            let start = runs
+                .iter()
+                .find(|(_, end, _)| *end > style.start)
+                .map(|&(from, _, to)| to + style.start.saturating_sub(from))?;
+            let end = runs
+                .iter()
+                .rev()
+                .find(|(from, _, _)| *from < style.end)
+                .map(|&(from, end, to)| to + style.end.min(end) - from)?;
+            (start < end).then(|| TextStyle {
+                start,
+                end,
+                ..style.clone()
+            })
+        })
+        .collect()
+}
+
+/// Push the trimmed text as a paragraph, shifting its styles to match
+fn push_paragraph(text: &mut String, styles: &mut Vec<TextStyle>, out: &mut Vec<ContentBlock>) {
+    let leading = text.len() - text.trim_start().len();
+    let trimmed = text.trim().to_string();
+    let mut styles = std::mem::take(styles);
+    styles.sort_by_key(|s| s.start);
+    for style in &mut styles {
+        style.start = style.start.saturating_sub(leading).min(trimmed.len());
+        style.end = style.end.saturating_sub(leading).min(trimmed.len());
+    }
+    styles.retain(|s| s.start < s.end);
+    if !trimmed.is_empty() {
+        out.push(ContentBlock::Paragraph {
+            text: trimmed,
+            styles,
+        });
+    }
+    text.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_inline() {
+        assert_eq!(to_unicode(r"x^2 + y_1 = \alpha"), "x² + y₁ = α");
+        assert_eq!(to_unicode(r"\frac{a+b}{2}"), "(a + b)/2");
+        assert_eq!(to_unicode(r"\frac{1}{2}\pi r^{n+1}"), "½πrⁿ⁺¹");
+        assert_eq!(to_unicode(r"\sqrt{x} \leq \sqrt[3]{y}"), "√x ≤ ∛y");
+        assert_eq!(to_unicode(r"e^{i\theta}"), "e^(iθ)");
+        assert_eq!(to_unicode(r"\mathbb{R}^n \to \mathbb{R}"), "ℝⁿ → ℝ");
+        assert_eq!(to_unicode(r"f'(x) = -\text{const}"), "f′(x) = −const");
+    }
+
+    #[test]
+    fn test_display_layout() {
+        assert_eq!(
+            to_lines(r"\frac{a+b}{c} = x"),
+            vec![" a + b", "─────── = x", "   c"]
+        );
+        assert_eq!(to_lines(r"\sum_{i=1}^{n} i"), vec![" n", " ∑ i", "i=1"]);
+        assert_eq!(
+            to_lines(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            vec!["⎛a  b⎞", "⎝c  d⎠"]
+        );
+    }
+
+    #[test]
+    fn test_mathml_output() {
+        assert_eq!(
+            to_mathml(r"\frac{1}{x^2}", false),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mfrac><mn>1</mn>\
+             <msup><mi>x</mi><mn>2</mn></msup></mfrac></math>"
+        );
+        assert!(to_mathml(r"\sum_{k} a < b", true)
+            .contains("display=\"block\"><mrow><munder><mo>∑</mo><mi>k</mi></munder>"));
+        assert!(to_mathml(r"a<b", false).contains("<mo>&lt;</mo>"));
+    }
+
+    #[test]
+    fn test_mathml_to_tex() {
+        let mathml = r#"<m:math xmlns:m="http://www.w3.org/1998/Math/MathML">
+            <m:mrow><m:msup><m:mi>x</m:mi><m:mn>2</m:mn></m:msup><m:mo>+</m:mo>
+            <m:mfrac><m:mi>&#x3B1;</m:mi><m:msqrt><m:mi>y</m:mi></m:msqrt></m:mfrac>
+            </m:mrow></m:math>"#;
+        let tex = mathml_to_tex(mathml).unwrap();
+        assert_eq!(tex, r"x^{2} + \frac{\alpha}{\sqrt{y}}");
+        assert_eq!(to_unicode(&tex), "x² + α/√y");
+
+        let annotated = r#"<math><semantics><mi>E</mi>
+            <annotation encoding="application/x-tex">E = mc^2</annotation></semantics></math>"#;
+        assert_eq!(mathml_to_tex(annotated).unwrap(), "E = mc^2");
+    }
+
+    #[test]
+    fn test_resolve_placeholders() {
+        let mut formulas = Formulas::default();
+        let text = format!(
+            "Energy {} holds.{}After",
+            formulas.placeholder("E=mc^2", false),
+            formulas.placeholder(r"\frac{a}{b}", true)
+        );
+        let blocks = formulas.resolve(vec![ContentBlock::Paragraph {
+            text,
+            styles: Vec::new(),
+        }]);
+
+        assert_eq!(blocks.len(), 3);
+        match &blocks[0] {
+            ContentBlock::Paragraph { text, styles } => {
+                assert_eq!(text, "Energy E = mc² holds.");
+                assert_eq!(&text[styles[0].start..styles[0].end], "E = mc²");
+                assert_eq!(styles[0].style_type, StyleType::Math);
+                assert_eq!(styles[0].data.as_deref(), Some("E=mc^2"));
+            }
+            other => panic!("unexpected block: {:?}", other),
+        }
+        assert!(matches!(&blocks[1], ContentBlock::Math { tex } if tex == r"\frac{a}{b}"));
+        assert!(matches!(&blocks[2], ContentBlock::Paragraph { text, .. } if text == "After"));
+    }
+
+    #[test]
+    fn test_resolve_keeps_styles() {
+        let mut formulas = Formulas::default();
+        let text = format!(
+            "Bold {} then italic{} text",
+            formulas.placeholder("x^2", false),
+            formulas.placeholder("y", true)
+        );
+        let style = |part: &str, style_type| {
+            let start = text.find(part).unwrap();
+            TextStyle {
+                start,
+                end: start + part.len(),
+                style_type,
+                data: None,
+            }
+        };
+        // Spans the display formula, so it is split in two
+        let mut underline = style(" text", StyleType::Underline);
+        underline.start = text.find("italic").unwrap();
+        let styles = vec![
+            style("Bold", StyleType::Bold),
+            style("italic", StyleType::Italic),
+            underline,
+        ];
+
+        let blocks = formulas.resolve(vec![ContentBlock::Paragraph {
+            text: text.clone(),
+            styles,
+        }]);
+        let spans = |block: &ContentBlock| match block {
+            ContentBlock::Paragraph { text, styles } => styles
+                .iter()
+                .map(|s| (s.style_type, text[s.start..s.end].to_string()))
+                .collect::<Vec<_>>(),
+            other => panic!("unexpected block: {:?}", other),
+        };
+        assert_eq!(
+            spans(&blocks[0]),
+            vec![
+                (StyleType::Bold, "Bold".to_string()),
+                (StyleType::Math, "x²".to_string()),
+                (StyleType::Italic, "italic".to_string()),
+                (StyleType::Underline, "italic".to_string()),
+            ]
+        );
+        assert_eq!(
+            spans(&blocks[2]),
+            vec![(StyleType::Underline, "text".to_string())]
+        );
+    }
+}
@@ -3,6 +3,13 @@
 //! Supports multiple book formats with a unified interface
 
 mod book;
+#[cfg(feature = "compressed")]
+pub mod compressed;
+pub mod detect;
+pub mod diagnose;
+pub mod lazy;
+pub mod math;
+pub mod writer;
 
 #[cfg(feature = "epub")]
 mod epub;
@@ -16,11 +23,19 @@ mod markdown;
 #[cfg(feature = "txt")]
 mod txt;
 
-pub use book::{Book, BookContent, BookMetadata, Chapter, ContentBlock, TocEntry};
+pub use book::{
+    Book, BookContent, BookMetadata, Chapter, ContentBlock, MetadataEdit, StyleType, TocEntry,
+};
+pub use lazy::{BookSkeleton, ChapterInfo, LazyBook};
 
 use anyhow::{Context, Result};
 use std::path::Path;
 
+/// Version of the parsers' output, stored with cached books
+///
+/// Bump this whenever a parser change alters the produced [`Book`].
+pub const PARSER_VERSION: u32 = 6;
+
 /// Supported book formats
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BookFormat {
@@ -29,11 +44,20 @@ pub enum BookFormat {
     Markdown,
     PlainText,
     Html,
+    Fb2,
+    Cbz,
+    Docx,
+    Odt,
     Unknown,
 }
 
 impl BookFormat {
-    /// Detect format from file extension
+    /// Detect format from file contents, falling back to the extension
+    pub fn detect(path: &Path) -> Self {
+        detect::detect(path)
+    }
+
+    /// Format claimed by the file extension
     pub fn from_path(path: &Path) -> Self {
         match path
             .extension()
@@ -46,6 +70,10 @@ impl BookFormat {
             Some("md") | Some("markdown") => BookFormat::Markdown,
             Some("txt") | Some("text") => BookFormat::PlainText,
             Some("html") | Some("htm") | Some("xhtml") => BookFormat::Html,
+            Some("fb2") => BookFormat::Fb2,
+            Some("cbz") => BookFormat::Cbz,
+            Some("docx") => BookFormat::Docx,
+            Some("odt") => BookFormat::Odt,
             _ => BookFormat::Unknown,
         }
     }
@@ -58,10 +86,30 @@ impl BookFormat {
             BookFormat::Markdown => "Markdown",
             BookFormat::PlainText => "Plain Text",
             BookFormat::Html => "HTML",
+            BookFormat::Fb2 => "FictionBook",
+            BookFormat::Cbz => "Comic Book Archive",
+            BookFormat::Docx => "Word Document",
+            BookFormat::Odt => "OpenDocument Text",
             BookFormat::Unknown => "Unknown",
         }
     }
 
+    /// Short identifier stored in the library (`epub`, `pdf`, ...)
+    pub fn id(&self) -> &'static str {
+        match self {
+            BookFormat::Epub => "epub",
+            BookFormat::Pdf => "pdf",
+            BookFormat::Markdown => "md",
+            BookFormat::PlainText => "txt",
+            BookFormat::Html => "html",
+            BookFormat::Fb2 => "fb2",
+            BookFormat::Cbz => "cbz",
+            BookFormat::Docx => "docx",
+            BookFormat::Odt => "odt",
+            BookFormat::Unknown => "unknown",
+        }
+    }
+
     /// Check if format is supported
     pub fn is_supported(&self) -> bool {
         match self {
@@ -82,14 +130,39 @@ impl BookFormat {
 
             BookFormat::PlainText => true,
             BookFormat::Html => true,
+            BookFormat::Fb2 | BookFormat::Cbz | BookFormat::Docx | BookFormat::Odt => false,
             BookFormat::Unknown => false,
         }
     }
 }
 
+/// Extensions that are always considered when scanning folders
+const BOOK_EXTENSIONS: &[&str] = &["epub", "pdf", "md", "markdown", "txt", "text"];
+
+/// Check whether a file should be picked up by a library scan
+///
+/// Files with a known book extension are accepted as-is; anything else is
+/// accepted only if its content identifies a supported format.
+pub fn is_book_file(path: &Path) -> bool {
+    let has_book_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| BOOK_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false);
+
+    has_book_extension || detect::sniff(path).is_some_and(|f| f.is_supported())
+}
+
 /// Parse a book from a file path
 pub fn parse_book(path: &Path) -> Result<Book> {
-    let format = BookFormat::from_path(path);
+    #[cfg(feature = "compressed")]
+    if let Some(unpacked) = compressed::unpack(path)? {
+        let mut book = parse_book(&unpacked.path)?;
+        book.source_path = path.to_path_buf();
+        return Ok(book);
+    }
+
+    let format = BookFormat::detect(path);
 
     if !format.is_supported() {
         anyhow::bail!("Unsupported format: {} ({})", format.name(), path.display());
@@ -105,16 +178,99 @@ pub fn parse_book(path: &Path) -> Result<Book> {
         #[cfg(feature = "markdown")]
         BookFormat::Markdown => markdown::parse(path),
 
-        BookFormat::PlainText | BookFormat::Html => txt::parse(path),
+        BookFormat::PlainText => txt::parse(path, false),
+        BookFormat::Html => txt::parse(path, true),
 
         _ => anyhow::bail!("Format not available: {}", format.name()),
     }
     .with_context(|| format!("Failed to parse book: {}", path.display()))
 }
 
+/// Parse a book one chapter at a time
+///
+/// Each chapter is handed to `sink` as soon as it is parsed and is not kept
+/// afterwards; the returned skeleton describes the whole book. Formats that
+/// cannot be split are parsed fully first.
+pub fn parse_streaming(
+    path: &Path,
+    sink: &mut dyn FnMut(Chapter) -> Result<()>,
+) -> Result<BookSkeleton> {
+    #[cfg(feature = "compressed")]
+    if let Some(unpacked) = compressed::unpack(path)? {
+        let mut skeleton = parse_streaming(&unpacked.path, sink)?;
+        skeleton.source_path = path.to_path_buf();
+        return Ok(skeleton);
+    }
+
+    match BookFormat::detect(path) {
+        #[cfg(feature = "epub")]
+        BookFormat::Epub => epub::parse_streaming(path, sink)
+            .with_context(|| format!("Failed to parse book: {}", path.display())),
+
+        // Chapters are found in the extracted text and parsed one at a time
+        #[cfg(feature = "pdf")]
+        BookFormat::Pdf => pdf::parse_streaming(path, sink)
+            .with_context(|| format!("Failed to parse book: {}", path.display())),
+
+        _ => {
+            let book = parse_book(path)?;
+            let mut skeleton = BookSkeleton::new(book.metadata, book.source_path, book.format);
+            skeleton.toc = book.content.toc;
+            for chapter in book.content.chapters {
+                skeleton.chapters.push(ChapterInfo::from(&chapter));
+                sink(chapter)?;
+            }
+            Ok(skeleton)
+        }
+    }
+}
+
+/// Open a book for chapter-on-demand reading
+///
+/// EPUB chapters are parsed from the archive and PDF chapters from their
+/// extracted text when first needed; other formats are parsed up front.
+pub fn open_lazy(path: &Path) -> Result<LazyBook> {
+    // A compressed book's temporary copy would not outlive this call
+    if !is_compressed(path) {
+        match BookFormat::detect(path) {
+            #[cfg(feature = "epub")]
+            BookFormat::Epub => {
+                return epub::open_lazy(path)
+                    .with_context(|| format!("Failed to parse book: {}", path.display()))
+            }
+            #[cfg(feature = "pdf")]
+            BookFormat::Pdf => {
+                return pdf::open_lazy(path)
+                    .with_context(|| format!("Failed to parse book: {}", path.display()))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(LazyBook::from_book(parse_book(path)?))
+}
+
+/// Check whether a book is stored inside a compressed wrapper
+pub fn is_compressed(path: &Path) -> bool {
+    #[cfg(feature = "compressed")]
+    {
+        compressed::compression(path).is_some()
+    }
+    #[cfg(not(feature = "compressed"))]
+    {
+        let _ = path;
+        false
+    }
+}
+
 /// Get metadata without parsing full content
 pub fn get_metadata(path: &Path) -> Result<BookMetadata> {
-    let format = BookFormat::from_path(path);
+    #[cfg(feature = "compressed")]
+    if let Some(unpacked) = compressed::unpack(path)? {
+        return get_metadata(&unpacked.path);
+    }
+
+    let format = BookFormat::detect(path);
 
     match format {
         #[cfg(feature = "epub")]
@@ -134,7 +290,12 @@ pub fn get_metadata(path: &Path) -> Result<BookMetadata> {
 /// Extract cover image from a book file
 /// Returns (image_data, mime_type) if successful
 pub fn extract_cover(path: &Path) -> Result<Option<(Vec<u8>, String)>> {
-    let format = BookFormat::from_path(path);
+    #[cfg(feature = "compressed")]
+    if let Some(unpacked) = compressed::unpack(path)? {
+        return extract_cover(&unpacked.path);
+    }
+
+    let format = BookFormat::detect(path);
 
     match format {
         #[cfg(feature = "epub")]
@@ -145,3 +306,57 @@ pub fn extract_cover(path: &Path) -> Result<Option<(Vec<u8>, String)>> {
         _ => Ok(None),
     }
 }
+
+/// Load a resource referenced by a book, such as an image, on demand
+///
+/// Image data is never kept in parsed or cached books; renderers call this
+/// with the block's `src` instead. Container formats resolve `src` inside
+/// the archive, other formats relative to the book's directory.
+pub fn load_resource(path: &Path, src: &str) -> Result<Option<(Vec<u8>, String)>> {
+    let src = src.split(['#', '?']).next().unwrap_or_default();
+    if src.is_empty() || src.contains("://") || src.starts_with("data:") {
+        return Ok(None);
+    }
+
+    match BookFormat::detect(path) {
+        #[cfg(feature = "epub")]
+        BookFormat::Epub => epub::load_resource(path, src),
+
+        _ => {
+            let relative = Path::new(src);
+            // Never read outside the book's directory
+            let escapes = relative.components().any(|c| {
+                !matches!(
+                    c,
+                    std::path::Component::Normal(_) | std::path::Component::CurDir
+                )
+            });
+            if escapes {
+                return Ok(None);
+            }
+
+            let Some(file) = path.parent().map(|dir| dir.join(relative)) else {
+                return Ok(None);
+            };
+            if !file.is_file() {
+                return Ok(None);
+            }
+
+            let mime = match file
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_lowercase())
+                .as_deref()
+            {
+                Some("png") => "image/png",
+                Some("jpg") | Some("jpeg") => "image/jpeg",
+                Some("gif") => "image/gif",
+                Some("webp") => "image/webp",
+                Some("svg") => "image/svg+xml",
+                Some("bmp") => "image/bmp",
+                _ => "application/octet-stream",
+            };
+            Ok(Some((std::fs::read(&file)?, mime.to_string())))
+        }
+    }
+}
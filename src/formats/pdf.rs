@@ -1,27 +1,42 @@
 //! PDF format parser
 
+use super::lazy::{BookSkeleton, ChapterInfo, ChapterLoader, LazyBook};
 use super::{Book, BookContent, BookMetadata, Chapter, ContentBlock, TocEntry};
 use anyhow::{Context, Result};
+use std::ops::Range;
 use std::path::Path;
 
 /// Parse a PDF file
 pub fn parse(path: &Path) -> Result<Book> {
-    let metadata = metadata(path)?;
-    let content = extract_content(path)?;
+    let mut chapters = Vec::new();
+    let skeleton = parse_streaming(path, &mut |chapter| {
+        chapters.push(chapter);
+        Ok(())
+    })?;
 
     Ok(Book {
-        metadata,
-        content,
-        source_path: path.to_path_buf(),
-        format: "pdf".to_string(),
+        metadata: skeleton.metadata,
+        content: BookContent {
+            chapters,
+            toc: skeleton.toc,
+        },
+        source_path: skeleton.source_path,
+        format: skeleton.format,
     })
 }
 
 /// Extract metadata from PDF
 pub fn metadata(path: &Path) -> Result<BookMetadata> {
-    let doc = lopdf::Document::load(path)
-        .with_context(|| format!("Failed to load PDF: {}", path.display()))?;
+    let doc = load(path)?;
+    Ok(doc_metadata(&doc, path))
+}
+
+fn load(path: &Path) -> Result<lopdf::Document> {
+    lopdf::Document::load(path).with_context(|| format!("Failed to load PDF: {}", path.display()))
+}
 
+/// Metadata from a PDF's document info dictionary
+fn doc_metadata(doc: &lopdf::Document, path: &Path) -> BookMetadata {
     let mut metadata = BookMetadata::default();
 
     // Try to get metadata from document info dictionary
@@ -101,7 +116,131 @@ pub fn metadata(path: &Path) -> Result<BookMetadata> {
             .to_string();
     }
 
-    Ok(metadata)
+    metadata
+}
+
+/// Open a PDF whose chapters are parsed when first read
+///
+/// The text is extracted once to find the chapter breaks and titles, and
+/// each chapter's blocks are built from it on demand.
+pub fn open_lazy(path: &Path) -> Result<LazyBook> {
+    match open_text(path)? {
+        (skeleton, Some(loader)) => Ok(LazyBook::new(skeleton, Box::new(loader))),
+        (skeleton, None) => Ok(LazyBook::from_book(Book {
+            metadata: skeleton.metadata,
+            content: extract_content_lopdf(path)?,
+            source_path: skeleton.source_path,
+            format: skeleton.format,
+        })),
+    }
+}
+
+/// Parse a PDF one chapter at a time, handing each chapter to `sink`
+pub fn parse_streaming(
+    path: &Path,
+    sink: &mut dyn FnMut(Chapter) -> Result<()>,
+) -> Result<BookSkeleton> {
+    let (mut skeleton, loader) = open_text(path)?;
+    match loader {
+        Some(loader) => {
+            for index in 0..skeleton.chapters.len() {
+                sink(loader.load(index)?)?;
+            }
+        }
+        None => {
+            let content = extract_content_lopdf(path)?;
+            skeleton.toc = content.toc;
+            for chapter in content.chapters {
+                skeleton.chapters.push(ChapterInfo::from(&chapter));
+                sink(chapter)?;
+            }
+        }
+    }
+    Ok(skeleton)
+}
+
+/// Extract a PDF's text and find its chapters
+///
+/// No loader is returned when the PDF has no text to extract.
+fn open_text(path: &Path) -> Result<(BookSkeleton, Option<TextLoader>)> {
+    let mut doc = load(path)?;
+    if doc.is_encrypted() {
+        doc.decrypt("")
+            .map_err(|e| anyhow::anyhow!("Failed to decrypt PDF: {}", e))?;
+    }
+
+    let mut skeleton = BookSkeleton::new(
+        doc_metadata(&doc, path),
+        path.to_path_buf(),
+        "pdf".to_string(),
+    );
+    let text = extract_pages(&doc);
+    if text.trim().is_empty() {
+        return Ok((skeleton, None));
+    }
+
+    // Chapters are summarized one at a time, so only the text stays in memory
+    let mut parts = Vec::new();
+    each_chapter(&text, |range, chapter| {
+        if range.is_some() {
+            skeleton.toc.push(TocEntry::new(
+                chapter.title.clone().unwrap_or_default(),
+                chapter.id.clone(),
+                0,
+            ));
+        }
+        skeleton.chapters.push(ChapterInfo::from(&chapter));
+        parts.push((chapter.order, range));
+    });
+
+    Ok((skeleton, Some(TextLoader { text, parts })))
+}
+
+/// Extract the text of every page
+///
+/// Pages pdf-extract cannot read fall back to lopdf's simpler extraction,
+/// and are skipped only when that fails too.
+fn extract_pages(doc: &lopdf::Document) -> String {
+    let mut text = String::new();
+    for page in doc.get_pages().into_keys() {
+        let mut page_text = String::new();
+        let mut output = pdf_extract::PlainTextOutput::new(&mut page_text);
+        match pdf_extract::output_doc_page(doc, &mut output, page) {
+            Ok(()) => text.push_str(&page_text),
+            Err(e) => {
+                tracing::debug!("pdf-extract failed on page {}, trying lopdf: {}", page, e);
+                match doc.extract_text(&[page]) {
+                    Ok(page_text) => {
+                        text.push_str("\n\n");
+                        text.push_str(&page_text);
+                    }
+                    Err(e) => tracing::debug!("Skipping unreadable page {}: {}", page, e),
+                }
+            }
+        }
+    }
+    text
+}
+
+/// Builds chapters from ranges of a PDF's extracted text
+struct TextLoader {
+    text: String,
+    /// Order and text range of each chapter; no range means the whole text
+    parts: Vec<(usize, Option<Range<usize>>)>,
+}
+
+impl ChapterLoader for TextLoader {
+    fn load(&self, index: usize) -> Result<Chapter> {
+        let (order, range) = self
+            .parts
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("Chapter {} out of range", index + 1))?;
+        match range {
+            Some(range) => text_chapter(&self.text[range.clone()], *order)
+                .ok_or_else(|| anyhow::anyhow!("Chapter {} has no text", index + 1)),
+            None => Ok(whole_text_chapter(&self.text)),
+        }
+    }
 }
 
 /// Decode PDF string, handling various encodings
@@ -133,25 +272,6 @@ fn parse_pdf_date(date: &str) -> Option<String> {
     None
 }
 
-fn extract_content(path: &Path) -> Result<BookContent> {
-    // Try pdf-extract first
-    match pdf_extract::extract_text(path) {
-        Ok(text) if !text.trim().is_empty() => {
-            let (chapters, toc) = parse_text_to_chapters(&text);
-            Ok(BookContent { chapters, toc })
-        }
-        Ok(_) => {
-            // Empty text - try lopdf directly
-            extract_content_lopdf(path)
-        }
-        Err(e) => {
-            // pdf-extract failed, try lopdf as fallback
-            tracing::warn!("pdf-extract failed, trying lopdf fallback: {}", e);
-            extract_content_lopdf(path)
-        }
-    }
-}
-
 /// Fallback extraction using lopdf directly
 fn extract_content_lopdf(path: &Path) -> Result<BookContent> {
     let doc = lopdf::Document::load(path)
@@ -207,11 +327,27 @@ fn extract_content_lopdf(path: &Path) -> Result<BookContent> {
     Ok(BookContent { chapters, toc })
 }
 
-/// Parse extracted text into chapters with smart detection
-fn parse_text_to_chapters(text: &str) -> (Vec<Chapter>, Vec<TocEntry>) {
-    let mut chapters = Vec::new();
-    let mut toc = Vec::new();
+/// Hand each detected chapter of the text to `f` with its range
+///
+/// When no chapter has any content, the whole text is handed over as a
+/// single chapter without a range.
+fn each_chapter(text: &str, mut f: impl FnMut(Option<Range<usize>>, Chapter)) {
+    let mut found = false;
+    for (i, range) in chapter_ranges(text).into_iter().enumerate() {
+        if let Some(chapter) = text_chapter(&text[range.clone()], i) {
+            found = true;
+            f(Some(range), chapter);
+        }
+    }
+
+    // Fallback to single chapter if nothing was created
+    if !found {
+        f(None, whole_text_chapter(text));
+    }
+}
 
+/// Split extracted text at its likely chapter breaks
+fn chapter_ranges(text: &str) -> Vec<Range<usize>> {
     // Detect chapter breaks by looking for patterns like:
     // - "Chapter X" or "CHAPTER X"
     // - "Part X" or "PART X"
@@ -250,72 +386,65 @@ fn parse_text_to_chapters(text: &str) -> (Vec<Chapter>, Vec<TocEntry>) {
 
     chapter_starts.push(text.len());
 
-    for (i, window) in chapter_starts.windows(2).enumerate() {
-        let start = window[0];
-        let end = window[1];
-        let chunk = &text[start..end];
+    chapter_starts
+        .windows(2)
+        .map(|window| window[0]..window[1])
+        .collect()
+}
 
-        if chunk.trim().is_empty() {
-            continue;
-        }
+/// Build the chapter at `order` from its text, if it has any content
+fn text_chapter(chunk: &str, order: usize) -> Option<Chapter> {
+    if chunk.trim().is_empty() {
+        return None;
+    }
 
-        let blocks = parse_text_content(chunk);
-        if blocks.is_empty() {
-            continue;
-        }
+    let blocks = parse_text_content(chunk);
+    if blocks.is_empty() {
+        return None;
+    }
 
-        // Try to extract chapter title from first heading or first line
-        let title = blocks
-            .iter()
-            .find_map(|b| match b {
-                ContentBlock::Heading { text, .. } => Some(text.clone()),
+    // Try to extract chapter title from first heading or first line
+    let title = blocks
+        .iter()
+        .find_map(|b| match b {
+            ContentBlock::Heading { text, .. } => Some(text.clone()),
+            _ => None,
+        })
+        .or_else(|| {
+            // Use first non-empty paragraph's first sentence
+            blocks.iter().find_map(|b| match b {
+                ContentBlock::Paragraph { text, .. } if !text.is_empty() => {
+                    let first_line = text.lines().next().unwrap_or("");
+                    if first_line.len() < 100 {
+                        Some(first_line.to_string())
+                    } else {
+                        None
+                    }
+                }
                 _ => None,
             })
-            .or_else(|| {
-                // Use first non-empty paragraph's first sentence
-                blocks.iter().find_map(|b| match b {
-                    ContentBlock::Paragraph { text, .. } if !text.is_empty() => {
-                        let first_line = text.lines().next().unwrap_or("");
-                        if first_line.len() < 100 {
-                            Some(first_line.to_string())
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                })
-            });
-
-        let chapter_id = format!("chapter-{}", i);
-        let display_title = title
-            .clone()
-            .unwrap_or_else(|| format!("Section {}", i + 1));
-
-        let mut chapter = Chapter::new(chapter_id.clone(), i);
-        chapter.title = Some(display_title.clone());
-        chapter.blocks = blocks;
-
-        toc.push(TocEntry::new(display_title, chapter_id, 0));
-        chapters.push(chapter);
-    }
+        });
 
-    // Fallback to single chapter if nothing was created
-    if chapters.is_empty() {
-        let blocks = parse_text_content(text);
-        let mut chapter = Chapter::new("main".to_string(), 0);
-        chapter.title = Some("Document".to_string());
-        chapter.blocks = if blocks.is_empty() {
-            vec![ContentBlock::Paragraph {
-                text: text.trim().to_string(),
-                styles: Vec::new(),
-            }]
-        } else {
-            blocks
-        };
-        chapters.push(chapter);
-    }
+    let mut chapter = Chapter::new(format!("chapter-{}", order), order);
+    chapter.title = Some(title.unwrap_or_else(|| format!("Section {}", order + 1)));
+    chapter.blocks = blocks;
+    Some(chapter)
+}
 
-    (chapters, toc)
+/// The whole text as a single chapter
+fn whole_text_chapter(text: &str) -> Chapter {
+    let blocks = parse_text_content(text);
+    let mut chapter = Chapter::new("main".to_string(), 0);
+    chapter.title = Some("Document".to_string());
+    chapter.blocks = if blocks.is_empty() {
+        vec![ContentBlock::Paragraph {
+            text: text.trim().to_string(),
+            styles: Vec::new(),
+        }]
+    } else {
+        blocks
+    };
+    chapter
 }
 
 fn parse_text_content(text: &str) -> Vec<ContentBlock> {
@@ -494,3 +623,40 @@ fn is_pdf_artifact(text: &str) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_chapters_load_from_their_ranges() {
+        let text = "Chapter 1\n\nThe first chapter has a few words of prose in it.\n\n\
+                    Chapter 2\n\nAnd the second one has some more words of prose.";
+
+        let mut chapters = Vec::new();
+        let mut parts = Vec::new();
+        each_chapter(text, |range, chapter| {
+            parts.push((chapter.order, range));
+            chapters.push(chapter);
+        });
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].title.as_deref(), Some("Chapter 2"));
+
+        let loader = TextLoader {
+            text: text.to_string(),
+            parts,
+        };
+        for (index, chapter) in chapters.iter().enumerate() {
+            let loaded = loader.load(index).unwrap();
+            assert_eq!(loaded.id, chapter.id);
+            assert_eq!(loaded.word_count(), chapter.word_count());
+        }
+        assert!(loader.load(2).is_err());
+
+        // Text without any blocks still makes one chapter
+        let mut whole = Vec::new();
+        each_chapter("12", |range, chapter| whole.push((range, chapter)));
+        assert_eq!(whole.len(), 1);
+        assert!(whole[0].0.is_none());
+    }
+}
@@ -5,20 +5,10 @@ use anyhow::{Context, Result};
 use std::path::Path;
 
 /// Parse a plain text or HTML file
-pub fn parse(path: &Path) -> Result<Book> {
-    let content_str = std::fs::read_to_string(path)
+pub fn parse(path: &Path, is_html: bool) -> Result<Book> {
+    let content_str = super::detect::read_text(path)
         .with_context(|| format!("Failed to read file: {}", path.display()))?;
 
-    let extension = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e.to_lowercase());
-
-    let is_html = matches!(
-        extension.as_deref(),
-        Some("html") | Some("htm") | Some("xhtml")
-    );
-
     let content = if is_html {
         parse_html_content(&content_str)
     } else {
@@ -0,0 +1,725 @@
+//! EPUB 3 writer
+//!
+//! Produces a package with an OPF carrying the full [`BookMetadata`], a
+//! `nav.xhtml` built from the book's table of contents (plus an NCX for
+//! older readers), one XHTML document per chapter, and embedded images.
+
+use super::xhtml;
+use crate::formats::{self, Book, BookMetadata, Chapter, ContentBlock, TocEntry};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLESHEET: &str = r#"body { margin: 0 5%; line-height: 1.5; }
+h1, h2, h3, h4, h5, h6 { line-height: 1.2; page-break-after: avoid; }
+p { margin: 0 0 0.8em 0; }
+blockquote { margin: 1em 2em; font-style: italic; }
+.attribution { text-align: right; font-style: normal; }
+pre { white-space: pre-wrap; font-size: 0.85em; }
+figure { margin: 1em 0; text-align: center; }
+figure img, .cover img { max-width: 100%; max-height: 100%; }
+figcaption, .caption { font-size: 0.9em; font-style: italic; text-align: center; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #888; padding: 0.2em 0.5em; }
+.smallcaps { font-variant: small-caps; }
+.link { text-decoration: underline; }
+.cover { margin: 0; padding: 0; text-align: center; }
+"#;
+
+/// Write a book as an EPUB 3 file
+///
+/// The package is assembled in a temporary file next to `path` and moved
+/// into place once complete, so a failed conversion never leaves a
+/// truncated book behind.
+pub fn write_epub(book: &Book, path: &Path) -> Result<()> {
+    let partial = path.with_extension("epub.part");
+    let file = File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))?;
+
+    let result = write_epub_to(book, BufWriter::new(file))
+        .and_then(|_| std::fs::rename(&partial, path).map_err(Into::into));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Write a book as EPUB 3 into any seekable writer
+fn write_epub_to<W: Write + Seek>(book: &Book, writer: W) -> Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype must be the first entry and stored uncompressed
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let metadata = &book.metadata;
+    let title = book_title(book);
+    let language = metadata
+        .language
+        .clone()
+        .unwrap_or_else(|| "en".to_string());
+    let identifier = metadata
+        .isbn
+        .clone()
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or_else(|| format!("urn:uuid:{}", uuid::Uuid::new_v4()));
+
+    let mut manifest = vec![
+        Item::new("nav", "nav.xhtml", "application/xhtml+xml").with_properties("nav"),
+        Item::new("ncx", "toc.ncx", "application/x-dtbncx+xml"),
+        Item::new("style", "style.css", "text/css"),
+    ];
+    let mut spine = Vec::new();
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLESHEET.as_bytes())?;
+
+    // Cover image and page
+    let cover = match &metadata.cover {
+        Some(data) => Some((
+            data.clone(),
+            metadata
+                .cover_mime
+                .clone()
+                .unwrap_or_else(|| formats::detect::image_mime(data).to_string()),
+        )),
+        None => formats::extract_cover(&book.source_path).unwrap_or_else(|e| {
+            warn!("Failed to extract cover: {}", e);
+            None
+        }),
+    };
+    let has_cover = cover.is_some();
+    if let Some((data, mime)) = cover {
+        let href = format!("images/cover.{}", extension_for(&mime));
+        zip.start_file(format!("OEBPS/{}", href), deflated)?;
+        zip.write_all(&data)?;
+        manifest.push(Item::new("cover-image", &href, &mime).with_properties("cover-image"));
+
+        zip.start_file("OEBPS/text/cover.xhtml", deflated)?;
+        zip.write_all(
+            document(
+                &title,
+                &language,
+                &format!(
+                    "<div class=\"cover\">\n<img src=\"../{}\" alt=\"{}\"/>\n</div>\n",
+                    xhtml::escape(&href),
+                    xhtml::escape(&title)
+                ),
+            )
+            .as_bytes(),
+        )?;
+        manifest.push(Item::new(
+            "cover",
+            "text/cover.xhtml",
+            "application/xhtml+xml",
+        ));
+        spine.push("cover".to_string());
+    }
+
+    // Chapters, collecting referenced images along the way
+    let chapters = &book.content.chapters;
+    let files: Vec<String> = (0..chapters.len())
+        .map(|i| format!("text/chapter-{:04}.xhtml", i + 1))
+        .collect();
+    let mut images = Images::new(&book.source_path);
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let body = xhtml::blocks(chapter, i, "", &[], &mut |src, data| {
+            images.resolve(src, data).map(|href| format!("../{}", href))
+        });
+        let content = format!(
+            "<section epub:type=\"chapter\" id=\"{}\">\n{}</section>\n",
+            xhtml::id("chapter", &(i + 1).to_string()),
+            body
+        );
+
+        zip.start_file(format!("OEBPS/{}", files[i]), deflated)?;
+        zip.write_all(document(&chapter.display_title(), &language, &content).as_bytes())?;
+
+        let id = format!("chapter-{}", i + 1);
+        let mut item = Item::new(&id, &files[i], "application/xhtml+xml");
+        if body.contains("<math ") {
+            item = item.with_properties("mathml");
+        }
+        manifest.push(item);
+        spine.push(id);
+    }
+
+    for image in &images.items {
+        zip.start_file(format!("OEBPS/{}", image.href), deflated)?;
+        zip.write_all(&image.data)?;
+        manifest.push(Item::new(&image.id, &image.href, &image.mime));
+    }
+
+    // Navigation
+    let nav = nav_points(book, &files);
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(nav_document(&title, &language, &nav).as_bytes())?;
+
+    zip.start_file("OEBPS/toc.ncx", deflated)?;
+    zip.write_all(ncx_document(&title, &identifier, &nav).as_bytes())?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(
+        package_document(
+            metadata,
+            &title,
+            &language,
+            &identifier,
+            &manifest,
+            &spine,
+            has_cover,
+        )
+        .as_bytes(),
+    )?;
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Title to use for the package, falling back to the source file name
+fn book_title(book: &Book) -> String {
+    let title = book.metadata.title.trim();
+    if !title.is_empty() {
+        return title.to_string();
+    }
+    book.source_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// A manifest item
+struct Item {
+    id: String,
+    href: String,
+    mime: String,
+    properties: Option<&'static str>,
+}
+
+impl Item {
+    fn new(id: &str, href: &str, mime: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            href: href.to_string(),
+            mime: mime.to_string(),
+            properties: None,
+        }
+    }
+
+    fn with_properties(mut self, properties: &'static str) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+}
+
+/// An image embedded in the package
+struct Image {
+    id: String,
+    href: String,
+    mime: String,
+    data: Vec<u8>,
+}
+
+/// Images referenced by chapters, deduplicated by source
+struct Images {
+    source: PathBuf,
+    by_src: HashMap<String, Option<String>>,
+    items: Vec<Image>,
+}
+
+impl Images {
+    fn new(source: &Path) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            by_src: HashMap::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Get the package path of an image, embedding it on first use
+    fn resolve(&mut self, src: &str, data: Option<&[u8]>) -> Option<String> {
+        if let Some(href) = self.by_src.get(src) {
+            return href.clone();
+        }
+
+        let loaded = match data {
+            Some(data) => Some((data.to_vec(), formats::detect::image_mime(data).to_string())),
+            None => formats::load_resource(&self.source, src).unwrap_or_else(|e| {
+                warn!("Failed to load image {}: {}", src, e);
+                None
+            }),
+        };
+
+        let href = loaded.map(|(data, mime)| {
+            let n = self.items.len() + 1;
+            let href = format!("images/image-{:04}.{}", n, extension_for(&mime));
+            self.items.push(Image {
+                id: format!("image-{}", n),
+                href: href.clone(),
+                mime,
+                data,
+            });
+            href
+        });
+        if href.is_none() {
+            warn!("Image {} not found, keeping its caption only", src);
+        }
+
+        self.by_src.insert(src.to_string(), href.clone());
+        href
+    }
+}
+
+fn extension_for(mime: &str) -> &'static str {
+    match mime {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "image/bmp" => "bmp",
+        _ => "bin",
+    }
+}
+
+/// Wrap body content in an XHTML content document
+fn document(title: &str, language: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="../style.css"/>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        lang = xhtml::escape(language),
+        title = xhtml::escape(title),
+        body = body
+    )
+}
+
+/// An entry in the navigation document
+#[derive(Debug)]
+struct NavPoint {
+    title: String,
+    href: String,
+    children: Vec<NavPoint>,
+}
+
+/// Build the navigation tree from the book's TOC
+///
+/// TOC entries are matched to chapters by id, by `heading-N` anchor or by
+/// title. Entries that match nothing are dropped; when none match, every
+/// chapter gets an entry instead.
+fn nav_points(book: &Book, files: &[String]) -> Vec<NavPoint> {
+    let chapters = &book.content.chapters;
+
+    let mut flat = Vec::new();
+    flatten_toc(&book.content.toc, 0, &mut flat);
+    let mut resolved: Vec<(usize, String, String)> = flat
+        .into_iter()
+        .filter_map(|(level, entry)| {
+            resolve_toc_entry(entry, chapters, files)
+                .map(|href| (level, entry.title.trim().to_string(), href))
+        })
+        .collect();
+
+    if resolved.is_empty() {
+        resolved = chapters
+            .iter()
+            .zip(files)
+            .map(|(chapter, file)| (0, chapter.display_title(), file.clone()))
+            .collect();
+    }
+
+    nest(resolved)
+}
+
+fn flatten_toc<'a>(entries: &'a [TocEntry], depth: usize, out: &mut Vec<(usize, &'a TocEntry)>) {
+    for entry in entries {
+        let level = entry.level.max(depth);
+        out.push((level, entry));
+        flatten_toc(&entry.children, level + 1, out);
+    }
+}
+
+fn resolve_toc_entry(entry: &TocEntry, chapters: &[Chapter], files: &[String]) -> Option<String> {
+    let target = entry.href.split('#').next().unwrap_or_default();
+    if let Some(i) = chapters
+        .iter()
+        .position(|c| c.id == entry.href || c.id == target)
+    {
+        return Some(files[i].clone());
+    }
+
+    if let Some(n) = target
+        .strip_prefix("heading-")
+        .and_then(|n| n.parse::<usize>().ok())
+    {
+        if let Some(i) = chapters
+            .iter()
+            .position(|c| matches!(c.blocks.get(n), Some(ContentBlock::Heading { .. })))
+        {
+            return Some(format!("{}#heading-{}", files[i], n));
+        }
+    }
+
+    let title = entry.title.trim();
+    if let Some(i) = chapters
+        .iter()
+        .position(|c| c.title.as_deref().map(str::trim) == Some(title))
+    {
+        return Some(files[i].clone());
+    }
+
+    chapters.iter().zip(files).find_map(|(chapter, file)| {
+        chapter
+            .blocks
+            .iter()
+            .position(|b| matches!(b, ContentBlock::Heading { text, .. } if text.trim() == title))
+            .map(|n| format!("{}#heading-{}", file, n))
+    })
+}
+
+/// Turn a flat list of `(level, title, href)` into a tree
+fn nest(flat: Vec<(usize, String, String)>) -> Vec<NavPoint> {
+    let mut root: Vec<NavPoint> = Vec::new();
+    let mut levels: Vec<usize> = Vec::new();
+
+    for (level, title, href) in flat {
+        while levels.last().is_some_and(|&l| l >= level) {
+            levels.pop();
+        }
+
+        let mut siblings = &mut root;
+        for _ in 0..levels.len() {
+            siblings = &mut siblings
+                .last_mut()
+                .expect("every open level has an entry")
+                .children;
+        }
+        siblings.push(NavPoint {
+            title,
+            href,
+            children: Vec::new(),
+        });
+        levels.push(level);
+    }
+
+    root
+}
+
+fn nav_document(title: &str, language: &str, nav: &[NavPoint]) -> String {
+    fn list(points: &[NavPoint], out: &mut String) {
+        out.push_str("<ol>\n");
+        for point in points {
+            out.push_str(&format!(
+                "<li><a href=\"{}\">{}</a>",
+                xhtml::escape(&point.href),
+                xhtml::escape(&point.title)
+            ));
+            if !point.children.is_empty() {
+                out.push('\n');
+                list(&point.children, out);
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ol>\n");
+    }
+
+    let mut body = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n");
+    list(nav, &mut body);
+    body.push_str("</nav>\n");
+
+    // nav.xhtml lives next to the stylesheet rather than in text/
+    document(title, language, &body).replace("href=\"../style.css\"", "href=\"style.css\"")
+}
+
+fn ncx_document(title: &str, identifier: &str, nav: &[NavPoint]) -> String {
+    fn nav_map(points: &[NavPoint], order: &mut usize, out: &mut String) {
+        for point in points {
+            *order += 1;
+            out.push_str(&format!(
+                "<navPoint id=\"navpoint-{n}\" playOrder=\"{n}\">\n<navLabel><text>{}</text></navLabel>\n<content src=\"{}\"/>\n",
+                xhtml::escape(&point.title),
+                xhtml::escape(&point.href),
+                n = order
+            ));
+            nav_map(&point.children, order, out);
+            out.push_str("</navPoint>\n");
+        }
+    }
+
+    fn depth(points: &[NavPoint]) -> usize {
+        points
+            .iter()
+            .map(|p| 1 + depth(&p.children))
+            .max()
+            .unwrap_or(0)
+    }
+
+    let mut map = String::new();
+    nav_map(nav, &mut 0, &mut map);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head>
+<meta name="dtb:uid" content="{}"/>
+<meta name="dtb:depth" content="{}"/>
+<meta name="dtb:totalPageCount" content="0"/>
+<meta name="dtb:maxPageNumber" content="0"/>
+</head>
+<docTitle><text>{}</text></docTitle>
+<navMap>
+{}</navMap>
+</ncx>
+"#,
+        xhtml::escape(identifier),
+        depth(nav).max(1),
+        xhtml::escape(title),
+        map
+    )
+}
+
+fn package_document(
+    metadata: &BookMetadata,
+    title: &str,
+    language: &str,
+    identifier: &str,
+    manifest: &[Item],
+    spine: &[String],
+    has_cover: bool,
+) -> String {
+    let mut meta = String::new();
+    meta.push_str(&format!(
+        "<dc:identifier id=\"book-id\">{}</dc:identifier>\n",
+        xhtml::escape(identifier)
+    ));
+    meta.push_str(&format!("<dc:title>{}</dc:title>\n", xhtml::escape(title)));
+    meta.push_str(&format!(
+        "<dc:language>{}</dc:language>\n",
+        xhtml::escape(language)
+    ));
+    for (i, author) in metadata.authors.iter().enumerate() {
+        meta.push_str(&format!(
+            "<dc:creator id=\"creator-{n}\">{}</dc:creator>\n<meta refines=\"#creator-{n}\" property=\"role\" scheme=\"marc:relators\">aut</meta>\n",
+            xhtml::escape(author),
+            n = i + 1
+        ));
+    }
+    let optional = [
+        ("publisher", &metadata.publisher),
+        ("date", &metadata.published),
+        ("description", &metadata.description),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            meta.push_str(&format!(
+                "<dc:{name}>{}</dc:{name}>\n",
+                xhtml::escape(value),
+                name = name
+            ));
+        }
+    }
+    for subject in &metadata.subjects {
+        meta.push_str(&format!(
+            "<dc:subject>{}</dc:subject>\n",
+            xhtml::escape(subject)
+        ));
+    }
+    if let Some(series) = &metadata.series {
+        meta.push_str(&format!(
+            "<meta property=\"belongs-to-collection\" id=\"series\">{}</meta>\n<meta refines=\"#series\" property=\"collection-type\">series</meta>\n",
+            xhtml::escape(series)
+        ));
+        // Calibre's series metadata is what most e-ink readers look at
+        meta.push_str(&format!(
+            "<meta name=\"calibre:series\" content=\"{}\"/>\n",
+            xhtml::escape(series)
+        ));
+        if let Some(index) = metadata.series_index {
+            meta.push_str(&format!(
+                "<meta refines=\"#series\" property=\"group-position\">{0}</meta>\n<meta name=\"calibre:series_index\" content=\"{0}\"/>\n",
+                index
+            ));
+        }
+    }
+    if has_cover {
+        // EPUB 2 readers find the cover through this
+        meta.push_str("<meta name=\"cover\" content=\"cover-image\"/>\n");
+    }
+    meta.push_str(&format!(
+        "<meta property=\"dcterms:modified\">{}</meta>\n",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+
+    let items: String = manifest
+        .iter()
+        .map(|item| {
+            let properties = item
+                .properties
+                .map(|p| format!(" properties=\"{}\"", p))
+                .unwrap_or_default();
+            format!(
+                "<item id=\"{}\" href=\"{}\" media-type=\"{}\"{}/>\n",
+                xhtml::escape(&item.id),
+                xhtml::escape(&item.href),
+                xhtml::escape(&item.mime),
+                properties
+            )
+        })
+        .collect();
+
+    let itemrefs: String = spine
+        .iter()
+        .map(|id| format!("<itemref idref=\"{}\"/>\n", xhtml::escape(id)))
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{lang}">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{meta}</metadata>
+<manifest>
+{items}</manifest>
+<spine toc="ncx">
+{itemrefs}</spine>
+</package>
+"#,
+        lang = xhtml::escape(language),
+        meta = meta,
+        items = items,
+        itemrefs = itemrefs
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::BookContent;
+    use std::io::{Cursor, Read};
+
+    fn sample_book() -> Book {
+        let mut one = Chapter::new("one".to_string(), 0);
+        one.title = Some("Beginning".to_string());
+        one.blocks = vec![
+            ContentBlock::Heading {
+                level: 1,
+                text: "Beginning".to_string(),
+            },
+            ContentBlock::Paragraph {
+                text: "Fish & chips".to_string(),
+                styles: vec![],
+            },
+            ContentBlock::Heading {
+                level: 2,
+                text: "A detail".to_string(),
+            },
+        ];
+        let mut two = Chapter::new("two".to_string(), 1);
+        two.blocks = vec![ContentBlock::Paragraph {
+            text: "The end.".to_string(),
+            styles: vec![],
+        }];
+
+        let mut detail = TocEntry::new("A detail".to_string(), "heading-2".to_string(), 1);
+        detail.level = 1;
+        Book {
+            metadata: BookMetadata {
+                title: "Sample".to_string(),
+                authors: vec!["Ann Author".to_string()],
+                series: Some("Samples".to_string()),
+                series_index: Some(2.0),
+                ..Default::default()
+            },
+            content: BookContent {
+                chapters: vec![one, two],
+                toc: vec![
+                    TocEntry::new("Beginning".to_string(), "one".to_string(), 0),
+                    detail,
+                    TocEntry::new("Missing".to_string(), "nowhere".to_string(), 0),
+                ],
+            },
+            source_path: PathBuf::from("sample.md"),
+            format: "markdown".to_string(),
+        }
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_write_epub_package() {
+        let mut out = Cursor::new(Vec::new());
+        write_epub_to(&sample_book(), &mut out).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(out.into_inner())).unwrap();
+
+        let first = archive.by_index(0).unwrap();
+        assert_eq!(first.name(), "mimetype");
+        assert_eq!(first.compression(), CompressionMethod::Stored);
+        drop(first);
+
+        let opf = read_entry(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Sample</dc:title>"));
+        assert!(opf.contains("<dc:creator id=\"creator-1\">Ann Author</dc:creator>"));
+        assert!(opf.contains("property=\"group-position\">2</meta>"));
+        assert!(opf.contains("properties=\"nav\""));
+
+        let nav = read_entry(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains("<a href=\"text/chapter-0001.xhtml\">Beginning</a>\n<ol>"));
+        assert!(nav.contains("text/chapter-0001.xhtml#heading-2"));
+        assert!(!nav.contains("Missing"));
+
+        let chapter = read_entry(&mut archive, "OEBPS/text/chapter-0001.xhtml");
+        assert!(chapter.contains("<p>Fish &amp; chips</p>"));
+    }
+
+    #[test]
+    fn test_nest_levels() {
+        let flat = vec![
+            (0, "a".to_string(), "a".to_string()),
+            (1, "a.1".to_string(), "a1".to_string()),
+            (2, "a.1.1".to_string(), "a11".to_string()),
+            (1, "a.2".to_string(), "a2".to_string()),
+            (0, "b".to_string(), "b".to_string()),
+        ];
+        let tree = nest(flat);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].children.len(), 2);
+        assert_eq!(tree[0].children[0].children[0].title, "a.1.1");
+        assert!(tree[1].children.is_empty());
+    }
+}
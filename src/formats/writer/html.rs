@@ -0,0 +1,123 @@
+//! Standalone HTML writer
+//!
+//! Produces one self-contained page: the stylesheet is inlined and images
+//! are embedded as data URIs, so the file can be opened or shared on its own.
+
+use super::{chapter_heading, xhtml, WriteOptions};
+use crate::formats::{self, Book};
+use base64::Engine;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Serialize a book as a standalone HTML page
+pub fn write(book: &Book, options: &WriteOptions) -> String {
+    let metadata = &book.metadata;
+    let mut images: HashMap<String, Option<String>> = HashMap::new();
+
+    let mut toc = String::new();
+    let mut body = String::new();
+    for (index, chapter) in book.content.chapters.iter().enumerate() {
+        let anchor = format!("chapter-{}", index + 1);
+        toc.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a></li>\n",
+            anchor,
+            xhtml::escape(&chapter.display_title())
+        ));
+
+        body.push_str(&format!("<section class=\"chapter\" id=\"{}\">\n", anchor));
+        if let Some(title) = chapter_heading(chapter) {
+            body.push_str(&format!("<h1>{}</h1>\n", xhtml::escape(title)));
+        }
+        body.push_str(&xhtml::blocks(
+            chapter,
+            index,
+            &format!("{}-", anchor),
+            &options.highlights,
+            &mut |src, data| {
+                images
+                    .entry(src.to_string())
+                    .or_insert_with(|| data_uri(book, src, data))
+                    .clone()
+            },
+        ));
+        body.push_str("</section>\n");
+    }
+
+    let byline = if metadata.authors.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<p class=\"book-author\">{}</p>\n",
+            xhtml::escape(&metadata.authors_string())
+        )
+    };
+    let description = metadata
+        .description
+        .as_ref()
+        .map(|d| format!("<p class=\"book-description\">{}</p>\n", xhtml::escape(d)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="generator" content="franko {version}">
+<title>{title}</title>
+<style>
+{css}</style>
+</head>
+<body>
+<main class="book">
+<header class="title-page">
+<h1 class="book-title">{title}</h1>
+{byline}{description}</header>
+<nav class="toc">
+<h2>Contents</h2>
+<ol>
+{toc}</ol>
+</nav>
+{body}</main>
+</body>
+</html>
+"#,
+        lang = xhtml::escape(metadata.language.as_deref().unwrap_or("en")),
+        version = env!("CARGO_PKG_VERSION"),
+        title = xhtml::escape(&metadata.title),
+        css = options.stylesheet,
+        byline = byline,
+        description = description,
+        toc = toc,
+        body = body
+    )
+}
+
+/// Embed an image as a data URI, keeping remote URLs as they are
+fn data_uri(book: &Book, src: &str, data: Option<&[u8]>) -> Option<String> {
+    if src.contains("://") || src.starts_with("data:") {
+        return Some(src.to_string());
+    }
+
+    let loaded = match data {
+        Some(data) => Some((data.to_vec(), "application/octet-stream".to_string())),
+        None => formats::load_resource(&book.source_path, src).unwrap_or_else(|e| {
+            warn!("Failed to load image {}: {}", src, e);
+            None
+        }),
+    };
+
+    let Some((data, mut mime)) = loaded else {
+        warn!("Image {} not found, keeping its caption only", src);
+        return None;
+    };
+    if mime == "application/octet-stream" {
+        mime = formats::detect::image_mime(&data).to_string();
+    }
+
+    Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(&data)
+    ))
+}
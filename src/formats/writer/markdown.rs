@@ -0,0 +1,519 @@
+//! Markdown writer
+//!
+//! Produces a single CommonMark document with a YAML frontmatter block that
+//! the Markdown parser reads back. Highlights use inline `<mark>` and
+//! comments become blockquotes after the annotated block.
+
+use super::{
+    apply_spans, block_highlights, chapter_heading, highlight_comment, highlight_range,
+    style_spans, Highlight, Span, StyleType, WriteOptions,
+};
+use crate::formats::{Book, BookMetadata, Chapter, ContentBlock, MetadataEdit};
+
+/// Serialize a book as Markdown
+pub fn write(book: &Book, options: &WriteOptions) -> String {
+    let math = book
+        .content
+        .chapters
+        .iter()
+        .flat_map(|c| &c.blocks)
+        .any(|b| match b {
+            ContentBlock::Math { .. } => true,
+            ContentBlock::Paragraph { styles, .. } => {
+                styles.iter().any(|s| s.style_type == StyleType::Math)
+            }
+            _ => false,
+        });
+    let mut out = frontmatter(&book.metadata, math);
+
+    for (index, chapter) in book.content.chapters.iter().enumerate() {
+        out.push('\n');
+        out.push_str(&chapter_markdown(chapter, index, &options.highlights));
+    }
+
+    out
+}
+
+/// Frontmatter for the book's metadata; `math` enables formula parsing
+fn frontmatter(metadata: &BookMetadata, math: bool) -> String {
+    let mut fields: Vec<(&str, String)> = vec![("title", metadata.title.clone())];
+    if !metadata.authors.is_empty() {
+        fields.push(("author", metadata.authors.join(", ")));
+    }
+    let optional = [
+        ("date", &metadata.published),
+        ("publisher", &metadata.publisher),
+        ("lang", &metadata.language),
+        ("isbn", &metadata.isbn),
+        ("series", &metadata.series),
+        ("description", &metadata.description),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            fields.push((key, value.clone()));
+        }
+    }
+    if let Some(index) = metadata.series_index {
+        fields.push(("series_index", index.to_string()));
+    }
+    if !metadata.subjects.is_empty() {
+        fields.push(("tags", metadata.subjects.join(", ")));
+    }
+    if math {
+        fields.push(("math", "true".to_string()));
+    }
+
+    let mut out = String::from("---\n");
+    for (key, value) in fields {
+        out.push_str(&frontmatter_line(key, &value));
+    }
+    out.push_str("---\n");
+    out
+}
+
+/// Format one quoted frontmatter field
+fn frontmatter_line(key: &str, value: &str) -> String {
+    // The frontmatter reader is line based, so keep each value on one line
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}: \"{}\"\n",
+        key,
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Rewrite the frontmatter of a Markdown document with edited metadata
+///
+/// Fields the edit does not touch, unknown keys and the document body are
+/// kept as they are. Documents without frontmatter get a new block holding
+/// just the edited fields.
+pub fn update_frontmatter(source: &str, edit: &MetadataEdit) -> String {
+    let list = |items: &[String]| {
+        items
+            .iter()
+            .map(|i| i.trim())
+            .filter(|i| !i.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    // Frontmatter key, the parser's aliases for it, and the new value
+    // (empty to remove the field)
+    let mut edits: Vec<(&str, &[&str], String)> = Vec::new();
+    if let Some(title) = &edit.title {
+        edits.push(("title", &["title"], title.clone()));
+    }
+    if let Some(authors) = &edit.authors {
+        edits.push(("author", &["author", "authors"], list(authors)));
+    }
+    if let Some(series) = &edit.series {
+        edits.push(("series", &["series"], series.clone()));
+        if series.trim().is_empty() && edit.series_index.is_none() {
+            edits.push(("series_index", &["series_index"], String::new()));
+        }
+    }
+    if let Some(index) = edit.series_index {
+        edits.push(("series_index", &["series_index"], index.to_string()));
+    }
+    if let Some(tags) = &edit.tags {
+        edits.push(("tags", &["tags", "subjects"], list(tags)));
+    }
+    if let Some(description) = &edit.description {
+        edits.push((
+            "description",
+            &["description", "summary"],
+            description.clone(),
+        ));
+    }
+    if let Some(publisher) = &edit.publisher {
+        edits.push(("publisher", &["publisher"], publisher.clone()));
+    }
+    if let Some(language) = &edit.language {
+        edits.push(("lang", &["lang", "language"], language.clone()));
+    }
+
+    // Same frontmatter boundaries as the parser
+    let (frontmatter, body) = match source
+        .strip_prefix("---")
+        .and_then(|rest| rest.find("---").map(|end| (&rest[..end], &rest[end + 3..])))
+    {
+        Some(found) => found,
+        None => ("\n", source),
+    };
+
+    let mut written = vec![false; edits.len()];
+    let mut out = String::from("---");
+    let mut replacing = false;
+    for line in frontmatter.split_inclusive('\n') {
+        // A replaced value may go on in indented lines or block list items
+        if replacing && (line.starts_with([' ', '\t']) || is_list_item(line)) {
+            continue;
+        }
+        replacing = false;
+
+        let key = line
+            .split_once(':')
+            .map(|(key, _)| key.trim().to_lowercase())
+            .unwrap_or_default();
+        match edits
+            .iter()
+            .position(|(_, aliases, _)| aliases.contains(&key.as_str()))
+        {
+            Some(i) => {
+                // Replace the first occurrence, drop any duplicates
+                if !written[i] && !edits[i].2.trim().is_empty() {
+                    out.push_str(&frontmatter_line(edits[i].0, edits[i].2.trim()));
+                }
+                written[i] = true;
+                replacing = true;
+            }
+            None => out.push_str(line),
+        }
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    for ((key, _, value), written) in edits.iter().zip(written) {
+        if !written && !value.trim().is_empty() {
+            out.push_str(&frontmatter_line(key, value.trim()));
+        }
+    }
+    out.push_str("---");
+    if !body.starts_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(body);
+    out
+}
+
+/// Whether a frontmatter line is an item of a YAML block list
+fn is_list_item(line: &str) -> bool {
+    let line = line.trim_end();
+    line == "-" || line.starts_with("- ")
+}
+
+fn chapter_markdown(chapter: &Chapter, chapter_index: usize, highlights: &[Highlight]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(title) = chapter_heading(chapter) {
+        parts.push(format!("# {}", escape_inline(title)));
+    }
+
+    for (index, block) in chapter.blocks.iter().enumerate() {
+        let block_highlights = block_highlights(highlights, chapter_index, index);
+        let mut marked = vec![false; block_highlights.len()];
+
+        let rendered = match block {
+            ContentBlock::Paragraph { text, styles } => {
+                let mut spans = Vec::new();
+                for (h, marked) in block_highlights.iter().zip(marked.iter_mut()) {
+                    if let Some((start, end)) = highlight_range(text, h) {
+                        spans.push(Span {
+                            start,
+                            end,
+                            open: "<mark>".to_string(),
+                            close: "</mark>".to_string(),
+                            replace: None,
+                        });
+                        *marked = true;
+                    }
+                }
+                spans.extend(style_spans(styles, tags, inline_math));
+                Some(escape_line_starts(&apply_spans(
+                    text,
+                    &spans,
+                    escape_inline,
+                )))
+            }
+            ContentBlock::Heading { level, text } => Some(format!(
+                "{} {}",
+                "#".repeat((*level).clamp(1, 6) as usize),
+                escape_inline(text)
+            )),
+            ContentBlock::Quote { text, attribution } => {
+                let mut quote = quote_lines(&escape_line_starts(&escape_inline(text)));
+                if let Some(attr) = attribution {
+                    quote.push_str(&format!("\n>\n> — {}", escape_inline(attr)));
+                }
+                Some(quote)
+            }
+            ContentBlock::Code { language, code } => {
+                // Use a fence longer than any backtick run in the code
+                let longest = code
+                    .split(|c| c != '`')
+                    .map(|run| run.len())
+                    .max()
+                    .unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                Some(format!(
+                    "{}{}\n{}\n{}",
+                    fence,
+                    language.as_deref().unwrap_or_default(),
+                    code.trim_end_matches('\n'),
+                    fence
+                ))
+            }
+            ContentBlock::Image {
+                src, alt, caption, ..
+            } => {
+                let title = caption
+                    .as_ref()
+                    .map(|c| format!(" \"{}\"", c.replace('"', "\\\"")))
+                    .unwrap_or_default();
+                Some(format!(
+                    "![{}](<{}>{})",
+                    escape_inline(alt.as_deref().unwrap_or_default()),
+                    src.replace('>', "%3E"),
+                    title
+                ))
+            }
+            ContentBlock::List { ordered, items } => Some(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        let marker = if *ordered {
+                            format!("{}. ", i + 1)
+                        } else {
+                            "- ".to_string()
+                        };
+                        let indent = " ".repeat(marker.len());
+                        let item = escape_inline(item).replace('\n', &format!("\n{}", indent));
+                        format!("{}{}", marker, item)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            ContentBlock::Separator => Some("***".to_string()),
+            ContentBlock::Footnote { id, content } => {
+                Some(format!("[^{}]: {}", id, escape_inline(content)))
+            }
+            ContentBlock::RawHtml { html } => Some(html.trim().to_string()),
+            ContentBlock::Table { headers, rows } => Some(table(headers, rows)),
+            ContentBlock::Break => None,
+            ContentBlock::Math { tex } => Some(format!("$$\n{}\n$$", tex.trim())),
+        };
+
+        if let Some(rendered) = rendered.filter(|r| !r.is_empty()) {
+            parts.push(rendered);
+        }
+        for (h, marked) in block_highlights.iter().zip(marked) {
+            if let Some(comment) = highlight_comment(h, marked) {
+                parts.push(quote_lines(&format!(
+                    "**Note:** {}",
+                    escape_inline(&comment)
+                )));
+            }
+        }
+    }
+
+    let mut out = parts.join("\n\n");
+    out.push('\n');
+    out
+}
+
+fn tags(style: StyleType, data: Option<&str>) -> (String, String) {
+    let (open, close) = match style {
+        StyleType::Bold => ("**", "**"),
+        StyleType::Italic => ("*", "*"),
+        StyleType::Strikethrough => ("~~", "~~"),
+        StyleType::Code => ("`", "`"),
+        StyleType::Underline => ("<u>", "</u>"),
+        StyleType::Superscript => ("<sup>", "</sup>"),
+        StyleType::Subscript => ("<sub>", "</sub>"),
+        StyleType::Link => match data {
+            Some(url) => return ("[".to_string(), format!("]({})", link_destination(url))),
+            None => ("", ""),
+        },
+        StyleType::SmallCaps | StyleType::Math => ("", ""),
+    };
+    (open.to_string(), close.to_string())
+}
+
+/// A link target as written in Markdown, in angle brackets when it has
+/// spaces or parentheses
+fn link_destination(url: &str) -> String {
+    if url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+fn inline_math(tex: &str) -> String {
+    format!("${}$", tex.trim())
+}
+
+fn table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let columns = rows
+        .iter()
+        .map(|r| r.len())
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+
+    let row = |cells: &[String]| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| {
+                cells
+                    .get(i)
+                    .map(|c| escape_inline(c).replace('|', "\\|").replace('\n', " "))
+                    .unwrap_or_default()
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![row(headers), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows.iter().map(|r| row(r)));
+    lines.join("\n")
+}
+
+fn quote_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                ">".to_string()
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Escape characters that would start inline Markdown syntax
+fn escape_inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '$') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape characters that would start a block when they open a line
+fn escape_line_starts(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let digits = trimmed.len()
+                - trimmed
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            if trimmed.starts_with(['#', '-', '+', '=', '|']) {
+                format!("\\{}", trimmed)
+            } else if digits > 0 && trimmed[digits..].starts_with(['.', ')']) {
+                // `1.` would start an ordered list
+                format!("{}\\{}", &trimmed[..digits], &trimmed[digits..])
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{BookContent, TocEntry};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_markdown_roundtrip_metadata() {
+        let mut chapter = Chapter::new("main".to_string(), 0);
+        chapter.blocks = vec![
+            ContentBlock::Heading {
+                level: 1,
+                text: "Opening".to_string(),
+            },
+            ContentBlock::Paragraph {
+                text: "1. Not a list, *not* emphasis".to_string(),
+                styles: vec![],
+            },
+        ];
+        let book = Book {
+            metadata: BookMetadata {
+                title: "Say \"hi\"".to_string(),
+                authors: vec!["A".to_string(), "B".to_string()],
+                subjects: vec!["x".to_string()],
+                ..Default::default()
+            },
+            content: BookContent {
+                chapters: vec![chapter],
+                toc: vec![TocEntry::new("Opening".into(), "heading-0".into(), 0)],
+            },
+            source_path: PathBuf::from("in.epub"),
+            format: "epub".to_string(),
+        };
+
+        let md = write(&book, &WriteOptions::default());
+        assert!(md.starts_with("---\ntitle: \"Say \\\"hi\\\"\"\nauthor: \"A, B\"\n"));
+        assert!(md.contains("\n# Opening\n\n1\\. Not a list, \\*not\\* emphasis\n"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.md");
+        std::fs::write(&path, &md).unwrap();
+        let parsed = crate::formats::parse_book(&path).unwrap();
+        assert_eq!(parsed.metadata.title, "Say \"hi\"");
+        assert_eq!(parsed.metadata.authors, vec!["A", "B"]);
+        assert_eq!(parsed.metadata.subjects, vec!["x"]);
+    }
+
+    #[test]
+    fn test_update_frontmatter_keeps_other_fields() {
+        let source = "---\ntitle: Old\nlayout: post\nauthors: A, B\n---\n\n# Body\n";
+        let edit = MetadataEdit {
+            title: Some("New \"one\"".to_string()),
+            authors: Some(vec!["C".to_string()]),
+            series: Some("Saga".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_frontmatter(source, &edit),
+            "---\ntitle: \"New \\\"one\\\"\"\nlayout: post\nauthor: \"C\"\nseries: \"Saga\"\n---\n\n# Body\n"
+        );
+
+        let cleared = MetadataEdit {
+            authors: Some(vec![]),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_frontmatter("---\nauthor: A\n---\nText", &cleared),
+            "---\n---\nText"
+        );
+    }
+
+    #[test]
+    fn test_update_frontmatter_replaces_block_lists() {
+        let edit = MetadataEdit {
+            tags: Some(vec!["gamma".to_string()]),
+            authors: Some(vec!["C".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_frontmatter(
+                "---\ntags:\n  - alpha\n  - beta\nauthors:\n- A\n- B\nlayout: post\n---\nText",
+                &edit
+            ),
+            "---\ntags: \"gamma\"\nauthor: \"C\"\nlayout: post\n---\nText"
+        );
+    }
+
+    #[test]
+    fn test_update_frontmatter_adds_block() {
+        let edit = MetadataEdit {
+            title: Some("Fresh".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            update_frontmatter("# Heading\n", &edit),
+            "---\ntitle: \"Fresh\"\n---\n# Heading\n"
+        );
+    }
+}
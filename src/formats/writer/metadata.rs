@@ -0,0 +1,439 @@
+//! In-place metadata updates
+//!
+//! Edits are written back into the book file itself: an EPUB gets its OPF
+//! package document rewritten, a Markdown file its frontmatter. Every other
+//! part of the file is copied unchanged, and the result replaces the
+//! original only once it has been written completely.
+
+use super::{markdown, xhtml};
+use crate::formats::{BookFormat, MetadataEdit};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Write metadata changes into a book file
+pub fn write_metadata(path: &Path, edit: &MetadataEdit) -> Result<()> {
+    #[cfg(feature = "compressed")]
+    if let Some(compression) = crate::formats::compressed::compression(path) {
+        bail!(
+            "Cannot write metadata into a {} compressed file: {}",
+            compression.name(),
+            path.display()
+        );
+    }
+
+    match BookFormat::detect(path) {
+        BookFormat::Epub => replace_file(path, |out| update_epub(path, edit, out)),
+        BookFormat::Markdown => {
+            let source = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let updated = markdown::update_frontmatter(&source, edit);
+            replace_file(path, |out| Ok(out.write_all(updated.as_bytes())?))
+        }
+        other => bail!(
+            "Writing metadata is only supported for EPUB and Markdown files, not {}",
+            other.name()
+        ),
+    }
+}
+
+/// Write a new version of `path` next to it, then move it into place
+fn replace_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let result = File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
+            Ok(())
+        })
+        .and_then(|_| {
+            // Keep the original file's permissions
+            if let Ok(meta) = std::fs::metadata(path) {
+                std::fs::set_permissions(&partial, meta.permissions())?;
+            }
+            std::fs::rename(&partial, path)
+                .with_context(|| format!("Failed to replace {}", path.display()))
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Copy an EPUB, rewriting only its package document
+fn update_epub<W: Write + Seek>(path: &Path, edit: &MetadataEdit, out: W) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = ZipArchive::new(BufReader::new(file))
+        .with_context(|| format!("Not a valid EPUB archive: {}", path.display()))?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = Regex::new(r#"<rootfile\b[^>]*\bfull-path\s*=\s*["']([^"']+)["']"#)
+        .unwrap()
+        .captures(&container)
+        .map(|c| c[1].to_string())
+        .context("EPUB container does not name a package document")?;
+    let opf = update_opf(&read_entry(&mut archive, &opf_path)?, edit)?;
+
+    let mut zip = ZipWriter::new(out);
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if entry.name() == opf_path {
+            let method = match entry.compression() {
+                CompressionMethod::Stored => CompressionMethod::Stored,
+                _ => CompressionMethod::Deflated,
+            };
+            drop(entry);
+            zip.start_file(
+                opf_path.as_str(),
+                SimpleFileOptions::default().compression_method(method),
+            )?;
+            zip.write_all(opf.as_bytes())?;
+        } else {
+            zip.raw_copy_file(entry)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String> {
+    let mut text = String::new();
+    archive
+        .by_name(name)
+        .with_context(|| format!("EPUB is missing {}", name))?
+        .read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Apply an edit to the `<metadata>` section of an OPF document
+///
+/// Elements for the edited fields are removed, together with any EPUB 3
+/// refinements pointing at them, and new ones are appended at the end of
+/// the section. Everything else is left byte for byte as it was.
+fn update_opf(opf: &str, edit: &MetadataEdit) -> Result<String> {
+    // An EPUB must keep a title and a language
+    for (name, value) in [("title", &edit.title), ("language", &edit.language)] {
+        if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+            bail!("The {} of an EPUB cannot be empty", name);
+        }
+    }
+
+    let section = Regex::new(r"(?s)<((?:\w+:)?metadata)\b[^>]*>(.*?)</((?:\w+:)?metadata)>")
+        .unwrap()
+        .captures(opf)
+        .context("Package document has no metadata section")?;
+    let body = section.get(2).unwrap();
+    let epub3 = Regex::new(r#"<(?:\w+:)?package\b[^>]*\bversion\s*=\s*["']3"#)
+        .unwrap()
+        .is_match(opf);
+
+    let mut metadata = body.as_str().to_string();
+    let mut added = Vec::new();
+    let mut text_element = |metadata: &mut String, name: &str, values: &[&str]| {
+        remove_elements(metadata, &format!(r"dc:{}", name), None);
+        for value in values.iter().map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            added.push(format!(
+                "<dc:{name}>{}</dc:{name}>",
+                xhtml::escape(value),
+                name = name
+            ));
+        }
+    };
+
+    if let Some(title) = &edit.title {
+        text_element(&mut metadata, "title", &[title]);
+        remove_elements(&mut metadata, "meta", Some("calibre:title_sort"));
+    }
+    if let Some(authors) = &edit.authors {
+        let authors: Vec<&str> = authors.iter().map(|a| a.as_str()).collect();
+        text_element(&mut metadata, "creator", &authors);
+        remove_elements(&mut metadata, "meta", Some("calibre:author_link_map"));
+    }
+    if let Some(tags) = &edit.tags {
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+        text_element(&mut metadata, "subject", &tags);
+    }
+    if let Some(description) = &edit.description {
+        text_element(&mut metadata, "description", &[description]);
+    }
+    if let Some(publisher) = &edit.publisher {
+        text_element(&mut metadata, "publisher", &[publisher]);
+    }
+    if let Some(language) = &edit.language {
+        text_element(&mut metadata, "language", &[language]);
+    }
+
+    if edit.series.is_some() || edit.series_index.is_some() {
+        // Series and index are stored together, so rewrite both
+        let current = |name: &str| {
+            Regex::new(&format!(
+                r#"<meta\b[^>]*\bname\s*=\s*["']{}["'][^>]*\bcontent\s*=\s*["']([^"']*)["']"#,
+                regex::escape(name)
+            ))
+            .unwrap()
+            .captures(&metadata)
+            .map(|c| c[1].to_string())
+        };
+        let collection = || {
+            Regex::new(r#"(?s)<meta\b[^>]*\bproperty\s*=\s*["']belongs-to-collection["'][^>]*>(.*?)</meta>"#)
+                .unwrap()
+                .captures(&metadata)
+                .map(|c| c[1].trim().to_string())
+        };
+        let series = edit
+            .series
+            .clone()
+            .or_else(|| current("calibre:series"))
+            .or_else(collection)
+            .filter(|s| !s.trim().is_empty());
+        // Clearing the series drops its index too
+        let index = match edit.series_index {
+            Some(index) => Some(index.to_string()),
+            None if series.is_none() => None,
+            None => current("calibre:series_index"),
+        };
+
+        remove_elements(&mut metadata, "meta", Some("calibre:series"));
+        remove_elements(&mut metadata, "meta", Some("calibre:series_index"));
+        remove_elements(&mut metadata, "meta", Some("belongs-to-collection"));
+
+        if let Some(series) = series {
+            let series = xhtml::escape(series.trim());
+            if epub3 {
+                added.push(format!(
+                    "<meta property=\"belongs-to-collection\" id=\"series\">{}</meta>",
+                    series
+                ));
+                added.push(
+                    "<meta refines=\"#series\" property=\"collection-type\">series</meta>"
+                        .to_string(),
+                );
+                if let Some(index) = &index {
+                    added.push(format!(
+                        "<meta refines=\"#series\" property=\"group-position\">{}</meta>",
+                        index
+                    ));
+                }
+            }
+            added.push(format!(
+                "<meta name=\"calibre:series\" content=\"{}\"/>",
+                series
+            ));
+            if let Some(index) = &index {
+                added.push(format!(
+                    "<meta name=\"calibre:series_index\" content=\"{}\"/>",
+                    index
+                ));
+            }
+        }
+    }
+
+    if epub3 {
+        remove_elements(&mut metadata, "meta", Some("dcterms:modified"));
+        added.push(format!(
+            "<meta property=\"dcterms:modified\">{}</meta>",
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        ));
+    }
+
+    // Match the indentation of the existing entries
+    let indent = Regex::new(r"\n([ \t]*)<")
+        .unwrap()
+        .captures(&metadata)
+        .map(|c| c[1].to_string())
+        .unwrap_or_default();
+    let closing = metadata.trim_end().len();
+    let trailing = metadata[closing..].to_string();
+    metadata.truncate(closing);
+    for element in added {
+        metadata.push('\n');
+        metadata.push_str(&indent);
+        metadata.push_str(&element);
+    }
+    metadata.push_str(if trailing.is_empty() { "\n" } else { &trailing });
+
+    Ok(format!(
+        "{}{}{}",
+        &opf[..body.start()],
+        metadata,
+        &opf[body.end()..]
+    ))
+}
+
+/// Remove elements named `tag`, optionally only those whose `name` or
+/// `property` attribute is `kind`, along with refinements of their ids
+fn remove_elements(metadata: &mut String, tag: &str, kind: Option<&str>) {
+    let tag = regex::escape(tag);
+    let element = Regex::new(&format!(
+        r"(?s)[ \t]*<{tag}\b([^>]*?)(?:/>|>.*?</{tag}>)[ \t]*\r?\n?",
+        tag = tag
+    ))
+    .unwrap();
+    let kind_attr = kind.map(|kind| {
+        Regex::new(&format!(
+            r#"\b(?:name|property)\s*=\s*["']{}["']"#,
+            regex::escape(kind)
+        ))
+        .unwrap()
+    });
+    let id_attr = Regex::new(r#"\bid\s*=\s*["']([^"']+)["']"#).unwrap();
+
+    let mut ids = Vec::new();
+    let updated = element.replace_all(metadata, |caps: &regex::Captures| {
+        let attrs = &caps[1];
+        if kind_attr.as_ref().is_some_and(|k| !k.is_match(attrs)) {
+            return caps[0].to_string();
+        }
+        if let Some(id) = id_attr.captures(attrs) {
+            ids.push(id[1].to_string());
+        }
+        String::new()
+    });
+    *metadata = updated.into_owned();
+
+    for id in ids {
+        let refinement = Regex::new(&format!(
+            r#"(?s)[ \t]*<meta\b[^>]*\brefines\s*=\s*["']#{}["'][^>]*?(?:/>|>.*?</meta>)[ \t]*\r?\n?"#,
+            regex::escape(&id)
+        ))
+        .unwrap();
+        *metadata = refinement.replace_all(metadata, "").into_owned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{Book, BookContent, BookMetadata, Chapter, ContentBlock};
+
+    const OPF: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:title id="t1">Old Title</dc:title>
+    <meta refines="#t1" property="title-type">main</meta>
+    <dc:creator id="c1">Old Author</dc:creator>
+    <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+    <meta name="calibre:series" content="Old Series"/>
+    <meta name="calibre:series_index" content="3"/>
+    <meta property="dcterms:modified">2020-01-01T00:00:00Z</meta>
+  </metadata>
+  <manifest/>
+</package>
+"##;
+
+    #[test]
+    fn test_update_opf_replaces_edited_fields() {
+        let edit = MetadataEdit {
+            title: Some("New & Improved".to_string()),
+            authors: Some(vec!["One".to_string(), "Two".to_string()]),
+            series_index: Some(4.0),
+            ..Default::default()
+        };
+        let opf = update_opf(OPF, &edit).unwrap();
+
+        assert!(opf.contains("<dc:identifier id=\"uid\">urn:uuid:1234</dc:identifier>"));
+        assert!(!opf.contains("Old Title"));
+        assert!(!opf.contains("refines=\"#t1\""));
+        assert!(!opf.contains("refines=\"#c1\""));
+        assert!(opf.contains("\n    <dc:title>New &amp; Improved</dc:title>"));
+        assert!(opf.contains("<dc:creator>One</dc:creator>\n    <dc:creator>Two</dc:creator>"));
+        // The series name is kept when only the index changes
+        assert!(opf.contains("property=\"belongs-to-collection\" id=\"series\">Old Series<"));
+        assert!(opf.contains("<meta name=\"calibre:series_index\" content=\"4\"/>"));
+        assert!(!opf.contains("2020-01-01"));
+        assert!(opf.ends_with("\n  </metadata>\n  <manifest/>\n</package>\n"));
+    }
+
+    #[test]
+    fn test_update_opf_clears_series() {
+        let edit = MetadataEdit {
+            series: Some(String::new()),
+            ..Default::default()
+        };
+        let opf = update_opf(OPF, &edit).unwrap();
+        assert!(!opf.contains("calibre:series"));
+        assert!(!opf.contains("belongs-to-collection"));
+        assert!(opf.contains("<dc:title id=\"t1\">Old Title</dc:title>"));
+    }
+
+    #[test]
+    fn test_update_opf_rejects_empty_required_fields() {
+        for edit in [
+            MetadataEdit {
+                title: Some(" ".to_string()),
+                ..Default::default()
+            },
+            MetadataEdit {
+                language: Some(String::new()),
+                ..Default::default()
+            },
+        ] {
+            assert!(update_opf(OPF, &edit).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_metadata_epub_roundtrip() {
+        let mut chapter = Chapter::new("one".to_string(), 0);
+        chapter.blocks.push(ContentBlock::Paragraph {
+            text: "Unchanged text.".to_string(),
+            styles: vec![],
+        });
+        let book = Book {
+            metadata: BookMetadata {
+                title: "Before".to_string(),
+                authors: vec!["Someone".to_string()],
+                ..Default::default()
+            },
+            content: BookContent {
+                chapters: vec![chapter],
+                toc: vec![],
+            },
+            source_path: PathBuf::from("in.md"),
+            format: "markdown".to_string(),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.epub");
+        super::super::epub::write_epub(&book, &path).unwrap();
+        let chapter_before = {
+            let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+            read_entry(&mut archive, "OEBPS/text/chapter-0001.xhtml").unwrap()
+        };
+
+        let edit = MetadataEdit {
+            title: Some("After".to_string()),
+            authors: Some(vec!["A".to_string(), "B".to_string()]),
+            series: Some("Saga".to_string()),
+            series_index: Some(2.0),
+            tags: Some(vec!["one".to_string(), "two".to_string()]),
+            ..Default::default()
+        };
+        write_metadata(&path, &edit).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(
+            read_entry(&mut archive, "OEBPS/text/chapter-0001.xhtml").unwrap(),
+            chapter_before
+        );
+        assert!(!dir.path().join("book.epub.part").exists());
+
+        let metadata = crate::formats::get_metadata(&path).unwrap();
+        assert_eq!(metadata.title, "After");
+        assert_eq!(metadata.authors, vec!["A", "B"]);
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(metadata.series_index, Some(2.0));
+        assert_eq!(metadata.subjects, vec!["one", "two"]);
+    }
+}
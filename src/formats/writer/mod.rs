@@ -0,0 +1,262 @@
+//! Book serialization
+//!
+//! Writers turn a parsed [`Book`] back into a file. The output format is
+//! chosen from the destination's extension. Metadata edits can also be
+//! written into an existing EPUB or Markdown file.
+
+mod epub;
+mod html;
+mod markdown;
+mod metadata;
+mod text;
+mod xhtml;
+
+pub use metadata::write_metadata;
+pub use xhtml::inline as inline_html;
+
+use super::book::{StyleType, TextStyle};
+use super::{Book, Chapter, ContentBlock};
+use crate::hyphenate::Hyphenator;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// Extensions accepted for output files, for error messages
+const SUPPORTED: &str = ".epub, .md, .txt or .html";
+
+/// Formats a book can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Epub,
+    Markdown,
+    Text,
+    Html,
+}
+
+impl OutputFormat {
+    /// Pick the output format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "epub" => Some(OutputFormat::Epub),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "txt" | "text" => Some(OutputFormat::Text),
+            "html" | "htm" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// Get the default file extension
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Epub => "epub",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Text => "txt",
+            OutputFormat::Html => "html",
+        }
+    }
+}
+
+/// A highlighted passage, with an optional comment, to include in exports
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    /// Chapter index
+    pub chapter: usize,
+
+    /// Block index within the chapter
+    pub block: usize,
+
+    /// Highlighted text
+    pub text: String,
+
+    /// User comment
+    pub note: Option<String>,
+}
+
+/// Options for the text-based writers
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Wrap width for plain text (0 disables wrapping)
+    pub width: usize,
+
+    /// Stylesheet inlined into HTML output
+    pub stylesheet: String,
+
+    /// Highlights and comments to include
+    pub highlights: Vec<Highlight>,
+
+    /// Hyphenation for wrapped plain text
+    pub hyphenator: Hyphenator,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            stylesheet: String::new(),
+            highlights: Vec::new(),
+            hyphenator: Hyphenator::NONE,
+        }
+    }
+}
+
+/// Write a book to `path` in the format implied by its extension
+pub fn write_book(book: &Book, path: &Path, options: &WriteOptions) -> Result<()> {
+    let output = match OutputFormat::from_path(path) {
+        Some(OutputFormat::Epub) => return epub::write_epub(book, path),
+        Some(OutputFormat::Markdown) => markdown::write(book, options),
+        Some(OutputFormat::Text) => text::write(book, options),
+        Some(OutputFormat::Html) => html::write(book, options),
+        None => bail!(
+            "Unsupported output format: {} (expected {})",
+            path.display(),
+            SUPPORTED
+        ),
+    };
+
+    std::fs::write(path, output).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Check that `path` names a supported output format
+pub fn check_output(path: &Path) -> Result<OutputFormat> {
+    OutputFormat::from_path(path).with_context(|| {
+        format!(
+            "Unsupported output format: {} (expected {})",
+            path.display(),
+            SUPPORTED
+        )
+    })
+}
+
+/// Title to print above a chapter that does not open with its own heading
+fn chapter_heading(chapter: &Chapter) -> Option<&str> {
+    let title = chapter.title.as_deref()?;
+    match chapter.blocks.first() {
+        Some(ContentBlock::Heading { .. }) => None,
+        _ => Some(title),
+    }
+}
+
+/// Highlights attached to one block
+fn block_highlights(highlights: &[Highlight], chapter: usize, block: usize) -> Vec<&Highlight> {
+    highlights
+        .iter()
+        .filter(|h| h.chapter == chapter && h.block == block)
+        .collect()
+}
+
+/// Byte range of a highlight's text inside a block, if it can be marked
+fn highlight_range(text: &str, highlight: &Highlight) -> Option<(usize, usize)> {
+    let needle = highlight.text.trim();
+    if needle.is_empty() {
+        return None;
+    }
+    text.find(needle).map(|start| (start, start + needle.len()))
+}
+
+/// Comment text printed after a block for a highlight
+///
+/// Highlights that could be marked inline only need their note; others
+/// quote the highlighted text as well.
+fn highlight_comment(highlight: &Highlight, marked: bool) -> Option<String> {
+    let quote = highlight.text.trim();
+    match (&highlight.note, marked) {
+        (Some(note), true) => Some(note.clone()),
+        (None, true) => None,
+        (Some(note), false) if quote.is_empty() => Some(note.clone()),
+        (Some(note), false) => Some(format!("“{}” — {}", quote, note)),
+        (None, false) if quote.is_empty() => None,
+        (None, false) => Some(format!("“{}”", quote)),
+    }
+}
+
+/// A marked-up byte range of a block's text
+struct Span {
+    start: usize,
+    end: usize,
+    open: String,
+    close: String,
+    /// Text written instead of the span's own text
+    replace: Option<String>,
+}
+
+/// Spans for a block's text styles, using `tags` to name the markup
+///
+/// Inline math spans replace their text with `math` applied to the
+/// formula's TeX source; `tags` gets the style's data, such as a link's
+/// target.
+fn style_spans(
+    styles: &[TextStyle],
+    tags: fn(StyleType, Option<&str>) -> (String, String),
+    math: fn(&str) -> String,
+) -> Vec<Span> {
+    let mut styles: Vec<&TextStyle> = styles.iter().collect();
+    styles.sort_by_key(|s| s.style_type as u8);
+    styles
+        .into_iter()
+        .map(|s| {
+            let (open, close) = tags(s.style_type, s.data.as_deref());
+            let replace = match (s.style_type, &s.data) {
+                (StyleType::Math, Some(tex)) => Some(math(tex)),
+                _ => None,
+            };
+            Span {
+                start: s.start,
+                end: s.end,
+                open,
+                close,
+                replace,
+            }
+        })
+        .collect()
+}
+
+/// Apply spans to text
+///
+/// The text is cut at every span boundary and each piece is wrapped in the
+/// spans covering it, outermost first, so overlapping spans still produce
+/// properly nested markup.
+fn apply_spans(text: &str, spans: &[Span], escape: fn(&str) -> String) -> String {
+    if spans.is_empty() {
+        return escape(text);
+    }
+
+    let mut bounds: Vec<usize> = vec![0, text.len()];
+    for span in spans {
+        bounds.push(span.start.min(text.len()));
+        bounds.push(span.end.min(text.len()));
+    }
+    bounds.retain(|&b| text.is_char_boundary(b));
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut out = String::new();
+    for pair in bounds.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        // Replaced text is written once, where its span starts
+        let replacement = spans
+            .iter()
+            .find(|s| s.replace.is_some() && s.start <= start && s.end >= end);
+        if replacement.is_some_and(|s| s.start != start) {
+            continue;
+        }
+
+        let mut active: Vec<&Span> = Vec::new();
+        for span in spans.iter().filter(|s| s.start <= start && s.end >= end) {
+            if !active.iter().any(|a| a.open == span.open) {
+                active.push(span);
+            }
+        }
+
+        for span in &active {
+            out.push_str(&span.open);
+        }
+        match replacement.and_then(|s| s.replace.as_deref()) {
+            Some(replace) => out.push_str(replace),
+            None => out.push_str(&escape(&text[start..end])),
+        }
+        for span in active.iter().rev() {
+            out.push_str(&span.close);
+        }
+    }
+    out
+}
@@ -0,0 +1,228 @@
+//! Plain text writer
+//!
+//! Paragraphs are wrapped at the configured width, hyphenated in the book's
+//! language; code blocks keep their lines. Highlights cannot be marked in plain text, so each one becomes a
+//! bracketed comment after its block.
+
+use super::{block_highlights, chapter_heading, highlight_comment, WriteOptions};
+use crate::formats::{math, Book, Chapter, ContentBlock};
+use crate::hyphenate::{self, Hyphenator};
+use unicode_width::UnicodeWidthStr;
+
+/// Serialize a book as plain text
+pub fn write(book: &Book, options: &WriteOptions) -> String {
+    let mut parts = vec![underline(&book.metadata.title, '=')];
+    if !book.metadata.authors.is_empty() {
+        parts[0].push_str(&format!("\nby {}", book.metadata.authors_string()));
+    }
+
+    for (index, chapter) in book.content.chapters.iter().enumerate() {
+        parts.push(chapter_text(chapter, index, options));
+    }
+
+    let mut out = parts.join("\n\n\n");
+    out.push('\n');
+    out
+}
+
+fn chapter_text(chapter: &Chapter, chapter_index: usize, options: &WriteOptions) -> String {
+    let (width, hyphenator) = (options.width, options.hyphenator);
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(title) = chapter_heading(chapter) {
+        parts.push(underline(title, '='));
+    }
+
+    for (index, block) in chapter.blocks.iter().enumerate() {
+        let rendered = match block {
+            ContentBlock::Paragraph { text, .. } => wrap(text, width, hyphenator, "", ""),
+            ContentBlock::Heading { level, text } => match level {
+                1 => underline(text, '='),
+                2 => underline(text, '-'),
+                _ => text.clone(),
+            },
+            ContentBlock::Quote { text, attribution } => {
+                let mut quote = wrap(text, width, hyphenator, "    ", "    ");
+                if let Some(attr) = attribution {
+                    quote.push_str(&format!("\n    — {}", attr));
+                }
+                quote
+            }
+            ContentBlock::Code { code, .. } => code
+                .trim_end_matches('\n')
+                .lines()
+                .map(|line| format!("    {}", line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ContentBlock::Image { alt, caption, .. } => caption
+                .as_ref()
+                .or(alt.as_ref())
+                .map(|text| format!("[Image: {}]", text))
+                .unwrap_or_else(|| "[Image]".to_string()),
+            ContentBlock::List { ordered, items } => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let marker = if *ordered {
+                        format!("  {}. ", i + 1)
+                    } else {
+                        "  • ".to_string()
+                    };
+                    let indent = " ".repeat(marker.width());
+                    wrap(item, width, hyphenator, &marker, &indent)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ContentBlock::Separator => center("* * *", width),
+            ContentBlock::Footnote { id, content } => {
+                let marker = format!("[{}] ", id);
+                let indent = " ".repeat(marker.width());
+                wrap(content, width, hyphenator, &marker, &indent)
+            }
+            ContentBlock::RawHtml { html } => {
+                let width = if width == 0 { usize::MAX } else { width };
+                html2text::from_read(html.as_bytes(), width)
+                    .trim_end()
+                    .to_string()
+            }
+            ContentBlock::Table { headers, rows } => table(headers, rows),
+            ContentBlock::Break => String::new(),
+            ContentBlock::Math { tex } => {
+                let lines = math::to_lines(tex);
+                let block_width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+                let pad = " ".repeat(width.saturating_sub(block_width) / 2);
+                lines
+                    .iter()
+                    .map(|line| format!("{}{}", pad, line).trim_end().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+
+        if !rendered.is_empty() {
+            parts.push(rendered);
+        }
+        for h in block_highlights(&options.highlights, chapter_index, index) {
+            if let Some(comment) = highlight_comment(h, false) {
+                parts.push(wrap(
+                    &format!("[Note: {}]", comment),
+                    width,
+                    hyphenator,
+                    "",
+                    "",
+                ));
+            }
+        }
+    }
+
+    parts.join("\n\n")
+}
+
+/// Wrap text, with `0` meaning no wrapping
+fn wrap(
+    text: &str,
+    width: usize,
+    hyphenator: Hyphenator,
+    initial: &str,
+    subsequent: &str,
+) -> String {
+    if width == 0 {
+        return format!(
+            "{}{}",
+            initial,
+            hyphenate::visible(text).replace('\n', &format!("\n{}", subsequent))
+        );
+    }
+
+    let indent = initial.width().max(subsequent.width());
+    hyphenator
+        .wrap(text, width.saturating_sub(indent))
+        .into_iter()
+        .enumerate()
+        .map(|(i, (range, hyphen))| {
+            let indent = if i == 0 { initial } else { subsequent };
+            let hyphen = if hyphen { "-" } else { "" };
+            format!("{}{}{}", indent, hyphenate::visible(&text[range]), hyphen)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn underline(text: &str, c: char) -> String {
+    let text = text.trim();
+    format!("{}\n{}", text, c.to_string().repeat(text.width().max(1)))
+}
+
+fn center(text: &str, width: usize) -> String {
+    let pad = width.saturating_sub(text.width()) / 2;
+    format!("{}{}", " ".repeat(pad), text)
+}
+
+fn table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let columns = rows
+        .iter()
+        .map(|r| r.len())
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut widths = vec![0; columns];
+    for row in std::iter::once(headers).chain(rows.iter().map(|r| r.as_slice())) {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.width());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        (0..columns)
+            .map(|i| {
+                let cell = cells.get(i).map(|c| c.as_str()).unwrap_or_default();
+                format!("{}{}", cell, " ".repeat(widths[i] - cell.width()))
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = Vec::new();
+    if !headers.is_empty() {
+        lines.push(line(headers));
+        lines.push(
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("-+-"),
+        );
+    }
+    lines.extend(rows.iter().map(|r| line(r)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_width() {
+        let text = "one two three four five six seven eight nine ten";
+        assert_eq!(
+            wrap(text, 20, Hyphenator::NONE, "", ""),
+            "one two three four\nfive six seven eight\nnine ten"
+        );
+        assert_eq!(wrap(text, 0, Hyphenator::NONE, "", ""), text);
+        assert_eq!(
+            wrap("alpha beta", 9, Hyphenator::NONE, "  • ", "    "),
+            "  • alpha\n    beta"
+        );
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let table = table(
+            &["Name".to_string(), "Qty".to_string()],
+            &[vec!["Apple".to_string(), "3".to_string()]],
+        );
+        assert_eq!(table, "Name  | Qty\n------+----\nApple | 3");
+    }
+}
@@ -0,0 +1,316 @@
+//! XHTML serialization of content blocks
+//!
+//! The output is well-formed XML so it can be used directly in EPUB content
+//! documents. Headings get `heading-{index}` ids, matching the anchors used
+//! by the Markdown parser's table of contents.
+
+use super::{
+    apply_spans, block_highlights, highlight_comment, highlight_range, style_spans, Highlight,
+    Span, StyleType, TextStyle,
+};
+use crate::formats::{math, Chapter, ContentBlock};
+
+/// Escape text for use in XML content and attribute values
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            // Control characters are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Turn an arbitrary string into a valid XML id
+pub fn id(prefix: &str, raw: &str) -> String {
+    let cleaned: String = raw
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}-{}", prefix, cleaned)
+}
+
+fn tags(style: StyleType, data: Option<&str>) -> (String, String) {
+    let (open, close) = match style {
+        StyleType::Bold => ("<strong>", "</strong>"),
+        StyleType::Italic => ("<em>", "</em>"),
+        StyleType::Underline => ("<u>", "</u>"),
+        StyleType::Strikethrough => ("<s>", "</s>"),
+        StyleType::Code => ("<code>", "</code>"),
+        StyleType::Link => match data {
+            Some(href) => return (format!("<a href=\"{}\">", escape(href)), "</a>".to_string()),
+            None => ("<span class=\"link\">", "</span>"),
+        },
+        StyleType::Superscript => ("<sup>", "</sup>"),
+        StyleType::Subscript => ("<sub>", "</sub>"),
+        StyleType::SmallCaps => ("<span class=\"smallcaps\">", "</span>"),
+        StyleType::Math => ("", ""),
+    };
+    (open.to_string(), close.to_string())
+}
+
+fn inline_math(tex: &str) -> String {
+    math::to_mathml(tex, false)
+}
+
+/// Render a paragraph's text with its inline styles
+pub fn inline(text: &str, styles: &[TextStyle]) -> String {
+    apply_spans(text, &style_spans(styles, tags, inline_math), escape)
+}
+
+/// Maps an image's `src` and embedded data to the URL written to the output
+pub type ImageSource<'a> = dyn FnMut(&str, Option<&[u8]>) -> Option<String> + 'a;
+
+/// Render a chapter's blocks as XHTML
+///
+/// Heading ids are prefixed with `anchor_prefix`. Highlights belonging to
+/// chapter `chapter_index` are marked with `<mark>` and their comments
+/// follow the block. `image_src` maps an image block's `src` (and embedded
+/// data, if any) to the value written to the `src` attribute; images it
+/// cannot resolve are replaced by their caption or alt text.
+pub fn blocks(
+    chapter: &Chapter,
+    chapter_index: usize,
+    anchor_prefix: &str,
+    highlights: &[Highlight],
+    image_src: &mut ImageSource,
+) -> String {
+    let mut html = String::new();
+
+    for (index, block) in chapter.blocks.iter().enumerate() {
+        let block_highlights = block_highlights(highlights, chapter_index, index);
+        let mut marked = vec![false; block_highlights.len()];
+
+        match block {
+            ContentBlock::Paragraph { text, styles } => {
+                let mut spans = Vec::new();
+                for (h, marked) in block_highlights.iter().zip(marked.iter_mut()) {
+                    if let Some((start, end)) = highlight_range(text, h) {
+                        spans.push(Span {
+                            start,
+                            end,
+                            open: "<mark>".to_string(),
+                            close: "</mark>".to_string(),
+                            replace: None,
+                        });
+                        *marked = true;
+                    }
+                }
+                spans.extend(style_spans(styles, tags, inline_math));
+                html.push_str(&format!("<p>{}</p>\n", apply_spans(text, &spans, escape)));
+            }
+            ContentBlock::Heading { level, text } => {
+                let level = (*level).clamp(1, 6);
+                html.push_str(&format!(
+                    "<h{} id=\"{}heading-{}\">{}</h{}>\n",
+                    level,
+                    anchor_prefix,
+                    index,
+                    escape(text),
+                    level
+                ));
+            }
+            ContentBlock::Quote { text, attribution } => {
+                html.push_str("<blockquote>\n");
+                for para in text.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                    html.push_str(&format!("<p>{}</p>\n", escape(para.trim())));
+                }
+                if let Some(attr) = attribution {
+                    html.push_str(&format!(
+                        "<p class=\"attribution\">— {}</p>\n",
+                        escape(attr)
+                    ));
+                }
+                html.push_str("</blockquote>\n");
+            }
+            ContentBlock::Code { language, code } => {
+                let class = language
+                    .as_ref()
+                    .map(|l| format!(" class=\"language-{}\"", escape(l)))
+                    .unwrap_or_default();
+                html.push_str(&format!(
+                    "<pre><code{}>{}</code></pre>\n",
+                    class,
+                    escape(code)
+                ));
+            }
+            ContentBlock::Image {
+                src,
+                alt,
+                caption,
+                data,
+            } => match image_src(src, data.as_deref()) {
+                Some(href) => {
+                    html.push_str("<figure>\n");
+                    html.push_str(&format!(
+                        "<img src=\"{}\" alt=\"{}\"/>\n",
+                        escape(&href),
+                        escape(alt.as_deref().or(caption.as_deref()).unwrap_or_default())
+                    ));
+                    if let Some(cap) = caption {
+                        html.push_str(&format!("<figcaption>{}</figcaption>\n", escape(cap)));
+                    }
+                    html.push_str("</figure>\n");
+                }
+                None => {
+                    if let Some(text) = caption.as_ref().or(alt.as_ref()) {
+                        html.push_str(&format!("<p class=\"caption\">{}</p>\n", escape(text)));
+                    }
+                }
+            },
+            ContentBlock::List { ordered, items } => {
+                let tag = if *ordered { "ol" } else { "ul" };
+                html.push_str(&format!("<{}>\n", tag));
+                for item in items {
+                    html.push_str(&format!("<li>{}</li>\n", escape(item)));
+                }
+                html.push_str(&format!("</{}>\n", tag));
+            }
+            ContentBlock::Separator => html.push_str("<hr/>\n"),
+            ContentBlock::Footnote {
+                id: note_id,
+                content,
+            } => {
+                html.push_str(&format!(
+                    "<aside epub:type=\"footnote\" id=\"{}\">\n<p>{}</p>\n</aside>\n",
+                    id("fn", note_id),
+                    escape(content)
+                ));
+            }
+            ContentBlock::RawHtml { .. } => {
+                // Source HTML is not necessarily well-formed XML, so keep
+                // only its text
+                for para in block.text().split("\n\n").filter(|p| !p.trim().is_empty()) {
+                    html.push_str(&format!("<p>{}</p>\n", escape(para.trim())));
+                }
+            }
+            ContentBlock::Table { headers, rows } => {
+                html.push_str("<table>\n");
+                if !headers.is_empty() {
+                    html.push_str("<thead>\n<tr>");
+                    for header in headers {
+                        html.push_str(&format!("<th>{}</th>", escape(header)));
+                    }
+                    html.push_str("</tr>\n</thead>\n");
+                }
+                html.push_str("<tbody>\n");
+                for row in rows {
+                    html.push_str("<tr>");
+                    for cell in row {
+                        html.push_str(&format!("<td>{}</td>", escape(cell)));
+                    }
+                    html.push_str("</tr>\n");
+                }
+                html.push_str("</tbody>\n</table>\n");
+            }
+            ContentBlock::Break => html.push_str("<br/>\n"),
+            ContentBlock::Math { tex } => {
+                html.push_str(&math::to_mathml(tex, true));
+                html.push('\n');
+            }
+        }
+
+        for (h, marked) in block_highlights.iter().zip(marked) {
+            if let Some(comment) = highlight_comment(h, marked) {
+                html.push_str(&format!(
+                    "<aside class=\"annotation\">{}</aside>\n",
+                    escape(&comment)
+                ));
+            }
+        }
+    }
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styled_text_nests_overlaps() {
+        let styles = vec![
+            TextStyle {
+                start: 0,
+                end: 9,
+                style_type: StyleType::Bold,
+                data: None,
+            },
+            TextStyle {
+                start: 5,
+                end: 14,
+                style_type: StyleType::Italic,
+                data: None,
+            },
+        ];
+        assert_eq!(
+            apply_spans(
+                "bold both <i>",
+                &style_spans(&styles, tags, inline_math),
+                escape
+            ),
+            "<strong>bold </strong><strong><em>both</em></strong><em> &lt;i&gt;</em>"
+        );
+    }
+
+    #[test]
+    fn test_links_keep_their_target() {
+        let styles = vec![TextStyle {
+            start: 4,
+            end: 8,
+            style_type: StyleType::Link,
+            data: Some("a b.xhtml?x=1&y=2".to_string()),
+        }];
+        assert_eq!(
+            inline("see here", &styles),
+            "see <a href=\"a b.xhtml?x=1&amp;y=2\">here</a>"
+        );
+    }
+
+    #[test]
+    fn test_highlights_marked_with_comments() {
+        let mut chapter = Chapter::new("c".to_string(), 0);
+        chapter.blocks.push(ContentBlock::Paragraph {
+            text: "It was a dark and stormy night.".to_string(),
+            styles: vec![],
+        });
+        let highlights = vec![
+            Highlight {
+                chapter: 0,
+                block: 0,
+                text: "stormy night".to_string(),
+                note: Some("Cliché".to_string()),
+            },
+            Highlight {
+                chapter: 1,
+                block: 0,
+                text: "dark".to_string(),
+                note: None,
+            },
+        ];
+
+        let html = blocks(&chapter, 0, "", &highlights, &mut |_, _| None);
+        assert_eq!(
+            html,
+            "<p>It was a dark and <mark>stormy night</mark>.</p>\n<aside class=\"annotation\">Cliché</aside>\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_drops_control_chars() {
+        assert_eq!(escape("a\u{0}b & c"), "ab &amp; c");
+    }
+}
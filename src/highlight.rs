@@ -0,0 +1,432 @@
+//! Syntax highlighting for code blocks
+//!
+//! With the `syntax-highlighting` feature, code is colored with syntect using
+//! the theme named by `formats.markdown.syntax_theme`: one of syntect's
+//! built-in themes, a `.tmTheme` file in the `syntax-themes` folder next to
+//! the config file, or a path to a `.tmTheme` file. Without the feature, or
+//! when highlighting is disabled, code comes back as plain tokens.
+//!
+//! Blocks without a declared language have it guessed from their content.
+
+use crate::config::MarkdownConfig;
+
+#[cfg(feature = "syntax-highlighting")]
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Theme, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// A run of code text in a single style
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    /// Foreground color, if highlighted
+    pub color: Option<(u8, u8, u8)>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Token {
+    fn plain(text: &str) -> Self {
+        Token {
+            text: text.to_string(),
+            color: None,
+            bold: false,
+            italic: false,
+            underline: false,
+        }
+    }
+}
+
+/// Highlights code blocks with the configured theme
+pub struct Highlighter {
+    #[cfg(feature = "syntax-highlighting")]
+    theme: Option<Theme>,
+}
+
+impl Highlighter {
+    /// Create a highlighter from the Markdown settings
+    pub fn new(config: &MarkdownConfig) -> Self {
+        #[cfg(feature = "syntax-highlighting")]
+        {
+            let theme = if config.syntax_highlighting {
+                let theme = load_theme(&config.syntax_theme);
+                if theme.is_none() {
+                    tracing::warn!("Unknown syntax theme: {}", config.syntax_theme);
+                }
+                theme
+            } else {
+                None
+            };
+            Highlighter { theme }
+        }
+
+        #[cfg(not(feature = "syntax-highlighting"))]
+        {
+            let _ = config;
+            Highlighter {}
+        }
+    }
+
+    /// Split code into lines of tokens
+    ///
+    /// `language` is the block's declared language; when it is missing the
+    /// language is guessed. Code that cannot be highlighted comes back as
+    /// one plain token per line.
+    pub fn tokens(&self, code: &str, language: Option<&str>) -> Vec<Vec<Token>> {
+        #[cfg(feature = "syntax-highlighting")]
+        if let Some(lines) = self.highlight(code, language) {
+            return lines;
+        }
+
+        let _ = language;
+        code.lines().map(|line| vec![Token::plain(line)]).collect()
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    fn highlight(&self, code: &str, language: Option<&str>) -> Option<Vec<Vec<Token>>> {
+        let theme = self.theme.as_ref()?;
+        let syntaxes = syntaxes();
+        let syntax = find_syntax(syntaxes, code, language)?;
+        let mut highlighter = HighlightLines::new(syntax, theme);
+
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let ranges = highlighter.highlight_line(line, syntaxes).ok()?;
+            let tokens = ranges
+                .into_iter()
+                .map(|(style, text)| Token {
+                    text: text.trim_end_matches(['\n', '\r']).to_string(),
+                    color: Some((style.foreground.r, style.foreground.g, style.foreground.b)),
+                    bold: style.font_style.contains(FontStyle::BOLD),
+                    italic: style.font_style.contains(FontStyle::ITALIC),
+                    underline: style.font_style.contains(FontStyle::UNDERLINE),
+                })
+                .filter(|t| !t.text.is_empty())
+                .collect();
+            lines.push(tokens);
+        }
+        Some(lines)
+    }
+
+    /// Background color of the theme, if highlighting
+    fn background(&self) -> Option<(u8, u8, u8)> {
+        #[cfg(feature = "syntax-highlighting")]
+        if let Some(color) = self.theme.as_ref().and_then(|t| t.settings.background) {
+            return Some((color.r, color.g, color.b));
+        }
+        None
+    }
+
+    /// Render a code block as HTML
+    ///
+    /// Highlighted code gets inline colors and the theme's background. The
+    /// `language-*` class names the declared or guessed language.
+    pub fn html(&self, code: &str, language: Option<&str>) -> String {
+        let language = language
+            .map(|l| l.to_string())
+            .or_else(|| guess_language(code).map(|l| l.to_string()));
+        let class = language
+            .as_ref()
+            .map(|l| format!(" class=\"language-{}\"", escape(l)))
+            .unwrap_or_default();
+        let pre_style = self
+            .background()
+            .map(|(r, g, b)| format!(" style=\"background-color:#{:02x}{:02x}{:02x}\"", r, g, b))
+            .unwrap_or_default();
+
+        let mut html = format!("<pre{}><code{}>", pre_style, class);
+        for (i, line) in self.tokens(code, language.as_deref()).iter().enumerate() {
+            if i > 0 {
+                html.push('\n');
+            }
+            for token in line {
+                let mut style = String::new();
+                if let Some((r, g, b)) = token.color {
+                    style.push_str(&format!("color:#{:02x}{:02x}{:02x};", r, g, b));
+                }
+                if token.bold {
+                    style.push_str("font-weight:bold;");
+                }
+                if token.italic {
+                    style.push_str("font-style:italic;");
+                }
+                if token.underline {
+                    style.push_str("text-decoration:underline;");
+                }
+                if style.is_empty() {
+                    html.push_str(&escape(&token.text));
+                } else {
+                    html.push_str(&format!(
+                        "<span style=\"{}\">{}</span>",
+                        style,
+                        escape(&token.text)
+                    ));
+                }
+            }
+        }
+        html.push_str("</code></pre>");
+        html
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: std::sync::OnceLock<SyntaxSet> = std::sync::OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn themes() -> &'static ThemeSet {
+    static THEMES: std::sync::OnceLock<ThemeSet> = std::sync::OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Folder holding user `.tmTheme` files
+#[cfg(feature = "syntax-highlighting")]
+fn theme_dir() -> Option<std::path::PathBuf> {
+    crate::config::config_path()
+        .ok()?
+        .parent()
+        .map(|dir| dir.join("syntax-themes"))
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn load_theme(name: &str) -> Option<Theme> {
+    if let Some(theme) = themes().themes.get(name) {
+        return Some(theme.clone());
+    }
+    let path = std::path::Path::new(name);
+    if path.extension().is_some_and(|e| e == "tmTheme") && path.exists() {
+        return ThemeSet::get_theme(path).ok();
+    }
+    let file = theme_dir()?.join(format!("{}.tmTheme", name));
+    ThemeSet::get_theme(file).ok()
+}
+
+/// Names of the available syntax themes, built-in ones first
+#[cfg(feature = "syntax-highlighting")]
+pub fn theme_names() -> Vec<String> {
+    let mut names: Vec<String> = themes().themes.keys().cloned().collect();
+    if let Some(entries) = theme_dir().and_then(|dir| std::fs::read_dir(dir).ok()) {
+        let mut user: Vec<String> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "tmTheme"))
+            .filter_map(|p| p.file_stem()?.to_str().map(|s| s.to_string()))
+            .collect();
+        user.sort();
+        names.extend(user);
+    }
+    names
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn find_syntax<'a>(
+    syntaxes: &'a SyntaxSet,
+    code: &str,
+    language: Option<&str>,
+) -> Option<&'a SyntaxReference> {
+    // Fence info strings can carry attributes, as in `rust,ignore`
+    let declared = language
+        .and_then(|l| l.split([',', ' ', '{']).next())
+        .map(|l| l.trim().trim_start_matches('.'))
+        .filter(|l| !l.is_empty());
+
+    match declared {
+        Some(token) => syntaxes.find_syntax_by_token(token),
+        None => code
+            .lines()
+            .next()
+            .and_then(|line| syntaxes.find_syntax_by_first_line(line))
+            .or_else(|| guess_language(code).and_then(|l| syntaxes.find_syntax_by_token(l))),
+    }
+}
+
+/// Guess a code block's language from its content
+///
+/// Returns the language's usual file extension, or `None` when nothing
+/// stands out.
+pub fn guess_language(code: &str) -> Option<&'static str> {
+    let first = code.lines().find(|l| !l.trim().is_empty())?.trim();
+
+    if let Some(interpreter) = first.strip_prefix("#!") {
+        let known = [
+            ("python", "py"),
+            ("node", "js"),
+            ("ruby", "rb"),
+            ("perl", "pl"),
+            ("sh", "sh"),
+        ];
+        return known
+            .iter()
+            .find(|(name, _)| interpreter.contains(name))
+            .map(|(_, ext)| *ext);
+    }
+    if first.starts_with("<?xml") {
+        return Some("xml");
+    }
+    if first.starts_with('{') || first.starts_with('[') {
+        let trimmed = code.trim();
+        if trimmed.contains("\":") && (trimmed.ends_with('}') || trimmed.ends_with(']')) {
+            return Some("json");
+        }
+    }
+
+    // Each language scores a point per marker found; two are needed
+    const MARKERS: &[(&str, &[&str])] = &[
+        (
+            "rs",
+            &[
+                "fn ",
+                "let mut ",
+                "impl ",
+                "pub fn ",
+                "use std::",
+                "::new(",
+                "#[derive",
+                "-> ",
+            ],
+        ),
+        (
+            "py",
+            &[
+                "def ", "import ", "self.", "elif ", "print(", "__init__", "):\n", "None",
+            ],
+        ),
+        ("go", &["package ", "func ", ":= ", "fmt.", "err != nil"]),
+        (
+            "cpp",
+            &[
+                "std::",
+                "cout",
+                "template<",
+                "#include <iostream>",
+                "nullptr",
+            ],
+        ),
+        (
+            "c",
+            &["#include", "int main(", "printf(", "malloc(", "void "],
+        ),
+        (
+            "java",
+            &[
+                "public class",
+                "public static void",
+                "System.out",
+                "import java.",
+                "@Override",
+            ],
+        ),
+        (
+            "js",
+            &[
+                "function ",
+                "const ",
+                "=> ",
+                "console.log",
+                "require(",
+                "===",
+                "document.",
+            ],
+        ),
+        (
+            "html",
+            &["<html", "<div", "<p>", "</p>", "<span", "<body", "<a href"],
+        ),
+        (
+            "sql",
+            &[
+                "SELECT ",
+                "FROM ",
+                "WHERE ",
+                "INSERT INTO",
+                "CREATE TABLE",
+                "JOIN ",
+            ],
+        ),
+        (
+            "sh",
+            &[
+                "echo ", "$ ", "fi\n", "; then", "export ", "sudo ", "apt ", "cd ",
+            ],
+        ),
+    ];
+
+    MARKERS
+        .iter()
+        .map(|(ext, markers)| (*ext, markers.iter().filter(|m| code.contains(*m)).count()))
+        .filter(|(_, score)| *score >= 2)
+        .fold(
+            None,
+            |best: Option<(&str, usize)>, (ext, score)| match best {
+                Some((_, top)) if top >= score => best,
+                _ => Some((ext, score)),
+            },
+        )
+        .map(|(ext, _)| ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_language() {
+        assert_eq!(
+            guess_language("fn main() {\n    let mut x = Vec::new();\n}\n"),
+            Some("rs")
+        );
+        assert_eq!(
+            guess_language("def greet(name):\n    print(name)\n"),
+            Some("py")
+        );
+        assert_eq!(guess_language("#!/usr/bin/env bash\necho hi\n"), Some("sh"));
+        assert_eq!(guess_language("{\n  \"a\": 1\n}"), Some("json"));
+        assert_eq!(guess_language("Just some words."), None);
+    }
+
+    #[test]
+    fn test_plain_tokens_and_html() {
+        let highlighter = Highlighter::new(&MarkdownConfig {
+            syntax_highlighting: false,
+            ..MarkdownConfig::default()
+        });
+        let code = "fn main() {\n    let mut a = 1 < 2;\n}";
+        let tokens = highlighter.tokens(code, None);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1], vec![Token::plain("    let mut a = 1 < 2;")]);
+        assert_eq!(
+            highlighter.html(code, None),
+            "<pre><code class=\"language-rs\">fn main() {\n    let mut a = 1 &lt; 2;\n}</code></pre>"
+        );
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_highlighted_tokens() {
+        let highlighter = Highlighter::new(&MarkdownConfig::default());
+        let tokens = highlighter.tokens("let x = 1;\nlet y = 2;\n", Some("rust,ignore"));
+        assert_eq!(tokens.len(), 2);
+        let line: String = tokens[0].iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(line, "let x = 1;");
+        assert!(tokens[0].iter().all(|t| t.color.is_some()));
+        // Keywords and numbers are colored differently
+        assert_ne!(tokens[0][0].color, tokens[0].last().unwrap().color);
+
+        let html = highlighter.html("x = 1", Some("py"));
+        assert!(html.starts_with("<pre style=\"background-color:#"));
+        assert!(html.contains("<code class=\"language-py\"><span style=\"color:#"));
+    }
+}
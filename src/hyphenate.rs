@@ -0,0 +1,279 @@
+//! Hyphenation for wrapped text
+//!
+//! Words too long for the rest of a line are broken where Knuth–Liang
+//! patterns from TeX allow, in the book's language or else in
+//! `reader.hyphenation_lang`. Soft hyphens already in a word are the only
+//! places it breaks, and are otherwise invisible. A line that breaks a word
+//! ends with a hyphen.
+//!
+//! Lines break where the Unicode line breaking algorithm (UAX #14) allows,
+//! so text without spaces, such as Chinese or Japanese, breaks between its
+//! characters. Kinsoku rules keep closing punctuation and small kana off
+//! the start of a line and opening brackets off its end.
+
+use crate::config::ReaderConfig;
+use hypher::Lang;
+use std::ops::Range;
+use textwrap::core::Word;
+use textwrap::WrapAlgorithm;
+use unicode_width::UnicodeWidthChar;
+
+/// The soft hyphen, marking where a word may break
+pub const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Breaks words by the patterns of a language
+#[derive(Debug, Clone, Copy)]
+pub struct Hyphenator {
+    lang: Option<Lang>,
+}
+
+impl Hyphenator {
+    /// Only breaks words at soft hyphens and hyphens
+    pub const NONE: Self = Self { lang: None };
+
+    /// The hyphenator the config asks for, in a book's language when there
+    /// are patterns for it
+    pub fn new(config: &ReaderConfig, language: Option<&str>) -> Self {
+        if !config.hyphenation {
+            return Self::NONE;
+        }
+        Self {
+            lang: language
+                .and_then(lang)
+                .or_else(|| lang(&config.hyphenation_lang)),
+        }
+    }
+
+    /// Wrap text at a width, breaking at its newlines too
+    ///
+    /// Each line is given as its byte range in the text, without trailing
+    /// whitespace, and whether it ends inside a word, to be drawn with a
+    /// hyphen.
+    pub fn wrap(&self, text: &str, width: usize) -> Vec<(Range<usize>, bool)> {
+        let width = width.max(1);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in text.split('\n') {
+            let words: Vec<Word> = words(line)
+                .into_iter()
+                .flat_map(|word| self.split(word))
+                .collect();
+            let words: Vec<Word> = words.iter().flat_map(|w| w.break_apart(width)).collect();
+            // Words are slices of the line, so their place follows from
+            // their address
+            let offset =
+                |word: &Word| start + (word.word.as_ptr() as usize - line.as_ptr() as usize);
+            let widths = [width];
+            let wrapped = WrapAlgorithm::new().wrap(&words, &widths);
+            for (i, words) in wrapped.iter().enumerate() {
+                if let (Some(first), Some(last)) = (words.first(), words.last()) {
+                    // The first line keeps any indentation
+                    let from = if i == 0 { start } else { offset(first) };
+                    lines.push((
+                        from..offset(last) + last.word.len(),
+                        !last.penalty.is_empty(),
+                    ));
+                }
+            }
+            // An empty line has no words
+            if lines.last().is_none_or(|(range, _)| range.end < start) {
+                lines.push((start..start, false));
+            }
+            start += line.len() + 1;
+        }
+        lines
+    }
+
+    /// A word in the pieces it may break into
+    fn split<'a>(&self, word: Word<'a>) -> Vec<Word<'a>> {
+        let mut pieces = Vec::new();
+        let mut from = 0;
+        for (at, penalty) in self.split_points(word.word) {
+            let mut piece = Word::from(&word.word[from..at]);
+            piece.penalty = penalty;
+            pieces.push(piece);
+            from = at;
+        }
+        let mut last = Word::from(&word.word[from..]);
+        last.whitespace = word.whitespace;
+        last.penalty = word.penalty;
+        pieces.push(last);
+        pieces
+    }
+
+    /// Byte offsets a word may break at, with the hyphen to end the line
+    /// there: none after a hyphen already in the word
+    fn split_points(&self, word: &str) -> Vec<(usize, &'static str)> {
+        if word.contains(SOFT_HYPHEN) {
+            return word
+                .match_indices(SOFT_HYPHEN)
+                .map(|(i, shy)| (i + shy.len(), "-"))
+                .filter(|&(at, _)| at < word.len())
+                .collect();
+        }
+
+        let mut points = Vec::new();
+        let mut start = 0;
+        for part in word.split_inclusive('-') {
+            let end = start + part.len();
+            // Only the letters of a part, without its hyphen or punctuation
+            let letters = part.trim_matches(|c: char| !c.is_alphabetic());
+            if let Some(lang) = self
+                .lang
+                .filter(|_| letters.chars().all(char::is_alphabetic))
+            {
+                let mut at = start + part.find(letters).unwrap_or(0);
+                let syllables: Vec<&str> = hypher::hyphenate(letters, lang).collect();
+                for syllable in &syllables[..syllables.len().saturating_sub(1)] {
+                    at += syllable.len();
+                    points.push((at, "-"));
+                }
+            }
+            let alphanumeric = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+            if part.ends_with('-')
+                && alphanumeric(part[..part.len() - 1].chars().next_back())
+                && alphanumeric(word[end..].chars().next())
+            {
+                points.push((end, ""));
+            }
+            start = end;
+        }
+        points
+    }
+}
+
+/// Words of a line, each running to a place it may break
+fn words(line: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut from = 0;
+    for (at, _) in unicode_linebreak::linebreaks(line) {
+        let (before, after) = line.split_at(at);
+        // Soft hyphens break as part of a word, with a hyphen
+        if before.ends_with(SOFT_HYPHEN) || (at < line.len() && kinsoku(before, after)) {
+            continue;
+        }
+        words.push(Word::from(&line[from..at]));
+        from = at;
+    }
+    words
+}
+
+/// Whether kinsoku rules forbid a line break between two pieces of text
+fn kinsoku(before: &str, after: &str) -> bool {
+    const NO_START: &str = "、。，．・：；？！ー）」』】〕〉》〙〗’”\
+                            ぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮヵヶ";
+    const NO_END: &str = "（「『【〔〈《〘〖‘“";
+    after.chars().next().is_some_and(|c| NO_START.contains(c))
+        || before
+            .chars()
+            .next_back()
+            .is_some_and(|c| NO_END.contains(c))
+}
+
+/// Hyphenation patterns for a language tag such as `en-us`
+fn lang(tag: &str) -> Option<Lang> {
+    let code = tag
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let code = match code.as_str() {
+        "nb" | "nn" => "no",
+        code => code,
+    };
+    Lang::from_iso(code.as_bytes().try_into().ok()?)
+}
+
+/// Text without its soft hyphens, as it is shown
+pub fn visible(text: &str) -> String {
+    text.replace(SOFT_HYPHEN, "")
+}
+
+/// Display width of a character, with soft hyphens taking no room
+pub fn char_width(c: char) -> usize {
+    if c == SOFT_HYPHEN {
+        0
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+/// Display width of text, with soft hyphens taking no room
+pub fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english() -> Hyphenator {
+        Hyphenator::new(&ReaderConfig::default(), Some("en-GB"))
+    }
+
+    fn lines(hyphenator: Hyphenator, text: &str, width: usize) -> Vec<String> {
+        hyphenator
+            .wrap(text, width)
+            .into_iter()
+            .map(|(range, hyphen)| {
+                let line = visible(&text[range]);
+                if hyphen {
+                    line + "-"
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert_eq!(
+            english().split_points("extensive,"),
+            vec![(2, "-"), (5, "-")]
+        );
+        assert_eq!(english().split_points("well-known"), vec![(5, "")]);
+        assert_eq!(
+            Hyphenator::NONE.split_points("co\u{ad}op\u{ad}er\u{ad}ate"),
+            vec![(4, "-"), (8, "-"), (12, "-")]
+        );
+        assert_eq!(lang("de-AT"), Some(Lang::German));
+        assert_eq!(lang("xx"), None);
+
+        let config = ReaderConfig {
+            hyphenation: false,
+            ..Default::default()
+        };
+        assert!(Hyphenator::new(&config, Some("en")).lang.is_none());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let text = "An extensive vocabulary\n\n  indented";
+        assert_eq!(
+            lines(english(), text, 10),
+            vec!["An ex-", "tensive", "vocabulary", "", "  indented"]
+        );
+        assert_eq!(
+            lines(Hyphenator::NONE, text, 10),
+            vec!["An", "extensive", "vocabulary", "", "  indented"]
+        );
+        assert_eq!(
+            lines(Hyphenator::NONE, "a co\u{ad}op\u{ad}er\u{ad}ate", 7),
+            vec!["a coop-", "erate"]
+        );
+        assert_eq!(width("co\u{ad}op"), 4);
+    }
+
+    #[test]
+    fn test_line_breaking() {
+        // Japanese breaks between characters, but not before 。 or small kana
+        assert_eq!(
+            lines(Hyphenator::NONE, "きょうは「いい」てんきです。", 8),
+            vec!["きょうは", "「いい」", "てんきで", "す。"]
+        );
+        assert!(kinsoku("は", "。"));
+        assert!(kinsoku("「", "い"));
+        assert!(!kinsoku("い", "て"));
+    }
+}
@@ -1,13 +1,13 @@
 //! Library database and data structures
 
 use crate::config::Config;
-use crate::formats::{self, BookMetadata};
+use crate::formats::{self, BookMetadata, MetadataEdit};
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
-use tracing::info;
+use tracing::debug;
 
 /// A library entry representing a book in the collection
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +59,18 @@ pub struct LibraryEntry {
 
     /// Annotations
     pub annotations: Vec<Annotation>,
+
+    /// Positions saved under lowercase letters
+    #[serde(default)]
+    pub marks: BTreeMap<char, Mark>,
+
+    /// Content hash of the book file, to find it again after it moves
+    #[serde(default)]
+    pub file_hash: Option<String>,
+
+    /// Size of the book file, so only files of that size are hashed
+    #[serde(default)]
+    pub file_size: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -94,6 +106,54 @@ pub struct Annotation {
     pub block: usize,
     pub color: String,
     pub created_at: DateTime<Utc>,
+    /// Highlighted text, when the annotation covers a selection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<TextRange>,
+}
+
+/// A span of text in a chapter, from one block's byte offset to another's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextRange {
+    pub start_block: usize,
+    pub start: usize,
+    pub end_block: usize,
+    pub end: usize,
+}
+
+impl TextRange {
+    /// The part of a block's text inside the range, as byte offsets
+    pub fn within(&self, block: usize, len: usize) -> Option<(usize, usize)> {
+        if block < self.start_block || block > self.end_block {
+            return None;
+        }
+        let start = if block == self.start_block {
+            self.start.min(len)
+        } else {
+            0
+        };
+        let end = if block == self.end_block {
+            self.end.min(len)
+        } else {
+            len
+        };
+        (start < end).then_some((start, end))
+    }
+}
+
+/// A position saved under a letter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mark {
+    pub chapter: usize,
+    pub block: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An uppercase mark, which also remembers its book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalMark {
+    pub book_id: String,
+    #[serde(flatten)]
+    pub mark: Mark,
 }
 
 /// The library database
@@ -102,6 +162,10 @@ pub struct Library {
     /// Library entries indexed by ID
     books: HashMap<String, LibraryEntry>,
 
+    /// Uppercase marks, which can point into any book
+    #[serde(default)]
+    marks: BTreeMap<char, GlobalMark>,
+
     /// Path to the database file
     #[serde(skip)]
     db_path: PathBuf,
@@ -121,6 +185,7 @@ impl Library {
         } else {
             Ok(Self {
                 books: HashMap::new(),
+                marks: BTreeMap::new(),
                 db_path,
                 config: config.clone(),
             })
@@ -138,7 +203,7 @@ impl Library {
         library.db_path = path.to_path_buf();
         library.config = config;
 
-        info!("Loaded library with {} books", library.books.len());
+        debug!("Loaded library with {} books", library.books.len());
         Ok(library)
     }
 
@@ -152,10 +217,30 @@ impl Library {
         let content = serde_json::to_string_pretty(&self)?;
         std::fs::write(&self.db_path, content)?;
 
-        info!("Saved library to {}", self.db_path.display());
+        debug!("Saved library to {}", self.db_path.display());
         Ok(())
     }
 
+    /// Save one book's entry, and the global marks into it, without undoing
+    /// changes other programs made to the library file since it was loaded
+    ///
+    /// The rest of the library is reloaded from the file.
+    pub fn save_book(&mut self, id: &str) -> Result<()> {
+        if self.db_path.exists() {
+            let mut current = Self::load(&self.db_path, self.config.clone())?;
+            if let Some(entry) = self.books.remove(id) {
+                current.books.insert(id.to_string(), entry);
+            }
+            current.marks.retain(|_, global| global.book_id != id);
+            let ours = std::mem::take(&mut self.marks);
+            current
+                .marks
+                .extend(ours.into_iter().filter(|(_, global)| global.book_id == id));
+            *self = current;
+        }
+        self.save()
+    }
+
     /// Get all books
     pub fn books(&self) -> Vec<LibraryEntry> {
         self.books.values().cloned().collect()
@@ -166,6 +251,44 @@ impl Library {
         self.books.get(id).cloned()
     }
 
+    /// Find the entry for a book file, by its path or else its content
+    ///
+    /// An entry found by content is moved to the new path, and entries
+    /// added before hashes were kept get one; save the library to keep it.
+    pub fn find_book(&mut self, path: &Path) -> Option<LibraryEntry> {
+        let canonical = path.canonicalize().ok();
+        let by_path = self.books.values_mut().find(|entry| {
+            entry.path == path || canonical.is_some() && entry.path.canonicalize().ok() == canonical
+        });
+        if let Some(entry) = by_path {
+            if entry.file_hash.is_none() {
+                entry.file_hash = crate::cache::hash_file(path).ok();
+            }
+            if entry.file_size.is_none() {
+                entry.file_size = std::fs::metadata(path).ok().map(|m| m.len());
+            }
+            return Some(entry.clone());
+        }
+
+        // Hashing a large book is slow, so it is only done when some entry
+        // has a file of the same size
+        let size = std::fs::metadata(path).ok()?.len();
+        let same_size = |entry: &LibraryEntry| {
+            entry.file_hash.is_some() && entry.file_size.is_none_or(|s| s == size)
+        };
+        if !self.books.values().any(same_size) {
+            return None;
+        }
+        let hash = crate::cache::hash_file(path).ok()?;
+        let entry = self
+            .books
+            .values_mut()
+            .find(|entry| same_size(entry) && entry.file_hash.as_ref() == Some(&hash))?;
+        debug!("Found {} moved to {}", entry.path.display(), path.display());
+        entry.path = path.to_path_buf();
+        Some(entry.clone())
+    }
+
     /// Add a book to the library
     pub fn add_book(&mut self, path: &Path, tags: Option<Vec<String>>) -> Result<LibraryEntry> {
         // Check if already exists
@@ -181,12 +304,8 @@ impl Library {
         // Generate ID
         let id = generate_id(&metadata.title);
 
-        // Detect format
-        let format = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase())
-            .unwrap_or_else(|| "unknown".to_string());
+        // Detect format from content, not just the extension
+        let format = formats::BookFormat::detect(path).id().to_string();
 
         let entry = LibraryEntry {
             id: id.clone(),
@@ -205,6 +324,9 @@ impl Library {
             cover_path: None,
             bookmarks: Vec::new(),
             annotations: Vec::new(),
+            marks: BTreeMap::new(),
+            file_hash: crate::cache::hash_file(path).ok(),
+            file_size: std::fs::metadata(path).ok().map(|m| m.len()),
         };
 
         self.books.insert(id, entry.clone());
@@ -306,8 +428,7 @@ impl Library {
         };
 
         for entry_path in entries {
-            let format = formats::BookFormat::from_path(&entry_path);
-            if format.is_supported() {
+            if formats::is_book_file(&entry_path) {
                 match self.add_book(&entry_path, None) {
                     Ok(_) => count += 1,
                     Err(e) => {
@@ -346,6 +467,33 @@ impl Library {
         Ok(())
     }
 
+    /// Edit a book's metadata
+    ///
+    /// With `write_file`, the changes are also written into the book file;
+    /// the library entry is only changed once that succeeded. Tags replace
+    /// the book's subjects and leave the entry's own tags alone.
+    pub fn update_metadata(
+        &mut self,
+        id: &str,
+        edit: &MetadataEdit,
+        write_file: bool,
+    ) -> Result<LibraryEntry> {
+        let entry = self
+            .books
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Book not found: {}", id))?;
+
+        if write_file {
+            formats::writer::write_metadata(&entry.path, edit)?;
+            // The rewritten file must still be recognized after a move
+            entry.file_hash = crate::cache::hash_file(&entry.path).ok();
+            entry.file_size = std::fs::metadata(&entry.path).ok().map(|m| m.len());
+        }
+
+        edit.apply(&mut entry.metadata);
+        Ok(entry.clone())
+    }
+
     /// Update reading progress
     pub fn update_progress(
         &mut self,
@@ -427,6 +575,48 @@ impl Library {
         }
     }
 
+    /// Save a position under a mark
+    ///
+    /// Lowercase marks belong to the book; uppercase ones are shared by the
+    /// whole library, so setting one moves it away from any other book.
+    pub fn set_mark(
+        &mut self,
+        book_id: &str,
+        name: char,
+        chapter: usize,
+        block: usize,
+    ) -> Result<()> {
+        let entry = self
+            .books
+            .get_mut(book_id)
+            .ok_or_else(|| anyhow::anyhow!("Book not found: {}", book_id))?;
+        let mark = Mark {
+            chapter,
+            block,
+            created_at: Utc::now(),
+        };
+
+        if name.is_ascii_lowercase() {
+            entry.marks.insert(name, mark);
+        } else if name.is_ascii_uppercase() {
+            let book_id = book_id.to_string();
+            self.marks.insert(name, GlobalMark { book_id, mark });
+        } else {
+            anyhow::bail!("Marks are named by a letter, not {:?}", name);
+        }
+        Ok(())
+    }
+
+    /// Look up a mark from a book, returning the book it points into
+    pub fn get_mark(&self, book_id: &str, name: char) -> Option<(String, Mark)> {
+        if name.is_ascii_uppercase() {
+            let global = self.marks.get(&name)?;
+            return Some((global.book_id.clone(), global.mark.clone()));
+        }
+        let mark = self.books.get(book_id)?.marks.get(&name)?;
+        Some((book_id.to_string(), mark.clone()))
+    }
+
     /// Get annotations for a book
     pub fn get_annotations(&self, book_id: &str) -> Result<Vec<Annotation>> {
         self.books
@@ -456,6 +646,7 @@ impl Library {
                 block,
                 color: color.unwrap_or_else(|| "yellow".to_string()),
                 created_at: Utc::now(),
+                range: None,
             };
 
             entry.annotations.push(annotation.clone());
@@ -465,6 +656,34 @@ impl Library {
         }
     }
 
+    /// Add a highlight of a range of text, with an optional note
+    pub fn add_highlight(
+        &mut self,
+        book_id: &str,
+        chapter: usize,
+        range: TextRange,
+        text: String,
+        note: Option<String>,
+        color: String,
+    ) -> Result<Annotation> {
+        let entry = self
+            .books
+            .get_mut(book_id)
+            .ok_or_else(|| anyhow::anyhow!("Book not found: {}", book_id))?;
+        let annotation = Annotation {
+            id: uuid::Uuid::new_v4().to_string(),
+            text,
+            note,
+            chapter,
+            block: range.start_block,
+            color,
+            created_at: Utc::now(),
+            range: Some(range),
+        };
+        entry.annotations.push(annotation.clone());
+        Ok(annotation)
+    }
+
     /// Remove an annotation
     pub fn remove_annotation(&mut self, book_id: &str, annotation_id: &str) -> Result<()> {
         if let Some(entry) = self.books.get_mut(book_id) {
@@ -647,3 +866,144 @@ fn escape_csv(s: &str) -> String {
         s.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library_with(ids: &[&str]) -> Library {
+        let mut library = Library {
+            books: HashMap::new(),
+            marks: BTreeMap::new(),
+            db_path: PathBuf::new(),
+            config: Config::default(),
+        };
+        for id in ids {
+            let entry = LibraryEntry {
+                id: id.to_string(),
+                path: PathBuf::from(format!("{}.epub", id)),
+                format: "epub".to_string(),
+                metadata: BookMetadata::default(),
+                tags: Vec::new(),
+                progress: 0.0,
+                position_chapter: 0,
+                position_block: 0,
+                position_offset: 0,
+                status: ReadingStatus::Unread,
+                added_at: Utc::now(),
+                last_read: None,
+                reading_time: 0,
+                cover_path: None,
+                bookmarks: Vec::new(),
+                annotations: Vec::new(),
+                marks: BTreeMap::new(),
+                file_hash: Some(format!("hash of {}", id)),
+                file_size: None,
+            };
+            library.books.insert(id.to_string(), entry);
+        }
+        library
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut library = library_with(&["one", "two"]);
+        library.set_mark("one", 'a', 2, 5).unwrap();
+        library.set_mark("one", 'A', 3, 1).unwrap();
+        assert!(library.set_mark("one", '1', 0, 0).is_err());
+        assert!(library.set_mark("three", 'a', 0, 0).is_err());
+
+        // Lowercase marks stay with their book, uppercase ones are shared
+        let (book, mark) = library.get_mark("one", 'a').unwrap();
+        assert_eq!((book.as_str(), mark.chapter, mark.block), ("one", 2, 5));
+        assert!(library.get_mark("two", 'a').is_none());
+        let (book, mark) = library.get_mark("two", 'A').unwrap();
+        assert_eq!((book.as_str(), mark.chapter), ("one", 3));
+
+        library.set_mark("two", 'A', 0, 4).unwrap();
+        assert_eq!(library.get_mark("one", 'A').unwrap().0, "two");
+    }
+
+    #[test]
+    fn test_find_moved_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("moved.epub");
+        std::fs::write(&path, "book content").unwrap();
+
+        let mut library = library_with(&["one", "two"]);
+        let hash = crate::cache::hash_file(&path).unwrap();
+        library.books.get_mut("two").unwrap().file_hash = Some(hash.clone());
+        library.books.get_mut("two").unwrap().file_size = Some(12);
+
+        let entry = library.find_book(&path).unwrap();
+        assert_eq!(entry.id, "two");
+        assert_eq!(library.books["two"].path, path);
+        assert!(library.find_book(Path::new("one.epub")).is_some());
+        assert!(library
+            .find_book(&dir.path().join("missing.epub"))
+            .is_none());
+
+        // A file of another size is not hashed, so does not match
+        library.books.get_mut("two").unwrap().path = PathBuf::from("gone.epub");
+        library.books.get_mut("two").unwrap().file_size = Some(13);
+        assert!(library.find_book(&path).is_none());
+    }
+
+    #[test]
+    fn test_update_metadata_keeps_user_tags() {
+        let mut library = library_with(&["one"]);
+        library.books.get_mut("one").unwrap().tags = vec!["to-read".to_string()];
+
+        let edit = MetadataEdit {
+            tags: Some(vec!["essays".to_string()]),
+            ..Default::default()
+        };
+        let entry = library.update_metadata("one", &edit, false).unwrap();
+        assert_eq!(entry.metadata.subjects, vec!["essays"]);
+        assert_eq!(entry.tags, vec!["to-read"]);
+    }
+
+    #[test]
+    fn test_written_book_is_found_after_moving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("one.md");
+        std::fs::write(&path, "# Draft\n\nText.\n").unwrap();
+
+        let mut library = library_with(&[]);
+        let id = library.add_book(&path, None).unwrap().id;
+        let edit = MetadataEdit {
+            title: Some("A much longer final title".to_string()),
+            ..Default::default()
+        };
+        library.update_metadata(&id, &edit, true).unwrap();
+
+        let moved = dir.path().join("moved.md");
+        std::fs::rename(&path, &moved).unwrap();
+        assert_eq!(library.find_book(&moved).unwrap().id, id);
+    }
+
+    #[test]
+    fn test_save_book_keeps_other_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ours = library_with(&["one", "two"]);
+        ours.db_path = dir.path().join("library.db");
+        ours.set_mark("two", 'B', 1, 1).unwrap();
+        ours.save().unwrap();
+
+        // Another program changes the other book and sets a mark meanwhile
+        let mut theirs = Library::load(&ours.db_path, Config::default()).unwrap();
+        theirs.update_progress("two", 7, 0, 0, 0.5).unwrap();
+        theirs.set_mark("two", 'C', 2, 2).unwrap();
+        theirs.save().unwrap();
+
+        ours.update_progress("one", 3, 0, 0, 0.25).unwrap();
+        ours.set_mark("one", 'A', 3, 0).unwrap();
+        ours.save_book("one").unwrap();
+
+        let saved = Library::load(&ours.db_path, Config::default()).unwrap();
+        assert_eq!(saved.books["one"].position_chapter, 3);
+        assert_eq!(saved.books["two"].position_chapter, 7);
+        assert_eq!(saved.marks.keys().collect::<String>(), "ABC");
+        assert_eq!(ours.books["two"].position_chapter, 7);
+    }
+}
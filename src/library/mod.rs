@@ -4,15 +4,15 @@
 
 mod database;
 
-pub use database::{Annotation, BookStats, Bookmark, Library, LibraryEntry, LibraryStats};
+pub use database::{
+    Annotation, BookStats, Bookmark, Library, LibraryEntry, LibraryStats, Mark, TextRange,
+};
 
 use crate::cli::LibraryCommand;
 use crate::config::Config;
+use crate::formats::{self, BookFormat, MetadataEdit};
 use anyhow::Result;
 
-/// Supported book extensions
-const BOOK_EXTENSIONS: &[&str] = &["epub", "pdf", "md", "markdown", "txt", "text"];
-
 /// Handle library commands
 pub async fn handle_command(cmd: LibraryCommand, config: &Config) -> Result<()> {
     let mut library = Library::new(config)?;
@@ -36,23 +36,19 @@ pub async fn handle_command(cmd: LibraryCommand, config: &Config) -> Result<()>
 
                 for entry in walker.into_iter().filter_map(|e| e.ok()) {
                     let file_path = entry.path();
-                    if file_path.is_file() {
-                        if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
-                            if BOOK_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
-                                match library.add_book(file_path, Some(tags.clone())) {
-                                    Ok(entry) => {
-                                        println!("  ✓ {}", entry.metadata.title);
-                                        added += 1;
-                                    }
-                                    Err(e) => {
-                                        eprintln!(
-                                            "  ✗ {:?}: {}",
-                                            file_path.file_name().unwrap_or_default(),
-                                            e
-                                        );
-                                        failed += 1;
-                                    }
-                                }
+                    if file_path.is_file() && formats::is_book_file(file_path) {
+                        match library.add_book(file_path, Some(tags.clone())) {
+                            Ok(entry) => {
+                                println!("  ✓ {}", entry.metadata.title);
+                                added += 1;
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "  ✗ {:?}: {}",
+                                    file_path.file_name().unwrap_or_default(),
+                                    e
+                                );
+                                failed += 1;
                             }
                         }
                     }
@@ -138,7 +134,26 @@ pub async fn handle_command(cmd: LibraryCommand, config: &Config) -> Result<()>
                 if let Some(lang) = &entry.metadata.language {
                     println!("Language:    {}", lang);
                 }
+                if let Some(series) = &entry.metadata.series {
+                    match entry.metadata.series_index {
+                        Some(index) => println!("Series:      {} #{}", series, index),
+                        None => println!("Series:      {}", series),
+                    }
+                }
+                if !entry.metadata.subjects.is_empty() {
+                    println!("Subjects:    {}", entry.metadata.subjects.join(", "));
+                }
+                let detected = BookFormat::detect(&entry.path);
+                let claimed = BookFormat::from_path(&entry.path);
                 println!("Format:      {}", entry.format);
+                println!("Detected:    {}", detected.name());
+                if claimed != detected {
+                    println!("Claimed:     {} (from file extension)", claimed.name());
+                }
+                #[cfg(feature = "compressed")]
+                if let Some(compression) = formats::compressed::compression(&entry.path) {
+                    println!("Compression: {}", compression.name());
+                }
                 println!("Progress:    {:.1}%", entry.progress * 100.0);
                 println!("Path:        {}", entry.path.display());
                 if !entry.tags.is_empty() {
@@ -151,6 +166,44 @@ pub async fn handle_command(cmd: LibraryCommand, config: &Config) -> Result<()>
             }
             None => println!("Book not found: {}", id),
         },
+        LibraryCommand::Edit {
+            id,
+            title,
+            authors,
+            series,
+            series_index,
+            tags,
+            clear_tags,
+            description,
+            publisher,
+            language,
+            write_file,
+        } => {
+            let edit = MetadataEdit {
+                title,
+                authors: (!authors.is_empty()).then_some(authors),
+                series,
+                series_index,
+                tags: (clear_tags || !tags.is_empty()).then_some(tags),
+                description,
+                publisher,
+                language,
+            };
+            if edit.is_empty() {
+                anyhow::bail!("Nothing to change; pass at least one field to edit");
+            }
+
+            let entry = library.update_metadata(&id, &edit, write_file)?;
+            library.save()?;
+            println!(
+                "Updated: {} by {}",
+                entry.metadata.title,
+                entry.metadata.authors_string()
+            );
+            if write_file {
+                println!("Wrote metadata to: {}", entry.path.display());
+            }
+        }
         LibraryCommand::Import { path, recursive } => {
             let count = library.import_directory(&path, recursive)?;
             println!("Imported {} books", count);
@@ -0,0 +1,61 @@
+//! Log output
+//!
+//! Logs are written to stderr, except while the TUI owns the terminal: then
+//! they are appended to a log file so they never draw over the reader.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Where logs go instead of stderr, if anywhere
+static REDIRECT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Writer handed to the tracing subscriber
+pub struct LogWriter;
+
+impl LogWriter {
+    fn with<T>(f: impl FnOnce(&mut dyn Write) -> io::Result<T>) -> io::Result<T> {
+        let mut redirect = REDIRECT.lock().unwrap_or_else(|e| e.into_inner());
+        match redirect.as_mut() {
+            Some(out) => f(out),
+            None => f(&mut io::stderr()),
+        }
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Self::with(|out| out.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Self::with(|out| out.flush())
+    }
+}
+
+/// Append logs to `path` until the returned guard is dropped
+///
+/// Logs are dropped meanwhile if the file cannot be opened.
+#[cfg(feature = "tui")]
+pub fn redirect_to(path: &std::path::Path) -> Redirect {
+    let out: Box<dyn Write + Send> = match std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+    {
+        Ok(file) => Box::new(file),
+        Err(_) => Box::new(io::sink()),
+    };
+    *REDIRECT.lock().unwrap_or_else(|e| e.into_inner()) = Some(out);
+    Redirect
+}
+
+/// Sends logs back to stderr when dropped
+#[cfg(feature = "tui")]
+pub struct Redirect;
+
+#[cfg(feature = "tui")]
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        *REDIRECT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
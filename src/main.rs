@@ -3,11 +3,17 @@
 //! A powerful, configurable book reader with TUI and Web interfaces,
 //! designed for power users who demand flexibility and control.
 
+mod cache;
 mod cli;
 mod config;
+mod convert;
+mod doctor;
 mod error;
 mod formats;
+mod highlight;
+mod hyphenate;
 mod library;
+mod logging;
 mod reader;
 
 #[cfg(feature = "search")]
@@ -36,7 +42,7 @@ fn setup_logging(verbose: bool) {
 
     tracing_subscriber::registry()
         .with(filter)
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(|| logging::LogWriter))
         .init();
 }
 
@@ -55,6 +61,32 @@ async fn main() -> Result<()> {
         Commands::Library(lib_cmd) => {
             library::handle_command(lib_cmd, &config).await?;
         }
+        Commands::Convert {
+            input,
+            output,
+            title,
+            author,
+            width,
+            annotations,
+        } => {
+            let options = convert::Options {
+                title,
+                authors: author,
+                width,
+                annotations,
+            };
+            convert::handle_command(&input, output.as_deref(), options, &config)?;
+        }
+        Commands::Doctor {
+            paths,
+            library,
+            json,
+        } => {
+            doctor::handle_command(paths, library, json, &config)?;
+        }
+        Commands::Cache(cache_cmd) => {
+            cache::handle_command(cache_cmd, &config)?;
+        }
         Commands::Config(cfg_cmd) => {
             config::handle_command(cfg_cmd, &config)?;
         }
@@ -5,15 +5,17 @@
 
 use std::path::Path;
 
+use crate::cache::BookCache;
 use crate::cli::Interface;
 use crate::config::Config;
 use crate::error::Result;
-use crate::formats::{parse_book, Book};
+use crate::formats::{Chapter, LazyBook};
+use std::sync::Arc;
 
 /// Open and read a book with the specified interface
 pub async fn read_book(path: &Path, config: &Config, interface: Option<Interface>) -> Result<()> {
-    // Parse the book
-    let book = parse_book(path)?;
+    // Open the book, reusing a cached parse when possible
+    let book = BookCache::new(config)?.open(path)?;
 
     // Determine which interface to use
     let interface = interface.unwrap_or_else(|| {
@@ -52,9 +54,9 @@ pub async fn read_book(path: &Path, config: &Config, interface: Option<Interface
 }
 
 /// Reading session state
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ReadingSession {
-    pub book: Book,
+    pub book: LazyBook,
     pub current_chapter: usize,
     pub current_block: usize,
     pub scroll_offset: usize,
@@ -62,7 +64,7 @@ pub struct ReadingSession {
 
 impl ReadingSession {
     /// Create a new reading session
-    pub fn new(book: Book) -> Self {
+    pub fn new(book: LazyBook) -> Self {
         Self {
             book,
             current_chapter: 0,
@@ -72,13 +74,12 @@ impl ReadingSession {
     }
 
     /// Create a session resuming from a saved position
-    pub fn resume(book: Book, chapter: usize, block: usize) -> Self {
-        let chapter = chapter.min(book.content.chapters.len().saturating_sub(1));
+    pub fn resume(book: LazyBook, chapter: usize, block: usize) -> Self {
+        let chapter = chapter.min(book.chapter_count().saturating_sub(1));
         let block = book
-            .content
-            .chapters
+            .chapters()
             .get(chapter)
-            .map(|c| block.min(c.blocks.len().saturating_sub(1)))
+            .map(|c| block.min(c.block_count.saturating_sub(1)))
             .unwrap_or(0);
 
         Self {
@@ -89,19 +90,19 @@ impl ReadingSession {
         }
     }
 
-    /// Get the current chapter
-    pub fn chapter(&self) -> Option<&crate::formats::Chapter> {
-        self.book.content.chapters.get(self.current_chapter)
+    /// Get the current chapter, loading it if needed
+    pub fn chapter(&self) -> Option<Arc<Chapter>> {
+        self.book.chapter(self.current_chapter)
     }
 
     /// Get total number of chapters
     pub fn total_chapters(&self) -> usize {
-        self.book.content.chapters.len()
+        self.book.chapter_count()
     }
 
     /// Go to next chapter
     pub fn next_chapter(&mut self) -> bool {
-        if self.current_chapter + 1 < self.book.content.chapters.len() {
+        if self.current_chapter + 1 < self.total_chapters() {
             self.current_chapter += 1;
             self.current_block = 0;
             self.scroll_offset = 0;
@@ -125,7 +126,7 @@ impl ReadingSession {
 
     /// Go to a specific chapter
     pub fn goto_chapter(&mut self, chapter: usize) -> bool {
-        if chapter < self.book.content.chapters.len() {
+        if chapter < self.total_chapters() {
             self.current_chapter = chapter;
             self.current_block = 0;
             self.scroll_offset = 0;
@@ -137,29 +138,17 @@ impl ReadingSession {
 
     /// Calculate reading progress (0.0 - 1.0)
     pub fn progress(&self) -> f64 {
-        if self.book.content.chapters.is_empty() {
-            return 0.0;
-        }
-
-        let total_blocks: usize = self
-            .book
-            .content
-            .chapters
-            .iter()
-            .map(|c| c.blocks.len())
-            .sum();
-
+        let total_blocks: usize = self.book.chapters().iter().map(|c| c.block_count).sum();
         if total_blocks == 0 {
             return 0.0;
         }
 
         let blocks_before: usize = self
             .book
-            .content
-            .chapters
+            .chapters()
             .iter()
             .take(self.current_chapter)
-            .map(|c| c.blocks.len())
+            .map(|c| c.block_count)
             .sum();
 
         let current = blocks_before + self.current_block;
@@ -174,7 +163,11 @@ impl ReadingSession {
             self.current_chapter + 1,
             self.total_chapters(),
             self.current_block + 1,
-            self.chapter().map(|c| c.blocks.len()).unwrap_or(0)
+            self.book
+                .chapters()
+                .get(self.current_chapter)
+                .map(|c| c.block_count)
+                .unwrap_or(0)
         )
     }
 
@@ -187,7 +180,7 @@ impl ReadingSession {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::formats::{BookContent, BookMetadata, Chapter, ContentBlock};
+    use crate::formats::{Book, BookContent, BookMetadata, ContentBlock};
     use std::path::PathBuf;
 
     fn create_test_book() -> Book {
@@ -213,6 +206,7 @@ mod tests {
                             },
                         ],
                         order: 0,
+                        anchors: vec![],
                     },
                     Chapter {
                         id: "ch2".to_string(),
@@ -223,6 +217,7 @@ mod tests {
                             styles: vec![],
                         }],
                         order: 1,
+                        anchors: vec![],
                     },
                 ],
                 toc: vec![],
@@ -234,7 +229,7 @@ mod tests {
 
     #[test]
     fn test_reading_session_navigation() {
-        let book = create_test_book();
+        let book = LazyBook::from_book(create_test_book());
         let mut session = ReadingSession::new(book);
 
         assert_eq!(session.current_chapter, 0);
@@ -249,7 +244,7 @@ mod tests {
 
     #[test]
     fn test_reading_session_progress() {
-        let book = create_test_book();
+        let book = LazyBook::from_book(create_test_book());
         let mut session = ReadingSession::new(book);
 
         assert_eq!(session.progress(), 0.0);
@@ -2,10 +2,14 @@
 
 use super::event::{convert_event, poll, InputEvent};
 use super::input::handle_input;
+use super::keymap::Dispatcher;
+use super::pages::LayoutMode;
 use super::render::render;
-use super::state::AppState;
+use super::state::{AppState, MessageType};
+use crate::cache::BookCache;
 use crate::config::Config;
-use crate::formats::Book;
+use crate::formats::LazyBook;
+use crate::library::Library;
 use anyhow::Result;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -14,11 +18,16 @@ use crossterm::{
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io::{self, Stdout};
-use std::time::Duration;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
 
 /// Run the TUI application
-pub fn run(book: Book, config: &Config) -> Result<()> {
+pub fn run(book: LazyBook, config: &Config) -> Result<()> {
+    // Logs would draw over the screen, so they go to a file meanwhile
+    let log = config
+        .data_dir()
+        .map(|dir| crate::logging::redirect_to(&dir.join("franko.log")));
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -38,9 +47,53 @@ pub fn run(book: Book, config: &Config) -> Result<()> {
     state.show_sidebar = config.tui.show_sidebar;
     state.show_status_bar = config.tui.status_bar;
     state.show_line_numbers = config.tui.line_numbers;
-    state.theme = config.theme.active.clone();
+    state.scrolloff = config.tui.scrolloff;
+    state.layout = LayoutMode::from_name(&config.reader.layout_mode, config.reader.pages_per_view)
+        .unwrap_or(LayoutMode::Scroll);
+    state.theme = match config.theme.resolve() {
+        Ok(theme) => theme,
+        Err(e) => {
+            state.show_message(e.to_string(), MessageType::Warning);
+            config.theme.clone()
+        }
+    };
+    state.keys = Dispatcher::new(&config.keybindings);
+    if let Some(conflict) = state.keys.conflicts.first() {
+        state.show_message(
+            format!("Keybinding conflict: {}", conflict),
+            MessageType::Warning,
+        );
+    }
+    #[cfg(feature = "image-support")]
+    {
+        state.graphics = super::graphics::Graphics::new(&config.tui.image_protocol);
+    }
 
-    info!("Starting TUI reader");
+    // Position, marks, bookmarks and notes are kept in the library
+    match Library::new(config) {
+        Ok(mut library) => {
+            let path = state.book.skeleton.source_path.clone();
+            if config.library.auto_add_opened && library.find_book(&path).is_none() {
+                match library
+                    .add_book(&path, None)
+                    .and_then(|entry| library.save_book(&entry.id))
+                {
+                    Ok(()) => debug!("Added {} to the library", path.display()),
+                    Err(e) => state.show_message(
+                        format!("Failed to add the book to the library: {}", e),
+                        MessageType::Error,
+                    ),
+                }
+            }
+            state.attach_library(library);
+        }
+        Err(e) => state.show_message(
+            format!("Marks will not be saved: {}", e),
+            MessageType::Warning,
+        ),
+    }
+
+    debug!("Starting TUI reader");
 
     // Main loop
     let result = run_loop(&mut terminal, &mut state, config);
@@ -53,6 +106,14 @@ pub fn run(book: Book, config: &Config) -> Result<()> {
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
+    drop(log);
+
+    // Remember where reading stopped
+    if state.book_id.is_some() {
+        if let Err(e) = state.save_progress(false) {
+            warn!("Failed to save progress: {}", e);
+        }
+    }
 
     result
 }
@@ -63,11 +124,24 @@ fn run_loop(
     config: &Config,
 ) -> Result<()> {
     let tick_rate = Duration::from_millis(100);
+    let auto_save = Duration::from_secs(config.general.auto_save_interval.max(1));
+    let mut last_save = Instant::now();
 
     loop {
         // Render
         terminal.draw(|f| render(f, state, config))?;
 
+        // Draw inline images over the frame
+        #[cfg(feature = "image-support")]
+        {
+            if state.graphics.needs_clear() {
+                terminal.clear()?;
+                terminal.draw(|f| render(f, state, config))?;
+            }
+            let book = state.book.skeleton.source_path.clone();
+            state.graphics.flush(&book, terminal.backend_mut())?;
+        }
+
         // Handle events
         if let Some(event) = poll(tick_rate)? {
             let input_event = convert_event(event);
@@ -77,6 +151,42 @@ fn run_loop(
             handle_input(state, InputEvent::Tick, config);
         }
 
+        // Write a highlight's note in $EDITOR
+        if std::mem::take(&mut state.edit_note) {
+            if let Some(range) = state.pending_highlight.take() {
+                match edit_note(terminal, config) {
+                    Ok(note) => state.add_highlight(range, note),
+                    Err(e) => {
+                        // Keep the selection so the note can be tried again
+                        state.pending_highlight = Some(range);
+                        state.show_message(format!("Cannot edit note: {}", e), MessageType::Error)
+                    }
+                }
+            }
+        }
+
+        // Save progress now and then
+        if config.general.auto_save && state.book_id.is_some() && last_save.elapsed() >= auto_save {
+            if let Err(e) = state.save_progress(false) {
+                state.show_message(
+                    format!("Failed to save progress: {}", e),
+                    MessageType::Error,
+                );
+            }
+            last_save = Instant::now();
+        }
+
+        // Follow a mark into another book
+        if let Some((path, chapter, block)) = state.open_book.take() {
+            match BookCache::new(config).and_then(|cache| cache.open(&path)) {
+                Ok(book) => state.switch_book(book, chapter, block),
+                Err(e) => state.show_message(
+                    format!("Cannot open {}: {}", path.display(), e),
+                    MessageType::Error,
+                ),
+            }
+        }
+
         // Check if we should quit
         if state.should_quit {
             break;
@@ -85,3 +195,43 @@ fn run_loop(
 
     Ok(())
 }
+
+/// Write a note in `$EDITOR`, handing it the terminal meanwhile
+fn edit_note(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    config: &Config,
+) -> Result<Option<String>> {
+    let path = std::env::temp_dir().join(format!("franko-note-{}.md", std::process::id()));
+    std::fs::write(&path, "")?;
+
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    // $EDITOR may carry arguments, such as `code -w`
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = match shlex::split(&editor).as_deref() {
+        Some([program, args @ ..]) => std::process::Command::new(program)
+            .args(args)
+            .arg(&path)
+            .status()
+            .map_err(anyhow::Error::from),
+        _ => Err(anyhow::anyhow!("Invalid $EDITOR: {}", editor)),
+    };
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    if config.tui.mouse_support {
+        execute!(terminal.backend_mut(), EnableMouseCapture)?;
+    }
+    terminal.clear()?;
+
+    let note = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    if !status?.success() {
+        anyhow::bail!("{} exited with an error", editor);
+    }
+    let note = note?.trim().to_string();
+    Ok((!note.is_empty()).then_some(note))
+}
@@ -0,0 +1,92 @@
+//! Bidirectional text for the TUI
+//!
+//! Lines keep their text in logical order, so selections, links and search
+//! highlights keep their byte offsets. Only drawing puts right-to-left runs
+//! in visual order, by the Unicode bidirectional algorithm (UAX #9), and
+//! right-to-left paragraphs are set flush right.
+
+use std::ops::Range;
+use unicode_bidi::{bidi_class, get_base_direction, BidiClass, BidiInfo, Direction, Level};
+
+/// Whether a paragraph runs right to left, by its first strong character
+pub fn is_rtl(text: &str) -> bool {
+    get_base_direction(text) == Direction::Rtl
+}
+
+/// Byte ranges of a line in the order they are shown, each with whether it
+/// runs right to left
+pub fn visual_runs(text: &str, rtl: bool) -> Vec<(Range<usize>, bool)> {
+    let right_to_left = |c| {
+        matches!(
+            bidi_class(c),
+            BidiClass::R | BidiClass::AL | BidiClass::RLE | BidiClass::RLO | BidiClass::RLI
+        )
+    };
+    if !rtl && !text.chars().any(right_to_left) {
+        return vec![(0..text.len(), false)];
+    }
+
+    let level = if rtl { Level::rtl() } else { Level::ltr() };
+    let info = BidiInfo::new(text, Some(level));
+    let Some(paragraph) = info.paragraphs.first() else {
+        return Vec::new();
+    };
+    let (levels, runs) = info.visual_runs(paragraph, paragraph.range.clone());
+    runs.into_iter()
+        .map(|run| {
+            let rtl = levels[run.start].is_rtl();
+            (run, rtl)
+        })
+        .collect()
+}
+
+/// A character as shown in a right-to-left run, with brackets mirrored
+pub fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of each run in visual order, reversed when right to left
+    fn shown(text: &str, rtl: bool) -> String {
+        visual_runs(text, rtl)
+            .into_iter()
+            .map(|(run, rtl)| {
+                if rtl {
+                    text[run].chars().rev().map(mirror).collect()
+                } else {
+                    text[run].to_string()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reordering() {
+        assert!(is_rtl("שלום world"));
+        assert!(!is_rtl("world שלום"));
+        assert_eq!(visual_runs("plain", false), vec![(0..5, false)]);
+
+        // Hebrew inside English is reversed in place
+        assert_eq!(shown("say שלום now", false), "say םולש now");
+        // English inside Hebrew keeps its order, and the runs swap
+        assert_eq!(shown("אבג abc", true), "abc גבא");
+        assert_eq!(shown("(אב)", true), "(בא)");
+    }
+}
@@ -0,0 +1,559 @@
+//! Inline images in the terminal
+//!
+//! Every image gets Unicode half-block lines in the line cache, so it shows
+//! up on any terminal with true color. When the terminal speaks a graphics
+//! protocol (kitty, sixel or iTerm2), the real image is drawn over those
+//! rows after each frame, as long as it is entirely on screen.
+
+use crate::formats;
+use anyhow::Result;
+use base64::Engine;
+use image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+use ratatui::style::{Color, Style};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Number of decoded images kept in memory
+const DECODED_IMAGES: usize = 8;
+
+/// Number of encoded images kept in memory, one per image and size
+const ENCODED_IMAGES: usize = 16;
+
+/// Terminal graphics protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    Iterm2,
+    HalfBlocks,
+}
+
+impl Protocol {
+    /// Pick the protocol from the `tui.image_protocol` setting
+    ///
+    /// `auto` looks at the environment; `none` disables images.
+    pub fn from_setting(setting: &str) -> Option<Self> {
+        match setting {
+            "kitty" => Some(Protocol::Kitty),
+            "sixel" => Some(Protocol::Sixel),
+            "iterm2" => Some(Protocol::Iterm2),
+            "halfblocks" => Some(Protocol::HalfBlocks),
+            "none" => None,
+            _ => Some(Self::detect(|name| std::env::var(name).ok())),
+        }
+    }
+
+    /// Guess the protocol from terminal environment variables
+    fn detect(var: impl Fn(&str) -> Option<String>) -> Self {
+        // Multiplexers swallow graphics escapes unless specially configured
+        if var("TMUX").is_some() || var("STY").is_some() {
+            return Protocol::HalfBlocks;
+        }
+
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+
+        if var("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Protocol::Kitty
+        } else if program == "iTerm.app" || var("LC_TERMINAL").as_deref() == Some("iTerm2") {
+            Protocol::Iterm2
+        } else if term.contains("sixel")
+            || ["foot", "mlterm", "contour", "yaft"]
+                .iter()
+                .any(|t| term.starts_with(t))
+        {
+            Protocol::Sixel
+        } else {
+            Protocol::HalfBlocks
+        }
+    }
+}
+
+/// An image laid out in the line cache
+#[derive(Debug, Clone)]
+pub struct InlineImage {
+    pub src: String,
+    /// Index of the first line in the line cache
+    pub first_line: usize,
+    /// Offset of the image from the start of its lines, in cells
+    pub indent: u16,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+/// Where an image is drawn on screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    image: usize,
+    x: u16,
+    y: u16,
+}
+
+/// Decoded images and their on-screen placements
+pub struct Graphics {
+    pub protocol: Option<Protocol>,
+    /// Size of a terminal cell in pixels
+    cell: (u32, u32),
+    decoded: Recent<(PathBuf, String), Option<DynamicImage>>,
+    encoded: Recent<(PathBuf, String, u16, u16), String>,
+    /// Images in the current line cache
+    pub images: Vec<InlineImage>,
+    /// Placements requested by the last render
+    pending: Vec<Placement>,
+    /// Placements currently on screen
+    shown: Vec<Placement>,
+}
+
+impl Graphics {
+    pub fn new(setting: &str) -> Self {
+        let cell = crossterm::terminal::window_size()
+            .ok()
+            .filter(|s| s.width > 0 && s.height > 0 && s.columns > 0 && s.rows > 0)
+            .map(|s| ((s.width / s.columns) as u32, (s.height / s.rows) as u32))
+            .unwrap_or((8, 16));
+
+        Graphics {
+            protocol: Protocol::from_setting(setting),
+            cell,
+            decoded: Recent::new(DECODED_IMAGES),
+            encoded: Recent::new(ENCODED_IMAGES),
+            images: Vec::new(),
+            pending: Vec::new(),
+            shown: Vec::new(),
+        }
+    }
+
+    /// Load and decode an image of a book, remembering failures
+    pub fn load(&mut self, book: &Path, src: &str) -> Option<&DynamicImage> {
+        self.decoded
+            .get_or_insert_with((book.to_path_buf(), src.to_string()), || {
+                let (data, _) = formats::load_resource(book, src).ok()??;
+                image::load_from_memory(&data)
+                    .map_err(|e| tracing::debug!("Cannot decode image {}: {}", src, e))
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// Size of a cell in image pixels
+    ///
+    /// Half blocks show two pixels per cell.
+    pub fn cell_size(&self) -> (u32, u32) {
+        match self.protocol {
+            Some(Protocol::HalfBlocks) | None => (1, 2),
+            _ => self.cell,
+        }
+    }
+
+    /// Queue the images lying entirely within the lines on screen
+    ///
+    /// `view` holds the lines of each column with the screen cell of its
+    /// first line. Returns the line ranges of the queued images, which the
+    /// frame should leave blank. Nothing is queued unless `show` is set and
+    /// the protocol draws real images.
+    pub fn place_visible(
+        &mut self,
+        view: &[(Range<usize>, (u16, u16))],
+        show: bool,
+    ) -> Vec<Range<usize>> {
+        self.pending.clear();
+        if !show
+            || !matches!(
+                self.protocol,
+                Some(Protocol::Kitty | Protocol::Sixel | Protocol::Iterm2)
+            )
+        {
+            return Vec::new();
+        }
+
+        let mut covered = Vec::new();
+        for (index, image) in self.images.iter().enumerate() {
+            let lines = image.first_line..image.first_line + image.rows as usize;
+            let Some((shown, origin)) = view
+                .iter()
+                .find(|(shown, _)| lines.start >= shown.start && lines.end <= shown.end)
+            else {
+                continue;
+            };
+            self.pending.push(Placement {
+                image: index,
+                x: origin.0,
+                y: origin.1 + (lines.start - shown.start) as u16,
+            });
+            covered.push(lines);
+        }
+        covered
+    }
+
+    /// Whether images left on screen must be wiped by a full redraw
+    ///
+    /// Sixel and iTerm2 images replace cell contents the TUI does not know
+    /// about, so moving or hiding them needs the screen cleared.
+    pub fn needs_clear(&self) -> bool {
+        matches!(self.protocol, Some(Protocol::Sixel | Protocol::Iterm2))
+            && !self.shown.is_empty()
+            && self.pending != self.shown
+    }
+
+    /// Draw the pending placements after a frame
+    pub fn flush(&mut self, book: &Path, out: &mut impl Write) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        if pending == self.shown {
+            return Ok(());
+        }
+
+        if self.protocol == Some(Protocol::Kitty) {
+            // Delete all placements, keeping nothing around
+            write!(out, "\x1b_Ga=d,d=A,q=2\x1b\\")?;
+        }
+        for placement in &pending {
+            let Some(image) = self.images.get(placement.image).cloned() else {
+                continue;
+            };
+            let Some(payload) = self.payload(book, &image) else {
+                continue;
+            };
+            crossterm::queue!(
+                out,
+                crossterm::cursor::MoveTo(placement.x + image.indent, placement.y)
+            )?;
+            out.write_all(payload.as_bytes())?;
+        }
+        out.flush()?;
+
+        self.shown = pending;
+        Ok(())
+    }
+
+    /// Escape sequence drawing an image at the cursor, cached per size
+    fn payload(&mut self, book: &Path, image: &InlineImage) -> Option<String> {
+        let key = (
+            book.to_path_buf(),
+            image.src.clone(),
+            image.cols,
+            image.rows,
+        );
+        if let Some(payload) = self.encoded.get(&key) {
+            return Some(payload.clone());
+        }
+
+        let (cw, ch) = self.cell;
+        let protocol = self.protocol?;
+        let pixels = self.load(book, &image.src)?.resize_exact(
+            image.cols as u32 * cw,
+            image.rows as u32 * ch,
+            FilterType::Triangle,
+        );
+        let payload = match protocol {
+            Protocol::Kitty => kitty(&pixels, image.cols, image.rows)?,
+            Protocol::Iterm2 => iterm2(&pixels, image.cols, image.rows)?,
+            Protocol::Sixel => sixel(&pixels.to_rgba8()),
+            Protocol::HalfBlocks => return None,
+        };
+        self.encoded.insert(key, payload.clone());
+        Some(payload)
+    }
+}
+
+/// The most recently used values, dropping the oldest past a capacity
+struct Recent<K, V> {
+    capacity: usize,
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> Recent<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The value of a key, marked as just used
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let pos = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(pos)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, value)| value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if let Some(pos) = self.entries.iter().position(|(k, _)| *k == key) {
+            self.entries.remove(pos);
+        } else if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, value));
+    }
+
+    /// The value of a key, made with `make` if it is not there
+    fn get_or_insert_with(&mut self, key: K, make: impl FnOnce() -> V) -> &V {
+        if self.get(&key).is_none() {
+            self.insert(key, make());
+        }
+        &self.entries.back().expect("entry was just used").1
+    }
+}
+
+/// Fit an image of `width` by `height` pixels into a box of cells
+///
+/// The image is never enlarged past its natural size.
+pub fn fit(
+    width: u32,
+    height: u32,
+    (cw, ch): (u32, u32),
+    max_cols: usize,
+    max_rows: usize,
+) -> (u16, u16) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let (cw, ch) = (cw as f64, ch as f64);
+
+    let mut cols = (width / cw).ceil().min(max_cols.max(1) as f64);
+    let mut rows = (cols * cw * height / width / ch).ceil();
+    if rows > max_rows.max(1) as f64 {
+        rows = max_rows.max(1) as f64;
+        cols = (rows * ch * width / height / cw).round().max(1.0);
+    }
+    (cols as u16, rows.max(1.0) as u16)
+}
+
+/// A line of text with colored byte ranges
+pub type ColoredLine = (String, Vec<(usize, usize, Style)>);
+
+/// Render an image as lines of upper half blocks
+///
+/// Each cell shows two pixels: the foreground color paints the top one and
+/// the background the bottom one. Colors are byte ranges into the text.
+pub fn half_blocks(image: &DynamicImage, cols: u16, rows: u16) -> Vec<ColoredLine> {
+    let pixels = image
+        .resize_exact(cols as u32, rows as u32 * 2, FilterType::Triangle)
+        .to_rgba8();
+
+    (0..rows as u32)
+        .map(|row| {
+            let mut text = String::new();
+            let mut colors = Vec::new();
+            for x in 0..cols as u32 {
+                let start = text.len();
+                text.push('▀');
+                let style = Style::default()
+                    .fg(color(pixels.get_pixel(x, row * 2)))
+                    .bg(color(pixels.get_pixel(x, row * 2 + 1)));
+                colors.push((start, text.len(), style));
+            }
+            (text, colors)
+        })
+        .collect()
+}
+
+fn color(pixel: &Rgba<u8>) -> Color {
+    let [r, g, b, a] = pixel.0;
+    if a < 128 {
+        Color::Reset
+    } else {
+        Color::Rgb(r, g, b)
+    }
+}
+
+fn png(image: &DynamicImage) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut data),
+            image::ImageOutputFormat::Png,
+        )
+        .ok()?;
+    Some(data)
+}
+
+/// Kitty graphics: transmit a PNG and display it over `cols` by `rows` cells
+fn kitty(image: &DynamicImage, cols: u16, rows: u16) -> Option<String> {
+    let data = base64::engine::general_purpose::STANDARD.encode(png(image)?);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).ok()?;
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,c={},r={},C=1,q=2,m={};{}\x1b\\",
+                cols, rows, more, chunk
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    Some(out)
+}
+
+/// iTerm2 inline image protocol
+fn iterm2(image: &DynamicImage, cols: u16, rows: u16) -> Option<String> {
+    let data = png(image)?;
+    Some(format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+        data.len(),
+        cols,
+        rows,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+/// Sixel graphics with a 6×6×6 color cube palette
+///
+/// Transparent pixels are left unpainted.
+fn sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let register = |p: &Rgba<u8>| {
+        let [r, g, b, a] = p.0;
+        (a >= 128).then(|| level(r) * 36 + level(g) * 6 + level(b))
+    };
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        let percent = |v: u32| v * 100 / 5;
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        ));
+    }
+
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        let mut registers: Vec<u32> = (band..band + band_rows)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter_map(|(x, y)| register(image.get_pixel(x, y)))
+            .collect();
+        registers.sort_unstable();
+        registers.dedup();
+
+        for (n, reg) in registers.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", reg));
+
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = (0..band_rows)
+                    .filter(|&y| register(image.get_pixel(x, band + y)) == Some(*reg))
+                    .fold(0u8, |bits, y| bits | (1 << y));
+                let ch = (63 + bits) as char;
+                run = match run {
+                    Some((c, count)) if c == ch => Some((c, count + 1)),
+                    Some(previous) => {
+                        push_run(&mut out, previous);
+                        Some((ch, 1))
+                    }
+                    None => Some((ch, 1)),
+                };
+            }
+            if let Some(last) = run {
+                push_run(&mut out, last);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, (ch, count): (char, usize)) {
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, ch));
+    } else {
+        out.extend(std::iter::repeat_n(ch, count));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_protocol() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert_eq!(
+            Protocol::detect(env(&[("TERM", "xterm-kitty")])),
+            Protocol::Kitty
+        );
+        assert_eq!(
+            Protocol::detect(env(&[("TERM_PROGRAM", "iTerm.app")])),
+            Protocol::Iterm2
+        );
+        assert_eq!(Protocol::detect(env(&[("TERM", "foot")])), Protocol::Sixel);
+        assert_eq!(
+            Protocol::detect(env(&[("TERM", "xterm-kitty"), ("TMUX", "1")])),
+            Protocol::HalfBlocks
+        );
+        assert_eq!(
+            Protocol::detect(env(&[("TERM", "xterm-256color")])),
+            Protocol::HalfBlocks
+        );
+    }
+
+    #[test]
+    fn test_fit() {
+        // Small images keep their natural size
+        assert_eq!(fit(20, 40, (1, 2), 80, 50), (20, 20));
+        // Wide images shrink to the wrap width
+        assert_eq!(fit(800, 400, (10, 20), 40, 50), (40, 10));
+        // Tall images shrink to the visible height
+        assert_eq!(fit(100, 1000, (1, 2), 80, 25), (5, 25));
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let mut pixels = RgbaImage::new(2, 2);
+        pixels.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        pixels.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+        let lines = half_blocks(&DynamicImage::ImageRgba8(pixels), 2, 1);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, "▀▀");
+        let style = lines[0].1[0].2;
+        assert_eq!(style.fg, Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(style.bg, Some(Color::Rgb(0, 0, 255)));
+        // Transparent pixels keep the terminal background
+        assert_eq!(lines[0].1[1].2.fg, Some(Color::Reset));
+    }
+
+    #[test]
+    fn test_recent_drops_least_used() {
+        let mut recent = Recent::new(2);
+        recent.insert("a", 1);
+        recent.insert("b", 2);
+        assert_eq!(recent.get(&"a"), Some(&1));
+        recent.insert("c", 3);
+        assert_eq!(recent.get(&"b"), None);
+        assert_eq!(recent.get_or_insert_with("a", || 10), &1);
+        assert_eq!(recent.get_or_insert_with("b", || 20), &20);
+        assert_eq!(recent.entries.len(), 2);
+        assert_eq!(recent.get(&"c"), None);
+    }
+
+    #[test]
+    fn test_sixel() {
+        let mut pixels = RgbaImage::new(5, 1);
+        for x in 0..5 {
+            pixels.put_pixel(x, 0, Rgba([255, 255, 255, 255]));
+        }
+        let sixel = sixel(&pixels);
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;5;1#0;2;0;0;0"));
+        assert!(sixel.ends_with("#215!5@-\x1b\\"));
+    }
+}
@@ -1,9 +1,14 @@
 //! Input handling for TUI
 
 use super::event::{InputEvent, KeyInput};
-use super::state::{AppState, MessageType, Mode};
-use crate::config::Config;
-use crossterm::event::{KeyCode, MouseEventKind};
+use super::keymap::{self, Key};
+use super::state::{AppState, MarkOp, MessageType, Mode, HINT_KEYS};
+use super::visual::{Motion, Selection};
+use crate::config::{Action, Config};
+use crossterm::event::{KeyCode, MouseButton, MouseEventKind};
+
+/// Largest count a motion takes
+const MAX_COUNT: usize = 99_999;
 
 /// Handle input events
 pub fn handle_input(state: &mut AppState, event: InputEvent, config: &Config) {
@@ -16,6 +21,13 @@ pub fn handle_input(state: &mut AppState, event: InputEvent, config: &Config) {
         }
         InputEvent::Tick => {
             state.tick_message();
+            if state.mode == Mode::Normal {
+                let modes = state.key_modes();
+                if let Some(action) = state.keys.tick(&modes) {
+                    let count = state.count.take();
+                    run_action(state, action, count);
+                }
+            }
         }
     }
 }
@@ -29,114 +41,147 @@ fn handle_key(state: &mut AppState, key: KeyInput, config: &Config) {
         Mode::TableOfContents => handle_toc_mode(state, key),
         Mode::Bookmark => handle_bookmark_mode(state, key),
         Mode::GoTo => handle_goto_mode(state, key),
+        Mode::Hint => handle_hint_mode(state, key),
+        Mode::Visual => handle_visual_mode(state, key),
+        Mode::Annotations => handle_annotation_mode(state, key),
     }
 }
 
 fn handle_normal_mode(state: &mut AppState, key: KeyInput, _config: &Config) {
-    // Check for quit first
-    if key.code == KeyCode::Char('q') && !key.is_ctrl() {
+    // Ctrl+c quits whatever the keybindings say
+    if key.code == KeyCode::Char('c') && key.is_ctrl() {
         state.should_quit = true;
         return;
     }
 
-    if key.code == KeyCode::Char('c') && key.is_ctrl() {
-        state.should_quit = true;
+    // The key after `m` or `'` names the mark
+    if let Some(op) = state.mark_op.take() {
+        state.count = None;
+        match (key.code, op) {
+            (KeyCode::Char(name), MarkOp::Set) => state.set_mark(name),
+            (KeyCode::Char(name), MarkOp::Jump) => state.go_to_mark(name),
+            _ => {}
+        }
         return;
     }
 
-    // Navigation
-    match key.code {
-        // Vim-style navigation
-        KeyCode::Char('j') | KeyCode::Down => state.scroll_down(1),
-        KeyCode::Char('k') | KeyCode::Up => state.scroll_up(1),
-        KeyCode::Char('h') | KeyCode::Left => state.prev_chapter(),
-        KeyCode::Char('l') | KeyCode::Right => state.next_chapter(),
-
-        // Page navigation
-        KeyCode::Char(' ') | KeyCode::PageDown => state.page_down(),
-        KeyCode::PageUp => state.page_up(),
-
-        // Ctrl+navigation
-        KeyCode::Char('f') if key.is_ctrl() => state.page_down(),
-        KeyCode::Char('b') if key.is_ctrl() => state.page_up(),
-        KeyCode::Char('d') if key.is_ctrl() => state.half_page_down(),
-        KeyCode::Char('u') if key.is_ctrl() => state.half_page_up(),
-
-        // Go to top/bottom
-        KeyCode::Char('g') if !key.is_ctrl() => state.go_to_top(),
-        KeyCode::Char('G') => state.go_to_bottom(),
-        KeyCode::Home => state.go_to_top(),
-        KeyCode::End => state.go_to_bottom(),
-
-        // Chapter navigation
-        KeyCode::Char('n') if !state.search.active => state.next_chapter(),
-        KeyCode::Char('n') if state.search.active => state.search_next(),
-        KeyCode::Char('N') | KeyCode::Char('p') => state.prev_chapter(),
-        KeyCode::Char(']') => state.next_chapter(),
-        KeyCode::Char('[') => state.prev_chapter(),
+    // Digits before a motion make up its count; 0 only continues one
+    if let KeyCode::Char(c @ '0'..='9') = key.code {
+        let plain = !key.is_ctrl() && !key.is_alt();
+        if plain && state.keys.pending().is_empty() && (c != '0' || state.count.is_some()) {
+            let digit = c as usize - '0' as usize;
+            let count = state.count.unwrap_or(0).saturating_mul(10);
+            state.count = Some(count.saturating_add(digit).min(MAX_COUNT));
+            return;
+        }
+    }
+
+    let modes = state.key_modes();
+    let actions = state.keys.feed(Key::new(key.code, key.modifiers), &modes);
+    if actions.is_empty() && !state.keys.pending().is_empty() {
+        return;
+    }
+    let count = state.count.take();
+    for action in actions {
+        run_action(state, action, count);
+    }
+}
+
+/// Run an action bound to a key, scaled by the count typed before it
+fn run_action(state: &mut AppState, action: Action, count: Option<usize>) {
+    let n = count.unwrap_or(1);
+    match action {
+        // Navigation
+        Action::ScrollDown => state.scroll_down(n),
+        Action::ScrollUp => state.scroll_up(n),
+        Action::PageDown => (0..n).for_each(|_| state.page_down()),
+        Action::PageUp => (0..n).for_each(|_| state.page_up()),
+        Action::HalfPageDown => (0..n).for_each(|_| state.half_page_down()),
+        Action::HalfPageUp => (0..n).for_each(|_| state.half_page_up()),
+        Action::GoToTop | Action::GoToBottom if count.is_some() => state.go_to_line(n),
+        Action::GoToTop => state.go_to_top(),
+        Action::GoToBottom => state.go_to_bottom(),
+        Action::GoToLine => {
+            state.set_mode(Mode::GoTo);
+            state.command_buffer.clear();
+        }
+        Action::GoToPercent => match count {
+            Some(percent) => state.go_to_percent(percent),
+            None => state.show_message(
+                "Type a count first, like 50%".to_string(),
+                MessageType::Info,
+            ),
+        },
+        Action::NextChapter => state.next_chapter(n),
+        Action::PrevChapter => state.prev_chapter(n),
+        Action::NextParagraph => (0..n).for_each(|_| state.next_block()),
+        Action::PrevParagraph => (0..n).for_each(|_| state.prev_block()),
+
+        // UI toggles
+        Action::ToggleFullscreen => state.toggle_fullscreen(),
+        Action::ToggleSidebar => state.toggle_sidebar(),
+        Action::ToggleStatusBar => state.toggle_status_bar(),
+        Action::ToggleLineNumbers => state.toggle_line_numbers(),
 
         // Search
-        KeyCode::Char('/') => {
+        Action::Search => {
             state.set_mode(Mode::Search);
             state.search.query.clear();
             state.search.cursor = 0;
         }
+        Action::SearchNext => (0..n).for_each(|_| state.search_next()),
+        Action::SearchPrev => (0..n).for_each(|_| state.search_prev()),
+        Action::ClearSearch => {
+            state.search.clear();
+            state.invalidate_cache();
+        }
 
         // Bookmarks
-        KeyCode::Char('m') => {
-            state.add_bookmark(None);
-        }
-        KeyCode::Char('\'') => {
+        Action::AddBookmark => state.add_bookmark(None),
+        Action::ListBookmarks | Action::GotoBookmark => {
+            state.bookmark_selected = 0;
             state.set_mode(Mode::Bookmark);
         }
-
-        // UI toggles
-        KeyCode::Char('s') => state.toggle_sidebar(),
-        KeyCode::Char('S') => state.toggle_status_bar(),
-        KeyCode::Char('L') => state.toggle_line_numbers(),
-        KeyCode::Char('f') if !key.is_ctrl() => state.toggle_fullscreen(),
-
-        // Help
-        KeyCode::Char('?') | KeyCode::F(1) => {
-            state.set_mode(Mode::Help);
+        Action::ListAnnotations | Action::GotoAnnotation => {
+            state.annotation_selected = 0;
+            state.set_mode(Mode::Annotations);
         }
-
-        // Table of contents
-        KeyCode::Char('t') => {
-            state.set_mode(Mode::TableOfContents);
+        Action::AddAnnotation => {
+            state.set_mode(Mode::Command);
+            state.command_buffer = "note ".to_string();
+            state.command_cursor = state.command_buffer.len();
         }
+        Action::SetMark => state.mark_op = Some(MarkOp::Set),
+        Action::GoToMark => state.mark_op = Some(MarkOp::Jump),
+
+        Action::StartSelection => state.start_selection(),
+
+        // Links and the jump list
+        Action::FollowLink => state.start_hints(),
+        Action::JumpBack => state.jump_back(),
+        Action::JumpForward => state.jump_forward(),
 
-        // Command mode
-        KeyCode::Char(':') => {
+        Action::Help => state.set_mode(Mode::Help),
+        Action::TableOfContents => state.open_toc(),
+        Action::CommandPalette => {
             state.set_mode(Mode::Command);
             state.command_buffer.clear();
             state.command_cursor = 0;
         }
-
-        // Go to line
-        KeyCode::Char('g') if key.is_ctrl() => {
-            state.set_mode(Mode::GoTo);
-            state.command_buffer.clear();
-        }
-
-        // Theme toggle
-        KeyCode::Char('T') => {
-            // Cycle through themes
-            state.theme = match state.theme.as_str() {
-                "dark" => "light".to_string(),
-                "light" => "sepia".to_string(),
-                _ => "dark".to_string(),
-            };
-            state.show_message(format!("Theme: {}", state.theme), MessageType::Info);
-        }
-
-        // Refresh
-        KeyCode::Char('r') if key.is_ctrl() => {
+        Action::Quit | Action::ForceQuit => state.should_quit = true,
+        Action::ToggleTheme => state.next_theme(),
+        Action::Refresh => {
             state.invalidate_cache();
             state.show_message("Refreshed".to_string(), MessageType::Info);
         }
+        Action::Escape | Action::Confirm | Action::Cancel => {}
 
-        _ => {}
+        _ => {
+            state.show_message(
+                format!("{} is not available in the terminal reader", action.name()),
+                MessageType::Warning,
+            );
+        }
     }
 }
 
@@ -145,12 +190,14 @@ fn handle_command_mode(state: &mut AppState, key: KeyInput) {
         KeyCode::Esc => {
             state.return_to_previous_mode();
             state.command_buffer.clear();
+            state.pending_highlight = None;
         }
         KeyCode::Enter => {
             let command = state.command_buffer.clone();
             state.return_to_previous_mode();
             execute_command(state, &command);
             state.command_buffer.clear();
+            state.pending_highlight = None;
         }
         KeyCode::Backspace => {
             if state.command_cursor > 0 {
@@ -230,36 +277,119 @@ fn handle_toc_mode(state: &mut AppState, key: KeyInput) {
             state.return_to_previous_mode();
         }
         KeyCode::Char('j') | KeyCode::Down => {
-            // Navigate TOC entries
+            let last = state.book.chapter_count().saturating_sub(1);
+            state.toc_selected = (state.toc_selected + 1).min(last);
         }
         KeyCode::Char('k') | KeyCode::Up => {
-            // Navigate TOC entries
+            state.toc_selected = state.toc_selected.saturating_sub(1);
         }
         KeyCode::Enter => {
-            // Go to selected TOC entry
             state.return_to_previous_mode();
+            state.go_to_chapter(state.toc_selected);
         }
         _ => {}
     }
 }
 
 fn handle_bookmark_mode(state: &mut AppState, key: KeyInput) {
+    let count = state.bookmarks.len();
     match key.code {
-        KeyCode::Esc | KeyCode::Char('\'') => {
+        KeyCode::Esc | KeyCode::Char('q') => {
             state.return_to_previous_mode();
         }
-        KeyCode::Char(c) if c.is_ascii_digit() => {
-            let index = c.to_digit(10).unwrap() as usize;
-            if index > 0 && index <= state.bookmarks.len() {
-                state.go_to_bookmark(index - 1);
+        KeyCode::Char('j') | KeyCode::Down => {
+            state.bookmark_selected = (state.bookmark_selected + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Char('k') | KeyCode::Up => {
+            state.bookmark_selected = state.bookmark_selected.saturating_sub(1);
+        }
+        KeyCode::Char('d') | KeyCode::Delete => {
+            state.remove_bookmark(state.bookmark_selected);
+        }
+        KeyCode::Char(c @ '1'..='9') => {
+            let index = c as usize - '1' as usize;
+            if index < count {
                 state.return_to_previous_mode();
+                state.go_to_bookmark(index);
             }
         }
         KeyCode::Enter => {
-            if !state.bookmarks.is_empty() {
-                state.go_to_bookmark(0);
-            }
             state.return_to_previous_mode();
+            state.go_to_bookmark(state.bookmark_selected);
+        }
+        _ => {}
+    }
+}
+
+fn handle_annotation_mode(state: &mut AppState, key: KeyInput) {
+    let count = state.annotations.len();
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            state.return_to_previous_mode();
+        }
+        KeyCode::Char('j') | KeyCode::Down => {
+            state.annotation_selected =
+                (state.annotation_selected + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Char('k') | KeyCode::Up => {
+            state.annotation_selected = state.annotation_selected.saturating_sub(1);
+        }
+        KeyCode::Char('d') | KeyCode::Delete => {
+            state.remove_annotation(state.annotation_selected);
+        }
+        KeyCode::Enter => {
+            state.return_to_previous_mode();
+            state.go_to_annotation(state.annotation_selected);
+        }
+        _ => {}
+    }
+}
+
+/// Keys in visual mode move the cursor or act on the selection
+fn handle_visual_mode(state: &mut AppState, key: KeyInput) {
+    let motion = match key.code {
+        KeyCode::Char('h') | KeyCode::Left => Some(Motion::Left),
+        KeyCode::Char('l') | KeyCode::Right => Some(Motion::Right),
+        KeyCode::Char('k') | KeyCode::Up => Some(Motion::Up),
+        KeyCode::Char('j') | KeyCode::Down => Some(Motion::Down),
+        KeyCode::Char('0') | KeyCode::Home => Some(Motion::LineStart),
+        KeyCode::Char('$') | KeyCode::End => Some(Motion::LineEnd),
+        KeyCode::Char('w') => Some(Motion::WordForward),
+        KeyCode::Char('b') => Some(Motion::WordBackward),
+        KeyCode::Char('e') => Some(Motion::WordEnd),
+        KeyCode::Char(')') => Some(Motion::SentenceForward),
+        KeyCode::Char('(') => Some(Motion::SentenceBackward),
+        _ => None,
+    };
+    if let Some(motion) = motion {
+        state.move_cursor(motion, 1);
+        return;
+    }
+
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('v') => state.set_mode(Mode::Normal),
+        KeyCode::Char('o') => state.selection.swap(),
+        KeyCode::Char('c') => state.cycle_highlight_color(),
+        KeyCode::Enter | KeyCode::Char('a') => {
+            if let Some(range) = state.take_selection() {
+                state.add_highlight(range, None);
+            }
+        }
+        // A note typed on the command line
+        KeyCode::Char('n') => {
+            if let Some(range) = state.take_selection() {
+                state.pending_highlight = Some(range);
+                state.set_mode(Mode::Command);
+                state.command_buffer = "note ".to_string();
+                state.command_cursor = state.command_buffer.len();
+            }
+        }
+        // A note written in $EDITOR, opened once this key is handled
+        KeyCode::Char('N') => {
+            if let Some(range) = state.take_selection() {
+                state.pending_highlight = Some(range);
+                state.edit_note = true;
+            }
         }
         _ => {}
     }
@@ -273,7 +403,7 @@ fn handle_goto_mode(state: &mut AppState, key: KeyInput) {
         }
         KeyCode::Enter => {
             if let Ok(line) = state.command_buffer.parse::<usize>() {
-                state.position.scroll_offset = line.saturating_sub(1);
+                state.go_to_line(line);
             }
             state.return_to_previous_mode();
             state.command_buffer.clear();
@@ -288,12 +418,73 @@ fn handle_goto_mode(state: &mut AppState, key: KeyInput) {
     }
 }
 
+fn handle_hint_mode(state: &mut AppState, key: KeyInput) {
+    let count = state.hints.matching().count();
+    match key.code {
+        KeyCode::Esc => {
+            state.return_to_previous_mode();
+        }
+        KeyCode::Tab | KeyCode::Down => {
+            state.hints.selected = (state.hints.selected + 1) % count.max(1);
+        }
+        KeyCode::BackTab | KeyCode::Up => {
+            state.hints.selected = (state.hints.selected + count.max(1) - 1) % count.max(1);
+        }
+        KeyCode::Enter => {
+            let link = state
+                .hints
+                .matching()
+                .nth(state.hints.selected)
+                .map(|h| h.1);
+            state.return_to_previous_mode();
+            if let Some(link) = link {
+                state.follow_link(link);
+            }
+        }
+        KeyCode::Backspace => {
+            state.hints.typed.pop();
+            state.hints.selected = 0;
+        }
+        KeyCode::Char(c) if HINT_KEYS.contains(c) => {
+            state.hints.typed.push(c);
+            let matches: Vec<(String, usize)> = state.hints.matching().cloned().collect();
+            match matches.as_slice() {
+                [] => {
+                    state.hints.typed.pop();
+                }
+                [(label, link)] if *label == state.hints.typed => {
+                    let link = *link;
+                    state.return_to_previous_mode();
+                    state.follow_link(link);
+                }
+                _ => state.hints.selected = 0,
+            }
+        }
+        _ => {}
+    }
+}
+
 fn handle_mouse(state: &mut AppState, mouse: super::event::MouseInput, _config: &Config) {
     match mouse.kind {
         MouseEventKind::ScrollUp => state.scroll_up(3),
         MouseEventKind::ScrollDown => state.scroll_down(3),
-        MouseEventKind::Down(_) => {
-            // Handle click - could be for selecting text, clicking links, etc.
+        // Dragging over text selects it, as in visual mode
+        MouseEventKind::Down(MouseButton::Left) => {
+            if matches!(state.mode, Mode::Normal | Mode::Visual) {
+                if let Some(at) = state.cursor_at(mouse.column, mouse.row) {
+                    state.selection = Selection::new(at);
+                }
+            }
+        }
+        MouseEventKind::Drag(MouseButton::Left) => {
+            if matches!(state.mode, Mode::Normal | Mode::Visual) {
+                if let Some(at) = state.cursor_at(mouse.column, mouse.row) {
+                    state.selection.cursor = at;
+                    if state.mode == Mode::Normal {
+                        state.set_mode(Mode::Visual);
+                    }
+                }
+            }
         }
         _ => {}
     }
@@ -306,22 +497,23 @@ fn execute_command(state: &mut AppState, command: &str) {
         Some("q") | Some("quit") | Some("exit") => {
             state.should_quit = true;
         }
-        Some("w") | Some("write") | Some("save") => {
-            // Save progress
-            state.dirty = false;
-            state.show_message("Progress saved".to_string(), MessageType::Success);
-        }
-        Some("wq") => {
-            state.dirty = false;
-            state.should_quit = true;
-        }
+        Some("w") | Some("write") | Some("save") => match state.save_progress(true) {
+            Ok(()) => state.show_message("Progress saved".to_string(), MessageType::Success),
+            Err(e) => {
+                state.show_message(format!("Cannot save progress: {}", e), MessageType::Error)
+            }
+        },
+        Some("wq") => match state.save_progress(true) {
+            Ok(()) => state.should_quit = true,
+            Err(e) => {
+                state.show_message(format!("Cannot save progress: {}", e), MessageType::Error)
+            }
+        },
         Some("chapter") | Some("ch") => {
             if let Some(num) = parts.get(1) {
                 if let Ok(n) = num.parse::<usize>() {
-                    if n > 0 && n <= state.book.content.chapters.len() {
-                        state.position.chapter = n - 1;
-                        state.position.scroll_offset = 0;
-                        state.invalidate_cache();
+                    if n > 0 && n <= state.book.chapter_count() {
+                        state.go_to_chapter(n - 1);
                         state.show_message(format!("Chapter {}", n), MessageType::Info);
                     } else {
                         state
@@ -330,12 +522,13 @@ fn execute_command(state: &mut AppState, command: &str) {
                 }
             }
         }
-        Some("theme") => {
-            if let Some(theme_name) = parts.get(1) {
-                state.theme = theme_name.to_string();
-                state.show_message(format!("Theme: {}", theme_name), MessageType::Info);
+        Some("theme") => match parts.get(1) {
+            Some(name) => state.set_theme(name),
+            None => {
+                let name = format!("Theme: {}", state.theme.active);
+                state.show_message(name, MessageType::Info);
             }
-        }
+        },
         Some("set") => {
             // Handle set commands
             if parts.len() >= 2 {
@@ -356,6 +549,7 @@ fn execute_command(state: &mut AppState, command: &str) {
                         state.show_sidebar = false;
                         state.invalidate_cache();
                     }
+                    Some(layout) if state.set_layout(layout) => {}
                     _ => {
                         state.show_message(
                             format!("Unknown option: {:?}", parts.get(1)),
@@ -369,7 +563,27 @@ fn execute_command(state: &mut AppState, command: &str) {
             state.set_mode(Mode::Help);
         }
         Some("toc") => {
-            state.set_mode(Mode::TableOfContents);
+            state.open_toc();
+        }
+        Some("marks") => {
+            let mut names: Vec<char> = state.marks.keys().copied().collect();
+            if let Some(library) = &state.library {
+                let id = state.book_id.as_deref().unwrap_or_default();
+                names.extend(('A'..='Z').filter(|&c| library.get_mark(id, c).is_some()));
+            }
+            let message = if names.is_empty() {
+                "No marks set".to_string()
+            } else {
+                format!(
+                    "Marks: {}",
+                    names
+                        .iter()
+                        .map(char::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            };
+            state.show_message(message, MessageType::Info);
         }
         Some("bookmark") | Some("bm") => {
             let name = if parts.len() > 1 {
@@ -379,10 +593,19 @@ fn execute_command(state: &mut AppState, command: &str) {
             };
             state.add_bookmark(name);
         }
+        Some("note") | Some("annotate") => {
+            let note = (parts.len() > 1).then(|| parts[1..].join(" "));
+            match state.pending_highlight.take() {
+                Some(range) => state.add_highlight(range, note),
+                None => state.add_annotation(note),
+            }
+        }
+        Some("map") => map_keys(state, &parts[1..]),
+        Some("unmap") => unmap_keys(state, &parts[1..]),
         Some(cmd) if cmd.chars().all(|c| c.is_ascii_digit()) => {
             // Go to line number
             if let Ok(line) = cmd.parse::<usize>() {
-                state.position.scroll_offset = line.saturating_sub(1);
+                state.go_to_line(line);
                 state.show_message(format!("Line {}", line), MessageType::Info);
             }
         }
@@ -392,3 +615,82 @@ fn execute_command(state: &mut AppState, command: &str) {
         }
     }
 }
+
+/// Split an optional leading key map mode off command arguments
+fn key_mode<'a>(args: &'a [&'a str]) -> (&'static str, &'a [&'a str]) {
+    match args
+        .first()
+        .and_then(|a| keymap::MODES.iter().find(|m| *m == a))
+    {
+        Some(mode) => (mode, &args[1..]),
+        None => (keymap::NORMAL, args),
+    }
+}
+
+/// `:map [mode] <keys> [action]` - bind keys, or show what they are bound to
+fn map_keys(state: &mut AppState, args: &[&str]) {
+    let (mode, args) = key_mode(args);
+    let (notation, action) = match args {
+        [notation] => (*notation, None),
+        [notation, action] => (*notation, Some(*action)),
+        _ => {
+            state.show_message(
+                "Usage: map [mode] <keys> [action]".to_string(),
+                MessageType::Error,
+            );
+            return;
+        }
+    };
+    let keys = match state.keys.parse(notation) {
+        Ok(keys) => keys,
+        Err(e) => {
+            state.show_message(e.to_string(), MessageType::Error);
+            return;
+        }
+    };
+    let shown = keymap::keys_to_string(&keys);
+
+    let Some(name) = action else {
+        let message = match state.keys.get(mode, &keys) {
+            Some(action) => format!("{} is mapped to {}", shown, action.name()),
+            None => format!("{} is not mapped", shown),
+        };
+        state.show_message(message, MessageType::Info);
+        return;
+    };
+    let Some(action) = Action::from_name(name) else {
+        state.show_message(format!("Unknown action: {}", name), MessageType::Error);
+        return;
+    };
+    let message = match state.keys.bind(mode, keys, action) {
+        Some(old) if old != action => {
+            format!("Mapped {} to {} (was {})", shown, name, old.name())
+        }
+        _ => format!("Mapped {} to {}", shown, name),
+    };
+    state.show_message(message, MessageType::Success);
+}
+
+/// `:unmap [mode] <keys>` - remove a binding
+fn unmap_keys(state: &mut AppState, args: &[&str]) {
+    let (mode, args) = key_mode(args);
+    let [notation] = args else {
+        state.show_message("Usage: unmap [mode] <keys>".to_string(), MessageType::Error);
+        return;
+    };
+    let keys = match state.keys.parse(notation) {
+        Ok(keys) => keys,
+        Err(e) => {
+            state.show_message(e.to_string(), MessageType::Error);
+            return;
+        }
+    };
+    let shown = keymap::keys_to_string(&keys);
+    match state.keys.unbind(mode, &keys) {
+        Some(action) => state.show_message(
+            format!("Unmapped {} from {}", shown, action.name()),
+            MessageType::Success,
+        ),
+        None => state.show_message(format!("{} is not mapped", shown), MessageType::Error),
+    }
+}
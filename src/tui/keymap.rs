@@ -0,0 +1,484 @@
+//! Key dispatch
+//!
+//! Key presses are matched against per-mode maps built from the configured
+//! keybindings and turned into [`Action`]s. A binding may be a sequence such
+//! as `gg`; when a sequence is also the start of a longer one, the dispatcher
+//! waits for the next key until the configured timeout runs out.
+
+use crate::config::keybindings::{Action, KeyBinding, Keybindings};
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Mode of bindings that do not name one
+pub const NORMAL: &str = "normal";
+
+/// Modes with their own key maps; `search` applies while a search is active
+pub const MODES: &[&str] = &[NORMAL, "search"];
+
+/// A key press with its modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// A key press, with Shift folded into the character it types
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Key { code, modifiers }
+    }
+}
+
+impl fmt::Display for Key {
+    /// Vim notation, such as `g`, `<C-o>` or `<PageDown>`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char('<') => "lt".to_string(),
+            KeyCode::Char(c) if self.modifiers.is_empty() => return write!(f, "{}", c),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::BackTab => "S-Tab".to_string(),
+            KeyCode::Backspace => "BS".to_string(),
+            KeyCode::Delete => "Del".to_string(),
+            KeyCode::Insert => "Insert".to_string(),
+            KeyCode::Up => "Up".to_string(),
+            KeyCode::Down => "Down".to_string(),
+            KeyCode::Left => "Left".to_string(),
+            KeyCode::Right => "Right".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::PageUp => "PageUp".to_string(),
+            KeyCode::PageDown => "PageDown".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => format!("{:?}", code),
+        };
+        write!(f, "<")?;
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "A-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "S-")?;
+        }
+        write!(f, "{}>", name)
+    }
+}
+
+/// Write a key sequence in Vim notation
+pub fn keys_to_string(keys: &[Key]) -> String {
+    keys.iter().map(|k| k.to_string()).collect()
+}
+
+/// A named key such as `PageDown` or `Esc`, ignoring case
+fn named_key(name: &str) -> Option<KeyCode> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "enter" | "return" | "cr" => KeyCode::Enter,
+        "escape" | "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" | "bs" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        lower => match lower.strip_prefix('f').map(str::parse) {
+            Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+            _ => return None,
+        },
+    };
+    Some(code)
+}
+
+/// Parse the inside of `<...>`, such as `C-o`, `S-Tab` or `Space`
+fn parse_bracketed(inner: &str) -> Result<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = inner;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => KeyModifiers::CONTROL,
+            b'A' | b'M' => KeyModifiers::ALT,
+            b'S' => KeyModifiers::SHIFT,
+            _ => bail!("Unknown modifier in <{}>", inner),
+        };
+        rest = &rest[2..];
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => named_key(rest).ok_or_else(|| anyhow::anyhow!("Unknown key: <{}>", inner))?,
+    };
+    // Terminals send Shift-Tab as BackTab
+    let code = match code {
+        KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+        code => code,
+    };
+    Ok(Key::new(code, modifiers))
+}
+
+/// Parse a key sequence such as `gg`, `<C-o>`, `<leader>t` or `PageDown`
+pub fn parse_keys(notation: &str, leader: &str) -> Result<Vec<Key>> {
+    if notation.is_empty() {
+        bail!("Empty key sequence");
+    }
+    if notation.chars().count() > 1 {
+        if let Some(code) = named_key(notation) {
+            return Ok(vec![Key::new(code, KeyModifiers::NONE)]);
+        }
+    }
+
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        let bracketed = rest
+            .strip_prefix('<')
+            .and_then(|r| r.find('>').filter(|&end| end > 0).map(|end| &r[..end]));
+        match bracketed {
+            Some(inner) => {
+                if inner.eq_ignore_ascii_case("leader") {
+                    if leader.is_empty() {
+                        bail!("No leader key is set");
+                    }
+                    keys.extend(parse_keys(leader, "")?);
+                } else {
+                    keys.push(parse_bracketed(inner)?);
+                }
+                rest = &rest[inner.len() + 2..];
+            }
+            None => {
+                keys.push(Key::new(KeyCode::Char(c), KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// The keys of a configured binding, its modifiers held with its last key
+fn binding_keys(binding: &KeyBinding, leader: &str) -> Result<Vec<Key>> {
+    let mut keys = parse_keys(&binding.key, leader)?;
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in &binding.modifiers {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            other => bail!("Unknown modifier: {}", other),
+        };
+    }
+    if let Some(last) = keys.last_mut() {
+        *last = Key::new(last.code, last.modifiers | modifiers);
+    }
+    Ok(keys)
+}
+
+/// Turns key presses into actions
+pub struct Dispatcher {
+    /// Key sequences and their actions, by mode
+    maps: HashMap<String, HashMap<Vec<Key>, Action>>,
+    /// Keys of a sequence still being typed
+    pending: Vec<Key>,
+    /// When the pending keys were last added to
+    since: Option<Instant>,
+    timeout: Duration,
+    leader: String,
+    /// Bindings that clash or cannot be parsed
+    pub conflicts: Vec<String>,
+}
+
+impl Dispatcher {
+    /// Build the key maps for configured keybindings
+    ///
+    /// When a key sequence is bound twice in one mode, custom bindings win
+    /// over the preset's, and otherwise the first action by name keeps it.
+    /// Either way the clash is listed in `conflicts`.
+    pub fn new(config: &Keybindings) -> Self {
+        let mut dispatcher = Dispatcher {
+            maps: HashMap::new(),
+            pending: Vec::new(),
+            since: None,
+            timeout: Duration::from_millis(config.timeout),
+            leader: config.leader.clone(),
+            conflicts: Vec::new(),
+        };
+
+        let mut bindings: Vec<(Action, Vec<KeyBinding>)> = config.resolved().into_iter().collect();
+        bindings.sort_by_key(|(action, _)| (!config.bindings.contains_key(action), action.name()));
+        for (action, bindings) in bindings {
+            for binding in bindings {
+                let mode = binding.mode.as_deref().unwrap_or(NORMAL);
+                let keys = match binding_keys(&binding, &dispatcher.leader) {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        dispatcher
+                            .conflicts
+                            .push(format!("{}: {}", action.name(), e));
+                        continue;
+                    }
+                };
+                match dispatcher.get(mode, &keys) {
+                    Some(bound) if bound != action => dispatcher.conflicts.push(format!(
+                        "{} in {} mode is bound to both {} and {}",
+                        keys_to_string(&keys),
+                        mode,
+                        bound.name(),
+                        action.name()
+                    )),
+                    Some(_) => {}
+                    None => {
+                        dispatcher.bind(mode, keys, action);
+                    }
+                }
+            }
+        }
+        dispatcher
+    }
+
+    /// Parse a key sequence with this dispatcher's leader key
+    pub fn parse(&self, notation: &str) -> Result<Vec<Key>> {
+        parse_keys(notation, &self.leader)
+    }
+
+    /// The action bound to a key sequence in a mode
+    pub fn get(&self, mode: &str, keys: &[Key]) -> Option<Action> {
+        self.maps.get(mode)?.get(keys).copied()
+    }
+
+    /// Bind a key sequence, returning the action it replaced
+    pub fn bind(&mut self, mode: &str, keys: Vec<Key>, action: Action) -> Option<Action> {
+        self.maps
+            .entry(mode.to_string())
+            .or_default()
+            .insert(keys, action)
+    }
+
+    /// Remove a binding, returning its action
+    pub fn unbind(&mut self, mode: &str, keys: &[Key]) -> Option<Action> {
+        self.maps.get_mut(mode)?.remove(keys)
+    }
+
+    /// Key sequences bound to an action in a mode, shortest first
+    pub fn keys_for(&self, mode: &str, action: Action) -> Vec<String> {
+        let mut keys: Vec<&Vec<Key>> = self
+            .maps
+            .get(mode)
+            .into_iter()
+            .flatten()
+            .filter(|&(_, &a)| a == action)
+            .map(|(keys, _)| keys)
+            .collect();
+        keys.sort_by_key(|k| (k.len(), keys_to_string(k)));
+        keys.into_iter().map(|k| keys_to_string(k)).collect()
+    }
+
+    /// Keys typed so far of an unfinished sequence
+    pub fn pending(&self) -> String {
+        keys_to_string(&self.pending)
+    }
+
+    /// Feed a key press, trying the maps of `modes` in order
+    ///
+    /// Returns the actions to run, which is none while a sequence is
+    /// unfinished or the keys are not bound.
+    pub fn feed(&mut self, key: Key, modes: &[&str]) -> Vec<Action> {
+        self.pending.push(key);
+        let (exact, longer) = self.lookup(&self.pending, modes);
+        if longer {
+            self.since = Some(Instant::now());
+            return Vec::new();
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        self.since = None;
+        if let Some(action) = exact {
+            return vec![action];
+        }
+
+        // A sequence was cut short: run what its keys so far were bound to,
+        // then start over from this key
+        if pending.len() > 1 {
+            let (before, _) = self.lookup(&pending[..pending.len() - 1], modes);
+            let mut actions: Vec<Action> = before.into_iter().collect();
+            actions.extend(self.feed(key, modes));
+            return actions;
+        }
+        Vec::new()
+    }
+
+    /// Finish a pending sequence once no key has come within the timeout
+    pub fn tick(&mut self, modes: &[&str]) -> Option<Action> {
+        if self.since?.elapsed() < self.timeout {
+            return None;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        self.since = None;
+        self.lookup(&pending, modes).0
+    }
+
+    /// The action bound to exactly `keys`, and whether longer sequences
+    /// start with them
+    fn lookup(&self, keys: &[Key], modes: &[&str]) -> (Option<Action>, bool) {
+        let maps: Vec<&HashMap<Vec<Key>, Action>> =
+            modes.iter().filter_map(|m| self.maps.get(*m)).collect();
+        let exact = maps.iter().find_map(|map| map.get(keys).copied());
+        let longer = maps
+            .iter()
+            .flat_map(|map| map.keys())
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys));
+        (exact, longer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> Key {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = parse_keys("g<C-o><Space>", "").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                key('g'),
+                Key::new(KeyCode::Char('o'), KeyModifiers::CONTROL),
+                key(' '),
+            ]
+        );
+        assert_eq!(keys_to_string(&keys), "g<C-o><Space>");
+        assert_eq!(
+            parse_keys("PageDown", "").unwrap(),
+            vec![Key::new(KeyCode::PageDown, KeyModifiers::NONE)]
+        );
+        assert_eq!(
+            parse_keys("<leader>t", ",").unwrap(),
+            vec![key(','), key('t')]
+        );
+        assert_eq!(parse_keys("<", "").unwrap(), vec![key('<')]);
+        assert!(parse_keys("<X-q>", "").is_err());
+
+        // Shift is part of the character, however it was written
+        let shifted = Key::new(KeyCode::Char('g'), KeyModifiers::SHIFT);
+        assert_eq!(shifted, key('G'));
+        assert_eq!(parse_keys("<S-g>", "").unwrap(), vec![key('G')]);
+        assert_eq!(
+            parse_keys("<S-Tab>", "").unwrap(),
+            vec![Key::new(KeyCode::BackTab, KeyModifiers::SHIFT)]
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let mut dispatcher = Dispatcher::new(&Keybindings::default());
+        let normal = [NORMAL];
+
+        assert_eq!(dispatcher.feed(key('j'), &normal), vec![Action::ScrollDown]);
+        assert!(dispatcher.feed(key('g'), &normal).is_empty());
+        assert_eq!(dispatcher.pending(), "g");
+        assert_eq!(dispatcher.feed(key('g'), &normal), vec![Action::GoToTop]);
+
+        // A prefix that is bound on its own runs once the sequence breaks off
+        dispatcher.bind(NORMAL, vec![key('z')], Action::Refresh);
+        dispatcher.bind(NORMAL, vec![key('z'), key('z')], Action::Help);
+        assert!(dispatcher.feed(key('z'), &normal).is_empty());
+        assert_eq!(
+            dispatcher.feed(key('j'), &normal),
+            vec![Action::Refresh, Action::ScrollDown]
+        );
+
+        // ...or when the timeout runs out
+        dispatcher.timeout = Duration::ZERO;
+        dispatcher.feed(key('z'), &normal);
+        assert_eq!(dispatcher.tick(&normal), Some(Action::Refresh));
+        assert!(dispatcher.pending().is_empty());
+    }
+
+    #[test]
+    fn test_modes_and_conflicts() {
+        let mut config = Keybindings::default();
+        let dispatcher = Dispatcher::new(&config);
+        assert!(dispatcher.conflicts.is_empty());
+        let n = [key('n')];
+        assert_eq!(
+            dispatcher.lookup(&n, &["search", NORMAL]).0,
+            Some(Action::SearchNext)
+        );
+        assert_eq!(
+            dispatcher.lookup(&n, &[NORMAL]).0,
+            Some(Action::NextChapter)
+        );
+
+        // Custom bindings take keys from the preset's
+        config.bindings.insert(
+            Action::Help,
+            vec![KeyBinding::new("j"), KeyBinding::new("F1")],
+        );
+        let dispatcher = Dispatcher::new(&config);
+        assert_eq!(
+            dispatcher.conflicts,
+            vec!["j in normal mode is bound to both help and scroll_down"]
+        );
+        assert_eq!(dispatcher.keys_for(NORMAL, Action::Help), vec!["<F1>", "j"]);
+        assert_eq!(
+            dispatcher.keys_for(NORMAL, Action::ScrollDown),
+            vec!["<Down>"]
+        );
+    }
+
+    #[test]
+    fn test_configured_bindings() {
+        let config: Keybindings = toml::from_str(
+            r#"
+            preset = "custom"
+            leader = ","
+
+            [bindings]
+            quit = "<leader>q"
+            go_to_top = ["gg", "<Home>"]
+            search_next = { key = "n", mode = "search" }
+            "#,
+        )
+        .unwrap();
+        let dispatcher = Dispatcher::new(&config);
+        assert!(dispatcher.conflicts.is_empty());
+        assert_eq!(dispatcher.keys_for(NORMAL, Action::Quit), vec![",q"]);
+        assert_eq!(
+            dispatcher.keys_for(NORMAL, Action::GoToTop),
+            vec!["<Home>", "gg"]
+        );
+        assert_eq!(dispatcher.keys_for("search", Action::SearchNext), vec!["n"]);
+        assert_eq!(dispatcher.get(NORMAL, &[key('j')]), None);
+    }
+}
@@ -3,10 +3,16 @@
 //! A beautiful, configurable terminal interface for reading books
 
 mod app;
+mod bidi;
 mod components;
 mod event;
+#[cfg(feature = "image-support")]
+mod graphics;
 mod input;
+mod keymap;
+mod pages;
 mod render;
 mod state;
+mod visual;
 
 pub use app::run;
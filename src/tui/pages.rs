@@ -0,0 +1,183 @@
+//! Paged layout for the TUI
+//!
+//! The wrapped lines of a chapter are cut into screen-sized pages, shown one
+//! or more at a time side by side.
+
+use super::state::RenderedLine;
+use std::ops::Range;
+
+/// Narrowest column worth splitting the screen for
+pub const MIN_COLUMN_WIDTH: usize = 40;
+
+/// How the chapter is laid out on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// One long column scrolled by lines
+    Scroll,
+    /// Pages turned whole, up to this many side by side
+    Paged(usize),
+}
+
+impl LayoutMode {
+    /// A layout by its config name: `scroll`, `paged` or `dual`
+    ///
+    /// `pages` is how many pages to show at once; `dual` shows at least two.
+    pub fn from_name(name: &str, pages: u8) -> Option<Self> {
+        let pages = pages.clamp(1, 3) as usize;
+        match name {
+            "scroll" => Some(Self::Scroll),
+            "paged" => Some(Self::Paged(pages)),
+            "dual" => Some(Self::Paged(pages.max(2))),
+            _ => None,
+        }
+    }
+
+    /// How many columns fit in a width, with `gap` cells between them
+    pub fn columns(self, width: usize, gap: usize) -> usize {
+        match self {
+            Self::Scroll => 1,
+            Self::Paged(pages) => (1..=pages)
+                .rev()
+                .find(|&n| n * MIN_COLUMN_WIDTH + (n - 1) * gap <= width)
+                .unwrap_or(1),
+        }
+    }
+}
+
+/// Cut lines into pages of at most `height` lines, giving the first line
+/// of each page
+///
+/// A page ends early rather than leave a heading at its foot, the first
+/// line of a paragraph alone at its foot or the last line alone at the top
+/// of the next page, as long as it stays two thirds full. Blank lines
+/// falling at the top of a page are left out.
+pub fn paginate(lines: &[RenderedLine], height: usize) -> Vec<usize> {
+    let height = height.max(1);
+    let mut pages = vec![0];
+    let mut start = 0;
+    while lines.len() - start > height {
+        let end = start + height;
+        let mut next = page_break(lines, start, end);
+        if next < start + height - height / 3 {
+            next = end;
+        }
+        while next < lines.len() && next == end && lines[next].text.is_empty() {
+            next += 1;
+        }
+        if next >= lines.len() {
+            break;
+        }
+        pages.push(next);
+        start = next;
+    }
+    pages
+}
+
+/// Lines of page `index`, given the page starts from [`paginate`]
+pub fn page_lines(pages: &[usize], index: usize, height: usize, total: usize) -> Range<usize> {
+    let Some(&start) = pages.get(index) else {
+        return total..total;
+    };
+    let end = pages.get(index + 1).copied().unwrap_or(total);
+    start..end.min(start + height).min(total)
+}
+
+/// Page holding a line
+pub fn page_of(pages: &[usize], line: usize) -> usize {
+    pages
+        .partition_point(|&start| start <= line)
+        .saturating_sub(1)
+}
+
+/// Where to end a page that could hold lines `start..end`
+fn page_break(lines: &[RenderedLine], start: usize, end: usize) -> usize {
+    let same = |a: usize, b: usize| {
+        lines[a].block_index == lines[b].block_index
+            && !lines[a].text.is_empty()
+            && !lines[b].text.is_empty()
+    };
+    let block_start = |mut i: usize| {
+        while i > start && same(i - 1, i) {
+            i -= 1;
+        }
+        i
+    };
+
+    // A heading goes with the text after it
+    let mut last = end - 1;
+    while last > start && lines[last].text.is_empty() {
+        last -= 1;
+    }
+    if lines[last].is_heading {
+        return block_start(last);
+    }
+
+    let mut next = end;
+    // The last line of a paragraph
+    if same(next - 1, next) && !(next + 1 < lines.len() && same(next, next + 1)) {
+        next -= 1;
+    }
+    // The first line of a paragraph
+    if next - 1 > start && same(next - 1, next) && !same(next - 2, next - 1) {
+        next -= 1;
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Paragraphs of the given line counts, with a blank line after each
+    fn lines(paragraphs: &[usize]) -> Vec<RenderedLine> {
+        let mut lines = Vec::new();
+        for (block, &count) in paragraphs.iter().enumerate() {
+            for i in 0..count {
+                let mut line = RenderedLine::empty(block);
+                line.text = format!("{} {}", block, i);
+                lines.push(line);
+            }
+            lines.push(RenderedLine::empty(block));
+        }
+        lines
+    }
+
+    #[test]
+    fn test_widows_and_orphans() {
+        // The last line of a paragraph does not start a page alone
+        let text = lines(&[4, 6]);
+        assert_eq!(paginate(&text, 10), vec![0, 9]);
+
+        // The first line of a paragraph is not left at a page's foot
+        let text = lines(&[8, 4]);
+        assert_eq!(paginate(&text, 10), vec![0, 9]);
+
+        // A paragraph longer than a page still fills it
+        let text = lines(&[30]);
+        assert_eq!(paginate(&text, 10), vec![0, 10, 20]);
+
+        // Blank lines do not start a page
+        let text = lines(&[9, 3]);
+        assert_eq!(paginate(&text, 9), vec![0, 10]);
+    }
+
+    #[test]
+    fn test_headings_and_layout() {
+        let mut text = lines(&[7, 1, 5]);
+        text[8].is_heading = true;
+        assert_eq!(paginate(&text, 10), vec![0, 8]);
+        assert_eq!(page_of(&[0, 8], 7), 0);
+        assert_eq!(page_of(&[0, 8], 12), 1);
+        assert_eq!(page_lines(&[0, 8], 1, 10, 15), 8..15);
+
+        assert_eq!(LayoutMode::from_name("dual", 1), Some(LayoutMode::Paged(2)));
+        assert_eq!(
+            LayoutMode::from_name("paged", 9),
+            Some(LayoutMode::Paged(3))
+        );
+        assert_eq!(LayoutMode::Paged(3).columns(200, 5), 3);
+        assert_eq!(LayoutMode::Paged(3).columns(100, 5), 2);
+        assert_eq!(LayoutMode::Paged(2).columns(60, 5), 1);
+        assert_eq!(LayoutMode::Scroll.columns(200, 5), 1);
+    }
+}
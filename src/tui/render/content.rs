@@ -1,18 +1,29 @@
 //! Content rendering for the main reader area
 
-use crate::config::Config;
-use crate::tui::state::AppState;
+use crate::config::{Config, ThemeConfig};
+use crate::hyphenate;
+use crate::tui::bidi;
+use crate::tui::pages::{self, LayoutMode};
+use crate::tui::state::{AppState, Mode, RenderedLine};
+use crate::tui::visual;
 use ratatui::{
-    layout::Rect,
+    layout::{Alignment, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Paragraph, Wrap},
     Frame,
 };
 
-use super::lines::build_lines_cache;
+use super::lines::{build_lines_cache, text_width};
 
-pub fn render_content(frame: &mut Frame, state: &mut AppState, area: Rect, config: &Config) {
+pub fn render_content(
+    frame: &mut Frame,
+    state: &mut AppState,
+    area: Rect,
+    config: &Config,
+    theme: &ThemeConfig,
+) {
+    let (ui, colors) = (&theme.ui, &theme.content);
     // Calculate margins
     let margin_left = config.tui.margin_left.min(area.width as usize / 4) as u16;
     let margin_right = config.tui.margin_right.min(area.width as usize / 4) as u16;
@@ -24,77 +35,346 @@ pub fn render_content(frame: &mut Frame, state: &mut AppState, area: Rect, confi
         height: area.height.saturating_sub(2),
     };
 
+    // Pages side by side, the gap between them taken as 8 pixels a cell
+    let gap = config.reader.page_gap as usize / 8;
+    let columns = state.layout.columns(content_area.width as usize, gap);
+    let column_width = (content_area.width as usize).saturating_sub(gap * (columns - 1)) / columns;
+
     // Build lines if cache is empty
     if state.lines_cache.is_empty() {
-        build_lines_cache(state, content_area.width as usize, config);
+        build_lines_cache(state, column_width, config);
     }
 
-    // Get visible lines
-    let visible_height = content_area.height as usize;
-    let start = state.position.scroll_offset;
-    let end = (start + visible_height).min(state.lines_cache.len());
+    // Cut the chapter into pages, keeping a row for page numbers
+    let paged = matches!(state.layout, LayoutMode::Paged(_));
+    let numbers_row = u16::from(paged && config.reader.show_page_numbers);
+    if paged {
+        let height = content_area.height.saturating_sub(numbers_row) as usize;
+        if state.pages.is_empty() || state.page_height != height {
+            state.pages = pages::paginate(&state.lines_cache, height);
+            state.page_height = height;
+        }
+        state.spread = columns;
+        state.align_to_pages();
+    }
 
-    let mut lines: Vec<Line> = Vec::new();
+    // The lines of each column and where their text goes, centered in the
+    // column when narrower
+    let gutter = if state.show_line_numbers { 5 } else { 0 };
+    let total = state.lines_cache.len();
+    let text = text_width(state, column_width, config) as u16;
+    let centered = |x: u16, width: u16| {
+        let width = width.saturating_sub(gutter);
+        let indent = width.saturating_sub(text) / 2;
+        (x + gutter + indent, width - indent)
+    };
+    state.view = match state.page_numbers() {
+        Some((shown, _)) => shown
+            .enumerate()
+            .map(|(i, page)| {
+                let lines = pages::page_lines(&state.pages, page - 1, state.page_height, total);
+                let (x, width) = centered(
+                    content_area.x + (i * (column_width + gap)) as u16,
+                    column_width as u16,
+                );
+                let area = Rect {
+                    x,
+                    y: content_area.y,
+                    width,
+                    height: state.page_height as u16,
+                };
+                (lines, area)
+            })
+            .collect(),
+        None => {
+            let start = state.position.scroll_offset.min(total);
+            let end = (start + content_area.height as usize).min(total);
+            let (x, width) = centered(content_area.x, content_area.width);
+            let area = Rect {
+                x,
+                width,
+                ..content_area
+            };
+            vec![(start..end, area)]
+        }
+    };
 
-    for (i, rendered) in state.lines_cache[start..end].iter().enumerate() {
-        let line_num = start + i + 1;
-        let mut spans = Vec::new();
+    // Images drawn by the terminal replace their half-block lines
+    #[cfg(feature = "image-support")]
+    let covered = {
+        let origins: Vec<_> = state
+            .view
+            .iter()
+            .map(|(lines, area)| (lines.clone(), (area.x, area.y)))
+            .collect();
+        state
+            .graphics
+            .place_visible(&origins, state.mode == Mode::Normal)
+    };
 
-        // Line numbers
-        if state.show_line_numbers {
-            spans.push(Span::styled(
-                format!("{:4} ", line_num),
-                Style::default().fg(Color::DarkGray),
-            ));
-        }
+    // Highlights in this chapter, in their colors, then the selection
+    let highlights: Vec<_> = state
+        .annotations
+        .iter()
+        .filter(|a| a.chapter == state.position.chapter)
+        .filter_map(|a| {
+            let style = Style::default()
+                .fg(Color::Black)
+                .bg(visual::color(&a.color));
+            Some((a.range?, style))
+        })
+        .collect();
+    let selected = Style::default()
+        .fg(ui.selection_fg.to_ratatui())
+        .bg(ui.selection_bg.to_ratatui());
+    let cursor = Style::default()
+        .fg(ui.background.to_ratatui())
+        .bg(ui.primary.to_ratatui())
+        .add_modifier(Modifier::BOLD);
+    let search = Style::default()
+        .fg(colors.background.to_ratatui())
+        .bg(colors.search_highlight.to_ratatui())
+        .add_modifier(Modifier::BOLD);
 
-        // Determine style based on content type
-        let style = if rendered.is_heading {
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD)
-        } else if rendered.is_quote {
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::ITALIC)
-        } else if rendered.is_code {
-            Style::default().fg(Color::Green)
-        } else {
-            Style::default().fg(Color::White)
-        };
+    for (shown, text_area) in &state.view {
+        let mut lines: Vec<Line> = Vec::new();
 
-        // Apply search highlights
-        if !rendered.highlights.is_empty() {
-            let text = &rendered.text;
-            let mut last_end = 0;
+        for index in shown.clone() {
+            let rendered = &state.lines_cache[index];
+            let mut spans = Vec::new();
 
-            for &(start, end) in &rendered.highlights {
-                // Text before highlight
-                if start > last_end {
-                    spans.push(Span::styled(text[last_end..start].to_string(), style));
-                }
-                // Highlighted text
+            #[cfg(feature = "image-support")]
+            if covered.iter().any(|lines| lines.contains(&index)) {
+                lines.push(Line::default());
+                continue;
+            }
+
+            // Line numbers
+            if state.show_line_numbers {
                 spans.push(Span::styled(
-                    text[start..end].to_string(),
-                    Style::default()
-                        .fg(Color::Black)
-                        .bg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
+                    format!("{:4} ", index + 1),
+                    Style::default().fg(ui.line_numbers.to_ratatui()),
                 ));
-                last_end = end;
             }
-            // Text after last highlight
-            if last_end < text.len() {
-                spans.push(Span::styled(text[last_end..].to_string(), style));
+
+            // Determine style based on content type
+            let style = if rendered.is_heading {
+                Style::default()
+                    .fg(colors.heading.to_ratatui())
+                    .add_modifier(Modifier::BOLD)
+            } else if rendered.is_quote {
+                Style::default()
+                    .fg(colors.quote.to_ratatui())
+                    .add_modifier(Modifier::ITALIC)
+            } else if rendered.is_code {
+                Style::default().fg(colors.code.to_ratatui())
+            } else {
+                Style::default().fg(colors.text.to_ratatui())
+            };
+
+            let mut marks: Vec<(usize, usize, Style)> = highlights
+                .iter()
+                .filter_map(|(range, style)| {
+                    let (from, to) = visual::line_span(rendered, range)?;
+                    Some((from, to, *style))
+                })
+                .collect();
+            if state.mode == Mode::Visual {
+                if let Some((from, to)) = state.selection.on_line(&state.lines_cache, index) {
+                    marks.push((from, to, selected));
+                }
+                let (line, col) = state.selection.cursor;
+                if line == index {
+                    if let Some(c) = rendered
+                        .text
+                        .get(col..)
+                        .and_then(|rest| rest.chars().next())
+                    {
+                        marks.push((col, col + c.len_utf8(), cursor));
+                    }
+                }
             }
-        } else {
-            spans.push(Span::styled(rendered.text.clone(), style));
+
+            spans.extend(styled_spans(rendered, style, search, &marks));
+
+            lines.push(Line::from(spans));
         }
 
-        lines.push(Line::from(spans));
+        // Unwrapped lines run off the edge rather than wrap here
+        let mut paragraph = Paragraph::new(lines);
+        if config.tui.wrap_mode != "none" {
+            paragraph = paragraph.wrap(Wrap { trim: false });
+        }
+        let column = Rect {
+            x: text_area.x - gutter,
+            width: text_area.width + gutter,
+            ..*text_area
+        };
+        frame.render_widget(paragraph, column);
     }
 
-    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    // Page numbers under each page
+    if let Some((shown, _)) = state.page_numbers().filter(|_| numbers_row > 0) {
+        for (page, (_, text_area)) in shown.zip(&state.view) {
+            let number = Paragraph::new(page.to_string())
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(ui.line_numbers.to_ratatui()));
+            let row = Rect {
+                x: text_area.x - gutter,
+                y: text_area.y + text_area.height,
+                width: text_area.width + gutter,
+                height: 1,
+            };
+            frame.render_widget(number, row);
+        }
+    }
 
-    frame.render_widget(paragraph, content_area);
+    if state.mode == Mode::Hint {
+        render_hints(frame, state, theme);
+    }
+}
+
+/// Draw hint labels over the start of the links they pick
+fn render_hints(frame: &mut Frame, state: &AppState, theme: &ThemeConfig) {
+    let label_style = Style::default()
+        .fg(theme.ui.background.to_ratatui())
+        .bg(theme.ui.warning.to_ratatui())
+        .add_modifier(Modifier::BOLD);
+    let selected_style = label_style.bg(theme.ui.primary.to_ratatui());
+
+    for (i, (label, link)) in state.hints.matching().enumerate() {
+        let placed = state.view.iter().find_map(|(shown, area)| {
+            state.lines_cache[shown.clone()]
+                .iter()
+                .enumerate()
+                .find_map(|(row, line)| {
+                    let &(from, _, _) = line.links.iter().find(|l| l.2 == *link)?;
+                    Some((area, row as u16, line.width_to(from) as u16))
+                })
+        });
+        let Some((area, row, column)) = placed else {
+            continue;
+        };
+        let x = area.x + column;
+        let width = (label.len() as u16).min((area.x + area.width).saturating_sub(x));
+        if width == 0 {
+            continue;
+        }
+        let style = if i == state.hints.selected {
+            selected_style
+        } else {
+            label_style
+        };
+        frame.render_widget(
+            Paragraph::new(label.as_str()).style(style),
+            Rect {
+                x,
+                y: area.y + row,
+                width,
+                height: 1,
+            },
+        );
+    }
+}
+
+/// Split a line into spans by its colors, search highlights and marks
+///
+/// Colors may overlap, as with bold text inside a link; their styles are
+/// layered over the line's base style, with search highlights and then
+/// marks, such as annotation highlights and the selection, on top.
+/// The stretch of a justified line is drawn after its spaces, in their style,
+/// soft hyphens are left out and a line breaking a word ends with a hyphen.
+/// Right-to-left runs are drawn in visual order, while every offset stays
+/// logical.
+fn styled_spans(
+    rendered: &RenderedLine,
+    base: Style,
+    highlight: Style,
+    marks: &[(usize, usize, Style)],
+) -> Vec<Span<'static>> {
+    let text = &rendered.text;
+    let runs = rendered.visual_runs();
+    if rendered.colors.is_empty()
+        && rendered.highlights.is_empty()
+        && rendered.stretch.is_empty()
+        && marks.is_empty()
+        && runs.iter().all(|(_, rtl)| !rtl)
+    {
+        let mut spans = vec![Span::styled(hyphenate::visible(text), base)];
+        if rendered.hyphen {
+            spans.push(Span::styled("-", base));
+        }
+        return spans;
+    }
+
+    let mut bounds: Vec<usize> = rendered
+        .colors
+        .iter()
+        .chain(marks)
+        .flat_map(|&(start, end, _)| [start, end])
+        .chain(
+            rendered
+                .highlights
+                .iter()
+                .flat_map(|&(start, end)| [start, end]),
+        )
+        .chain(rendered.stretch.iter().map(|&(at, _)| at + 1))
+        .chain(runs.iter().map(|(run, _)| run.start))
+        .chain([0, text.len()])
+        .filter(|&b| b <= text.len())
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let segments: Vec<(usize, usize, Style)> = bounds
+        .windows(2)
+        .map(|w| {
+            let (start, end) = (w[0], w[1]);
+            let mut style = base;
+            for &(from, to, color) in &rendered.colors {
+                if from <= start && to >= end {
+                    style = style.patch(color);
+                }
+            }
+            if rendered
+                .highlights
+                .iter()
+                .any(|&(from, to)| from <= start && to >= end)
+            {
+                style = style.patch(highlight);
+            }
+            for &(from, to, mark) in marks {
+                if from <= start && to >= end {
+                    style = style.patch(mark);
+                }
+            }
+            (start, end, style)
+        })
+        .collect();
+
+    // Segments are drawn run by run in visual order, right-to-left runs
+    // backwards and with their brackets mirrored
+    let mut spans = Vec::new();
+    for (run, rtl) in runs {
+        let inside = segments
+            .iter()
+            .filter(|&&(start, end, _)| start >= run.start && end <= run.end);
+        let inside: Vec<_> = if rtl {
+            inside.rev().collect()
+        } else {
+            inside.collect()
+        };
+        for &(start, end, style) in inside {
+            let mut span = hyphenate::visible(&text[start..end]);
+            span.push_str(&" ".repeat(rendered.stretch_at(end - 1)));
+            if rtl {
+                span = span.chars().rev().map(bidi::mirror).collect();
+            }
+            spans.push(Span::styled(span, style));
+        }
+    }
+    if rendered.hyphen {
+        spans.push(Span::styled("-", base));
+    }
+    spans
 }
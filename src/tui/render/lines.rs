@@ -1,89 +1,401 @@
 //! Line cache building and text wrapping
 
+use super::table::{self, Borders, TableStyle};
+use crate::config::theme::ContentTheme;
 use crate::config::Config;
-use crate::formats::ContentBlock;
+use crate::formats::{math, ContentBlock, StyleType};
+use crate::highlight::Highlighter;
+use crate::hyphenate::{self, Hyphenator};
+use crate::tui::bidi;
+#[cfg(feature = "image-support")]
+use crate::tui::graphics::{self, InlineImage};
 use crate::tui::state::{AppState, RenderedLine};
-use textwrap::wrap;
+use ratatui::style::{Color, Modifier, Style};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How text is broken into lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WrapMode {
+    /// Between words
+    Word,
+    /// At any character
+    Char,
+    /// Only where the text breaks
+    Off,
+}
+
+impl WrapMode {
+    /// A wrap mode by its config name: `word`, `char` or `none`
+    fn from_name(name: &str) -> Self {
+        match name {
+            "char" => Self::Char,
+            "none" => Self::Off,
+            _ => Self::Word,
+        }
+    }
+}
+
+/// How text is set in its column
+#[derive(Debug, Clone, Copy)]
+struct Typeset {
+    width: usize,
+    wrap: WrapMode,
+    hyphenator: Hyphenator,
+}
+
+impl Typeset {
+    /// The same setting, narrowed to make room for a prefix
+    fn narrowed(self, by: usize) -> Self {
+        Self {
+            width: self.width.saturating_sub(by),
+            ..self
+        }
+    }
+}
+
+/// Width of the text column in a screen column of the given width
+pub fn text_width(state: &AppState, width: usize, config: &Config) -> usize {
+    let width = width.saturating_sub(if state.show_line_numbers { 6 } else { 0 });
+    match config.tui.max_width {
+        0 => width,
+        max => width.min(max),
+    }
+}
 
 /// Build the lines cache for rendering
 pub fn build_lines_cache(state: &mut AppState, width: usize, config: &Config) {
     state.lines_cache.clear();
+    #[cfg(feature = "image-support")]
+    state.graphics.images.clear();
 
-    let wrap_width = if config.tui.max_width > 0 && config.tui.max_width < width {
-        config.tui.max_width
-    } else {
-        width.saturating_sub(if state.show_line_numbers { 6 } else { 0 })
+    let wrap_width = text_width(state, width, config);
+    let set = Typeset {
+        width: wrap_width,
+        wrap: WrapMode::from_name(&config.tui.wrap_mode),
+        hyphenator: Hyphenator::new(
+            &config.reader,
+            state.book.skeleton.metadata.language.as_deref(),
+        ),
     };
+    let justify = config.reader.justify && set.wrap == WrapMode::Word;
 
     // Extract search state before the loop to avoid borrow issues
     let search_active = state.search.active;
     let search_query = state.search.query.clone();
+    let highlighter = Highlighter::new(&config.formats.markdown);
+    let ui = state.theme.ui.clone();
+    let theme = &state.theme.content.clone();
+    let table_style = TableStyle {
+        borders: Borders::from_style(if config.tui.unicode {
+            &ui.border_style
+        } else {
+            "ascii"
+        }),
+        border: Style::default().fg(ui.border.to_ratatui()),
+        header: Style::default().add_modifier(Modifier::BOLD),
+    };
 
-    if let Some(chapter) = state.current_chapter().cloned() {
+    if let Some(chapter) = state.current_chapter() {
         for (block_idx, block) in chapter.blocks.iter().enumerate() {
+            let first_line = state.lines_cache.len();
             match block {
-                ContentBlock::Paragraph { text, .. } => {
-                    build_paragraph_lines(state, text, block_idx, wrap_width, search_active, &search_query);
+                ContentBlock::Paragraph { text, styles } => {
+                    let mut links = Vec::new();
+                    for style in styles {
+                        if let (StyleType::Link, Some(href)) = (style.style_type, &style.data) {
+                            links.push((style.start, style.end, state.links.len()));
+                            state.links.push(href.clone());
+                        }
+                    }
+                    let styles: Vec<(usize, usize, Style)> = styles
+                        .iter()
+                        .filter_map(|s| Some((s.start, s.end, inline_style(s.style_type, theme)?)))
+                        .collect();
+                    build_paragraph_lines(
+                        state,
+                        text,
+                        &styles,
+                        block_idx,
+                        set,
+                        search_active,
+                        &search_query,
+                    );
+                    // Wrapping the text again lines its links up with its lines
+                    let lines = state.lines_cache[first_line..].iter_mut();
+                    for (line, (_, _, _, links)) in lines.zip(wrap_styled(text, &links, set)) {
+                        line.links = links;
+                    }
                 }
                 ContentBlock::Heading { text, level } => {
-                    build_heading_lines(state, text, *level, block_idx, wrap_width, search_active, &search_query);
+                    build_heading_lines(
+                        state,
+                        text,
+                        *level,
+                        block_idx,
+                        set,
+                        search_active,
+                        &search_query,
+                    );
                 }
                 ContentBlock::Quote { text, .. } => {
-                    build_quote_lines(state, text, block_idx, wrap_width);
+                    build_quote_lines(state, text, block_idx, set);
                 }
-                ContentBlock::Code { code, .. } => {
-                    build_code_lines(state, code, block_idx);
+                ContentBlock::Code { language, code } => {
+                    build_code_lines(
+                        state,
+                        code,
+                        language.as_deref(),
+                        &highlighter,
+                        block_idx,
+                        config.tui.tab_size,
+                    );
                 }
                 ContentBlock::Separator => {
                     build_separator_lines(state, block_idx, wrap_width);
                 }
                 ContentBlock::List { ordered, items } => {
-                    build_list_lines(state, items, *ordered, block_idx, wrap_width);
+                    build_list_lines(state, items, *ordered, block_idx, set);
+                }
+                ContentBlock::Math { tex } => {
+                    build_math_lines(state, tex, block_idx, wrap_width);
+                }
+                ContentBlock::Table { headers, rows } => {
+                    build_table_lines(state, headers, rows, block_idx, wrap_width, &table_style);
+                }
+                #[cfg(feature = "image-support")]
+                ContentBlock::Image { src, .. } => {
+                    build_image_lines(state, src, &block.text(), block_idx, set);
                 }
                 _ => {
                     // For other block types, just get text
                     let text = block.text();
                     if !text.is_empty() {
-                        build_generic_lines(state, &text, block_idx, wrap_width);
+                        build_generic_lines(state, &text, block_idx, set);
                     }
                 }
             }
+            let lines = &mut state.lines_cache[first_line..];
+            if lines.iter().any(|line| line.source.is_some()) {
+                let text = block.text();
+                if justify
+                    && matches!(
+                        block,
+                        ContentBlock::Paragraph { .. }
+                            | ContentBlock::Quote { .. }
+                            | ContentBlock::List { .. }
+                    )
+                {
+                    justify_lines(lines, &text, wrap_width);
+                }
+                if bidi::is_rtl(&text) {
+                    align_right(lines, wrap_width);
+                }
+            }
         }
     }
 
     state.total_lines = state.lines_cache.len();
+
+    // Show the start of a block a link pointed at
+    if let Some(block) = state.jump_block.take() {
+        state.position.scroll_offset = state
+            .lines_cache
+            .iter()
+            .position(|line| line.block_index >= block)
+            .unwrap_or(0);
+    }
 }
 
 fn build_paragraph_lines(
     state: &mut AppState,
     text: &str,
+    styles: &[(usize, usize, Style)],
     block_idx: usize,
-    wrap_width: usize,
+    set: Typeset,
     search_active: bool,
     search_query: &str,
 ) {
-    let wrapped = wrap(text, wrap_width);
-    for line in wrapped {
+    for (line, start, hyphen, colors) in wrap_styled(text, styles, set) {
         state.lines_cache.push(RenderedLine {
-            text: line.to_string(),
+            highlights: find_highlights(&line, search_active, search_query),
+            text: line,
             block_index: block_idx,
             is_heading: false,
             heading_level: 0,
             is_quote: false,
             is_code: false,
-            highlights: find_highlights(&line, search_active, search_query),
+            colors,
+            links: Vec::new(),
+            source: Some((start, 0)),
+            stretch: Vec::new(),
+            hyphen,
+            rtl: false,
         });
     }
     // Empty line after paragraph
     state.lines_cache.push(RenderedLine::empty(block_idx));
 }
 
+/// Tagged byte ranges of a line, such as its colors or links
+type Ranges<T> = Vec<(usize, usize, T)>;
+
+/// Wrap text, carrying tagged byte ranges onto the wrapped lines, each
+/// with its byte offset in the text and whether it ends with a hyphen
+fn wrap_styled<T: Copy>(
+    text: &str,
+    styles: &[(usize, usize, T)],
+    set: Typeset,
+) -> Vec<(String, usize, bool, Ranges<T>)> {
+    wrap_offsets(text, set)
+        .into_iter()
+        .map(|(line, start, hyphen)| {
+            let end = start + line.len();
+            let colors = styles
+                .iter()
+                .filter(|&&(from, to, _)| from < end && to > start)
+                .map(|&(from, to, style)| (from.max(start) - start, to.min(end) - start, style))
+                .collect();
+            (line, start, hyphen, colors)
+        })
+        .collect()
+}
+
+/// Wrap text, keeping the byte offset each line starts at and whether it
+/// breaks a word with a hyphen
+fn wrap_offsets(text: &str, set: Typeset) -> Vec<(String, usize, bool)> {
+    if set.wrap != WrapMode::Word {
+        return break_chars(text, set);
+    }
+    set.hyphenator
+        .wrap(text, set.width)
+        .into_iter()
+        .map(|(range, hyphen)| (text[range.clone()].to_string(), range.start, hyphen))
+        .collect()
+}
+
+/// Break text at its newlines and, wrapping by characters, wherever a line
+/// reaches the width
+fn break_chars(text: &str, set: Typeset) -> Vec<(String, usize, bool)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in text.split('\n') {
+        let mut from = 0;
+        let mut width = 0;
+        for (col, c) in line.char_indices() {
+            let w = hyphenate::char_width(c);
+            if set.wrap == WrapMode::Char && col > from && width + w > set.width {
+                lines.push((line[from..col].to_string(), start + from, false));
+                (from, width) = (col, 0);
+            }
+            width += w;
+        }
+        lines.push((line[from..].to_string(), start + from, false));
+        start += line.len() + 1;
+    }
+    lines
+}
+
+/// Justify lines of a block, wrapped from its text, by stretching their
+/// spaces to fill the width
+///
+/// Lines ending a paragraph, or before a break in the text, stay as they are.
+fn justify_lines(lines: &mut [RenderedLine], text: &str, width: usize) {
+    for i in 0..lines.len().saturating_sub(1) {
+        let (Some((start, prefix)), Some((next, _))) = (lines[i].source, lines[i + 1].source)
+        else {
+            continue;
+        };
+        let end = start + lines[i].text.len().saturating_sub(prefix);
+        if text.get(end..next).is_some_and(|gap| !gap.contains('\n')) {
+            stretch(&mut lines[i], width);
+        }
+    }
+}
+
+/// Share the cells a line is short of a width among its spaces between
+/// words, leftmost first
+fn stretch(line: &mut RenderedLine, width: usize) {
+    let prefix = line.source.map_or(0, |(_, prefix)| prefix);
+    let words = line.text[prefix..].trim_end();
+    let gaps: Vec<usize> = words
+        .char_indices()
+        .filter(|&(i, c)| c == ' ' && i > 0 && !words[..i].ends_with(' '))
+        .map(|(i, _)| prefix + i)
+        .collect();
+    let short = width.saturating_sub(hyphenate::width(&line.text) + usize::from(line.hyphen));
+    if gaps.is_empty() || short == 0 {
+        return;
+    }
+    let (each, rest) = (short / gaps.len(), short % gaps.len());
+    line.stretch = gaps
+        .into_iter()
+        .enumerate()
+        .map(|(i, at)| (at, each + usize::from(i < rest)))
+        .filter(|&(_, cells)| cells > 0)
+        .collect();
+}
+
+/// Mark the text lines of a right-to-left block, setting those without a
+/// prefix flush right
+///
+/// The padding becomes the lines' prefix, so their byte ranges move past it.
+fn align_right(lines: &mut [RenderedLine], width: usize) {
+    for line in lines.iter_mut().filter(|line| line.source.is_some()) {
+        line.rtl = true;
+        let Some((start, 0)) = line.source else {
+            continue;
+        };
+        let pad = width.saturating_sub(line.width_to(line.text.len()) + usize::from(line.hyphen));
+        if pad == 0 {
+            continue;
+        }
+        line.text.insert_str(0, &" ".repeat(pad));
+        line.source = Some((start, pad));
+        for (from, to, _) in line.colors.iter_mut() {
+            (*from, *to) = (*from + pad, *to + pad);
+        }
+        for (from, to, _) in line.links.iter_mut() {
+            (*from, *to) = (*from + pad, *to + pad);
+        }
+        for (from, to) in line.highlights.iter_mut() {
+            (*from, *to) = (*from + pad, *to + pad);
+        }
+        for (at, _) in line.stretch.iter_mut() {
+            *at += pad;
+        }
+    }
+}
+
+/// Terminal style for an inline text style, colored by the theme
+fn inline_style(style_type: StyleType, theme: &ContentTheme) -> Option<Style> {
+    let style = Style::default();
+    Some(match style_type {
+        StyleType::Bold => style
+            .fg(theme.strong.to_ratatui())
+            .add_modifier(Modifier::BOLD),
+        StyleType::Italic => style
+            .fg(theme.emphasis.to_ratatui())
+            .add_modifier(Modifier::ITALIC),
+        StyleType::Underline => style.add_modifier(Modifier::UNDERLINED),
+        StyleType::Strikethrough => style.add_modifier(Modifier::CROSSED_OUT),
+        StyleType::Code => style
+            .fg(theme.code.to_ratatui())
+            .bg(theme.code_bg.to_ratatui()),
+        StyleType::Link => style
+            .fg(theme.link.to_ratatui())
+            .add_modifier(Modifier::UNDERLINED),
+        StyleType::Superscript | StyleType::Subscript | StyleType::SmallCaps | StyleType::Math => {
+            return None
+        }
+    })
+}
+
 fn build_heading_lines(
     state: &mut AppState,
     text: &str,
     level: u8,
     block_idx: usize,
-    wrap_width: usize,
+    set: Typeset,
     search_active: bool,
     search_query: &str,
 ) {
@@ -92,16 +404,26 @@ fn build_heading_lines(
         state.lines_cache.push(RenderedLine::empty(block_idx));
     }
 
-    let wrapped = wrap(text, wrap_width);
-    for line in wrapped {
+    // Headings are not hyphenated
+    let set = Typeset {
+        hyphenator: Hyphenator::NONE,
+        ..set
+    };
+    for (line, start, _) in wrap_offsets(text, set) {
         state.lines_cache.push(RenderedLine {
-            text: line.to_string(),
+            highlights: find_highlights(&line, search_active, search_query),
+            text: line,
             block_index: block_idx,
             is_heading: true,
             heading_level: level,
             is_quote: false,
             is_code: false,
-            highlights: find_highlights(&line, search_active, search_query),
+            colors: Vec::new(),
+            links: Vec::new(),
+            source: Some((start, 0)),
+            stretch: Vec::new(),
+            hyphen: false,
+            rtl: false,
         });
     }
 
@@ -109,32 +431,116 @@ fn build_heading_lines(
     state.lines_cache.push(RenderedLine::empty(block_idx));
 }
 
-fn build_quote_lines(state: &mut AppState, text: &str, block_idx: usize, wrap_width: usize) {
-    let wrapped = wrap(text, wrap_width.saturating_sub(2));
-    for line in wrapped {
+fn build_quote_lines(state: &mut AppState, text: &str, block_idx: usize, set: Typeset) {
+    const BAR: &str = "│ ";
+    for (line, start, hyphen) in wrap_offsets(text, set.narrowed(2)) {
         state.lines_cache.push(RenderedLine {
-            text: format!("│ {}", line),
+            text: format!("{}{}", BAR, line),
             block_index: block_idx,
             is_heading: false,
             heading_level: 0,
             is_quote: true,
             is_code: false,
             highlights: Vec::new(),
+            colors: Vec::new(),
+            links: Vec::new(),
+            source: Some((start, BAR.len())),
+            stretch: Vec::new(),
+            hyphen,
+            rtl: false,
         });
     }
     state.lines_cache.push(RenderedLine::empty(block_idx));
 }
 
-fn build_code_lines(state: &mut AppState, code: &str, block_idx: usize) {
-    for line in code.lines() {
+fn build_code_lines(
+    state: &mut AppState,
+    code: &str,
+    language: Option<&str>,
+    highlighter: &Highlighter,
+    block_idx: usize,
+    tab_size: usize,
+) {
+    for tokens in highlighter.tokens(code, language) {
+        // Token ranges are offset by the two-space indent
+        let mut text = String::from("  ");
+        let mut colors = Vec::new();
+        for token in tokens {
+            let start = text.len();
+            push_expanded(&mut text, 2, &token.text, tab_size);
+            if let Some((r, g, b)) = token.color {
+                let mut style = Style::default().fg(Color::Rgb(r, g, b));
+                if token.bold {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                if token.italic {
+                    style = style.add_modifier(Modifier::ITALIC);
+                }
+                if token.underline {
+                    style = style.add_modifier(Modifier::UNDERLINED);
+                }
+                colors.push((start, text.len(), style));
+            }
+        }
         state.lines_cache.push(RenderedLine {
-            text: format!("  {}", line),
+            text,
             block_index: block_idx,
             is_heading: false,
             heading_level: 0,
             is_quote: false,
             is_code: true,
             highlights: Vec::new(),
+            colors,
+            links: Vec::new(),
+            source: None,
+            stretch: Vec::new(),
+            hyphen: false,
+            rtl: false,
+        });
+    }
+    state.lines_cache.push(RenderedLine::empty(block_idx));
+}
+
+/// Push code onto a line, expanding tabs to stops `tab_size` cells apart
+/// from the line's indent
+fn push_expanded(line: &mut String, indent: usize, code: &str, tab_size: usize) {
+    let tab_size = tab_size.max(1);
+    let mut column = line.width().saturating_sub(indent);
+    for c in code.chars() {
+        if c == '\t' {
+            let spaces = tab_size - column % tab_size;
+            line.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            line.push(c);
+            column += c.width().unwrap_or(0);
+        }
+    }
+}
+
+fn build_table_lines(
+    state: &mut AppState,
+    headers: &[String],
+    rows: &[Vec<String>],
+    block_idx: usize,
+    wrap_width: usize,
+    style: &TableStyle,
+) {
+    for line in table::layout(headers, rows, wrap_width, style) {
+        state.lines_cache.push(RenderedLine {
+            text: line.text,
+            block_index: block_idx,
+            is_heading: false,
+            heading_level: 0,
+            is_quote: false,
+            is_code: false,
+            highlights: Vec::new(),
+            colors: line.styles,
+            links: Vec::new(),
+            source: None,
+            stretch: Vec::new(),
+            hyphen: false,
+            rtl: false,
         });
     }
     state.lines_cache.push(RenderedLine::empty(block_idx));
@@ -149,6 +555,12 @@ fn build_separator_lines(state: &mut AppState, block_idx: usize, wrap_width: usi
         is_quote: false,
         is_code: false,
         highlights: Vec::new(),
+        colors: Vec::new(),
+        links: Vec::new(),
+        source: None,
+        stretch: Vec::new(),
+        hyphen: false,
+        rtl: false,
     });
     state.lines_cache.push(RenderedLine::empty(block_idx));
 }
@@ -158,16 +570,18 @@ fn build_list_lines(
     items: &[String],
     ordered: bool,
     block_idx: usize,
-    wrap_width: usize,
+    set: Typeset,
 ) {
+    // The block's text joins its items with newlines
+    let mut item_start = 0;
     for (i, item) in items.iter().enumerate() {
         let prefix = if ordered {
             format!("{}. ", i + 1)
         } else {
             "• ".to_string()
         };
-        let wrapped = wrap(item, wrap_width.saturating_sub(prefix.len()));
-        for (j, line) in wrapped.iter().enumerate() {
+        let wrapped = wrap_offsets(item, set.narrowed(prefix.len()));
+        for (j, (line, start, hyphen)) in wrapped.into_iter().enumerate() {
             let text = if j == 0 {
                 format!("{}{}", prefix, line)
             } else {
@@ -181,23 +595,127 @@ fn build_list_lines(
                 is_quote: false,
                 is_code: false,
                 highlights: Vec::new(),
+                colors: Vec::new(),
+                links: Vec::new(),
+                source: Some((item_start + start, prefix.len())),
+                stretch: Vec::new(),
+                hyphen,
+                rtl: false,
             });
         }
+        item_start += item.len() + 1;
     }
     state.lines_cache.push(RenderedLine::empty(block_idx));
 }
 
-fn build_generic_lines(state: &mut AppState, text: &str, block_idx: usize, wrap_width: usize) {
-    let wrapped = wrap(text, wrap_width);
-    for line in wrapped {
+/// Display math is laid out over several lines and centered, never wrapped
+fn build_math_lines(state: &mut AppState, tex: &str, block_idx: usize, wrap_width: usize) {
+    let lines = math::to_lines(tex);
+    let width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+    let pad = " ".repeat(wrap_width.saturating_sub(width) / 2);
+    for line in lines {
         state.lines_cache.push(RenderedLine {
-            text: line.to_string(),
+            text: format!("{}{}", pad, line),
             block_index: block_idx,
             is_heading: false,
             heading_level: 0,
             is_quote: false,
             is_code: false,
             highlights: Vec::new(),
+            colors: Vec::new(),
+            links: Vec::new(),
+            source: None,
+            stretch: Vec::new(),
+            hyphen: false,
+            rtl: false,
+        });
+    }
+    state.lines_cache.push(RenderedLine::empty(block_idx));
+}
+
+/// Lay out an image as half-block lines, followed by its caption
+///
+/// Images that cannot be loaded show just their caption or alt text.
+#[cfg(feature = "image-support")]
+fn build_image_lines(
+    state: &mut AppState,
+    src: &str,
+    caption: &str,
+    block_idx: usize,
+    set: Typeset,
+) {
+    let wrap_width = set.width;
+    // Keep room for the status bar so whole images fit on screen
+    let max_rows = (state.terminal_size.1 as usize).saturating_sub(6).max(4);
+    let book = state.book.skeleton.source_path.clone();
+    let first_line = state.lines_cache.len();
+
+    let cell = state.graphics.cell_size();
+    let laid_out = match state.graphics.protocol {
+        Some(_) => state.graphics.load(&book, src).map(|image| {
+            let (cols, rows) =
+                graphics::fit(image.width(), image.height(), cell, wrap_width, max_rows);
+            (cols, rows, graphics::half_blocks(image, cols, rows))
+        }),
+        None => None,
+    };
+
+    if let Some((cols, rows, lines)) = laid_out {
+        let indent = wrap_width.saturating_sub(cols as usize) / 2;
+        state.graphics.images.push(InlineImage {
+            src: src.to_string(),
+            first_line,
+            indent: indent as u16,
+            cols,
+            rows,
+        });
+        for (text, colors) in lines {
+            state.lines_cache.push(RenderedLine {
+                text: format!("{}{}", " ".repeat(indent), text),
+                block_index: block_idx,
+                is_heading: false,
+                heading_level: 0,
+                is_quote: false,
+                is_code: false,
+                highlights: Vec::new(),
+                colors: colors
+                    .into_iter()
+                    .map(|(start, end, style)| (start + indent, end + indent, style))
+                    .collect(),
+                links: Vec::new(),
+                source: None,
+                stretch: Vec::new(),
+                hyphen: false,
+                rtl: false,
+            });
+        }
+        if caption.is_empty() {
+            state.lines_cache.push(RenderedLine::empty(block_idx));
+            return;
+        }
+    }
+
+    if !caption.is_empty() {
+        build_generic_lines(state, caption, block_idx, set);
+    }
+}
+
+fn build_generic_lines(state: &mut AppState, text: &str, block_idx: usize, set: Typeset) {
+    for (line, start, hyphen) in wrap_offsets(text, set) {
+        state.lines_cache.push(RenderedLine {
+            text: line,
+            block_index: block_idx,
+            is_heading: false,
+            heading_level: 0,
+            is_quote: false,
+            is_code: false,
+            highlights: Vec::new(),
+            colors: Vec::new(),
+            links: Vec::new(),
+            source: Some((start, 0)),
+            stretch: Vec::new(),
+            hyphen,
+            rtl: false,
         });
     }
     state.lines_cache.push(RenderedLine::empty(block_idx));
@@ -222,3 +740,79 @@ fn find_highlights(text: &str, search_active: bool, search_query: &str) -> Vec<(
 
     highlights
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_styled() {
+        let text = "plain bold words end";
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let set = Typeset {
+            width: 10,
+            wrap: WrapMode::Word,
+            hyphenator: Hyphenator::NONE,
+        };
+        let lines = wrap_styled(text, &[(6, 16, bold)], set);
+
+        let spans: Vec<(&str, Vec<&str>)> = lines
+            .iter()
+            .map(|(line, _, _, colors)| {
+                let styled = colors.iter().map(|&(s, e, _)| &line[s..e]).collect();
+                (line.as_str(), styled)
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![("plain bold", vec!["bold"]), ("words end", vec!["words"]),]
+        );
+    }
+
+    #[test]
+    fn test_typesetting() {
+        let set = |width, wrap| Typeset {
+            width,
+            wrap,
+            hyphenator: Hyphenator::NONE,
+        };
+        let text = "a bc def\nghij";
+        let lines = |set| wrap_offsets(text, set);
+        assert_eq!(
+            lines(set(4, WrapMode::Char)),
+            vec![
+                ("a bc".to_string(), 0, false),
+                (" def".to_string(), 4, false),
+                ("ghij".to_string(), 9, false),
+            ]
+        );
+        assert_eq!(
+            lines(set(4, WrapMode::Off)),
+            vec![
+                ("a bc def".to_string(), 0, false),
+                ("ghij".to_string(), 9, false)
+            ]
+        );
+
+        // Spaces are stretched leftmost first, but not before a break
+        let text = "one two three four\nfive six";
+        let mut lines: Vec<RenderedLine> = wrap_offsets(text, set(14, WrapMode::Word))
+            .into_iter()
+            .map(|(line, start, _)| {
+                let mut rendered = RenderedLine::empty(0);
+                rendered.text = line;
+                rendered.source = Some((start, 0));
+                rendered
+            })
+            .collect();
+        justify_lines(&mut lines, text, 14);
+        assert_eq!(lines[0].text, "one two three");
+        assert_eq!(lines[0].stretch, vec![(3, 1)]);
+        assert_eq!(lines[0].width_to(7), 8);
+        assert!(lines[1].stretch.is_empty());
+
+        let mut code = String::from("  ");
+        push_expanded(&mut code, 2, "a\tb\t\tc", 4);
+        assert_eq!(code, "  a   b       c");
+    }
+}
@@ -4,24 +4,35 @@ mod content;
 mod lines;
 mod overlays;
 mod status;
+mod table;
 
 use super::state::{AppState, MessageType, Mode};
-use crate::config::Config;
+use crate::config::{Config, ThemeConfig};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::Paragraph,
+    style::Style,
+    widgets::{Block, BorderType, Paragraph},
     Frame,
 };
 
 /// Render the application
 pub fn render(frame: &mut Frame, state: &mut AppState, config: &Config) {
-    let theme = &config.theme;
+    let theme = &state.theme.clone();
 
     // Update terminal size
     let size = frame.area();
     state.terminal_size = (size.width, size.height);
 
+    // The page: the theme's text on its background
+    frame.render_widget(
+        Block::default().style(
+            Style::default()
+                .fg(theme.content.text.to_ratatui())
+                .bg(theme.content.background.to_ratatui()),
+        ),
+        size,
+    );
+
     // Build layout
     let main_chunks = if state.show_status_bar && !state.fullscreen {
         Layout::default()
@@ -57,7 +68,7 @@ pub fn render(frame: &mut Frame, state: &mut AppState, config: &Config) {
     };
 
     // Render main content
-    content::render_content(frame, state, content_area, config);
+    content::render_content(frame, state, content_area, config, theme);
 
     // Render status bar
     if state.show_status_bar && !state.fullscreen && main_chunks.len() > 2 {
@@ -67,22 +78,39 @@ pub fn render(frame: &mut Frame, state: &mut AppState, config: &Config) {
 
     // Render overlays
     match state.mode {
-        Mode::Help => overlays::render_help_overlay(frame, state, size),
-        Mode::Command => overlays::render_command_line(frame, state, size),
-        Mode::Search => overlays::render_search_line(frame, state, size),
-        Mode::TableOfContents => overlays::render_toc_overlay(frame, state, size),
-        Mode::Bookmark => overlays::render_bookmark_overlay(frame, state, size),
-        Mode::GoTo => overlays::render_goto_line(frame, state, size),
+        Mode::Help => overlays::render_help_overlay(frame, state, size, theme),
+        Mode::Command => overlays::render_command_line(frame, state, size, theme),
+        Mode::Search => overlays::render_search_line(frame, state, size, theme),
+        Mode::TableOfContents => overlays::render_toc_overlay(frame, state, size, theme),
+        Mode::Bookmark => overlays::render_bookmark_overlay(frame, state, size, theme),
+        Mode::Annotations => overlays::render_annotation_overlay(frame, state, size, theme),
+        Mode::GoTo => overlays::render_goto_line(frame, state, size, theme),
         _ => {}
     }
 
     // Render message if any
     if let Some((ref msg, msg_type)) = state.message {
-        render_message(frame, msg, msg_type, size);
+        render_message(frame, msg, msg_type, size, theme);
+    }
+}
+
+/// Border lines for a theme's border style
+fn border_type(style: &str) -> BorderType {
+    match style {
+        "rounded" => BorderType::Rounded,
+        "double" => BorderType::Double,
+        "thick" => BorderType::Thick,
+        _ => BorderType::Plain,
     }
 }
 
-fn render_message(frame: &mut Frame, msg: &str, msg_type: MessageType, area: Rect) {
+fn render_message(
+    frame: &mut Frame,
+    msg: &str,
+    msg_type: MessageType,
+    area: Rect,
+    theme: &ThemeConfig,
+) {
     let y = area.height.saturating_sub(2);
     let msg_area = Rect {
         x: 1,
@@ -92,14 +120,17 @@ fn render_message(frame: &mut Frame, msg: &str, msg_type: MessageType, area: Rec
     };
 
     let color = match msg_type {
-        MessageType::Info => Color::Blue,
-        MessageType::Success => Color::Green,
-        MessageType::Warning => Color::Yellow,
-        MessageType::Error => Color::Red,
+        MessageType::Info => &theme.ui.primary,
+        MessageType::Success => &theme.ui.success,
+        MessageType::Warning => &theme.ui.warning,
+        MessageType::Error => &theme.ui.error,
     };
 
-    let paragraph =
-        Paragraph::new(format!(" {} ", msg)).style(Style::default().fg(Color::White).bg(color));
+    let paragraph = Paragraph::new(format!(" {} ", msg)).style(
+        Style::default()
+            .fg(theme.ui.background.to_ratatui())
+            .bg(color.to_ratatui()),
+    );
 
     frame.render_widget(paragraph, msg_area);
 }
@@ -1,14 +1,48 @@
-//! Overlay rendering (help, command line, search, TOC, bookmarks)
+//! Overlay rendering (help, command line, search, TOC, bookmarks, annotations)
 
+use super::border_type;
+use crate::config::{Action, ThemeConfig};
+use crate::tui::keymap;
 use crate::tui::state::AppState;
+use crate::tui::visual;
 use ratatui::{
     layout::Rect,
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-pub fn render_command_line(frame: &mut Frame, state: &AppState, area: Rect) {
+/// A popup's border and title in the theme's colors
+fn popup_block<'a>(title: &'a str, theme: &ThemeConfig) -> Block<'a> {
+    let ui = &theme.ui;
+    Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_type(border_type(&ui.border_style))
+        .border_style(Style::default().fg(ui.popup_border.to_ratatui()))
+        .style(
+            Style::default()
+                .fg(ui.foreground.to_ratatui())
+                .bg(ui.popup_bg.to_ratatui()),
+        )
+}
+
+/// Style of the input line at the bottom of the screen
+fn input_style(theme: &ThemeConfig) -> Style {
+    Style::default()
+        .fg(theme.ui.foreground.to_ratatui())
+        .bg(theme.ui.popup_bg.to_ratatui())
+}
+
+/// Style of the highlighted entry of a list
+fn selected_style(theme: &ThemeConfig) -> Style {
+    Style::default()
+        .fg(theme.ui.selection_fg.to_ratatui())
+        .bg(theme.ui.selection_bg.to_ratatui())
+}
+
+pub fn render_command_line(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let y = area.height.saturating_sub(1);
     let command_area = Rect {
         x: 0,
@@ -18,13 +52,13 @@ pub fn render_command_line(frame: &mut Frame, state: &AppState, area: Rect) {
     };
 
     let text = format!(":{}", state.command_buffer);
-    let paragraph = Paragraph::new(text).style(Style::default().fg(Color::White).bg(Color::Black));
+    let paragraph = Paragraph::new(text).style(input_style(theme));
 
     frame.render_widget(Clear, command_area);
     frame.render_widget(paragraph, command_area);
 }
 
-pub fn render_search_line(frame: &mut Frame, state: &AppState, area: Rect) {
+pub fn render_search_line(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let y = area.height.saturating_sub(1);
     let search_area = Rect {
         x: 0,
@@ -44,13 +78,13 @@ pub fn render_search_line(frame: &mut Frame, state: &AppState, area: Rect) {
     };
 
     let text = format!("/{}{}", state.search.query, results_info);
-    let paragraph = Paragraph::new(text).style(Style::default().fg(Color::White).bg(Color::Black));
+    let paragraph = Paragraph::new(text).style(input_style(theme));
 
     frame.render_widget(Clear, search_area);
     frame.render_widget(paragraph, search_area);
 }
 
-pub fn render_goto_line(frame: &mut Frame, state: &AppState, area: Rect) {
+pub fn render_goto_line(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let y = area.height.saturating_sub(1);
     let goto_area = Rect {
         x: 0,
@@ -60,15 +94,67 @@ pub fn render_goto_line(frame: &mut Frame, state: &AppState, area: Rect) {
     };
 
     let text = format!("Go to line: {}", state.command_buffer);
-    let paragraph = Paragraph::new(text).style(Style::default().fg(Color::White).bg(Color::Black));
+    let paragraph = Paragraph::new(text).style(input_style(theme));
 
     frame.render_widget(Clear, goto_area);
     frame.render_widget(paragraph, goto_area);
 }
 
-pub fn render_help_overlay(frame: &mut Frame, _state: &AppState, area: Rect) {
+/// A help row: the actions whose first keys are listed, and what they do
+type HelpRow = (&'static [Action], &'static str);
+
+const HELP_SECTIONS: &[(&str, &[HelpRow])] = &[
+    (
+        "Navigation",
+        &[
+            (&[Action::ScrollDown, Action::ScrollUp], "Scroll down/up"),
+            (
+                &[Action::PrevChapter, Action::NextChapter],
+                "Previous/next chapter",
+            ),
+            (&[Action::PageDown, Action::PageUp], "Page down/up"),
+            (&[Action::GoToTop, Action::GoToBottom], "Go to top/bottom"),
+            (
+                &[Action::HalfPageDown, Action::HalfPageUp],
+                "Half page down/up",
+            ),
+            (&[Action::FollowLink], "Follow a link on screen"),
+            (
+                &[Action::JumpBack, Action::JumpForward],
+                "Jump back/forward",
+            ),
+            (&[Action::GoToPercent], "Go to N% with a count"),
+        ],
+    ),
+    (
+        "Commands",
+        &[
+            (&[Action::Search], "Search"),
+            (
+                &[Action::SearchNext, Action::SearchPrev],
+                "Next/prev search result",
+            ),
+            (&[Action::CommandPalette], "Command mode"),
+            (&[Action::AddBookmark], "Add bookmark"),
+            (&[Action::ListBookmarks], "List bookmarks"),
+            (&[Action::StartSelection], "Select text to highlight"),
+            (&[Action::ListAnnotations], "List highlights and notes"),
+            (&[Action::SetMark, Action::GoToMark], "Set/jump to a mark"),
+            (&[Action::TableOfContents], "Table of contents"),
+            (
+                &[Action::ToggleSidebar, Action::ToggleStatusBar],
+                "Toggle sidebar/status bar",
+            ),
+            (&[Action::ToggleFullscreen], "Toggle fullscreen"),
+            (&[Action::ToggleTheme], "Cycle themes"),
+            (&[Action::Quit], "Quit"),
+        ],
+    ),
+];
+
+pub fn render_help_overlay(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let width = 60.min(area.width.saturating_sub(4));
-    let height = 20.min(area.height.saturating_sub(4));
+    let height = 32.min(area.height.saturating_sub(4));
     let x = (area.width - width) / 2;
     let y = (area.height - height) / 2;
 
@@ -79,49 +165,37 @@ pub fn render_help_overlay(frame: &mut Frame, _state: &AppState, area: Rect) {
         height,
     };
 
-    let help_text = vec![
-        "",
-        "  Navigation",
-        "  ──────────",
-        "  j/k, ↑/↓     Scroll up/down",
-        "  h/l, ←/→     Previous/next chapter",
-        "  Space, PgDn  Page down",
-        "  PgUp         Page up",
-        "  g/G          Go to top/bottom",
-        "  Ctrl+d/u     Half page down/up",
-        "",
-        "  Commands",
-        "  ────────",
-        "  /            Search",
-        "  n/N          Next/prev search result",
-        "  :            Command mode",
-        "  m            Add bookmark",
-        "  '            List bookmarks",
-        "  t            Table of contents",
-        "  s/S          Toggle sidebar/status bar",
-        "  f            Toggle fullscreen",
-        "  T            Cycle themes",
-        "  q            Quit",
-        "",
-        "  Press ? or Esc to close",
-    ];
-
-    let block = Block::default()
-        .title(" Help ")
-        .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Black));
+    // Show the keys actually bound, search mode's first where it has its own
+    let first_key = |action: Action| {
+        ["search", keymap::NORMAL]
+            .iter()
+            .find_map(|mode| state.keys.keys_for(mode, action).into_iter().next())
+    };
+    let mut help_text = vec![String::new()];
+    for (title, rows) in HELP_SECTIONS {
+        help_text.push(format!("  {}", title));
+        help_text.push(format!("  {}", "─".repeat(title.chars().count())));
+        for (actions, description) in *rows {
+            let keys: Vec<String> = actions.iter().filter_map(|&a| first_key(a)).collect();
+            if !keys.is_empty() {
+                help_text.push(format!("  {:<12} {}", keys.join("/"), description));
+            }
+        }
+        help_text.push(String::new());
+    }
+    help_text.push("  Press ? or Esc to close".to_string());
 
-    let paragraph = Paragraph::new(help_text.join("\n"))
-        .block(block)
-        .style(Style::default().fg(Color::White));
+    let block = popup_block(" Help ", theme);
+
+    let paragraph = Paragraph::new(help_text.join("\n")).block(block);
 
     frame.render_widget(Clear, help_area);
     frame.render_widget(paragraph, help_area);
 }
 
-pub fn render_toc_overlay(frame: &mut Frame, state: &AppState, area: Rect) {
+pub fn render_toc_overlay(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let width = 50.min(area.width.saturating_sub(4));
-    let height = (state.book.content.chapters.len() as u16 + 4).min(area.height.saturating_sub(4));
+    let height = (state.book.chapter_count() as u16 + 4).min(area.height.saturating_sub(4));
     let x = (area.width - width) / 2;
     let y = (area.height - height) / 2;
 
@@ -134,38 +208,41 @@ pub fn render_toc_overlay(frame: &mut Frame, state: &AppState, area: Rect) {
 
     let items: Vec<ListItem> = state
         .book
-        .content
-        .chapters
+        .chapters()
         .iter()
         .enumerate()
         .map(|(i, ch)| {
             let style = if i == state.position.chapter {
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(theme.ui.primary.to_ratatui())
                     .add_modifier(Modifier::BOLD)
             } else {
-                Style::default().fg(Color::White)
+                Style::default()
             };
             ListItem::new(format!(" {}. {}", i + 1, ch.display_title())).style(style)
         })
         .collect();
 
-    let block = Block::default()
-        .title(" Table of Contents ")
-        .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Black));
+    let block = popup_block(" Table of Contents ", theme);
 
-    let list = List::new(items).block(block);
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(selected_style(theme));
+    let mut list_state = ListState::default().with_selected(Some(state.toc_selected));
 
     frame.render_widget(Clear, toc_area);
-    frame.render_widget(list, toc_area);
+    frame.render_stateful_widget(list, toc_area, &mut list_state);
 }
 
-pub fn render_bookmark_overlay(frame: &mut Frame, state: &AppState, area: Rect) {
-    let width = 50.min(area.width.saturating_sub(4));
-    let height = (state.bookmarks.len() as u16 + 4)
-        .max(6)
-        .min(area.height.saturating_sub(4));
+pub fn render_bookmark_overlay(
+    frame: &mut Frame,
+    state: &AppState,
+    area: Rect,
+    theme: &ThemeConfig,
+) {
+    let count = state.bookmarks.len();
+    let width = 60.min(area.width.saturating_sub(4));
+    let height = (count as u16 + 4).max(6).min(area.height.saturating_sub(4));
     let x = (area.width - width) / 2;
     let y = (area.height - height) / 2;
 
@@ -176,27 +253,103 @@ pub fn render_bookmark_overlay(frame: &mut Frame, state: &AppState, area: Rect)
         height,
     };
 
-    let items: Vec<ListItem> = if state.bookmarks.is_empty() {
-        vec![ListItem::new(" No bookmarks yet. Press 'm' to add one.")]
+    let items: Vec<ListItem> = if count == 0 {
+        let hint = match state
+            .keys
+            .keys_for(keymap::NORMAL, Action::AddBookmark)
+            .first()
+        {
+            Some(key) => format!(" No bookmarks yet. Press '{}' to add one.", key),
+            None => " No bookmarks yet. Use :bm to add one.".to_string(),
+        };
+        vec![ListItem::new(hint)]
     } else {
         state
             .bookmarks
             .iter()
             .enumerate()
             .map(|(i, bm)| {
-                ListItem::new(format!(" {}. {}", i + 1, bm.name))
-                    .style(Style::default().fg(Color::White))
+                let number = if i < 9 {
+                    format!("{}.", i + 1)
+                } else {
+                    "  ".to_string()
+                };
+                ListItem::new(format!(" {} {} (Ch.{})", number, bm.name, bm.chapter + 1))
             })
             .collect()
     };
 
-    let block = Block::default()
-        .title(" Bookmarks ")
-        .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Black));
+    let block = popup_block(" Bookmarks ", theme).title_bottom(" Enter: go  d: delete ");
 
-    let list = List::new(items).block(block);
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(selected_style(theme));
+    let mut list_state =
+        ListState::default().with_selected((count > 0).then_some(state.bookmark_selected));
 
     frame.render_widget(Clear, bookmark_area);
-    frame.render_widget(list, bookmark_area);
+    frame.render_stateful_widget(list, bookmark_area, &mut list_state);
+}
+
+pub fn render_annotation_overlay(
+    frame: &mut Frame,
+    state: &AppState,
+    area: Rect,
+    theme: &ThemeConfig,
+) {
+    let count = state.annotations.len();
+    let width = 70.min(area.width.saturating_sub(4));
+    let height = (count as u16 + 4).max(6).min(area.height.saturating_sub(4));
+    let x = (area.width - width) / 2;
+    let y = (area.height - height) / 2;
+
+    let annotation_area = Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    let items: Vec<ListItem> = if count == 0 {
+        let hint = match state
+            .keys
+            .keys_for(keymap::NORMAL, Action::StartSelection)
+            .first()
+        {
+            Some(key) => format!(" No highlights yet. Press '{}' to select text.", key),
+            None => " No highlights yet. Use :note to add a note.".to_string(),
+        };
+        vec![ListItem::new(hint)]
+    } else {
+        state
+            .annotations
+            .iter()
+            .map(|a| {
+                let text: String = a.text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let mut spans = vec![
+                    Span::styled(" ▌", Style::default().fg(visual::color(&a.color))),
+                    Span::raw(format!("Ch.{} ", a.chapter + 1)),
+                    Span::raw(format!("“{}”", text)),
+                ];
+                if let Some(note) = &a.note {
+                    spans.push(Span::styled(
+                        format!("  ✎ {}", note.lines().next().unwrap_or("")),
+                        Style::default().fg(theme.ui.secondary.to_ratatui()),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect()
+    };
+
+    let block = popup_block(" Highlights & Notes ", theme).title_bottom(" Enter: go  d: delete ");
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(selected_style(theme));
+    let mut list_state =
+        ListState::default().with_selected((count > 0).then_some(state.annotation_selected));
+
+    frame.render_widget(Clear, annotation_area);
+    frame.render_stateful_widget(list, annotation_area, &mut list_state);
 }
@@ -4,30 +4,36 @@ use crate::config::ThemeConfig;
 use crate::tui::state::{AppState, Mode};
 use ratatui::{
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
     Frame,
 };
 
-pub fn render_sidebar(frame: &mut Frame, state: &AppState, area: Rect, _theme: &ThemeConfig) {
+pub fn render_sidebar(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
+    let ui = &theme.ui;
     let block = Block::default()
         .title(" Table of Contents ")
-        .borders(Borders::RIGHT);
+        .borders(Borders::RIGHT)
+        .border_style(Style::default().fg(ui.border.to_ratatui()))
+        .style(
+            Style::default()
+                .fg(ui.sidebar_fg.to_ratatui())
+                .bg(ui.sidebar_bg.to_ratatui()),
+        );
 
     let items: Vec<ListItem> = state
         .book
-        .content
-        .chapters
+        .chapters()
         .iter()
         .enumerate()
         .map(|(i, ch)| {
             let title = ch.display_title();
             let style = if i == state.position.chapter {
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(ui.primary.to_ratatui())
                     .add_modifier(Modifier::BOLD)
             } else {
-                Style::default().fg(Color::Gray)
+                Style::default()
             };
             ListItem::new(title).style(style)
         })
@@ -37,28 +43,40 @@ pub fn render_sidebar(frame: &mut Frame, state: &AppState, area: Rect, _theme: &
     frame.render_widget(list, area);
 }
 
-pub fn render_progress_bar(frame: &mut Frame, state: &AppState, area: Rect, _theme: &ThemeConfig) {
+pub fn render_progress_bar(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
     let progress = state.progress();
-    let gauge = Gauge::default()
-        .ratio(progress)
-        .gauge_style(Style::default().fg(Color::Cyan).bg(Color::DarkGray));
+    let gauge = Gauge::default().ratio(progress).gauge_style(
+        Style::default()
+            .fg(theme.content.progress.to_ratatui())
+            .bg(theme.ui.scrollbar.to_ratatui()),
+    );
     frame.render_widget(gauge, area);
 }
 
-pub fn render_status_bar(frame: &mut Frame, state: &AppState, area: Rect, _theme: &ThemeConfig) {
-    let chapter_info = if let Some(ch) = state.current_chapter() {
+pub fn render_status_bar(frame: &mut Frame, state: &AppState, area: Rect, theme: &ThemeConfig) {
+    let chapter_info = if let Some(ch) = state.book.chapters().get(state.position.chapter) {
         ch.display_title()
     } else {
         "No chapter".to_string()
     };
 
+    let place = match state.page_numbers() {
+        Some((shown, total)) if shown.len() > 1 => {
+            format!("Page {}-{}/{}", shown.start, shown.end - 1, total)
+        }
+        Some((shown, total)) => format!("Page {}/{}", shown.start, total),
+        None => format!(
+            "Line {}/{}",
+            state.position.scroll_offset + 1,
+            state.total_lines
+        ),
+    };
     let position_info = format!(
-        "Ch {}/{} | {} | Line {}/{}",
+        "Ch {}/{} | {} | {}",
         state.position.chapter + 1,
-        state.book.content.chapters.len(),
+        state.book.chapter_count(),
         state.progress_string(),
-        state.position.scroll_offset + 1,
-        state.total_lines,
+        place,
     );
 
     let mode_str = match state.mode {
@@ -69,16 +87,29 @@ pub fn render_status_bar(frame: &mut Frame, state: &AppState, area: Rect, _theme
         Mode::TableOfContents => "TOC",
         Mode::Bookmark => "BOOKMARKS",
         Mode::GoTo => "GOTO",
+        Mode::Hint => "HINT",
+        Mode::Visual => "VISUAL",
+        Mode::Annotations => "ANNOTATIONS",
     };
 
     let left = format!(" {} ", chapter_info);
-    let right = format!(" {} | {} ", mode_str, position_info);
+    // Keys of an unfinished sequence, like Vim's showcmd
+    let count = state.count.map(|n| n.to_string()).unwrap_or_default();
+    let pending = format!("{}{}", count, state.keys.pending());
+    let right = if pending.is_empty() {
+        format!(" {} | {} ", mode_str, position_info)
+    } else {
+        format!(" {} | {} | {} ", pending, mode_str, position_info)
+    };
 
     let status_width = area.width as usize;
     let padding = status_width.saturating_sub(left.len() + right.len());
     let status_text = format!("{}{}{}", left, " ".repeat(padding), right);
 
-    let paragraph =
-        Paragraph::new(status_text).style(Style::default().fg(Color::White).bg(Color::DarkGray));
+    let paragraph = Paragraph::new(status_text).style(
+        Style::default()
+            .fg(theme.ui.statusbar_fg.to_ratatui())
+            .bg(theme.ui.statusbar_bg.to_ratatui()),
+    );
     frame.render_widget(paragraph, area);
 }
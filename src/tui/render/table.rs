@@ -0,0 +1,382 @@
+//! Table layout
+//!
+//! Columns are sized by display width to fit the wrap width, and cell text
+//! wraps inside its column. Tables whose columns cannot fit even with their
+//! words broken are shown as stacked records instead.
+
+use ratatui::style::Style;
+use textwrap::wrap;
+use unicode_width::UnicodeWidthStr;
+
+/// Narrowest a column gets before the table is stacked as records
+const MIN_COLUMN: usize = 6;
+
+/// Longest word a column makes room for before breaking it
+const MAX_WORD: usize = 16;
+
+/// A laid out line with styled byte ranges
+pub struct TableLine {
+    pub text: String,
+    pub styles: Vec<(usize, usize, Style)>,
+}
+
+impl TableLine {
+    fn new() -> Self {
+        TableLine {
+            text: String::new(),
+            styles: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, text: &str, style: Option<Style>) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        if let Some(style) = style.filter(|_| !text.is_empty()) {
+            self.styles.push((start, self.text.len(), style));
+        }
+    }
+}
+
+/// Box-drawing characters for table borders
+pub struct Borders {
+    horizontal: &'static str,
+    vertical: &'static str,
+    /// Corners and joints, top to bottom: left, middle, right
+    top: [&'static str; 3],
+    middle: [&'static str; 3],
+    bottom: [&'static str; 3],
+}
+
+impl Borders {
+    /// Borders for a theme's `border_style`
+    pub fn from_style(style: &str) -> Self {
+        match style {
+            "ascii" => Borders {
+                horizontal: "-",
+                vertical: "|",
+                top: ["+", "+", "+"],
+                middle: ["+", "+", "+"],
+                bottom: ["+", "+", "+"],
+            },
+            "double" => Borders {
+                horizontal: "═",
+                vertical: "║",
+                top: ["╔", "╦", "╗"],
+                middle: ["╠", "╬", "╣"],
+                bottom: ["╚", "╩", "╝"],
+            },
+            "thick" => Borders {
+                horizontal: "━",
+                vertical: "┃",
+                top: ["┏", "┳", "┓"],
+                middle: ["┣", "╋", "┫"],
+                bottom: ["┗", "┻", "┛"],
+            },
+            "rounded" => Borders {
+                top: ["╭", "┬", "╮"],
+                bottom: ["╰", "┴", "╯"],
+                ..Self::from_style("plain")
+            },
+            _ => Borders {
+                horizontal: "─",
+                vertical: "│",
+                top: ["┌", "┬", "┐"],
+                middle: ["├", "┼", "┤"],
+                bottom: ["└", "┴", "┘"],
+            },
+        }
+    }
+}
+
+/// Styles for the parts of a table
+pub struct TableStyle {
+    pub borders: Borders,
+    pub border: Style,
+    pub header: Style,
+}
+
+/// Lay out a table within `width` display columns
+pub fn layout(
+    headers: &[String],
+    rows: &[Vec<String>],
+    width: usize,
+    style: &TableStyle,
+) -> Vec<TableLine> {
+    let columns = rows
+        .iter()
+        .map(|r| r.len())
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+
+    match column_widths(headers, rows, columns, width) {
+        Some(widths) => grid(headers, rows, &widths, style),
+        None => records(headers, rows, width, style),
+    }
+}
+
+/// Share out the width between columns, or `None` if they cannot fit
+///
+/// Every column gets room for its longest word (up to `MAX_WORD`), then
+/// the rest is split by how much each column still wants.
+fn column_widths(
+    headers: &[String],
+    rows: &[Vec<String>],
+    columns: usize,
+    width: usize,
+) -> Option<Vec<usize>> {
+    // Each column costs a border and a space on both sides
+    let available = width.checked_sub(columns * 3 + 1)?;
+
+    let cells = |col: usize| {
+        std::iter::once(headers.get(col))
+            .chain(rows.iter().map(move |r| r.get(col)))
+            .flatten()
+    };
+    let wanted: Vec<usize> = (0..columns)
+        .map(|col| cells(col).map(|c| c.width()).max().unwrap_or(0).max(1))
+        .collect();
+    let least: Vec<usize> = (0..columns)
+        .map(|col| {
+            let word = cells(col)
+                .flat_map(|c| c.split_whitespace())
+                .map(|w| w.width())
+                .max()
+                .unwrap_or(0);
+            word.clamp(1, MAX_WORD).min(wanted[col])
+        })
+        .collect();
+
+    if wanted.iter().sum::<usize>() <= available {
+        return Some(wanted);
+    }
+    let floor: Vec<usize> = least.iter().map(|&l| l.max(MIN_COLUMN)).collect();
+    let floor_total: usize = floor.iter().sum();
+    if floor_total > available {
+        return None;
+    }
+
+    let spare = available - floor_total;
+    let extra: Vec<usize> = wanted
+        .iter()
+        .zip(&floor)
+        .map(|(w, f)| w.saturating_sub(*f))
+        .collect();
+    let extra_total: usize = extra.iter().sum::<usize>().max(1);
+    let mut widths: Vec<usize> = floor
+        .iter()
+        .zip(&extra)
+        .map(|(f, e)| f + e * spare / extra_total)
+        .collect();
+
+    // Hand out what rounding left over, widest wishes first
+    let mut left = available - widths.iter().sum::<usize>();
+    let mut order: Vec<usize> = (0..columns).collect();
+    order.sort_by_key(|&c| std::cmp::Reverse(extra[c]));
+    for &col in order.iter().cycle().take(columns * 2) {
+        if left == 0 {
+            break;
+        }
+        if widths[col] < wanted[col] {
+            widths[col] += 1;
+            left -= 1;
+        }
+    }
+    Some(widths)
+}
+
+fn grid(
+    headers: &[String],
+    rows: &[Vec<String>],
+    widths: &[usize],
+    style: &TableStyle,
+) -> Vec<TableLine> {
+    let b = &style.borders;
+    let rule = |[left, joint, right]: [&str; 3]| {
+        let mut line = TableLine::new();
+        let segments: Vec<String> = widths.iter().map(|w| b.horizontal.repeat(w + 2)).collect();
+        line.push(
+            &format!("{}{}{}", left, segments.join(joint), right),
+            Some(style.border),
+        );
+        line
+    };
+
+    let wrapped = |cells: &[String]| -> Vec<Vec<String>> {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(col, &w)| {
+                let cell = cells.get(col).map(|c| c.as_str()).unwrap_or("");
+                wrap(cell, w).into_iter().map(|l| l.into_owned()).collect()
+            })
+            .collect()
+    };
+    let row_lines = |cells: &[Vec<String>], text_style: Option<Style>, out: &mut Vec<TableLine>| {
+        let height = cells.iter().map(|c| c.len()).max().unwrap_or(0).max(1);
+        for i in 0..height {
+            let mut line = TableLine::new();
+            line.push(b.vertical, Some(style.border));
+            for (cell, &w) in cells.iter().zip(widths) {
+                let text = cell.get(i).map(|s| s.as_str()).unwrap_or("");
+                line.push(" ", None);
+                line.push(text, text_style);
+                line.push(&" ".repeat(w.saturating_sub(text.width()) + 1), None);
+                line.push(b.vertical, Some(style.border));
+            }
+            out.push(line);
+        }
+    };
+
+    let body: Vec<Vec<Vec<String>>> = rows.iter().map(|r| wrapped(r)).collect();
+    // Rows only need rules between them once their cells wrap
+    let ruled = body.iter().any(|cells| cells.iter().any(|c| c.len() > 1));
+
+    let mut lines = vec![rule(b.top)];
+    if !headers.is_empty() {
+        row_lines(&wrapped(headers), Some(style.header), &mut lines);
+        if !body.is_empty() {
+            lines.push(rule(b.middle));
+        }
+    }
+    for (i, cells) in body.iter().enumerate() {
+        if ruled && i > 0 {
+            lines.push(rule(b.middle));
+        }
+        row_lines(cells, None, &mut lines);
+    }
+    lines.push(rule(b.bottom));
+    lines
+}
+
+/// Stack each row as a record of `header: value` lines
+fn records(
+    headers: &[String],
+    rows: &[Vec<String>],
+    width: usize,
+    style: &TableStyle,
+) -> Vec<TableLine> {
+    let label = |col: usize| {
+        headers
+            .get(col)
+            .filter(|h| !h.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("{}", col + 1))
+    };
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let label_width = (0..columns)
+        .map(|c| label(c).width())
+        .max()
+        .unwrap_or(0)
+        .min(width / 3);
+    let value_width = width.saturating_sub(label_width + 2).max(1);
+
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            let mut line = TableLine::new();
+            line.push(
+                &style.borders.horizontal.repeat(width.min(40)),
+                Some(style.border),
+            );
+            lines.push(line);
+        }
+        for (col, value) in row.iter().enumerate() {
+            let name = label(col);
+            let names = wrap(&name, label_width.max(1));
+            let values = wrap(value, value_width);
+            for j in 0..names.len().max(values.len()) {
+                let name = names.get(j).map(|n| n.as_ref()).unwrap_or("");
+                let mut line = TableLine::new();
+                line.push(name, Some(style.header));
+                line.push(
+                    &" ".repeat(label_width.saturating_sub(name.width()) + 2),
+                    None,
+                );
+                line.push(values.get(j).map(|v| v.as_ref()).unwrap_or(""), None);
+                lines.push(line);
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(headers: &[&str], rows: &[&[&str]], width: usize) -> Vec<String> {
+        let headers: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|r| r.iter().map(|s| s.to_string()).collect())
+            .collect();
+        let style = TableStyle {
+            borders: Borders::from_style("plain"),
+            border: Style::default(),
+            header: Style::default(),
+        };
+        layout(&headers, &rows, width, &style)
+            .into_iter()
+            .map(|l| l.text.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_natural_widths() {
+        assert_eq!(
+            table(&["Name", "Qty"], &[&["Apple", "3"], &["日本", "12"]], 40),
+            vec![
+                "┌───────┬─────┐",
+                "│ Name  │ Qty │",
+                "├───────┼─────┤",
+                "│ Apple │ 3   │",
+                "│ 日本  │ 12  │",
+                "└───────┴─────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wrapped_cells() {
+        let lines = table(
+            &["Term", "Meaning"],
+            &[
+                &["id", "the unique identifier of a record"],
+                &["name", "shown in lists"],
+            ],
+            30,
+        );
+        assert!(lines.iter().all(|l| l.width() <= 30));
+        assert_eq!(lines[3], "│ id     │ the unique        │");
+        assert_eq!(lines[4], "│        │ identifier of a   │");
+        // Wrapped rows get rules between them
+        assert_eq!(lines[6], "├────────┼───────────────────┤");
+    }
+
+    #[test]
+    fn test_records_fallback() {
+        let lines = table(
+            &["Alpha", "Beta", "Gamma", "Delta"],
+            &[&["one", "two", "three", "four"], &["a", "b", "c", "d"]],
+            20,
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "Alpha  one",
+                "Beta   two",
+                "Gamma  three",
+                "Delta  four",
+                "────────────────────",
+                "Alpha  a",
+                "Beta   b",
+                "Gamma  c",
+                "Delta  d",
+            ]
+        );
+    }
+}
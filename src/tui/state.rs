@@ -1,6 +1,20 @@
 //! TUI application state
 
-use crate::formats::{Book, Chapter};
+use super::bidi;
+use super::keymap::{self, Dispatcher};
+use super::pages::{self, LayoutMode};
+use super::visual::{self, Motion, Selection};
+use crate::config::Keybindings;
+use crate::config::{ThemeConfig, ThemeRegistry};
+use crate::formats::{Chapter, LazyBook};
+use crate::hyphenate;
+use crate::library::{Annotation, Bookmark, Library, Mark, TextRange};
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Application mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,6 +33,12 @@ pub enum Mode {
     TableOfContents,
     /// Go to line/page
     GoTo,
+    /// Picking a link on screen by its hint label
+    Hint,
+    /// Selecting text to highlight
+    Visual,
+    /// Annotation list
+    Annotations,
 }
 
 /// Reading position
@@ -81,30 +101,94 @@ impl SearchState {
     }
 }
 
-/// Bookmark data
-#[derive(Debug, Clone)]
-pub struct Bookmark {
-    pub id: String,
-    pub name: String,
-    pub position: Position,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+/// Keys hint labels are made of
+pub const HINT_KEYS: &str = "asdfghjkl";
+
+/// Most positions kept in the jump list
+const MAX_JUMPS: usize = 100;
+
+/// Labels for `n` hints, all the same length so none is a prefix of another
+pub fn hint_labels(n: usize) -> Vec<String> {
+    let keys: Vec<char> = HINT_KEYS.chars().collect();
+    let mut length = 1;
+    while keys.len().pow(length) < n {
+        length += 1;
+    }
+    (0..n)
+        .map(|mut i| {
+            let mut label = vec![keys[0]; length as usize];
+            for slot in label.iter_mut().rev() {
+                *slot = keys[i % keys.len()];
+                i /= keys.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
 }
 
-/// Annotation data
-#[derive(Debug, Clone)]
-pub struct Annotation {
-    pub id: String,
-    pub text: String,
-    pub position: Position,
-    pub note: Option<String>,
-    pub color: String,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+/// Link hints on screen
+#[derive(Debug, Clone, Default)]
+pub struct HintState {
+    /// Hint labels and the links they point at
+    pub labels: Vec<(String, usize)>,
+    /// Label keys typed so far
+    pub typed: String,
+    /// Hint picked by cycling with Tab
+    pub selected: usize,
+}
+
+impl HintState {
+    /// Hints whose labels start with what was typed
+    pub fn matching(&self) -> impl Iterator<Item = &(String, usize)> {
+        self.labels
+            .iter()
+            .filter(|(label, _)| label.starts_with(&self.typed))
+    }
+}
+
+/// Positions left by following links, for going back and forth
+#[derive(Debug, Clone, Default)]
+pub struct JumpList {
+    back: Vec<Position>,
+    forward: Vec<Position>,
+}
+
+impl JumpList {
+    /// Record `from` before jumping away from it
+    pub fn push(&mut self, from: Position) {
+        self.back.push(from);
+        self.forward.clear();
+        if self.back.len() > MAX_JUMPS {
+            self.back.remove(0);
+        }
+    }
+
+    /// Step back from `current`
+    pub fn back(&mut self, current: Position) -> Option<Position> {
+        let position = self.back.pop()?;
+        self.forward.push(current);
+        Some(position)
+    }
+
+    /// Step forward again from `current`
+    pub fn forward(&mut self, current: Position) -> Option<Position> {
+        let position = self.forward.pop()?;
+        self.back.push(current);
+        Some(position)
+    }
+}
+
+/// What the next key names a mark for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkOp {
+    Set,
+    Jump,
 }
 
 /// Main application state
 pub struct AppState {
     /// The book being read
-    pub book: Book,
+    pub book: LazyBook,
 
     /// Current reading position
     pub position: Position,
@@ -142,12 +226,91 @@ pub struct AppState {
     /// Fullscreen mode (hide all UI)
     pub fullscreen: bool,
 
-    /// Current theme name
-    pub theme: String,
+    /// Colors of the current theme
+    pub theme: ThemeConfig,
 
     /// Rendered lines cache
     pub lines_cache: Vec<RenderedLine>,
 
+    /// Link targets in the lines cache
+    pub links: Vec<String>,
+
+    /// Link hints while picking a link
+    pub hints: HintState,
+
+    /// Positions to return to after following links
+    pub jumps: JumpList,
+
+    /// Key maps for normal mode
+    pub keys: Dispatcher,
+
+    /// Count typed before a motion, like the 5 in `5j`
+    pub count: Option<usize>,
+
+    /// Set when the next key names a mark
+    pub mark_op: Option<MarkOp>,
+
+    /// Lowercase marks in this book
+    pub marks: BTreeMap<char, Mark>,
+
+    /// The library, kept even when the book is not in it
+    pub library: Option<Library>,
+
+    /// The book's ID in the library, when it is in there
+    pub book_id: Option<String>,
+
+    /// A book to open in place of this one, at a chapter and block
+    pub open_book: Option<(PathBuf, usize, usize)>,
+
+    /// Highlighted entry of the table of contents
+    pub toc_selected: usize,
+
+    /// Highlighted entry of the bookmark list
+    pub bookmark_selected: usize,
+
+    /// Highlighted entry of the annotation list
+    pub annotation_selected: usize,
+
+    /// Text selected in visual mode
+    pub selection: Selection,
+
+    /// Index into the highlight colors for the next highlight
+    pub highlight_color: usize,
+
+    /// A highlight waiting for its note
+    pub pending_highlight: Option<TextRange>,
+
+    /// Set when the pending highlight's note is to be written in `$EDITOR`
+    pub edit_note: bool,
+
+    /// Scrolled or paged layout
+    pub layout: LayoutMode,
+
+    /// First line of each page, when paged
+    pub pages: Vec<usize>,
+
+    /// Lines per page
+    pub page_height: usize,
+
+    /// Pages shown side by side
+    pub spread: usize,
+
+    /// Lines on screen, per column, with the screen area of their text
+    pub view: Vec<(Range<usize>, Rect)>,
+
+    /// Lines kept on screen above and below the visual mode cursor
+    pub scrolloff: usize,
+
+    /// Chapter and block last written to the library
+    pub saved_at: (usize, usize),
+
+    /// Block to scroll to once the lines cache is rebuilt
+    pub jump_block: Option<usize>,
+
+    /// Images laid out in the lines cache
+    #[cfg(feature = "image-support")]
+    pub graphics: super::graphics::Graphics,
+
     /// Total lines in current chapter
     pub total_lines: usize,
 
@@ -193,6 +356,21 @@ pub struct RenderedLine {
     pub is_code: bool,
     /// Search highlights (start, end)
     pub highlights: Vec<(usize, usize)>,
+    /// Syntax colors (start, end, style)
+    pub colors: Vec<(usize, usize, Style)>,
+    /// Links (start, end, index into the state's links)
+    pub links: Vec<(usize, usize, usize)>,
+    /// Where the line starts in its block's text, and the length of any
+    /// prefix (such as a quote bar) before that text; `None` when the line
+    /// cannot be selected
+    pub source: Option<(usize, usize)>,
+    /// Extra cells drawn after spaces to justify the line (byte offset of
+    /// the space, cells)
+    pub stretch: Vec<(usize, usize)>,
+    /// Whether a hyphen is drawn after the text, the line breaking a word
+    pub hyphen: bool,
+    /// Whether the line belongs to a right-to-left paragraph
+    pub rtl: bool,
 }
 
 impl RenderedLine {
@@ -206,12 +384,67 @@ impl RenderedLine {
             is_quote: false,
             is_code: false,
             highlights: Vec::new(),
+            colors: Vec::new(),
+            links: Vec::new(),
+            source: None,
+            stretch: Vec::new(),
+            hyphen: false,
+            rtl: false,
         }
     }
+
+    /// Extra cells drawn after the character at a byte offset
+    pub fn stretch_at(&self, col: usize) -> usize {
+        self.stretch
+            .iter()
+            .find(|&&(at, _)| at == col)
+            .map_or(0, |&(_, cells)| cells)
+    }
+
+    /// Byte ranges of the line in the order they are shown, each with
+    /// whether it runs right to left; any prefix comes first
+    pub fn visual_runs(&self) -> Vec<(Range<usize>, bool)> {
+        let prefix = self
+            .source
+            .map_or(0, |(_, prefix)| prefix.min(self.text.len()));
+        let runs = bidi::visual_runs(&self.text[prefix..], self.rtl)
+            .into_iter()
+            .map(|(run, rtl)| (run.start + prefix..run.end + prefix, rtl));
+        (prefix > 0)
+            .then_some((0..prefix, false))
+            .into_iter()
+            .chain(runs)
+            .collect()
+    }
+
+    /// Characters of the line, with their byte offsets, in the order they
+    /// are shown
+    pub fn visual_chars(&self) -> Vec<(usize, char)> {
+        let mut chars = Vec::new();
+        for (run, rtl) in self.visual_runs() {
+            let start = chars.len();
+            let run_chars = self.text[run.clone()].char_indices();
+            chars.extend(run_chars.map(|(i, c)| (run.start + i, c)));
+            if rtl {
+                chars[start..].reverse();
+            }
+        }
+        chars
+    }
+
+    /// Display width of the line shown before the character at a byte
+    /// offset, stretch included
+    pub fn width_to(&self, col: usize) -> usize {
+        self.visual_chars()
+            .into_iter()
+            .take_while(|&(at, _)| at != col)
+            .map(|(at, c)| hyphenate::char_width(c) + self.stretch_at(at))
+            .sum()
+    }
 }
 
 impl AppState {
-    pub fn new(book: Book) -> Self {
+    pub fn new(book: LazyBook) -> Self {
         Self {
             book,
             position: Position::new(),
@@ -226,8 +459,35 @@ impl AppState {
             show_status_bar: true,
             show_line_numbers: false,
             fullscreen: false,
-            theme: "dark".to_string(),
+            theme: ThemeConfig::default(),
             lines_cache: Vec::new(),
+            links: Vec::new(),
+            hints: HintState::default(),
+            jumps: JumpList::default(),
+            keys: Dispatcher::new(&Keybindings::default()),
+            count: None,
+            mark_op: None,
+            marks: BTreeMap::new(),
+            library: None,
+            book_id: None,
+            open_book: None,
+            toc_selected: 0,
+            bookmark_selected: 0,
+            annotation_selected: 0,
+            selection: Selection::new((0, 0)),
+            highlight_color: 0,
+            pending_highlight: None,
+            edit_note: false,
+            layout: LayoutMode::Scroll,
+            pages: Vec::new(),
+            page_height: 0,
+            spread: 1,
+            view: Vec::new(),
+            scrolloff: 0,
+            saved_at: (0, 0),
+            jump_block: None,
+            #[cfg(feature = "image-support")]
+            graphics: super::graphics::Graphics::new("none"),
             total_lines: 0,
             terminal_size: (80, 24),
             message: None,
@@ -237,62 +497,171 @@ impl AppState {
         }
     }
 
-    /// Get current chapter
-    pub fn current_chapter(&self) -> Option<&Chapter> {
-        self.book.content.chapters.get(self.position.chapter)
+    /// Key map modes to look keys up in, most specific first
+    pub fn key_modes(&self) -> Vec<&'static str> {
+        if self.search.active {
+            vec!["search", keymap::NORMAL]
+        } else {
+            vec![keymap::NORMAL]
+        }
     }
 
-    /// Get current chapter mut
-    pub fn current_chapter_mut(&mut self) -> Option<&mut Chapter> {
-        self.book.content.chapters.get_mut(self.position.chapter)
+    /// Get current chapter, loading it if needed
+    pub fn current_chapter(&self) -> Option<Arc<Chapter>> {
+        self.book.chapter(self.position.chapter)
     }
 
-    /// Move to next chapter
-    pub fn next_chapter(&mut self) {
-        if self.position.chapter < self.book.content.chapters.len() - 1 {
-            self.position.chapter += 1;
-            self.position.block = 0;
-            self.position.scroll_offset = 0;
-            self.invalidate_cache();
-        }
+    /// Move n chapters forward
+    pub fn next_chapter(&mut self, n: usize) {
+        let last = self.book.chapter_count().saturating_sub(1);
+        self.go_to_chapter(self.position.chapter.saturating_add(n).min(last));
     }
 
-    /// Move to previous chapter
-    pub fn prev_chapter(&mut self) {
-        if self.position.chapter > 0 {
-            self.position.chapter -= 1;
-            self.position.block = 0;
-            self.position.scroll_offset = 0;
-            self.invalidate_cache();
+    /// Move n chapters back
+    pub fn prev_chapter(&mut self, n: usize) {
+        self.go_to_chapter(self.position.chapter.saturating_sub(n));
+    }
+
+    /// Open a chapter at its start, remembering where we were
+    pub fn go_to_chapter(&mut self, chapter: usize) {
+        if chapter == self.position.chapter || chapter >= self.book.chapter_count() {
+            return;
         }
+        self.push_jump();
+        self.position.chapter = chapter;
+        self.position.block = 0;
+        self.position.scroll_offset = 0;
+        self.invalidate_cache();
+    }
+
+    /// Record the current position in the jump list
+    pub fn push_jump(&mut self) {
+        self.jumps.push(self.position.clone());
     }
 
-    /// Scroll down by n lines
+    /// Block at the top of the screen
+    pub fn top_block(&self) -> usize {
+        self.lines_cache
+            .get(self.position.scroll_offset)
+            .map_or(self.position.block, |line| line.block_index)
+    }
+
+    /// Scroll down by n lines, or turn at least a page when paged
     pub fn scroll_down(&mut self, n: usize) {
+        if self.is_paged() {
+            let page = pages::page_of(&self.pages, self.position.scroll_offset + n);
+            let turned = pages::page_of(&self.pages, self.position.scroll_offset) + self.spread;
+            self.show_page(page.max(turned));
+            return;
+        }
         self.position.scroll_offset = self.position.scroll_offset.saturating_add(n);
         if self.position.scroll_offset >= self.total_lines.saturating_sub(1) {
             self.position.scroll_offset = self.total_lines.saturating_sub(1);
         }
     }
 
-    /// Scroll up by n lines
+    /// Scroll up by n lines, or turn at least a page back when paged
     pub fn scroll_up(&mut self, n: usize) {
+        if self.is_paged() {
+            let page = pages::page_of(&self.pages, self.position.scroll_offset.saturating_sub(n));
+            let turned = pages::page_of(&self.pages, self.position.scroll_offset)
+                .saturating_sub(self.spread);
+            self.show_page(page.min(turned));
+            return;
+        }
         self.position.scroll_offset = self.position.scroll_offset.saturating_sub(n);
     }
 
+    /// Whether the chapter is cut into pages
+    pub fn is_paged(&self) -> bool {
+        !self.pages.is_empty()
+    }
+
+    /// Show the spread of pages holding a page
+    fn show_page(&mut self, page: usize) {
+        let page = page.min(self.pages.len().saturating_sub(1));
+        let first = page / self.spread.max(1) * self.spread.max(1);
+        if let Some(&line) = self.pages.get(first) {
+            self.position.scroll_offset = line;
+        }
+    }
+
+    /// Move to the start of the spread of pages holding the top line
+    pub fn align_to_pages(&mut self) {
+        if self.is_paged() {
+            self.show_page(pages::page_of(&self.pages, self.position.scroll_offset));
+        }
+    }
+
+    /// Pages on screen, counting from 1, and the pages in the chapter
+    pub fn page_numbers(&self) -> Option<(Range<usize>, usize)> {
+        if !self.is_paged() {
+            return None;
+        }
+        let spread = self.spread.max(1);
+        let first = pages::page_of(&self.pages, self.position.scroll_offset) / spread * spread;
+        let last = (first + spread).min(self.pages.len());
+        Some((first + 1..last + 1, self.pages.len()))
+    }
+
+    /// Lines on screen
+    pub fn on_screen(&self) -> Range<usize> {
+        let len = self.lines_cache.len();
+        if let Some((shown, _)) = self.page_numbers() {
+            let last = pages::page_lines(&self.pages, shown.end - 2, self.page_height, len);
+            return self.pages[shown.start - 1]..last.end;
+        }
+        let start = self.position.scroll_offset.min(len);
+        start..(start + self.visible_height()).min(len)
+    }
+
+    /// Switch between scrolling and pages: `scroll`, `paged` or `dual`
+    pub fn set_layout(&mut self, name: &str) -> bool {
+        let pages = match self.layout {
+            LayoutMode::Paged(n) => n as u8,
+            LayoutMode::Scroll => 1,
+        };
+        let Some(layout) = LayoutMode::from_name(name, pages) else {
+            return false;
+        };
+        self.layout = layout;
+        self.jump_block = Some(self.top_block());
+        self.invalidate_cache();
+        true
+    }
+
     /// Go to top
     pub fn go_to_top(&mut self) {
+        self.push_jump();
         self.position.scroll_offset = 0;
     }
 
     /// Go to bottom
     pub fn go_to_bottom(&mut self) {
-        if self.total_lines > 0 {
+        if self.is_paged() {
+            self.push_jump();
+            self.show_page(self.pages.len() - 1);
+        } else if self.total_lines > 0 {
+            self.push_jump();
             let visible_height = self.visible_height();
             self.position.scroll_offset = self.total_lines.saturating_sub(visible_height);
         }
     }
 
+    /// Go to a line of the chapter, counting from 1
+    pub fn go_to_line(&mut self, line: usize) {
+        self.push_jump();
+        self.position.scroll_offset = line
+            .saturating_sub(1)
+            .min(self.total_lines.saturating_sub(1));
+    }
+
+    /// Go to a percentage of the way through the chapter
+    pub fn go_to_percent(&mut self, percent: usize) {
+        let line = (self.total_lines * percent.min(100)).div_ceil(100);
+        self.go_to_line(line.max(1));
+    }
+
     /// Page down
     pub fn page_down(&mut self) {
         let height = self.visible_height();
@@ -305,6 +674,35 @@ impl AppState {
         self.scroll_up(height.saturating_sub(2));
     }
 
+    /// Scroll to the start of the next block
+    pub fn next_block(&mut self) {
+        let scroll = self.position.scroll_offset;
+        let Some(top) = self.lines_cache.get(scroll).map(|l| l.block_index) else {
+            return;
+        };
+        if let Some(offset) = self.lines_cache[scroll..]
+            .iter()
+            .position(|l| l.block_index > top)
+        {
+            self.position.scroll_offset += offset;
+        }
+    }
+
+    /// Scroll to the start of the block above the top line
+    pub fn prev_block(&mut self) {
+        let scroll = self.position.scroll_offset.min(self.lines_cache.len());
+        let Some(above) = scroll
+            .checked_sub(1)
+            .map(|i| self.lines_cache[i].block_index)
+        else {
+            return;
+        };
+        self.position.scroll_offset = self.lines_cache[..scroll]
+            .iter()
+            .rposition(|l| l.block_index < above)
+            .map_or(0, |i| i + 1);
+    }
+
     /// Half page down
     pub fn half_page_down(&mut self) {
         let height = self.visible_height();
@@ -341,6 +739,13 @@ impl AppState {
     /// Invalidate the lines cache
     pub fn invalidate_cache(&mut self) {
         self.lines_cache.clear();
+        self.links.clear();
+        self.pages.clear();
+        self.view.clear();
+        // A selection does not survive the text being wrapped again
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+        }
     }
 
     /// Set mode
@@ -394,55 +799,440 @@ impl AppState {
         }
     }
 
-    /// Add a bookmark at current position
+    /// Add a bookmark at the top of the screen
     pub fn add_bookmark(&mut self, name: Option<String>) {
-        let id = uuid::Uuid::new_v4().to_string();
-        let name = name.unwrap_or_else(|| {
-            format!(
-                "Bookmark at Ch.{} P.{}",
-                self.position.chapter + 1,
-                self.position.block + 1
-            )
-        });
-
-        self.bookmarks.push(Bookmark {
-            id,
-            name,
-            position: self.position.clone(),
-            created_at: chrono::Utc::now(),
-        });
-
-        self.dirty = true;
-        self.show_message("Bookmark added".to_string(), MessageType::Success);
+        let (chapter, block) = (self.position.chapter, self.top_block());
+        let (Some(library), Some(id)) = (&mut self.library, &self.book_id) else {
+            self.bookmarks.push(Bookmark {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: name.unwrap_or_else(|| format!("Bookmark at Ch.{}", chapter + 1)),
+                chapter,
+                block,
+                created_at: chrono::Utc::now(),
+            });
+            self.show_message(
+                "Bookmark added for this session; the book is not in the library".to_string(),
+                MessageType::Warning,
+            );
+            return;
+        };
+
+        let added = library
+            .add_bookmark(id, name, chapter, block)
+            .and_then(|bookmark| library.save_book(id).map(|()| bookmark));
+        match added {
+            Ok(bookmark) => {
+                self.bookmarks.push(bookmark);
+                self.show_message("Bookmark added".to_string(), MessageType::Success);
+            }
+            Err(e) => self.show_message(format!("Cannot save bookmark: {}", e), MessageType::Error),
+        }
+    }
+
+    /// Add a note on the block at the top of the screen
+    pub fn add_annotation(&mut self, note: Option<String>) {
+        let (chapter, block) = (self.position.chapter, self.top_block());
+        let text = self
+            .current_chapter()
+            .and_then(|c| c.blocks.get(block).map(|b| b.text()))
+            .unwrap_or_default();
+        let (Some(library), Some(id)) = (&mut self.library, &self.book_id) else {
+            self.show_message(
+                "Add the book to the library to keep notes".to_string(),
+                MessageType::Error,
+            );
+            return;
+        };
+
+        let added = library
+            .add_annotation(id, text, note, chapter, block, None)
+            .and_then(|annotation| library.save_book(id).map(|()| annotation));
+        match added {
+            Ok(annotation) => {
+                self.annotations.push(annotation);
+                self.show_message("Note added".to_string(), MessageType::Success);
+            }
+            Err(e) => self.show_message(format!("Cannot save note: {}", e), MessageType::Error),
+        }
     }
 
     /// Go to a bookmark
     pub fn go_to_bookmark(&mut self, index: usize) {
-        if let Some(bookmark) = self.bookmarks.get(index) {
-            let position = bookmark.position.clone();
-            let name = bookmark.name.clone();
-            self.position = position;
+        if let Some(b) = self.bookmarks.get(index) {
+            let (chapter, block, name) = (b.chapter, b.block, b.name.clone());
+            self.go_to_block(chapter, block, &name);
+        }
+    }
+
+    /// Go to an annotation
+    pub fn go_to_annotation(&mut self, index: usize) {
+        if let Some(a) = self.annotations.get(index) {
+            let label = a.note.clone().unwrap_or_else(|| a.text.clone());
+            let (chapter, block) = (a.chapter, a.block);
+            self.go_to_block(chapter, block, &label);
+        }
+    }
+
+    /// Jump to the start of a block, remembering where we were
+    fn go_to_block(&mut self, chapter: usize, block: usize, label: &str) {
+        self.push_jump();
+        self.position.chapter = chapter;
+        self.position.block = block;
+        self.jump_block = Some(block);
+        self.invalidate_cache();
+        let label: String = label.chars().take(40).collect();
+        self.show_message(format!("Jumped to: {}", label), MessageType::Info);
+    }
+
+    /// Delete a bookmark, from the library too
+    pub fn remove_bookmark(&mut self, index: usize) {
+        if index >= self.bookmarks.len() {
+            return;
+        }
+        let bookmark = self.bookmarks.remove(index);
+        let removed = match (&mut self.library, &self.book_id) {
+            (Some(library), Some(id)) => library
+                .remove_bookmark(id, &bookmark.id)
+                .and_then(|()| library.save_book(id)),
+            _ => Ok(()),
+        };
+        self.bookmark_selected = self
+            .bookmark_selected
+            .min(self.bookmarks.len().saturating_sub(1));
+        self.report_removal(removed);
+    }
+
+    /// Delete an annotation, from the library too
+    pub fn remove_annotation(&mut self, index: usize) {
+        if index >= self.annotations.len() {
+            return;
+        }
+        let annotation = self.annotations.remove(index);
+        let removed = match (&mut self.library, &self.book_id) {
+            (Some(library), Some(id)) => library
+                .remove_annotation(id, &annotation.id)
+                .and_then(|()| library.save_book(id)),
+            _ => Ok(()),
+        };
+        self.annotation_selected = self
+            .annotation_selected
+            .min(self.annotations.len().saturating_sub(1));
+        if annotation.range.is_some() {
             self.invalidate_cache();
-            self.show_message(format!("Jumped to: {}", name), MessageType::Info);
         }
+        self.report_removal(removed);
+    }
+
+    fn report_removal(&mut self, removed: anyhow::Result<()>) {
+        match removed {
+            Ok(()) => self.show_message("Deleted".to_string(), MessageType::Info),
+            Err(e) => self.show_message(format!("Cannot delete: {}", e), MessageType::Error),
+        }
+    }
+
+    /// Enter visual mode with the cursor on the first text on screen
+    pub fn start_selection(&mut self) {
+        let mut screen = self.on_screen();
+        let top = screen.start;
+        let line = screen
+            .find(|&i| self.lines_cache[i].source.is_some())
+            .unwrap_or(top);
+        let col = self.lines_cache.get(line).map_or(0, visual::first_col);
+        self.selection = Selection::new((line, col));
+        self.set_mode(Mode::Visual);
+    }
+
+    /// Move the visual mode cursor n times, scrolling to keep it on screen
+    /// with `scrolloff` lines around it
+    pub fn move_cursor(&mut self, motion: Motion, n: usize) {
+        for _ in 0..n {
+            self.selection.cursor = visual::apply(&self.lines_cache, self.selection.cursor, motion);
+        }
+        let line = self.selection.cursor.0;
+        if self.is_paged() {
+            if !self.on_screen().contains(&line) {
+                self.show_page(pages::page_of(&self.pages, line));
+            }
+            return;
+        }
+        let height = self.visible_height().max(1);
+        let context = self.scrolloff.min((height - 1) / 2);
+        if line < self.position.scroll_offset + context {
+            self.position.scroll_offset = line.saturating_sub(context);
+        } else if line + context >= self.position.scroll_offset + height {
+            self.position.scroll_offset = (line + context + 1 - height)
+                .min(self.total_lines.saturating_sub(height))
+                .max(self.position.scroll_offset);
+        }
+    }
+
+    /// The cursor under a point on screen, if it is over the text
+    pub fn cursor_at(&self, column: u16, row: u16) -> Option<visual::Cursor> {
+        let (lines, area) = self.view.iter().find(|(_, area)| {
+            (area.x..area.x + area.width).contains(&column)
+                && (area.y..area.y + area.height).contains(&row)
+        })?;
+        let line = lines.start + (row - area.y) as usize;
+        (line < lines.end).then(|| visual::hit(&self.lines_cache, line, (column - area.x) as usize))
+    }
+
+    /// Leave visual mode with the selected range, if it covers any text
+    pub fn take_selection(&mut self) -> Option<TextRange> {
+        let range = self.selection.range(&self.lines_cache);
+        self.set_mode(Mode::Normal);
+        if range.is_none() {
+            self.show_message("Nothing selected".to_string(), MessageType::Warning);
+        }
+        range
+    }
+
+    /// Use the next highlight color
+    pub fn cycle_highlight_color(&mut self) {
+        self.highlight_color = (self.highlight_color + 1) % visual::COLORS.len();
+        let color = visual::COLORS[self.highlight_color];
+        self.show_message(format!("Highlight color: {}", color), MessageType::Info);
+    }
+
+    /// Highlight a range of the current chapter, with an optional note
+    pub fn add_highlight(&mut self, range: TextRange, note: Option<String>) {
+        let chapter = self.position.chapter;
+        let text = self.range_text(&range);
+        let color = visual::COLORS[self.highlight_color % visual::COLORS.len()].to_string();
+        let (Some(library), Some(id)) = (&mut self.library, &self.book_id) else {
+            self.show_message(
+                "Add the book to the library to keep highlights".to_string(),
+                MessageType::Error,
+            );
+            return;
+        };
+
+        let added = library
+            .add_highlight(id, chapter, range, text, note, color)
+            .and_then(|annotation| library.save_book(id).map(|()| annotation));
+        match added {
+            Ok(annotation) => {
+                self.annotations.push(annotation);
+                self.invalidate_cache();
+                self.show_message("Highlight added".to_string(), MessageType::Success);
+            }
+            Err(e) => {
+                self.show_message(format!("Cannot save highlight: {}", e), MessageType::Error)
+            }
+        }
+    }
+
+    /// Text of a range of the current chapter, one line per block
+    fn range_text(&self, range: &TextRange) -> String {
+        let Some(chapter) = self.current_chapter() else {
+            return String::new();
+        };
+        let blocks = chapter.blocks.iter().enumerate();
+        blocks
+            .take(range.end_block + 1)
+            .skip(range.start_block)
+            .filter_map(|(i, block)| {
+                let text = block.text();
+                let (start, end) = range.within(i, text.len())?;
+                text.get(start..end).map(str::to_string)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Save the top of the screen under a mark
+    ///
+    /// Lowercase marks belong to this book and uppercase ones to the whole
+    /// library; both are saved to the library when the book is in it.
+    pub fn set_mark(&mut self, name: char) {
+        let (chapter, block) = (self.position.chapter, self.top_block());
+        if name.is_ascii_lowercase() {
+            let mark = Mark {
+                chapter,
+                block,
+                created_at: chrono::Utc::now(),
+            };
+            self.marks.insert(name, mark);
+        } else if !name.is_ascii_uppercase() {
+            self.show_message(format!("Not a mark name: {}", name), MessageType::Error);
+            return;
+        }
+
+        let saved = match (&mut self.library, &self.book_id) {
+            (Some(library), Some(id)) => library
+                .set_mark(id, name, chapter, block)
+                .and_then(|()| library.save_book(id)),
+            _ if name.is_ascii_uppercase() => Err(anyhow::anyhow!(
+                "add the book to the library to use global marks"
+            )),
+            _ => Ok(()),
+        };
+        match saved {
+            Ok(()) => self.show_message(format!("Mark {} set", name), MessageType::Success),
+            Err(e) => self.show_message(
+                format!("Cannot save mark {}: {}", name, e),
+                MessageType::Error,
+            ),
+        }
+    }
+
+    /// Jump to a mark, which may be in another book
+    ///
+    /// The mark names `'` and `` ` `` go back to the last jump, as in Vim.
+    pub fn go_to_mark(&mut self, name: char) {
+        if name == '\'' || name == '`' {
+            self.jump_back();
+            return;
+        }
+
+        let found = match &self.library {
+            Some(library) if name.is_ascii_uppercase() => library
+                .get_mark(self.book_id.as_deref().unwrap_or_default(), name)
+                .map(|(book, mark)| (library.get_book(&book).map(|b| b.path), mark)),
+            _ => self.marks.get(&name).map(|mark| (None, mark.clone())),
+        };
+        let Some((book, mark)) = found else {
+            self.show_message(format!("Mark {} is not set", name), MessageType::Warning);
+            return;
+        };
+
+        match book {
+            Some(path) if path != self.book.skeleton.source_path => {
+                self.open_book = Some((path, mark.chapter, mark.block));
+            }
+            _ => {
+                self.push_jump();
+                self.position.chapter = mark.chapter;
+                self.position.block = mark.block;
+                self.jump_block = Some(mark.block);
+                self.invalidate_cache();
+            }
+        }
+    }
+
+    /// Keep the library, reading the book's marks and position from it if
+    /// the book is in there
+    pub fn attach_library(&mut self, mut library: Library) {
+        self.marks.clear();
+        self.book_id = None;
+        if let Some(entry) = library.find_book(&self.book.skeleton.source_path) {
+            if entry.position_chapter < self.book.chapter_count() {
+                self.position.chapter = entry.position_chapter;
+                self.position.block = entry.position_block;
+                self.jump_block = Some(entry.position_block);
+                self.invalidate_cache();
+            }
+            self.marks = entry.marks;
+            self.bookmarks = entry.bookmarks;
+            self.annotations = entry.annotations;
+            self.saved_at = (entry.position_chapter, entry.position_block);
+            self.book_id = Some(entry.id);
+        }
+        self.library = Some(library);
+    }
+
+    /// Write the reading position to the library
+    ///
+    /// Does nothing when the book is not in the library or the position
+    /// is unchanged since the last save, unless `force` is set.
+    pub fn save_progress(&mut self, force: bool) -> anyhow::Result<()> {
+        let at = (self.position.chapter, self.top_block());
+        if !force && at == self.saved_at {
+            return Ok(());
+        }
+        self.position.block = at.1;
+        let progress = self.progress();
+        let (Some(library), Some(id)) = (&mut self.library, &self.book_id) else {
+            anyhow::bail!("the book is not in the library");
+        };
+        library.update_progress(id, at.0, at.1, 0, progress)?;
+        library.save_book(id)?;
+        self.saved_at = at;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Replace the book being read, opening it at a chapter and block
+    pub fn switch_book(&mut self, book: LazyBook, chapter: usize, block: usize) {
+        // A book outside the library has no progress to keep
+        let saved = match self.book_id {
+            Some(_) => self.save_progress(false),
+            None => Ok(()),
+        };
+        self.book = book;
+        self.jumps = JumpList::default();
+        self.search.clear();
+        self.bookmarks.clear();
+        self.annotations.clear();
+        if let Some(library) = self.library.take() {
+            self.attach_library(library);
+        }
+        self.position = Position {
+            chapter: chapter.min(self.book.chapter_count().saturating_sub(1)),
+            block,
+            ..Default::default()
+        };
+        self.jump_block = Some(block);
+        self.invalidate_cache();
+        match saved {
+            Ok(()) => {
+                let title = self.book.skeleton.metadata.title.clone();
+                self.show_message(format!("Opened {}", title), MessageType::Info);
+            }
+            Err(e) => self.show_message(
+                format!("Failed to save progress: {}", e),
+                MessageType::Error,
+            ),
+        }
+    }
+
+    /// Switch to a theme file or built-in theme
+    pub fn set_theme(&mut self, name: &str) {
+        match ThemeRegistry::load().get(name) {
+            Ok(theme) => {
+                self.theme = theme;
+                self.invalidate_cache();
+                self.show_message(format!("Theme: {}", name), MessageType::Info);
+            }
+            Err(e) => self.show_message(e.to_string(), MessageType::Error),
+        }
+    }
+
+    /// Switch to the theme after the current one
+    pub fn next_theme(&mut self) {
+        let names = ThemeRegistry::load().names();
+        let next = match names.iter().position(|n| *n == self.theme.active) {
+            Some(i) => &names[(i + 1) % names.len()],
+            None => &names[0],
+        };
+        self.set_theme(&next.clone());
+    }
+
+    /// Show the table of contents with the current chapter highlighted
+    pub fn open_toc(&mut self) {
+        self.toc_selected = self.position.chapter;
+        self.set_mode(Mode::TableOfContents);
     }
 
     /// Calculate reading progress (0.0 - 1.0)
     pub fn progress(&self) -> f64 {
-        let total_chapters = self.book.content.chapters.len();
+        let total_chapters = self.book.chapter_count();
         if total_chapters == 0 {
             return 0.0;
         }
 
+        // The end of the last chapter is on screen
+        let at_end = self.total_lines > 0 && self.on_screen().end >= self.total_lines;
+        if at_end && self.position.chapter + 1 == total_chapters {
+            return 1.0;
+        }
+
         let chapter_progress = self.position.chapter as f64 / total_chapters as f64;
-        let block_progress = if let Some(chapter) = self.current_chapter() {
-            if chapter.blocks.is_empty() {
-                0.0
-            } else {
-                self.position.block as f64 / chapter.blocks.len() as f64 / total_chapters as f64
+        // Lazily opened books only estimate the blocks of unread chapters
+        let block_progress = match self.current_chapter() {
+            Some(chapter) if !chapter.blocks.is_empty() => {
+                self.top_block() as f64 / chapter.blocks.len() as f64 / total_chapters as f64
             }
-        } else {
-            0.0
+            _ => 0.0,
         };
 
         (chapter_progress + block_progress).min(1.0)
@@ -453,6 +1243,97 @@ impl AppState {
         format!("{:.1}%", self.progress() * 100.0)
     }
 
+    /// Label the links on screen and start picking one
+    pub fn start_hints(&mut self) {
+        let mut visible: Vec<usize> = Vec::new();
+        for line in &self.lines_cache[self.on_screen()] {
+            for &(_, _, link) in &line.links {
+                if !visible.contains(&link) {
+                    visible.push(link);
+                }
+            }
+        }
+
+        if visible.is_empty() {
+            self.show_message("No links on screen".to_string(), MessageType::Warning);
+            return;
+        }
+        self.hints = HintState {
+            labels: hint_labels(visible.len())
+                .into_iter()
+                .zip(visible)
+                .collect(),
+            ..Default::default()
+        };
+        self.set_mode(Mode::Hint);
+    }
+
+    /// Follow a link from the lines cache
+    ///
+    /// URLs open in the default browser; links into the book jump to their
+    /// chapter and block, remembering where they left from.
+    pub fn follow_link(&mut self, link: usize) {
+        let Some(href) = self.links.get(link).cloned() else {
+            return;
+        };
+        if href.contains(':') {
+            match open::that(&href) {
+                Ok(()) => self.show_message(format!("Opened {}", href), MessageType::Info),
+                Err(e) => {
+                    self.show_message(format!("Cannot open {}: {}", href, e), MessageType::Error)
+                }
+            }
+            return;
+        }
+
+        let (id, fragment) = href.split_once('#').unwrap_or((&href, ""));
+        let chapter = if id.is_empty() {
+            Some(self.position.chapter)
+        } else {
+            self.book.chapters().iter().position(|c| c.id == id)
+        };
+        let Some(chapter) = chapter else {
+            self.show_message(
+                format!("Link target not found: {}", href),
+                MessageType::Warning,
+            );
+            return;
+        };
+        let block = self
+            .book
+            .chapter(chapter)
+            .and_then(|c| c.anchor(fragment))
+            .unwrap_or(0);
+
+        self.push_jump();
+        self.position.chapter = chapter;
+        self.position.block = block;
+        self.jump_block = Some(block);
+        self.invalidate_cache();
+    }
+
+    /// Go back to where the last link was followed from
+    pub fn jump_back(&mut self) {
+        match self.jumps.back(self.position.clone()) {
+            Some(position) => self.jump_to(position),
+            None => self.show_message("No earlier position".to_string(), MessageType::Info),
+        }
+    }
+
+    /// Undo a jump back
+    pub fn jump_forward(&mut self) {
+        match self.jumps.forward(self.position.clone()) {
+            Some(position) => self.jump_to(position),
+            None => self.show_message("No later position".to_string(), MessageType::Info),
+        }
+    }
+
+    fn jump_to(&mut self, position: Position) {
+        self.position = position;
+        self.jump_block = None;
+        self.invalidate_cache();
+    }
+
     /// Perform search
     pub fn perform_search(&mut self) {
         self.search.results.clear();
@@ -464,7 +1345,10 @@ impl AppState {
 
         let query_lower = self.search.query.to_lowercase();
 
-        for (chapter_idx, chapter) in self.book.content.chapters.iter().enumerate() {
+        for chapter_idx in 0..self.book.chapter_count() {
+            let Some(chapter) = self.book.chapter(chapter_idx) else {
+                continue;
+            };
             for (block_idx, block) in chapter.blocks.iter().enumerate() {
                 let text = block.text();
                 let text_lower = text.to_lowercase();
@@ -499,8 +1383,10 @@ impl AppState {
         if let Some(&(chapter_idx, block_idx, _, _)) =
             self.search.results.get(self.search.current_result)
         {
+            self.push_jump();
             self.position.chapter = chapter_idx;
             self.position.block = block_idx;
+            self.jump_block = Some(block_idx);
             self.invalidate_cache();
         }
     }
@@ -517,3 +1403,214 @@ impl AppState {
         self.go_to_search_result();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{Book, BookContent};
+
+    fn state_with_chapters(n: usize) -> AppState {
+        let book = Book {
+            metadata: Default::default(),
+            content: BookContent {
+                chapters: (0..n)
+                    .map(|i| Chapter::new(format!("ch{}", i), i))
+                    .collect(),
+                toc: vec![],
+            },
+            source_path: PathBuf::from("book.epub"),
+            format: "epub".to_string(),
+        };
+        AppState::new(LazyBook::from_book(book))
+    }
+
+    #[test]
+    fn test_resume_from_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.md");
+        std::fs::write(&path, "# One\n\nFirst.\n\n# Two\n\nSecond.\n\nThird.\n").unwrap();
+        let mut config = crate::config::Config::default();
+        config.library.database_path = Some(dir.path().join("library.db"));
+
+        let mut library = Library::new(&config).unwrap();
+        let id = library.add_book(&path, None).unwrap().id;
+        library.save().unwrap();
+
+        let mut state = AppState::new(crate::formats::open_lazy(&path).unwrap());
+        state.attach_library(Library::new(&config).unwrap());
+        state.position.block = 2;
+        state.save_progress(false).unwrap();
+        state.add_bookmark(Some("here".to_string()));
+        state.add_annotation(Some("nice".to_string()));
+        state.add_annotation(None);
+        let range = TextRange {
+            start_block: 3,
+            start: 3,
+            end_block: 4,
+            end: 3,
+        };
+        state.add_highlight(range, Some("why".to_string()));
+
+        let entry = Library::new(&config).unwrap().get_book(&id).unwrap();
+        assert_eq!((entry.position_chapter, entry.position_block), (0, 2));
+        assert!(entry.progress > 0.0 && entry.last_read.is_some());
+
+        // A new session picks up where this one stopped
+        let mut state = AppState::new(crate::formats::open_lazy(&path).unwrap());
+        state.attach_library(Library::new(&config).unwrap());
+        assert_eq!((state.position.block, state.jump_block), (2, Some(2)));
+
+        // Bookmarks and notes come along, and deleting one deletes it for good
+        assert_eq!(state.bookmarks[0].name, "here");
+        assert_eq!(state.annotations[0].note.as_deref(), Some("nice"));
+        assert_eq!(state.annotations[1].text, "Two");
+        assert_eq!(state.annotations[2].text, "ond.\nThi");
+        assert_eq!(state.annotations[2].range, Some(range));
+        assert_eq!(state.annotations[2].color, "yellow");
+        state.remove_annotation(0);
+        let entry = Library::new(&config).unwrap().get_book(&id).unwrap();
+        assert_eq!((entry.bookmarks.len(), entry.annotations.len()), (1, 2));
+
+        // A book outside the library keeps the library, for global marks
+        let other = dir.path().join("other.md");
+        std::fs::write(&other, "# Other\n\nText.\n").unwrap();
+        let mut state = AppState::new(crate::formats::open_lazy(&other).unwrap());
+        state.attach_library(Library::new(&config).unwrap());
+        assert!(state.library.is_some() && state.book_id.is_none());
+
+        // Following a global mark from it has no progress to save
+        state.switch_book(crate::formats::open_lazy(&path).unwrap(), 0, 1);
+        assert_eq!(state.book_id.as_deref(), Some(id.as_str()));
+        assert!(state.message.as_ref().unwrap().0.starts_with("Opened"));
+    }
+
+    #[test]
+    fn test_counts_marks_and_jumps() {
+        let mut state = state_with_chapters(5);
+        state.next_chapter(3);
+        assert_eq!(state.position.chapter, 3);
+        state.next_chapter(9);
+        assert_eq!(state.position.chapter, 4);
+        state.prev_chapter(9);
+        assert_eq!(state.position.chapter, 0);
+        state.jump_back();
+        assert_eq!(state.position.chapter, 4);
+
+        // Marks remember the chapter and block, and `''` goes back again
+        state.position.block = 7;
+        state.set_mark('a');
+        state.go_to_chapter(1);
+        state.go_to_mark('a');
+        assert_eq!((state.position.chapter, state.jump_block), (4, Some(7)));
+        state.go_to_mark('\'');
+        assert_eq!(state.position.chapter, 1);
+
+        // Global marks need the library
+        state.set_mark('A');
+        assert!(matches!(state.message, Some((_, MessageType::Error))));
+        assert!(!state.marks.contains_key(&'A'));
+
+        state.total_lines = 200;
+        state.go_to_percent(50);
+        assert_eq!(state.position.scroll_offset, 99);
+        state.go_to_line(1000);
+        assert_eq!(state.position.scroll_offset, 199);
+    }
+
+    #[test]
+    fn test_scrolloff() {
+        let mut state = state_with_chapters(1);
+        state.lines_cache = (0..50)
+            .map(|i| {
+                let mut line = RenderedLine::empty(i);
+                line.text = "text".to_string();
+                line.source = Some((0, 0));
+                line
+            })
+            .collect();
+        state.total_lines = 50;
+        state.scrolloff = 5;
+
+        // The cursor keeps five lines below it on screen, then above it
+        state.move_cursor(Motion::Down, 16);
+        assert_eq!(state.position.scroll_offset, 2);
+        state.move_cursor(Motion::Up, 10);
+        assert_eq!(state.position.scroll_offset, 1);
+        state.move_cursor(Motion::Down, 40);
+        assert_eq!(state.position.scroll_offset, 30);
+    }
+
+    #[test]
+    fn test_visual_order() {
+        let mut line = RenderedLine::empty(0);
+        line.text = "  אב cd".to_string();
+        line.source = Some((0, 2));
+        line.rtl = true;
+
+        // The prefix comes first, then the runs of a right-to-left line
+        // from right to left, while offsets stay logical
+        let chars: String = line.visual_chars().iter().map(|&(_, c)| c).collect();
+        assert_eq!(chars, "  cd בא");
+        assert_eq!(line.visual_chars()[2], (7, 'c'));
+        assert_eq!(line.width_to(2), 6);
+        assert_eq!(line.width_to(7), 2);
+    }
+
+    #[test]
+    fn test_page_turns() {
+        let mut state = state_with_chapters(1);
+        state.lines_cache = (0..50).map(RenderedLine::empty).collect();
+        state.total_lines = 50;
+        state.pages = vec![0, 10, 20, 30, 40];
+        state.page_height = 10;
+        state.spread = 2;
+
+        state.scroll_down(1);
+        assert_eq!(state.position.scroll_offset, 20);
+        assert_eq!(state.page_numbers(), Some((3..5, 5)));
+        assert_eq!(state.on_screen(), 20..40);
+        state.scroll_down(25);
+        assert_eq!(state.position.scroll_offset, 40);
+        assert_eq!(state.on_screen(), 40..50);
+        state.scroll_down(1);
+        assert_eq!(state.position.scroll_offset, 40);
+        state.page_up();
+        assert_eq!(state.position.scroll_offset, 20);
+
+        // Other motions land anywhere and are brought back to a spread
+        state.go_to_line(15);
+        state.align_to_pages();
+        assert_eq!(state.position.scroll_offset, 0);
+        state.go_to_bottom();
+        assert_eq!(state.position.scroll_offset, 40);
+    }
+
+    #[test]
+    fn test_hint_labels() {
+        assert_eq!(hint_labels(3), vec!["a", "s", "d"]);
+        let labels = hint_labels(12);
+        assert_eq!(labels[..3], ["aa", "as", "ad"]);
+        assert_eq!(labels[9..], ["sa", "ss", "sd"]);
+        assert_eq!(hint_labels(0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_jump_list() {
+        let at = |chapter| Position {
+            chapter,
+            ..Default::default()
+        };
+        let mut jumps = JumpList::default();
+        jumps.push(at(0));
+        jumps.push(at(3));
+
+        assert_eq!(jumps.back(at(5)).map(|p| p.chapter), Some(3));
+        assert_eq!(jumps.back(at(3)).map(|p| p.chapter), Some(0));
+        assert!(jumps.back(at(0)).is_none());
+        assert_eq!(jumps.forward(at(0)).map(|p| p.chapter), Some(3));
+
+        // A new jump drops the positions ahead
+        jumps.push(at(3));
+        assert!(jumps.forward(at(7)).is_none());
+    }
+}
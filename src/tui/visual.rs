@@ -0,0 +1,426 @@
+//! Visual mode: a character cursor and selection over the rendered lines
+
+use crate::config::theme::Color;
+use crate::hyphenate;
+use crate::library::TextRange;
+use crate::tui::state::RenderedLine;
+use unicode_width::UnicodeWidthChar;
+
+/// A position in the rendered lines: line index and byte column
+pub type Cursor = (usize, usize);
+
+/// Highlight colors cycled through in visual mode
+pub const COLORS: [&str; 5] = ["yellow", "green", "blue", "red", "purple"];
+
+/// Terminal color for a highlight color, named or in hex
+pub fn color(name: &str) -> ratatui::style::Color {
+    Color::named(name).to_ratatui()
+}
+
+/// Lines scanned on either side of the cursor by word and sentence motions
+const SCAN_LINES: usize = 200;
+
+/// Cursor motions in visual mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    SentenceForward,
+    SentenceBackward,
+}
+
+/// A selection between a fixed anchor and the moving cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Cursor,
+    pub cursor: Cursor,
+}
+
+impl Selection {
+    pub fn new(at: Cursor) -> Self {
+        Self {
+            anchor: at,
+            cursor: at,
+        }
+    }
+
+    /// The ends of the selection in reading order
+    pub fn ordered(&self) -> (Cursor, Cursor) {
+        if self.anchor <= self.cursor {
+            (self.anchor, self.cursor)
+        } else {
+            (self.cursor, self.anchor)
+        }
+    }
+
+    /// Swap the anchor and the cursor
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.anchor, &mut self.cursor);
+    }
+
+    /// Selected bytes of a line, end exclusive
+    pub fn on_line(&self, lines: &[RenderedLine], index: usize) -> Option<(usize, usize)> {
+        let ((first, from), (last, to)) = self.ordered();
+        if index < first || index > last {
+            return None;
+        }
+        let line = lines.get(index)?;
+        let start = if index == first {
+            from
+        } else {
+            first_col(line)
+        };
+        let end = if index == last {
+            char_end(&line.text, to)
+        } else {
+            line.text.len()
+        };
+        (start < end).then_some((start, end))
+    }
+
+    /// The selection as a range of block text, if it covers any
+    pub fn range(&self, lines: &[RenderedLine]) -> Option<TextRange> {
+        let ((first, from), (last, to)) = self.ordered();
+        let start = (first..=last).find_map(|i| {
+            let line = lines.get(i)?;
+            let (offset, prefix) = line.source?;
+            let col = if i == first { from.max(prefix) } else { prefix };
+            (col < line.text.len()).then_some((line.block_index, offset + col - prefix))
+        })?;
+        let end = (first..=last).rev().find_map(|i| {
+            let line = lines.get(i)?;
+            let (offset, prefix) = line.source?;
+            let col = if i == last {
+                char_end(&line.text, to)
+            } else {
+                line.text.len()
+            };
+            (col > prefix).then_some((line.block_index, offset + col - prefix))
+        })?;
+        (start < end).then_some(TextRange {
+            start_block: start.0,
+            start: start.1,
+            end_block: end.0,
+            end: end.1,
+        })
+    }
+}
+
+/// The bytes of a line covered by a range of block text
+pub fn line_span(line: &RenderedLine, range: &TextRange) -> Option<(usize, usize)> {
+    let (offset, prefix) = line.source?;
+    let (start, end) = range.within(line.block_index, usize::MAX)?;
+    let len = line.text.len() - prefix;
+    let start = start.max(offset) - offset;
+    let end = end.saturating_sub(offset).min(len);
+    (start < end).then_some((start + prefix, end + prefix))
+}
+
+/// Where the cursor lands after a motion
+pub fn apply(lines: &[RenderedLine], at: Cursor, motion: Motion) -> Cursor {
+    let Some(line) = lines.get(at.0) else {
+        return at;
+    };
+    let text = &line.text;
+    // The lines may have been rewrapped since the cursor was placed
+    let at = if text.is_char_boundary(at.1) {
+        at
+    } else {
+        (at.0, first_col(line))
+    };
+    let width: usize = text
+        .get(first_col(line)..at.1)
+        .map_or(0, |before| before.chars().filter_map(|c| c.width()).sum());
+    match motion {
+        Motion::Left => match text[..at.1].char_indices().next_back() {
+            Some((col, _)) if col >= first_col(line) => (at.0, col),
+            _ => at,
+        },
+        Motion::Right => {
+            let col = char_end(text, at.1);
+            if col <= last_col(line) {
+                (at.0, col)
+            } else {
+                at
+            }
+        }
+        Motion::Up if at.0 > 0 => (at.0 - 1, col_at(&lines[at.0 - 1], width)),
+        Motion::Down if at.0 + 1 < lines.len() => (at.0 + 1, col_at(&lines[at.0 + 1], width)),
+        Motion::Up | Motion::Down => at,
+        Motion::LineStart => (at.0, first_col(line)),
+        Motion::LineEnd => (at.0, last_col(line)),
+        Motion::WordForward => {
+            let chars = scan(lines, at.0, (at.0 + SCAN_LINES).min(lines.len() - 1));
+            let mut i = chars
+                .iter()
+                .position(|&(c, _)| c >= at)
+                .unwrap_or(chars.len());
+            if let Some(&(_, ch)) = chars.get(i) {
+                let kind = class(ch);
+                while kind != 0 && chars.get(i).is_some_and(|&(_, c)| class(c) == kind) {
+                    i += 1;
+                }
+            }
+            while chars.get(i).is_some_and(|&(_, c)| c.is_whitespace()) {
+                i += 1;
+            }
+            chars.get(i).map_or(at, |&(c, _)| c)
+        }
+        Motion::WordEnd => {
+            let chars = scan(lines, at.0, (at.0 + SCAN_LINES).min(lines.len() - 1));
+            let mut i = chars
+                .iter()
+                .position(|&(c, _)| c > at)
+                .unwrap_or(chars.len());
+            while chars.get(i).is_some_and(|&(_, c)| c.is_whitespace()) {
+                i += 1;
+            }
+            let Some(&(_, ch)) = chars.get(i) else {
+                return at;
+            };
+            while chars
+                .get(i + 1)
+                .is_some_and(|&(_, c)| class(c) == class(ch))
+            {
+                i += 1;
+            }
+            chars[i].0
+        }
+        Motion::WordBackward => {
+            let chars = scan(lines, at.0.saturating_sub(SCAN_LINES), at.0);
+            let Some(mut i) = chars.iter().rposition(|&(c, _)| c < at) else {
+                return at;
+            };
+            while i > 0 && chars[i].1.is_whitespace() {
+                i -= 1;
+            }
+            let ch = chars[i].1;
+            if ch.is_whitespace() {
+                return at;
+            }
+            while i > 0 && class(chars[i - 1].1) == class(ch) {
+                i -= 1;
+            }
+            chars[i].0
+        }
+        Motion::SentenceForward => {
+            let chars = scan(lines, at.0, (at.0 + SCAN_LINES).min(lines.len() - 1));
+            sentence_starts(&chars)
+                .into_iter()
+                .map(|i| chars[i].0)
+                .find(|&c| c > at)
+                .unwrap_or(at)
+        }
+        Motion::SentenceBackward => {
+            let chars = scan(lines, at.0.saturating_sub(SCAN_LINES), at.0);
+            sentence_starts(&chars)
+                .into_iter()
+                .rev()
+                .map(|i| chars[i].0)
+                .find(|&c| c < at)
+                .unwrap_or(at)
+        }
+    }
+}
+
+/// The cursor under a display column of a line, as when clicked, counting
+/// the stretch of justified lines and right-to-left runs shown reversed
+pub fn hit(lines: &[RenderedLine], index: usize, column: usize) -> Cursor {
+    let index = index.min(lines.len().saturating_sub(1));
+    match lines.get(index) {
+        Some(line) => {
+            let mut width = 0;
+            let col = line
+                .visual_chars()
+                .into_iter()
+                .find(|&(col, c)| {
+                    width += hyphenate::char_width(c) + line.stretch_at(col);
+                    width > column
+                })
+                .map_or(line.text.len(), |(col, _)| col);
+            (index, col.clamp(first_col(line), last_col(line)))
+        }
+        None => (0, 0),
+    }
+}
+
+/// The first column the cursor can rest on, past any prefix
+pub fn first_col(line: &RenderedLine) -> usize {
+    line.source
+        .map_or(0, |(_, prefix)| prefix.min(line.text.len()))
+}
+
+/// The last column the cursor can rest on
+fn last_col(line: &RenderedLine) -> usize {
+    match line.text.char_indices().next_back() {
+        Some((col, _)) => col.max(first_col(line)),
+        None => 0,
+    }
+}
+
+/// Column on a line at a display width from its first column
+fn col_at(line: &RenderedLine, width: usize) -> usize {
+    let first = first_col(line);
+    let mut seen = 0;
+    line.text[first..]
+        .char_indices()
+        .find(|&(_, c)| {
+            seen += c.width().unwrap_or(0);
+            seen > width
+        })
+        .map_or(last_col(line), |(col, _)| first + col)
+}
+
+/// Byte just past the character at a column
+fn char_end(text: &str, col: usize) -> usize {
+    text.get(col..)
+        .and_then(|rest| rest.chars().next())
+        .map_or(col.min(text.len()), |c| col + c.len_utf8())
+}
+
+/// Characters of a run of lines, each line ending in a newline
+fn scan(lines: &[RenderedLine], first: usize, last: usize) -> Vec<(Cursor, char)> {
+    let mut chars = Vec::new();
+    for (index, line) in lines.iter().enumerate().take(last + 1).skip(first) {
+        if line.source.is_some() {
+            let first = first_col(line);
+            chars.extend(
+                line.text[first..]
+                    .char_indices()
+                    .map(|(col, c)| ((index, first + col), c)),
+            );
+        }
+        chars.push(((index, line.text.len()), '\n'));
+    }
+    chars
+}
+
+/// Word motions stop where the class changes: space, word or punctuation
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Indices of the characters that start a sentence or a paragraph
+fn sentence_starts(chars: &[(Cursor, char)]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut boundary = true;
+    let mut ended = false;
+    let mut newlines = 0;
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        if c.is_whitespace() {
+            boundary |= ended;
+            if c == '\n' {
+                newlines += 1;
+                boundary |= newlines > 1;
+            }
+            continue;
+        }
+        newlines = 0;
+        if boundary {
+            starts.push(i);
+            boundary = false;
+        }
+        ended =
+            matches!(c, '.' | '!' | '?') || (ended && matches!(c, '"' | '\'' | ')' | '”' | '’'));
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of one paragraph block, then a blank line
+    fn paragraph(lines: &[&str]) -> Vec<RenderedLine> {
+        let mut offset = 0;
+        let mut rendered: Vec<RenderedLine> = lines
+            .iter()
+            .map(|text| {
+                let mut line = RenderedLine::empty(0);
+                line.text = text.to_string();
+                line.source = Some((offset, 0));
+                offset += text.len() + 1;
+                line
+            })
+            .collect();
+        rendered.push(RenderedLine::empty(0));
+        rendered
+    }
+
+    #[test]
+    fn test_motions() {
+        let lines = paragraph(&["One two, three. Four", "five! Six"]);
+        let walk = |from: Cursor, motion: Motion, times: usize| {
+            (0..times).fold(from, |at, _| apply(&lines, at, motion))
+        };
+
+        assert_eq!(walk((0, 0), Motion::WordForward, 1), (0, 4));
+        assert_eq!(walk((0, 0), Motion::WordForward, 2), (0, 7));
+        assert_eq!(walk((0, 0), Motion::WordForward, 5), (0, 16));
+        assert_eq!(walk((0, 16), Motion::WordForward, 1), (1, 0));
+        assert_eq!(walk((1, 0), Motion::WordBackward, 1), (0, 16));
+        assert_eq!(walk((0, 0), Motion::WordEnd, 1), (0, 2));
+        assert_eq!(walk((0, 2), Motion::WordEnd, 1), (0, 6));
+
+        assert_eq!(walk((0, 0), Motion::SentenceForward, 1), (0, 16));
+        assert_eq!(walk((0, 0), Motion::SentenceForward, 2), (1, 6));
+        assert_eq!(walk((1, 8), Motion::SentenceBackward, 1), (1, 6));
+        assert_eq!(walk((1, 6), Motion::SentenceBackward, 1), (0, 16));
+
+        assert_eq!(walk((0, 0), Motion::Right, 3), (0, 3));
+        assert_eq!(walk((0, 3), Motion::LineEnd, 1), (0, 19));
+        assert_eq!(walk((0, 19), Motion::Down, 1), (1, 8));
+        assert_eq!(walk((0, 0), Motion::Left, 1), (0, 0));
+        assert_eq!(walk((1, 8), Motion::Down, 1), (2, 0));
+    }
+
+    #[test]
+    fn test_selection_range() {
+        let mut lines = paragraph(&["One two, three.", "Four five"]);
+        lines.extend(paragraph(&["Next"]).into_iter().map(|mut line| {
+            line.block_index = 1;
+            line
+        }));
+
+        let mut selection = Selection::new((1, 5));
+        selection.cursor = (0, 4);
+        assert_eq!(selection.on_line(&lines, 0), Some((4, 15)));
+        assert_eq!(selection.on_line(&lines, 1), Some((0, 6)));
+        let range = selection.range(&lines).unwrap();
+        assert_eq!(
+            range,
+            TextRange {
+                start_block: 0,
+                start: 4,
+                end_block: 0,
+                end: 22,
+            }
+        );
+        assert_eq!(line_span(&lines[1], &range), Some((0, 6)));
+
+        // Blank lines between blocks are left out of the range
+        selection.swap();
+        selection.cursor = (3, 1);
+        let range = selection.range(&lines).unwrap();
+        assert_eq!((range.start_block, range.start), (0, 4));
+        assert_eq!((range.end_block, range.end), (1, 2));
+        assert_eq!(line_span(&lines[2], &range), None);
+        assert_eq!(line_span(&lines[0], &range), Some((4, 15)));
+
+        assert_eq!(hit(&lines, 0, 5), (0, 5));
+        assert_eq!(hit(&lines, 1, 40), (1, 8));
+    }
+}
@@ -1,6 +1,9 @@
 //! Book-related API handlers
 
 use super::types::*;
+use crate::formats::LazyBook;
+use crate::highlight::Highlighter;
+use crate::library::LibraryEntry;
 use crate::web::AppState;
 use axum::{
     body::Body,
@@ -11,9 +14,6 @@ use axum::{
 };
 use std::sync::Arc;
 
-/// Supported book extensions
-const BOOK_EXTENSIONS: &[&str] = &["epub", "pdf", "md", "markdown", "txt", "text"];
-
 /// List all books
 pub async fn list_books(
     State(state): State<Arc<AppState>>,
@@ -137,26 +137,22 @@ pub async fn scan_folder(
 
     for entry in walker.into_iter().filter_map(|e| e.ok()) {
         let file_path = entry.path();
-        if file_path.is_file() {
-            if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
-                if BOOK_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
-                    match library.add_book(file_path, Some(tags.clone())) {
-                        Ok(entry) => {
-                            books.push(BookSummary {
-                                id: entry.id.clone(),
-                                title: entry.metadata.title.clone(),
-                                authors: entry.metadata.authors.clone(),
-                                format: entry.format.clone(),
-                                progress: 0.0,
-                                cover_url: None,
-                            });
-                            added += 1;
-                        }
-                        Err(e) => {
-                            errors.push(format!("{}: {}", file_path.display(), e));
-                            failed += 1;
-                        }
-                    }
+        if file_path.is_file() && crate::formats::is_book_file(file_path) {
+            match library.add_book(file_path, Some(tags.clone())) {
+                Ok(entry) => {
+                    books.push(BookSummary {
+                        id: entry.id.clone(),
+                        title: entry.metadata.title.clone(),
+                        authors: entry.metadata.authors.clone(),
+                        format: entry.format.clone(),
+                        progress: 0.0,
+                        cover_url: None,
+                    });
+                    added += 1;
+                }
+                Err(e) => {
+                    errors.push(format!("{}: {}", file_path.display(), e));
+                    failed += 1;
                 }
             }
         }
@@ -185,31 +181,59 @@ pub async fn get_book(
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => {
-            // Parse the book to get chapter count
-            let chapter_count = if let Ok(book) = crate::formats::parse_book(&entry.path) {
-                book.content.chapters.len()
-            } else {
-                0
-            };
+        Some(entry) => Json(ApiResponse::ok(book_detail(&state, &entry))),
+        None => Json(ApiResponse::err("Book not found")),
+    }
+}
 
-            Json(ApiResponse::ok(BookDetail {
-                id: entry.id.clone(),
-                title: entry.metadata.title.clone(),
-                authors: entry.metadata.authors.clone(),
-                publisher: entry.metadata.publisher.clone(),
-                published: entry.metadata.published.clone(),
-                description: entry.metadata.description.clone(),
-                language: entry.metadata.language.clone(),
-                subjects: entry.metadata.subjects.clone(),
-                format: entry.format.clone(),
-                word_count: entry.metadata.word_count,
-                reading_time: entry.metadata.reading_time,
-                chapter_count,
-                progress: entry.progress,
-            }))
+/// Edit a book's metadata, optionally writing it into the book file
+pub async fn update_metadata(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<MetadataRequest>,
+) -> Json<ApiResponse<BookDetail>> {
+    let mut library = state.library.write().await;
+
+    match library.update_metadata(&id, &request.edit, request.write_file) {
+        Ok(entry) => {
+            if let Err(e) = library.save() {
+                return Json(ApiResponse::err(format!(
+                    "Metadata updated but failed to save library: {}",
+                    e
+                )));
+            }
+            Json(ApiResponse::ok(book_detail(&state, &entry)))
         }
-        None => Json(ApiResponse::err("Book not found")),
+        Err(e) => Json(ApiResponse::err(e.to_string())),
+    }
+}
+
+/// Build the detail view of a library entry
+fn book_detail(state: &AppState, entry: &LibraryEntry) -> BookDetail {
+    // Parse the book to get chapter count
+    let chapter_count = if let Ok(book) = state.cache.open(&entry.path) {
+        book.chapter_count()
+    } else {
+        0
+    };
+
+    BookDetail {
+        id: entry.id.clone(),
+        title: entry.metadata.title.clone(),
+        authors: entry.metadata.authors.clone(),
+        publisher: entry.metadata.publisher.clone(),
+        published: entry.metadata.published.clone(),
+        description: entry.metadata.description.clone(),
+        language: entry.metadata.language.clone(),
+        subjects: entry.metadata.subjects.clone(),
+        series: entry.metadata.series.clone(),
+        series_index: entry.metadata.series_index,
+        tags: entry.tags.clone(),
+        format: entry.format.clone(),
+        word_count: entry.metadata.word_count,
+        reading_time: entry.metadata.reading_time,
+        chapter_count,
+        progress: entry.progress,
     }
 }
 
@@ -234,31 +258,16 @@ pub async fn get_book_content(
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => match crate::formats::parse_book(&entry.path) {
+        Some(entry) => match state.cache.open(&entry.path) {
             Ok(book) => {
-                let chapters: Vec<ChapterContent> = book
-                    .content
-                    .chapters
-                    .iter()
-                    .enumerate()
-                    .map(|(i, ch)| {
-                        let content_html = super::helpers::chapter_to_html(ch);
-                        ChapterContent {
-                            id: ch.id.clone(),
-                            title: ch.title.clone(),
-                            number: ch.number,
-                            content_html,
-                            word_count: ch.word_count(),
-                            prev_chapter: if i > 0 {
-                                Some(book.content.chapters[i - 1].id.clone())
-                            } else {
-                                None
-                            },
-                            next_chapter: book.content.chapters.get(i + 1).map(|c| c.id.clone()),
-                        }
-                    })
+                let highlighter = Highlighter::new(&state.config.formats.markdown);
+                let chapters: anyhow::Result<Vec<ChapterContent>> = (0..book.chapter_count())
+                    .map(|i| chapter_content(&book, Some(&id), i, &highlighter))
                     .collect();
-                Json(ApiResponse::ok(chapters))
+                match chapters {
+                    Ok(chapters) => Json(ApiResponse::ok(chapters)),
+                    Err(e) => Json(ApiResponse::err(e.to_string())),
+                }
             }
             Err(e) => Json(ApiResponse::err(e.to_string())),
         },
@@ -274,36 +283,62 @@ pub async fn get_chapter(
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => match crate::formats::parse_book(&entry.path) {
-            Ok(book) => match book.content.chapters.get(chapter_idx) {
-                Some(ch) => {
-                    let content_html = super::helpers::chapter_to_html(ch);
-                    Json(ApiResponse::ok(ChapterContent {
-                        id: ch.id.clone(),
-                        title: ch.title.clone(),
-                        number: ch.number,
-                        content_html,
-                        word_count: ch.word_count(),
-                        prev_chapter: if chapter_idx > 0 {
-                            Some(book.content.chapters[chapter_idx - 1].id.clone())
-                        } else {
-                            None
-                        },
-                        next_chapter: book
-                            .content
-                            .chapters
-                            .get(chapter_idx + 1)
-                            .map(|c| c.id.clone()),
-                    }))
+        Some(entry) => match state.cache.open(&entry.path) {
+            Ok(book) if chapter_idx < book.chapter_count() => {
+                let highlighter = Highlighter::new(&state.config.formats.markdown);
+                match chapter_content(&book, Some(&id), chapter_idx, &highlighter) {
+                    Ok(content) => Json(ApiResponse::ok(content)),
+                    Err(e) => Json(ApiResponse::err(e.to_string())),
                 }
-                None => Json(ApiResponse::err("Chapter not found")),
-            },
+            }
+            Ok(_) => Json(ApiResponse::err("Chapter not found")),
             Err(e) => Json(ApiResponse::err(e.to_string())),
         },
         None => Json(ApiResponse::err("Book not found")),
     }
 }
 
+/// Get a chapter of the book opened in single-book mode
+pub async fn get_current_chapter(
+    State(state): State<Arc<AppState>>,
+    Path(chapter_idx): Path<usize>,
+) -> Json<ApiResponse<ChapterContent>> {
+    match state.current_book {
+        Some(ref book) if chapter_idx < book.chapter_count() => {
+            let highlighter = Highlighter::new(&state.config.formats.markdown);
+            match chapter_content(book, None, chapter_idx, &highlighter) {
+                Ok(content) => Json(ApiResponse::ok(content)),
+                Err(e) => Json(ApiResponse::err(e.to_string())),
+            }
+        }
+        Some(_) => Json(ApiResponse::err("Chapter not found")),
+        None => Json(ApiResponse::err("No book is open")),
+    }
+}
+
+/// Load a chapter and render it for the API
+fn chapter_content(
+    book: &LazyBook,
+    book_id: Option<&str>,
+    index: usize,
+    highlighter: &Highlighter,
+) -> anyhow::Result<ChapterContent> {
+    let ch = book.load_chapter(index)?;
+    let chapters = book.chapters();
+    Ok(ChapterContent {
+        id: ch.id.clone(),
+        title: ch.title.clone(),
+        number: ch.number,
+        content_html: super::helpers::chapter_to_html(book_id, &ch, highlighter),
+        word_count: ch.word_count(),
+        prev_chapter: index
+            .checked_sub(1)
+            .and_then(|i| chapters.get(i))
+            .map(|c| c.id.clone()),
+        next_chapter: chapters.get(index + 1).map(|c| c.id.clone()),
+    })
+}
+
 /// Get book cover image
 pub async fn get_book_cover(
     State(state): State<Arc<AppState>>,
@@ -341,6 +376,37 @@ pub async fn get_book_cover(
     }
 }
 
+/// Serve an image or other resource referenced by a book
+pub async fn get_book_resource(
+    State(state): State<Arc<AppState>>,
+    Path((id, src)): Path<(String, String)>,
+) -> Response {
+    let library = state.library.read().await;
+
+    match library.get_book(&id) {
+        Some(entry) => match crate::formats::load_resource(&entry.path, &src) {
+            Ok(Some((data, mime))) => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, mime)
+                .header(header::CACHE_CONTROL, "public, max-age=86400")
+                .body(Body::from(data))
+                .unwrap(),
+            Ok(None) => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Resource not found"))
+                .unwrap(),
+            Err(e) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("Error loading resource: {}", e)))
+                .unwrap(),
+        },
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Book not found"))
+            .unwrap(),
+    }
+}
+
 /// Serve the raw PDF file for the PDF viewer
 pub async fn get_pdf_file(
     State(state): State<Arc<AppState>>,
@@ -1,6 +1,7 @@
-//! Miscellaneous API handlers (bookmarks, annotations, statistics, settings)
+//! Miscellaneous API handlers (bookmarks, annotations, statistics, settings, themes)
 
 use super::types::*;
+use crate::config::{registry, ThemeRegistry};
 use crate::web::AppState;
 use axum::{
     extract::{Path, State},
@@ -125,6 +126,19 @@ pub async fn get_settings(
     ))
 }
 
+/// List the built-in and user themes
+pub async fn list_themes() -> Json<ApiResponse<Vec<ThemeSummary>>> {
+    let themes = ThemeRegistry::load()
+        .names()
+        .into_iter()
+        .map(|name| ThemeSummary {
+            builtin: registry::is_builtin(&name),
+            name,
+        })
+        .collect();
+    Json(ApiResponse::ok(themes))
+}
+
 /// Update settings
 pub async fn update_settings(
     State(_state): State<Arc<AppState>>,
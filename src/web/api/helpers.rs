@@ -1,15 +1,27 @@
 //! API helper functions
 
-use crate::formats::{Chapter, ContentBlock};
+use crate::formats::{math, writer, Chapter, ContentBlock};
+use crate::highlight::Highlighter;
 
 /// Convert a chapter to HTML
-pub fn chapter_to_html(chapter: &Chapter) -> String {
+///
+/// Relative image sources of library books are pointed at the book's
+/// resource endpoint so images are only loaded when the browser displays them.
+pub fn chapter_to_html(
+    book_id: Option<&str>,
+    chapter: &Chapter,
+    highlighter: &Highlighter,
+) -> String {
     let mut html = String::new();
 
     for block in &chapter.blocks {
         match block {
-            ContentBlock::Paragraph { text, .. } => {
-                html.push_str(&format!("<p>{}</p>\n", escape_html(text)));
+            ContentBlock::Paragraph { text, styles } => {
+                html.push_str(&format!("<p>{}</p>\n", writer::inline_html(text, styles)));
+            }
+            ContentBlock::Math { tex } => {
+                html.push_str(&math::to_mathml(tex, true));
+                html.push('\n');
             }
             ContentBlock::Heading { level, text } => {
                 html.push_str(&format!("<h{}>{}</h{}>\n", level, escape_html(text), level));
@@ -23,15 +35,8 @@ pub fn chapter_to_html(chapter: &Chapter) -> String {
                 html.push_str("</blockquote>\n");
             }
             ContentBlock::Code { language, code } => {
-                let lang_attr = language
-                    .as_ref()
-                    .map(|l| format!(" class=\"language-{}\"", l))
-                    .unwrap_or_default();
-                html.push_str(&format!(
-                    "<pre><code{}>{}</code></pre>\n",
-                    lang_attr,
-                    escape_html(code)
-                ));
+                html.push_str(&highlighter.html(code, language.as_deref()));
+                html.push('\n');
             }
             ContentBlock::List { ordered, items } => {
                 let tag = if *ordered { "ol" } else { "ul" };
@@ -52,7 +57,28 @@ pub fn chapter_to_html(chapter: &Chapter) -> String {
                     .as_ref()
                     .map(|a| format!(" alt=\"{}\"", escape_html(a)))
                     .unwrap_or_default();
-                html.push_str(&format!("<img src=\"{}\"{}>\n", escape_html(src), alt_attr));
+                let src = match book_id {
+                    Some(id)
+                        if !src.contains("://")
+                            && !src.starts_with("data:")
+                            && !src.starts_with('/') =>
+                    {
+                        format!(
+                            "/api/books/{}/resource/{}",
+                            encode_path_segment(id),
+                            src.split('/')
+                                .map(encode_path_segment)
+                                .collect::<Vec<_>>()
+                                .join("/")
+                        )
+                    }
+                    _ => src.clone(),
+                };
+                html.push_str(&format!(
+                    "<img src=\"{}\"{} loading=\"lazy\">\n",
+                    escape_html(&src),
+                    alt_attr
+                ));
                 if let Some(cap) = caption {
                     html.push_str(&format!("<figcaption>{}</figcaption>\n", escape_html(cap)));
                 }
@@ -81,6 +107,20 @@ pub fn chapter_to_html(chapter: &Chapter) -> String {
 }
 
 /// Escape HTML special characters
+/// Percent-encode one segment of a URL path
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
 fn escape_html(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -21,10 +21,12 @@ pub fn router() -> Router<Arc<AppState>> {
         .route("/books", post(books::add_book))
         .route("/books/:id", get(books::get_book))
         .route("/books/:id", delete(books::remove_book))
+        .route("/books/:id/metadata", put(books::update_metadata))
         .route("/books/:id/content", get(books::get_book_content))
         .route("/books/:id/chapter/:chapter", get(books::get_chapter))
         .route("/books/:id/cover", get(books::get_book_cover))
         .route("/books/:id/pdf", get(books::get_pdf_file))
+        .route("/books/:id/resource/*src", get(books::get_book_resource))
         // Progress API
         .route("/books/:id/progress", get(progress::get_progress))
         .route("/books/:id/progress", post(progress::save_progress))
@@ -47,6 +49,8 @@ pub fn router() -> Router<Arc<AppState>> {
             "/books/:id/annotations/:annotation_id",
             delete(handlers::remove_annotation),
         )
+        // Single-book mode
+        .route("/current/chapter/:chapter", get(books::get_current_chapter))
         // Search API
         .route("/search", get(search::search_library))
         .route("/books/:id/search", get(search::search_book))
@@ -58,4 +62,6 @@ pub fn router() -> Router<Arc<AppState>> {
         // Settings API
         .route("/settings", get(handlers::get_settings))
         .route("/settings", put(handlers::update_settings))
+        // Themes API
+        .route("/themes", get(handlers::list_themes))
 }
@@ -41,12 +41,16 @@ pub async fn search_book(
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => match crate::formats::parse_book(&entry.path) {
+        Some(entry) => match state.cache.open(&entry.path) {
             Ok(book) => {
                 let mut results = Vec::new();
                 let query_lower = query.q.to_lowercase();
 
-                for (ch_idx, chapter) in book.content.chapters.iter().enumerate() {
+                for ch_idx in 0..book.chapter_count() {
+                    let chapter = match book.load_chapter(ch_idx) {
+                        Ok(chapter) => chapter,
+                        Err(e) => return Json(ApiResponse::err(e.to_string())),
+                    };
                     for (block_idx, block) in chapter.blocks.iter().enumerate() {
                         let text = block.text();
                         if text.to_lowercase().contains(&query_lower) {
@@ -1,5 +1,6 @@
 //! API response and request types
 
+use crate::formats::MetadataEdit;
 use serde::{Deserialize, Serialize};
 
 /// Generic API response wrapper
@@ -50,6 +51,9 @@ pub struct BookDetail {
     pub description: Option<String>,
     pub language: Option<String>,
     pub subjects: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub tags: Vec<String>,
     pub format: String,
     pub word_count: Option<usize>,
     pub reading_time: Option<usize>,
@@ -86,6 +90,17 @@ pub struct AddBookRequest {
     pub tags: Option<Vec<String>>,
 }
 
+/// Request to edit a book's metadata
+#[derive(Deserialize)]
+pub struct MetadataRequest {
+    #[serde(flatten)]
+    pub edit: MetadataEdit,
+
+    /// Also write the changes into the book file
+    #[serde(default)]
+    pub write_file: bool,
+}
+
 /// Request to scan a folder for books
 #[derive(Deserialize)]
 pub struct ScanFolderRequest {
@@ -153,3 +168,10 @@ pub struct SearchResult {
     pub block_index: usize,
     pub snippet: String,
 }
+
+/// Theme listing entry
+#[derive(Serialize)]
+pub struct ThemeSummary {
+    pub name: String,
+    pub builtin: bool,
+}
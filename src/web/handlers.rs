@@ -14,6 +14,14 @@ pub struct ReaderQuery {
     chapter: Option<usize>,
 }
 
+/// Chapter API used by single-book mode
+const CURRENT_CHAPTER_URL: &str = "/api/current/chapter/{}";
+
+/// Chapter API for a library book, with `{}` standing for the chapter index
+fn chapter_url(book_id: &str) -> String {
+    format!("/api/books/{}/chapter/{{}}", book_id)
+}
+
 pub async fn index(State(state): State<Arc<AppState>>) -> Html<String> {
     let library = state.library.read().await;
     let books = library.books();
@@ -46,8 +54,13 @@ pub async fn reader(
                 ));
             }
 
-            match crate::formats::parse_book(&entry.path) {
-                Ok(book) => Html(templates::reader(&state.config, &book, 0)),
+            match state.cache.open(&entry.path) {
+                Ok(book) => Html(templates::reader(
+                    &state.config,
+                    &book,
+                    &chapter_url(&entry.id),
+                    0,
+                )),
                 Err(e) => Html(templates::error(&format!("Failed to parse book: {}", e))),
             }
         }
@@ -62,8 +75,13 @@ pub async fn reader_chapter(
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => match crate::formats::parse_book(&entry.path) {
-            Ok(book) => Html(templates::reader(&state.config, &book, chapter)),
+        Some(entry) => match state.cache.open(&entry.path) {
+            Ok(book) => Html(templates::reader(
+                &state.config,
+                &book,
+                &chapter_url(&entry.id),
+                chapter,
+            )),
             Err(e) => Html(templates::error(&format!("Failed to parse book: {}", e))),
         },
         None => Html(templates::error("Book not found")),
@@ -74,7 +92,7 @@ pub async fn book_info(State(state): State<Arc<AppState>>, Path(id): Path<String
     let library = state.library.read().await;
 
     match library.get_book(&id) {
-        Some(entry) => match crate::formats::parse_book(&entry.path) {
+        Some(entry) => match state.cache.open(&entry.path) {
             Ok(book) => Html(templates::book_info(&state.config, &book)),
             Err(e) => Html(templates::error(&format!("Failed to parse book: {}", e))),
         },
@@ -84,9 +102,13 @@ pub async fn book_info(State(state): State<Arc<AppState>>, Path(id): Path<String
 
 /// Handler for single-book mode (when opening a book directly with --web)
 pub async fn single_book_reader(State(state): State<Arc<AppState>>) -> Html<String> {
-    if let Some(ref book_lock) = state.current_book {
-        let book = book_lock.read().await;
-        Html(templates::reader(&state.config, &book, 0))
+    if let Some(ref book) = state.current_book {
+        Html(templates::reader(
+            &state.config,
+            book,
+            CURRENT_CHAPTER_URL,
+            0,
+        ))
     } else {
         Html(templates::error("No book loaded"))
     }
@@ -97,9 +119,13 @@ pub async fn single_book_chapter(
     State(state): State<Arc<AppState>>,
     Path(chapter): Path<usize>,
 ) -> Html<String> {
-    if let Some(ref book_lock) = state.current_book {
-        let book = book_lock.read().await;
-        Html(templates::reader(&state.config, &book, chapter))
+    if let Some(ref book) = state.current_book {
+        Html(templates::reader(
+            &state.config,
+            book,
+            CURRENT_CHAPTER_URL,
+            chapter,
+        ))
     } else {
         Html(templates::error("No book loaded"))
     }
@@ -7,8 +7,9 @@ mod handlers;
 mod static_files;
 mod templates;
 
+use crate::cache::BookCache;
 use crate::config::Config;
-use crate::formats::Book;
+use crate::formats::LazyBook;
 use anyhow::Result;
 use axum::{routing::get, Router};
 use std::net::SocketAddr;
@@ -24,7 +25,8 @@ use tracing::info;
 pub struct AppState {
     pub config: Config,
     pub library: Arc<RwLock<crate::library::Library>>,
-    pub current_book: Option<Arc<RwLock<Book>>>,
+    pub cache: BookCache,
+    pub current_book: Option<Arc<LazyBook>>,
 }
 
 /// Start the web server
@@ -35,6 +37,7 @@ pub async fn serve(config: &Config, bind: String, port: u16) -> Result<()> {
     let state = Arc::new(AppState {
         config: config.clone(),
         library: Arc::new(RwLock::new(library)),
+        cache: BookCache::new(config)?,
         current_book: None,
     });
 
@@ -74,7 +77,7 @@ pub async fn serve(config: &Config, bind: String, port: u16) -> Result<()> {
 }
 
 /// Start the web server with a single book preloaded
-pub async fn serve_book(book: Book, config: &Config) -> Result<()> {
+pub async fn serve_book(book: LazyBook, config: &Config) -> Result<()> {
     let library = crate::library::Library::new(config)?;
     let port = config.web.port;
     let bind = config.web.bind.clone();
@@ -82,7 +85,8 @@ pub async fn serve_book(book: Book, config: &Config) -> Result<()> {
     let state = Arc::new(AppState {
         config: config.clone(),
         library: Arc::new(RwLock::new(library)),
-        current_book: Some(Arc::new(RwLock::new(book))),
+        cache: BookCache::new(config)?,
+        current_book: Some(Arc::new(book)),
     });
 
     // Build router for single-book mode
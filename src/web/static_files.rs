@@ -1,10 +1,13 @@
 //! Static file serving
 
+use crate::config::ThemeRegistry;
 use axum::{
     body::Body,
     extract::Path,
     http::{header, Response, StatusCode},
 };
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 /// Serve embedded static files
 pub async fn serve_static(Path(path): Path<String>) -> Response<Body> {
@@ -20,6 +23,14 @@ pub async fn serve_static(Path(path): Path<String>) -> Response<Body> {
         _ => "application/octet-stream",
     };
 
+    if let Some(css) = theme_stylesheet(&path) {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(css))
+            .unwrap();
+    }
+
     let content = match path.as_str() {
         // Legacy monolithic files (kept for backward compatibility)
         "style.css" => Some(include_str!("../../assets/style.css")),
@@ -66,111 +77,8 @@ pub async fn serve_static(Path(path): Path<String>) -> Response<Body> {
         "css/pages/_settings.css" => Some(include_str!("../../assets/css/pages/_settings.css")),
         "css/pages/_book-info.css" => Some(include_str!("../../assets/css/pages/_book-info.css")),
 
-        // Themes
-        "css/themes/_index.css" => Some(include_str!("../../assets/css/themes/_index.css")),
-        "css/themes/light.css" => Some(include_str!("../../assets/css/themes/light.css")),
-        "css/themes/sepia.css" => Some(include_str!("../../assets/css/themes/sepia.css")),
+        // Themes: the index and each theme's stylesheet are generated
         "css/themes/custom.css" => Some(include_str!("../../assets/css/themes/custom.css")),
-        "css/themes/tokyo-night.css" => {
-            Some(include_str!("../../assets/css/themes/tokyo-night.css"))
-        }
-        "css/themes/dracula.css" => Some(include_str!("../../assets/css/themes/dracula.css")),
-        "css/themes/nord.css" => Some(include_str!("../../assets/css/themes/nord.css")),
-        "css/themes/one-dark.css" => Some(include_str!("../../assets/css/themes/one-dark.css")),
-        "css/themes/monokai.css" => Some(include_str!("../../assets/css/themes/monokai.css")),
-        "css/themes/solarized-dark.css" => {
-            Some(include_str!("../../assets/css/themes/solarized-dark.css"))
-        }
-        "css/themes/solarized-light.css" => {
-            Some(include_str!("../../assets/css/themes/solarized-light.css"))
-        }
-        "css/themes/gruvbox-dark.css" => {
-            Some(include_str!("../../assets/css/themes/gruvbox-dark.css"))
-        }
-        "css/themes/gruvbox-light.css" => {
-            Some(include_str!("../../assets/css/themes/gruvbox-light.css"))
-        }
-        "css/themes/catppuccin-mocha.css" => {
-            Some(include_str!("../../assets/css/themes/catppuccin-mocha.css"))
-        }
-        "css/themes/catppuccin-macchiato.css" => Some(include_str!(
-            "../../assets/css/themes/catppuccin-macchiato.css"
-        )),
-        "css/themes/catppuccin-frappe.css" => Some(include_str!(
-            "../../assets/css/themes/catppuccin-frappe.css"
-        )),
-        "css/themes/catppuccin-latte.css" => {
-            Some(include_str!("../../assets/css/themes/catppuccin-latte.css"))
-        }
-        "css/themes/github-dark.css" => {
-            Some(include_str!("../../assets/css/themes/github-dark.css"))
-        }
-        "css/themes/github-light.css" => {
-            Some(include_str!("../../assets/css/themes/github-light.css"))
-        }
-        "css/themes/rose-pine.css" => Some(include_str!("../../assets/css/themes/rose-pine.css")),
-        "css/themes/rose-pine-moon.css" => {
-            Some(include_str!("../../assets/css/themes/rose-pine-moon.css"))
-        }
-        "css/themes/rose-pine-dawn.css" => {
-            Some(include_str!("../../assets/css/themes/rose-pine-dawn.css"))
-        }
-        "css/themes/everforest-dark.css" => {
-            Some(include_str!("../../assets/css/themes/everforest-dark.css"))
-        }
-        "css/themes/everforest-light.css" => {
-            Some(include_str!("../../assets/css/themes/everforest-light.css"))
-        }
-        "css/themes/kanagawa.css" => Some(include_str!("../../assets/css/themes/kanagawa.css")),
-        "css/themes/high-contrast.css" => {
-            Some(include_str!("../../assets/css/themes/high-contrast.css"))
-        }
-        "css/themes/amoled.css" => Some(include_str!("../../assets/css/themes/amoled.css")),
-        "css/themes/paper.css" => Some(include_str!("../../assets/css/themes/paper.css")),
-        "css/themes/material-dark.css" => {
-            Some(include_str!("../../assets/css/themes/material-dark.css"))
-        }
-        "css/themes/night-owl.css" => Some(include_str!("../../assets/css/themes/night-owl.css")),
-        "css/themes/night-owl-light.css" => {
-            Some(include_str!("../../assets/css/themes/night-owl-light.css"))
-        }
-        "css/themes/atom-one-dark.css" => {
-            Some(include_str!("../../assets/css/themes/atom-one-dark.css"))
-        }
-        "css/themes/atom-one-light.css" => {
-            Some(include_str!("../../assets/css/themes/atom-one-light.css"))
-        }
-        "css/themes/palenight.css" => Some(include_str!("../../assets/css/themes/palenight.css")),
-        "css/themes/shades-of-purple.css" => {
-            Some(include_str!("../../assets/css/themes/shades-of-purple.css"))
-        }
-        "css/themes/ayu-dark.css" => Some(include_str!("../../assets/css/themes/ayu-dark.css")),
-        "css/themes/ayu-mirage.css" => Some(include_str!("../../assets/css/themes/ayu-mirage.css")),
-        "css/themes/ayu-light.css" => Some(include_str!("../../assets/css/themes/ayu-light.css")),
-        "css/themes/horizon.css" => Some(include_str!("../../assets/css/themes/horizon.css")),
-        "css/themes/cobalt2.css" => Some(include_str!("../../assets/css/themes/cobalt2.css")),
-        "css/themes/synthwave84.css" => {
-            Some(include_str!("../../assets/css/themes/synthwave84.css"))
-        }
-        "css/themes/iceberg.css" => Some(include_str!("../../assets/css/themes/iceberg.css")),
-        "css/themes/zenburn.css" => Some(include_str!("../../assets/css/themes/zenburn.css")),
-        "css/themes/poimandres.css" => Some(include_str!("../../assets/css/themes/poimandres.css")),
-        "css/themes/vesper.css" => Some(include_str!("../../assets/css/themes/vesper.css")),
-        "css/themes/flexoki-dark.css" => {
-            Some(include_str!("../../assets/css/themes/flexoki-dark.css"))
-        }
-        "css/themes/flexoki-light.css" => {
-            Some(include_str!("../../assets/css/themes/flexoki-light.css"))
-        }
-        "css/themes/oxocarbon-dark.css" => {
-            Some(include_str!("../../assets/css/themes/oxocarbon-dark.css"))
-        }
-        "css/themes/kindle.css" => Some(include_str!("../../assets/css/themes/kindle.css")),
-        "css/themes/kobo.css" => Some(include_str!("../../assets/css/themes/kobo.css")),
-        "css/themes/midnight-blue.css" => {
-            Some(include_str!("../../assets/css/themes/midnight-blue.css"))
-        }
-        "css/themes/warm-night.css" => Some(include_str!("../../assets/css/themes/warm-night.css")),
 
         // ========== JS Modular Files ==========
         // Main entry point
@@ -198,6 +106,9 @@ pub async fn serve_static(Path(path): Path<String>) -> Response<Body> {
             Some(include_str!("../../assets/js/features/settings-panel.js"))
         }
         "js/features/position.js" => Some(include_str!("../../assets/js/features/position.js")),
+        "js/features/lazy-chapters.js" => {
+            Some(include_str!("../../assets/js/features/lazy-chapters.js"))
+        }
 
         _ => None,
     };
@@ -214,3 +125,29 @@ pub async fn serve_static(Path(path): Path<String>) -> Response<Body> {
             .unwrap(),
     }
 }
+
+/// Theme stylesheets, generated from the built-in and user themes alike
+///
+/// All of them are generated on the first request for one and reused after.
+fn theme_stylesheet(path: &str) -> Option<&'static str> {
+    static STYLESHEETS: OnceLock<(String, HashMap<String, String>)> = OnceLock::new();
+
+    let name = match path.strip_prefix("themes/") {
+        Some(file) => Some(file.strip_suffix(".css")?),
+        None if path == "css/themes/_index.css" => None,
+        None => return None,
+    };
+    let (index, themes) = STYLESHEETS.get_or_init(|| {
+        let registry = ThemeRegistry::load();
+        let themes = registry
+            .names()
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), registry.css(&name).ok()?)))
+            .collect();
+        (registry.index_css(), themes)
+    });
+    match name {
+        Some(name) => themes.get(name).map(String::as_str),
+        None => Some(index),
+    }
+}
@@ -1,9 +1,10 @@
 //! Template helper functions
 
-use crate::formats::{Chapter, ContentBlock};
+use crate::formats::{math, writer, Chapter, ContentBlock};
+use crate::highlight::Highlighter;
 
 /// Convert a chapter to HTML
-pub fn chapter_to_html(chapter: &Chapter) -> String {
+pub fn chapter_to_html(chapter: &Chapter, highlighter: &Highlighter) -> String {
     let mut html = String::new();
 
     if let Some(title) = &chapter.title {
@@ -15,8 +16,12 @@ pub fn chapter_to_html(chapter: &Chapter) -> String {
 
     for block in &chapter.blocks {
         match block {
-            ContentBlock::Paragraph { text, .. } => {
-                html.push_str(&format!("<p>{}</p>\n", escape_html(text)));
+            ContentBlock::Paragraph { text, styles } => {
+                html.push_str(&format!("<p>{}</p>\n", writer::inline_html(text, styles)));
+            }
+            ContentBlock::Math { tex } => {
+                html.push_str(&math::to_mathml(tex, true));
+                html.push('\n');
             }
             ContentBlock::Heading { level, text } => {
                 let tag_level = (*level + 1).min(6); // Offset by 1 since chapter title is h2
@@ -35,15 +40,8 @@ pub fn chapter_to_html(chapter: &Chapter) -> String {
                 html.push_str("</blockquote>\n");
             }
             ContentBlock::Code { language, code } => {
-                let lang_class = language
-                    .as_ref()
-                    .map(|l| format!(" class=\"language-{}\"", l))
-                    .unwrap_or_default();
-                html.push_str(&format!(
-                    "<pre><code{}>{}</code></pre>\n",
-                    lang_class,
-                    escape_html(code)
-                ));
+                html.push_str(&highlighter.html(code, language.as_deref()));
+                html.push('\n');
             }
             ContentBlock::List { ordered, items } => {
                 let tag = if *ordered { "ol" } else { "ul" };
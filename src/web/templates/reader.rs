@@ -1,32 +1,52 @@
 //! Reader page templates
 
-use crate::config::Config;
-use crate::formats::Book;
 use super::base::base;
 use super::helpers::{chapter_to_html, escape_html, format_word_count};
+use crate::config::Config;
+use crate::formats::LazyBook;
+use crate::highlight::Highlighter;
 
 /// Generate the reader page for books
-pub fn reader(config: &Config, book: &Book, _chapter_index: usize) -> String {
+///
+/// Only the chapters around `chapter_index` are rendered; the others are
+/// placeholders sized by word count that the page fetches from
+/// `chapter_url` (with `{}` standing for the chapter index) as they scroll
+/// into view.
+pub fn reader(config: &Config, book: &LazyBook, chapter_url: &str, chapter_index: usize) -> String {
     // Calculate word counts
-    let chapter_word_counts: Vec<usize> = book
-        .content
-        .chapters
-        .iter()
-        .map(|ch| ch.word_count())
-        .collect();
+    let chapter_word_counts: Vec<usize> = book.chapters().iter().map(|ch| ch.word_count).collect();
     let total_word_count: usize = chapter_word_counts.iter().sum();
+    let chapter_index = chapter_index.min(book.chapter_count().saturating_sub(1));
 
     // Build continuous content from all chapters with markers
+    let highlighter = Highlighter::new(&config.formats.markdown);
     let mut book_content = String::new();
     let mut cumulative_words = 0usize;
-    for (i, chapter) in book.content.chapters.iter().enumerate() {
-        let chapter_words = chapter_word_counts[i];
+    for (i, &chapter_words) in chapter_word_counts.iter().enumerate() {
+        let eager = i + 1 >= chapter_index && i <= chapter_index + 1;
+        let loaded = if eager { book.chapter(i) } else { None };
+
         // Add chapter marker/anchor with word count data
-        book_content.push_str(&format!(
-            r#"<section class="chapter" id="chapter-{}" data-chapter="{}" data-words="{}" data-cumulative-words="{}">"#,
-            i, i, chapter_words, cumulative_words
-        ));
-        book_content.push_str(&chapter_to_html(chapter));
+        match loaded {
+            Some(chapter) => {
+                book_content.push_str(&format!(
+                    r#"<section class="chapter" id="chapter-{}" data-chapter="{}" data-words="{}" data-cumulative-words="{}">"#,
+                    i, i, chapter_words, cumulative_words
+                ));
+                book_content.push_str(&chapter_to_html(&chapter, &highlighter));
+            }
+            None => {
+                // Roughly ten words per line keeps scroll positions stable
+                book_content.push_str(&format!(
+                    r#"<section class="chapter" id="chapter-{}" data-chapter="{}" data-words="{}" data-cumulative-words="{}" data-lazy="true" style="min-height: {}em">"#,
+                    i,
+                    i,
+                    chapter_words,
+                    cumulative_words,
+                    (chapter_words / 10).max(1) * 17 / 10
+                ));
+            }
+        }
         book_content.push_str("</section>\n");
         cumulative_words += chapter_words;
     }
@@ -40,8 +60,7 @@ pub fn reader(config: &Config, book: &Book, _chapter_index: usize) -> String {
 
     // Build TOC with anchor links and word counts
     let toc_items: String = book
-        .content
-        .chapters
+        .chapters()
         .iter()
         .enumerate()
         .map(|(i, ch)| {
@@ -86,6 +105,7 @@ pub fn reader(config: &Config, book: &Book, _chapter_index: usize) -> String {
                     </div>
                 </header>
                 <div class="reader-container" id="reader-container" data-layout="scroll" data-book-id="{book_id}"
+                     data-chapter-url="{chapter_url}" data-initial-chapter="{chapter_index}"
                      data-total-words="{total_words}" data-chapter-words="[{chapter_words}]">
                     <article class="reader-content" id="content">
                         {book_content}
@@ -111,9 +131,12 @@ pub fn reader(config: &Config, book: &Book, _chapter_index: usize) -> String {
         </div>
     "##,
         toc_items = toc_items,
-        title = escape_html(&book.metadata.title),
+        title = escape_html(&book.metadata().title),
         book_content = book_content,
+        chapter_url = escape_html(chapter_url),
+        chapter_index = chapter_index,
         book_id = book
+            .skeleton
             .source_path
             .file_stem()
             .and_then(|s| s.to_str())
@@ -124,7 +147,7 @@ pub fn reader(config: &Config, book: &Book, _chapter_index: usize) -> String {
         settings_panel = settings_panel(),
     );
 
-    base(&book.metadata.title, &content, config)
+    base(&book.metadata().title, &content, config)
 }
 
 /// PDF viewer using PDF.js
@@ -177,9 +200,9 @@ pub fn pdf_reader(config: &Config, book_id: &str, title: &str) -> String {
 }
 
 /// Generate book info page
-pub fn book_info(config: &Config, book: &Book) -> String {
-    let subjects: String = book
-        .metadata
+pub fn book_info(config: &Config, book: &LazyBook) -> String {
+    let metadata = book.metadata();
+    let subjects: String = metadata
         .subjects
         .iter()
         .map(|s| format!(r#"<span class="tag">{}</span>"#, escape_html(s)))
@@ -222,39 +245,33 @@ pub fn book_info(config: &Config, book: &Book) -> String {
             </div>
         </main>
     "#,
-        title = escape_html(&book.metadata.title),
-        author = escape_html(&book.metadata.authors_string()),
-        description = book
-            .metadata
+        title = escape_html(&metadata.title),
+        author = escape_html(&metadata.authors_string()),
+        description = metadata
             .description
             .as_ref()
             .map(|d| format!(r#"<p class="description">{}</p>"#, escape_html(d)))
             .unwrap_or_default(),
-        publisher = book
-            .metadata
+        publisher = metadata
             .publisher
             .as_ref()
             .map(|p| format!(r#"<dt>Publisher</dt><dd>{}</dd>"#, escape_html(p)))
             .unwrap_or_default(),
-        published = book
-            .metadata
+        published = metadata
             .published
             .as_ref()
             .map(|p| format!(r#"<dt>Published</dt><dd>{}</dd>"#, escape_html(p)))
             .unwrap_or_default(),
-        language = book
-            .metadata
+        language = metadata
             .language
             .as_ref()
             .map(|l| format!(r#"<dt>Language</dt><dd>{}</dd>"#, escape_html(l)))
             .unwrap_or_default(),
-        word_count = book
-            .metadata
+        word_count = metadata
             .word_count
             .map(|w| format!(r#"<dt>Word Count</dt><dd>{}</dd>"#, w))
             .unwrap_or_default(),
-        reading_time = book
-            .metadata
+        reading_time = metadata
             .reading_time
             .map(|t| format!(r#"<dt>Reading Time</dt><dd>~{} min</dd>"#, t))
             .unwrap_or_default(),
@@ -262,7 +279,7 @@ pub fn book_info(config: &Config, book: &Book) -> String {
         book_id = "main", // TODO: Use actual book ID
     );
 
-    base(&book.metadata.title, &content, config)
+    base(&metadata.title, &content, config)
 }
 
 /// Generate error page
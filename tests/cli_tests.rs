@@ -75,3 +75,314 @@ fn test_init_creates_config() {
         .success()
         .stdout(predicate::str::contains("Configuration initialized"));
 }
+
+#[test]
+fn test_cache_stats_and_clear() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+
+    franko()
+        .args(["cache", "stats"])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Entries:     0"));
+
+    franko()
+        .args(["cache", "clear"])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 cached books"));
+}
+
+#[test]
+fn test_convert_markdown_to_epub() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("notes.md");
+    std::fs::write(&md_path, "# Notes\n\nSome text.\n\n## More\n\nEven more.").unwrap();
+
+    franko()
+        .args(["convert", md_path.to_str().unwrap(), "--author", "Tester"])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Converted"));
+
+    let epub_path = temp.path().join("notes.epub");
+    assert!(epub_path.exists());
+
+    franko()
+        .args(["library", "add", epub_path.to_str().unwrap()])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added: Notes by Tester"));
+}
+
+#[test]
+fn test_convert_markdown_to_text() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("plain.md");
+    let txt_path = temp.path().join("plain.txt");
+    std::fs::write(
+        &md_path,
+        "---\ntitle: Plain\nauthor: Someone\n---\n\n# One\n\nalpha beta gamma delta epsilon\n",
+    )
+    .unwrap();
+
+    franko()
+        .args([
+            "convert",
+            md_path.to_str().unwrap(),
+            "-o",
+            txt_path.to_str().unwrap(),
+            "--width",
+            "12",
+        ])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(&txt_path).unwrap();
+    assert!(text.starts_with("Plain\n=====\nby Someone"));
+    assert!(text.contains("alpha beta\ngamma delta\nepsilon"));
+}
+
+#[test]
+fn test_convert_hyphenates_text() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("long.md");
+    let txt_path = temp.path().join("long.txt");
+    std::fs::write(
+        &md_path,
+        "# One\n\nAn extraordinarily comprehensive vocabulary, co\u{ad}operatively.\n",
+    )
+    .unwrap();
+
+    franko()
+        .args([
+            "convert",
+            md_path.to_str().unwrap(),
+            "-o",
+            txt_path.to_str().unwrap(),
+            "--width",
+            "16",
+        ])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success();
+
+    // Words break by the English patterns; soft hyphens are left out
+    let text = std::fs::read_to_string(&txt_path).unwrap();
+    assert!(text.contains("An extraordinar-\nily comprehen-\nsive"));
+    assert!(text.contains("\ncooperatively."));
+}
+
+#[test]
+fn test_convert_markdown_math() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("math.md");
+    std::fs::write(
+        &md_path,
+        "---\ntitle: Math\nmath: true\n---\n\nWe know $a^2 + b^2 = c^2$ for $5.\n\n\
+         $$\\frac{1}{\\sqrt{x}}$$\n\n`$code$`\n",
+    )
+    .unwrap();
+
+    let convert = |output: &std::path::Path| {
+        franko()
+            .args([
+                "convert",
+                md_path.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ])
+            .env("HOME", temp.path())
+            .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+            .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+            .assert()
+            .success();
+    };
+
+    let html_path = temp.path().join("math.html");
+    convert(&html_path);
+    let html = std::fs::read_to_string(&html_path).unwrap();
+    assert!(
+        html.contains("We know <math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><msup>")
+    );
+    assert!(html.contains("display=\"block\"><mfrac><mn>1</mn><msqrt><mi>x</mi></msqrt></mfrac>"));
+    assert!(html.contains("for $5."));
+
+    // EPUB output carries MathML, which the EPUB parser reads back
+    let epub_path = temp.path().join("math.epub");
+    convert(&epub_path);
+    let txt_path = temp.path().join("math.txt");
+    franko()
+        .args([
+            "convert",
+            epub_path.to_str().unwrap(),
+            "-o",
+            txt_path.to_str().unwrap(),
+            "--width",
+            "20",
+        ])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success();
+    let text = std::fs::read_to_string(&txt_path).unwrap();
+    assert!(text.contains("a² + b² = c²"));
+    assert!(text.contains("         1\n        ────\n          _\n         √x\n"));
+    assert!(text.contains("$code$"));
+}
+
+#[test]
+fn test_convert_keeps_inline_styles() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("styles.md");
+    std::fs::write(
+        &md_path,
+        "# Styles\n\nSome **bold**, *italic* and `code` in a [link](https://example.org).\n",
+    )
+    .unwrap();
+
+    let convert = |input: &std::path::Path, output: &std::path::Path| {
+        franko()
+            .args([
+                "convert",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ])
+            .env("HOME", temp.path())
+            .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+            .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+            .assert()
+            .success();
+    };
+
+    // Styles survive Markdown to EPUB and back through the EPUB parser
+    let epub_path = temp.path().join("styles.epub");
+    let out_path = temp.path().join("out.md");
+    convert(&md_path, &epub_path);
+    convert(&epub_path, &out_path);
+    let markdown = std::fs::read_to_string(&out_path).unwrap();
+    assert!(
+        markdown.contains("Some **bold**, *italic* and `code` in a [link](https://example.org).")
+    );
+
+    // Links keep their target from Markdown to Markdown too
+    let direct_path = temp.path().join("direct.md");
+    convert(&md_path, &direct_path);
+    let markdown = std::fs::read_to_string(&direct_path).unwrap();
+    assert!(markdown.contains("[link](https://example.org)"));
+}
+
+#[test]
+fn test_library_edit_writes_file() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("draft.md");
+    std::fs::write(
+        &md_path,
+        "---\ntitle: Draft\nlayout: book\n---\n\n# Draft\n\nText.\n",
+    )
+    .unwrap();
+
+    let run = |args: &[&str]| {
+        franko()
+            .args(args)
+            .env("HOME", temp.path())
+            .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+            .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+            .assert()
+            .success()
+    };
+
+    run(&["library", "add", md_path.to_str().unwrap()]);
+    let listed = run(&["library", "list", "--output", "plain"]);
+    let stdout = String::from_utf8_lossy(&listed.get_output().stdout).to_string();
+    let id = stdout
+        .lines()
+        .find_map(|line| line.strip_suffix(" - Draft by Unknown Author"))
+        .unwrap()
+        .to_string();
+
+    run(&[
+        "library",
+        "edit",
+        &id,
+        "--title",
+        "Final",
+        "--author",
+        "Writer",
+        "--tag",
+        "essays",
+        "--write-file",
+    ])
+    .stdout(predicate::str::contains("Updated: Final by Writer"));
+
+    let source = std::fs::read_to_string(&md_path).unwrap();
+    assert!(source.starts_with(
+        "---\ntitle: \"Final\"\nlayout: book\nauthor: \"Writer\"\ntags: \"essays\"\n---\n"
+    ));
+    assert!(source.ends_with("# Draft\n\nText.\n"));
+
+    run(&["library", "info", &id])
+        .stdout(predicate::str::contains("Title:       Final"))
+        .stdout(predicate::str::contains("Subjects:    essays"));
+}
+
+#[test]
+fn test_doctor_reports_stages() {
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let md_path = temp.path().join("tiny.md");
+    std::fs::write(&md_path, "# Tiny\n\nToo short.\n").unwrap();
+
+    franko()
+        .args(["doctor", md_path.to_str().unwrap()])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("container"))
+        .stdout(predicate::str::contains("under 50 words"));
+
+    franko()
+        .args(["doctor", temp.path().join("gone.epub").to_str().unwrap()])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Cannot read file"));
+}
@@ -100,3 +100,39 @@ fn test_unsupported_format() {
         .assert()
         .failure(); // Should fail for unsupported format
 }
+
+#[test]
+fn test_add_file_without_extension() {
+    use assert_cmd::Command;
+    use predicates::prelude::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    let temp = tempdir().unwrap();
+    let path = temp.path().join("README");
+
+    fs::write(&path, "A book without an extension.\n\nIt is plain text.").unwrap();
+
+    // Content sniffing should pick plain text even with no extension
+    #[allow(deprecated)]
+    Command::cargo_bin("franko")
+        .unwrap()
+        .args(["library", "add", path.to_str().unwrap()])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added"));
+
+    #[allow(deprecated)]
+    Command::cargo_bin("franko")
+        .unwrap()
+        .args(["library", "list", "--output", "csv"])
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .env("XDG_DATA_HOME", temp.path().join(".local/share"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(",txt,"));
+}